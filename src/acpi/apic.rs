@@ -50,6 +50,7 @@ pub static IOAPIC: Lazy<Mutex<IoApic>> = Lazy::new(|| unsafe {
 #[repr(u8)]
 pub enum IrqVector {
     Keyboard = 1,
+    Serial = 4,
     Mouse = 12,
     HpetTimer,
 }
@@ -62,6 +63,7 @@ pub fn init() {
         ioapic_add_entry(IrqVector::Keyboard, InterruptIndex::Keyboard);
         ioapic_add_entry(IrqVector::Mouse, InterruptIndex::Mouse);
         ioapic_add_entry(IrqVector::HpetTimer, InterruptIndex::HpetTimer);
+        ioapic_add_entry(IrqVector::Serial, InterruptIndex::Serial);
     };
 
     APIC_INIT.store(true, Ordering::SeqCst);
@@ -37,6 +37,11 @@ impl Hpet {
         ticks + (duration.as_nanos() * 1_000_000 / self.fms_per_tick as u128) as u64
     }
 
+    /// The current value of the main counter.
+    pub fn ticks(&self) -> u64 {
+        self.elapsed_ticks()
+    }
+
     pub fn set_timer(&self, value: u64) {
         let comparator_addr = self.address + 0x108;
         unsafe {
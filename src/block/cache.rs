@@ -0,0 +1,386 @@
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::{Lazy, Mutex};
+
+use super::{BlockDeviceRef, BlockError};
+
+pub const CACHE_PAGE_SIZE: usize = 4096;
+const CACHE_CAPACITY: usize = 2048;
+
+pub static BUFFER_CACHE: Lazy<BufferCache> = Lazy::new(|| BufferCache::new(CACHE_CAPACITY));
+
+type CacheKey = (usize, u64);
+
+struct CachePage {
+    data: Box<[u8]>,
+    len: usize,
+    /// Whether `data` holds the disk contents yet.
+    loaded: bool,
+    dirty: bool,
+}
+
+struct CacheEntry {
+    page: Arc<Mutex<CachePage>>,
+    last_used: u64,
+}
+
+struct CacheState {
+    pages: BTreeMap<CacheKey, CacheEntry>,
+    devices: BTreeMap<usize, BlockDeviceRef>,
+    tick: u64,
+}
+
+/// Shared LRU page cache sitting between filesystems and block devices.
+///
+/// Pages are keyed by the whole-disk device, so a partition and the disk it
+/// lives on see the same cached data. Writes stay in the cache until the page
+/// is evicted or the device is synced.
+///
+/// The index is behind one lock, but device I/O only holds the lock of the
+/// page being read or written back, so transfers to different pages don't
+/// wait on each other. A page being loaded stays locked until it is filled.
+/// The capacity is a soft limit: a page that is used again while it is
+/// written back for eviction stays cached.
+pub struct BufferCache {
+    state: Mutex<CacheState>,
+    capacity: usize,
+}
+
+fn device_id(device: &BlockDeviceRef) -> usize {
+    Arc::as_ptr(device) as *const () as usize
+}
+
+fn resolve(device: &BlockDeviceRef) -> (BlockDeviceRef, u64) {
+    match device.parent() {
+        Some((parent, start)) => (parent.clone(), start * parent.sector_size() as u64),
+        None => (device.clone(), 0),
+    }
+}
+
+fn write_back(
+    disk: &BlockDeviceRef,
+    page_index: u64,
+    page: &mut CachePage,
+) -> Result<(), BlockError> {
+    let sector = page_index * CACHE_PAGE_SIZE as u64 / disk.sector_size() as u64;
+    disk.write_sectors(sector, &page.data[..page.len])?;
+    page.dirty = false;
+    Ok(())
+}
+
+impl BufferCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                pages: BTreeMap::new(),
+                devices: BTreeMap::new(),
+                tick: 0,
+            }),
+            capacity,
+        }
+    }
+
+    pub fn read(
+        &self,
+        device: &BlockDeviceRef,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, BlockError> {
+        let size = device.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+
+        let (disk, base) = resolve(device);
+        let mut done = 0;
+        while done < len {
+            let position = base + offset + done as u64;
+            let page_index = position / CACHE_PAGE_SIZE as u64;
+            let page_offset = (position % CACHE_PAGE_SIZE as u64) as usize;
+            let count = (len - done).min(CACHE_PAGE_SIZE - page_offset);
+
+            self.with_page(&disk, page_index, false, |page| {
+                buf[done..done + count]
+                    .copy_from_slice(&page.data[page_offset..page_offset + count]);
+            })?;
+            done += count;
+        }
+
+        Ok(len)
+    }
+
+    pub fn write(
+        &self,
+        device: &BlockDeviceRef,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize, BlockError> {
+        if offset + buf.len() as u64 > device.size() {
+            return Err(BlockError::OutOfRange);
+        }
+
+        let (disk, base) = resolve(device);
+        let mut done = 0;
+        while done < buf.len() {
+            let position = base + offset + done as u64;
+            let page_index = position / CACHE_PAGE_SIZE as u64;
+            let page_offset = (position % CACHE_PAGE_SIZE as u64) as usize;
+            let count = (buf.len() - done).min(CACHE_PAGE_SIZE - page_offset);
+
+            let overwrite = page_offset == 0 && count == CACHE_PAGE_SIZE;
+            self.with_page(&disk, page_index, overwrite, |page| {
+                page.data[page_offset..page_offset + count]
+                    .copy_from_slice(&buf[done..done + count]);
+                page.dirty = true;
+            })?;
+            done += count;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Writes back every dirty page of the disk behind `device` and flushes it.
+    pub fn sync(&self, device: &BlockDeviceRef) -> Result<(), BlockError> {
+        let (disk, _) = resolve(device);
+        let id = device_id(&disk);
+
+        let pages = self
+            .state
+            .lock()
+            .pages
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|(&(_, page_index), entry)| (page_index, entry.page.clone()))
+            .collect::<Vec<_>>();
+
+        for (page_index, page) in pages {
+            let mut page = page.lock();
+            if page.dirty {
+                write_back(&disk, page_index, &mut page)?;
+            }
+        }
+
+        disk.flush()
+    }
+
+    /// Syncs every cached disk, carrying on past failing ones and
+    /// returning the first error.
+    pub fn sync_all(&self) -> Result<(), BlockError> {
+        let devices = self
+            .state
+            .lock()
+            .devices
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut result = Ok(());
+        for device in devices {
+            result = result.and(self.sync(&device));
+        }
+        result
+    }
+
+    /// Drops every cached page of the disk behind `device` without writing it back.
+    pub fn invalidate(&self, device: &BlockDeviceRef) {
+        let (disk, _) = resolve(device);
+        let id = device_id(&disk);
+        let mut state = self.state.lock();
+        state.pages.retain(|(page_device, _), _| *page_device != id);
+        state.devices.remove(&id);
+    }
+}
+
+impl BufferCache {
+    /// Runs `f` on the cached page, loading it first unless the caller is
+    /// about to `overwrite` all of it.
+    fn with_page<R>(
+        &self,
+        disk: &BlockDeviceRef,
+        page_index: u64,
+        overwrite: bool,
+        f: impl FnOnce(&mut CachePage) -> R,
+    ) -> Result<R, BlockError> {
+        let sector_size = disk.sector_size();
+        if !CACHE_PAGE_SIZE.is_multiple_of(sector_size) {
+            return Err(BlockError::Unaligned);
+        }
+
+        let id = device_id(disk);
+        let key = (id, page_index);
+
+        let cached = {
+            let mut state = self.state.lock();
+            state.tick += 1;
+            let tick = state.tick;
+            state.pages.get_mut(&key).map(|entry| {
+                entry.last_used = tick;
+                entry.page.clone()
+            })
+        };
+
+        let page = match cached {
+            Some(page) => page,
+            None => {
+                self.evict()?;
+
+                let position = page_index * CACHE_PAGE_SIZE as u64;
+                let len = (disk.size() - position).min(CACHE_PAGE_SIZE as u64) as usize;
+
+                let mut state = self.state.lock();
+                state.devices.entry(id).or_insert_with(|| disk.clone());
+                let last_used = state.tick;
+                state
+                    .pages
+                    .entry(key)
+                    .or_insert_with(|| CacheEntry {
+                        page: Arc::new(Mutex::new(CachePage {
+                            data: alloc::vec![0u8; CACHE_PAGE_SIZE].into_boxed_slice(),
+                            len,
+                            loaded: false,
+                            dirty: false,
+                        })),
+                        last_used,
+                    })
+                    .page
+                    .clone()
+            }
+        };
+
+        let mut page = page.lock();
+        if !page.loaded {
+            if !overwrite {
+                let position = page_index * CACHE_PAGE_SIZE as u64;
+                let len = page.len;
+                disk.read_sectors(position / sector_size as u64, &mut page.data[..len])?;
+            }
+            page.loaded = true;
+        }
+        Ok(f(&mut page))
+    }
+
+    /// Makes room for one more page by dropping the least recently used one,
+    /// writing it back first if it is dirty.
+    fn evict(&self) -> Result<(), BlockError> {
+        let victim = {
+            let state = self.state.lock();
+            if state.pages.len() < self.capacity {
+                return Ok(());
+            }
+            state
+                .pages
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(&key, entry)| (key, entry.page.clone(), state.devices[&key.0].clone()))
+        };
+        let Some((key, page, disk)) = victim else {
+            return Ok(());
+        };
+
+        {
+            let mut page = page.lock();
+            if page.dirty {
+                write_back(&disk, key.1, &mut page)?;
+            }
+        }
+
+        // Someone may have dirtied the page again, or be using it, since.
+        let mut state = self.state.lock();
+        let unused = state
+            .pages
+            .get(&key)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.page, &page) && Arc::strong_count(&page) == 2);
+        if unused && page.try_lock().is_some_and(|page| !page.dirty) {
+            state.pages.remove(&key);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::{BufferCache, CACHE_PAGE_SIZE};
+    use crate::block::{BlockDevice, BlockDeviceRef, Partition, RamDisk};
+
+    const SECTOR_SIZE: usize = 512;
+
+    fn disk(pages: usize) -> BlockDeviceRef {
+        let sectors = (pages * CACHE_PAGE_SIZE / SECTOR_SIZE) as u64;
+        Arc::new(RamDisk::new(SECTOR_SIZE, sectors))
+    }
+
+    fn raw_page(disk: &BlockDeviceRef, page: usize) -> alloc::vec::Vec<u8> {
+        let mut buf = alloc::vec![0u8; CACHE_PAGE_SIZE];
+        let sector = (page * CACHE_PAGE_SIZE / SECTOR_SIZE) as u64;
+        disk.read_sectors(sector, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn writes_stay_cached_until_sync() {
+        let disk = disk(2);
+        let cache = BufferCache::new(4);
+
+        cache.write(&disk, 100, b"hello").unwrap();
+        assert!(raw_page(&disk, 0).iter().all(|&byte| byte == 0));
+
+        let mut buf = [0u8; 5];
+        cache.read(&disk, 100, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+
+        cache.sync_all().unwrap();
+        assert_eq!(&raw_page(&disk, 0)[100..105], b"hello");
+    }
+
+    #[test]
+    fn evicts_least_recently_used_page() {
+        let disk = disk(3);
+        let cache = BufferCache::new(2);
+
+        cache.write(&disk, 0, b"first").unwrap();
+        cache
+            .write(&disk, CACHE_PAGE_SIZE as u64, b"second")
+            .unwrap();
+
+        // Touching page 0 leaves page 1 as the eviction victim.
+        let mut buf = [0u8; 1];
+        cache.read(&disk, 0, &mut buf).unwrap();
+        cache
+            .read(&disk, 2 * CACHE_PAGE_SIZE as u64, &mut buf)
+            .unwrap();
+
+        assert_eq!(cache.state.lock().pages.len(), 2);
+        assert_eq!(&raw_page(&disk, 1)[..6], b"second");
+        assert!(raw_page(&disk, 0).iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn reads_past_the_end_are_short() {
+        let disk = disk(1);
+        let cache = BufferCache::new(4);
+
+        let mut buf = [0u8; 16];
+        let end = CACHE_PAGE_SIZE as u64;
+        assert_eq!(cache.read(&disk, end - 4, &mut buf), Ok(4));
+        assert_eq!(cache.read(&disk, end, &mut buf), Ok(0));
+        assert!(cache.write(&disk, end - 4, &buf).is_err());
+    }
+
+    #[test]
+    fn partitions_share_pages_with_their_disk() {
+        let disk = disk(2);
+        let partition: BlockDeviceRef = Arc::new(Partition::new(disk.clone(), 8, 8));
+        let cache = BufferCache::new(4);
+
+        cache.write(&partition, 0, b"shared").unwrap();
+
+        let mut buf = [0u8; 6];
+        cache.read(&disk, 8 * SECTOR_SIZE as u64, &mut buf).unwrap();
+        assert_eq!(&buf, b"shared");
+        assert_eq!(cache.state.lock().pages.len(), 1);
+
+        cache.sync(&partition).unwrap();
+        assert_eq!(&raw_page(&disk, 1)[..6], b"shared");
+    }
+}
@@ -0,0 +1,88 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+};
+use spin::Mutex;
+
+use crate::fs::{
+    operation::kernel_open,
+    vfs::{block::BlockFS, inode::mount_to},
+};
+
+pub mod cache;
+pub mod partition;
+pub mod ram;
+
+pub use cache::BUFFER_CACHE;
+pub use partition::Partition;
+pub use ram::RamDisk;
+
+pub type BlockDeviceRef = Arc<dyn BlockDevice>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    OutOfRange,
+    Unaligned,
+    ReadOnly,
+    Io,
+}
+
+pub trait BlockDevice: Send + Sync {
+    fn sector_size(&self) -> usize;
+    fn sector_count(&self) -> u64;
+
+    fn read_sectors(&self, start: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+    fn write_sectors(&self, start: u64, buf: &[u8]) -> Result<(), BlockError>;
+    fn flush(&self) -> Result<(), BlockError> {
+        Ok(())
+    }
+
+    /// The whole-disk device and the sector this device starts at, for
+    /// devices that are a window onto another one (such as partitions).
+    fn parent(&self) -> Option<(BlockDeviceRef, u64)> {
+        None
+    }
+
+    fn size(&self) -> u64 {
+        self.sector_count() * self.sector_size() as u64
+    }
+}
+
+pub static BLOCK_DEVICES: Mutex<BTreeMap<String, BlockDeviceRef>> = Mutex::new(BTreeMap::new());
+
+pub fn get_block_device(name: &str) -> Option<BlockDeviceRef> {
+    BLOCK_DEVICES.lock().get(name).cloned()
+}
+
+/// Resolves a block device by name or by its `/dev` path.
+pub fn find_block_device(source: &str) -> Option<BlockDeviceRef> {
+    get_block_device(source.strip_prefix("/dev/").unwrap_or(source))
+}
+
+fn publish(name: &str, device: BlockDeviceRef) {
+    BLOCK_DEVICES.lock().insert(name.to_string(), device.clone());
+
+    if let Some(dev_fs) = kernel_open("/dev".to_string()) {
+        mount_to(BlockFS::new(device), dev_fs, name.to_string());
+    }
+
+    log::info!("Registered block device /dev/{}", name);
+}
+
+/// Registers a whole-disk device as `/dev/<name>` together with every
+/// partition found in its MBR or GPT as `/dev/<name><n>`.
+pub fn register_block_device(name: &str, device: BlockDeviceRef) {
+    publish(name, device.clone());
+
+    let separator = if name.ends_with(|c: char| c.is_ascii_digit()) {
+        "p"
+    } else {
+        ""
+    };
+
+    for (number, partition) in partition::scan(&device) {
+        let partition_name = alloc::format!("{}{}{}", name, separator, number);
+        publish(&partition_name, Arc::new(partition));
+    }
+}
@@ -0,0 +1,250 @@
+use alloc::vec::Vec;
+
+use super::{BUFFER_CACHE, BlockDevice, BlockDeviceRef, BlockError};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+
+/// A contiguous range of sectors on another block device.
+pub struct Partition {
+    disk: BlockDeviceRef,
+    start: u64,
+    count: u64,
+}
+
+impl Partition {
+    pub fn new(disk: BlockDeviceRef, start: u64, count: u64) -> Self {
+        Self { disk, start, count }
+    }
+
+    fn check_range(&self, start: u64, len: usize) -> Result<(), BlockError> {
+        let sectors = len.div_ceil(self.sector_size()) as u64;
+        if start + sectors > self.count {
+            return Err(BlockError::OutOfRange);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for Partition {
+    fn sector_size(&self) -> usize {
+        self.disk.sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.count
+    }
+
+    fn read_sectors(&self, start: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_range(start, buf.len())?;
+        self.disk.read_sectors(self.start + start, buf)
+    }
+
+    fn write_sectors(&self, start: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_range(start, buf.len())?;
+        self.disk.write_sectors(self.start + start, buf)
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        self.disk.flush()
+    }
+
+    fn parent(&self) -> Option<(BlockDeviceRef, u64)> {
+        Some((self.disk.clone(), self.start))
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_sector(disk: &BlockDeviceRef, sector: u64) -> Option<Vec<u8>> {
+    let sector_size = disk.sector_size();
+    let mut buf = alloc::vec![0u8; sector_size];
+    let offset = sector * sector_size as u64;
+    match BUFFER_CACHE.read(disk, offset, &mut buf) {
+        Ok(len) if len == sector_size => Some(buf),
+        _ => None,
+    }
+}
+
+/// Parses the partition table of `disk`, returning each partition with its
+/// number (the MBR slot, or the index in the GPT entry array, starting at 1).
+pub fn scan(disk: &BlockDeviceRef) -> Vec<(usize, Partition)> {
+    let Some(mbr) = read_sector(disk, 0) else {
+        return Vec::new();
+    };
+    if mbr.len() < 512 || mbr[510..512] != MBR_SIGNATURE {
+        return Vec::new();
+    }
+
+    let entries = (0..4)
+        .map(|index| {
+            let entry = MBR_TABLE_OFFSET + index * MBR_ENTRY_SIZE;
+            (mbr[entry + 4], read_u32(&mbr, entry + 8), read_u32(&mbr, entry + 12))
+        })
+        .collect::<Vec<_>>();
+
+    if entries
+        .iter()
+        .any(|&(ty, _, _)| ty == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return scan_gpt(disk);
+    }
+
+    let mut partitions = Vec::new();
+    for (index, &(ty, start, count)) in entries.iter().enumerate() {
+        if ty == 0 || count == 0 || MBR_TYPE_EXTENDED.contains(&ty) {
+            continue;
+        }
+        if start as u64 + count as u64 > disk.sector_count() {
+            log::warn!("MBR partition {} lies outside the disk", index + 1);
+            continue;
+        }
+        partitions.push((
+            index + 1,
+            Partition::new(disk.clone(), start as u64, count as u64),
+        ));
+    }
+
+    partitions
+}
+
+fn scan_gpt(disk: &BlockDeviceRef) -> Vec<(usize, Partition)> {
+    let mut partitions = Vec::new();
+
+    let Some(header) = read_sector(disk, 1) else {
+        return partitions;
+    };
+    if &header[0..8] != GPT_SIGNATURE {
+        log::warn!("Protective MBR found but GPT header is missing");
+        return partitions;
+    }
+
+    let entry_lba = read_u64(&header, 72);
+    let entry_count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    if entry_size < 128 {
+        return partitions;
+    }
+
+    let sector_size = disk.sector_size();
+    let mut table = alloc::vec![0u8; entry_count * entry_size];
+    let offset = entry_lba * sector_size as u64;
+    if BUFFER_CACHE.read(disk, offset, &mut table).ok() != Some(table.len()) {
+        return partitions;
+    }
+
+    for index in 0..entry_count {
+        let entry = &table[index * entry_size..(index + 1) * entry_size];
+        if entry[0..16].iter().all(|&byte| byte == 0) {
+            continue;
+        }
+
+        let first = read_u64(entry, 32);
+        let last = read_u64(entry, 40);
+        if last < first || last >= disk.sector_count() {
+            log::warn!("GPT partition {} lies outside the disk", index + 1);
+            continue;
+        }
+
+        partitions.push((
+            index + 1,
+            Partition::new(disk.clone(), first, last - first + 1),
+        ));
+    }
+
+    partitions
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use super::{MBR_ENTRY_SIZE, MBR_TABLE_OFFSET, Partition, scan};
+    use crate::block::{BUFFER_CACHE, BlockDevice, BlockDeviceRef, RamDisk};
+
+    const SECTOR_SIZE: usize = 512;
+    const SECTORS: usize = 128;
+
+    fn image() -> Vec<u8> {
+        let mut image = alloc::vec![0u8; SECTORS * SECTOR_SIZE];
+        image[510..512].copy_from_slice(&[0x55, 0xaa]);
+        image
+    }
+
+    fn mbr_entry(image: &mut [u8], slot: usize, ty: u8, start: u32, count: u32) {
+        let entry = MBR_TABLE_OFFSET + slot * MBR_ENTRY_SIZE;
+        image[entry + 4] = ty;
+        image[entry + 8..entry + 12].copy_from_slice(&start.to_le_bytes());
+        image[entry + 12..entry + 16].copy_from_slice(&count.to_le_bytes());
+    }
+
+    /// Scans the image, dropping its pages from the shared cache afterwards
+    /// so a later disk at the same address doesn't see them.
+    fn scan_image(image: Vec<u8>) -> Vec<(usize, u64, u64)> {
+        let disk: BlockDeviceRef = Arc::new(RamDisk::from_image(SECTOR_SIZE, image));
+        let partitions = scan(&disk)
+            .into_iter()
+            .map(|(number, partition): (usize, Partition)| {
+                let (_, start) = partition.parent().unwrap();
+                (number, start, partition.sector_count())
+            })
+            .collect();
+        BUFFER_CACHE.invalidate(&disk);
+        partitions
+    }
+
+    #[test]
+    fn reads_mbr_primary_partitions() {
+        let mut image = image();
+        mbr_entry(&mut image, 0, 0x83, 8, 16);
+        mbr_entry(&mut image, 1, 0x05, 24, 8);
+        mbr_entry(&mut image, 2, 0x83, 100, 100);
+        mbr_entry(&mut image, 3, 0x0c, 32, 64);
+
+        assert_eq!(scan_image(image), [(1, 8, 16), (4, 32, 64)]);
+    }
+
+    #[test]
+    fn ignores_disks_without_a_signature() {
+        let mut image = image();
+        mbr_entry(&mut image, 0, 0x83, 8, 16);
+        image[510] = 0;
+
+        assert!(scan_image(image).is_empty());
+    }
+
+    #[test]
+    fn reads_gpt_entries() {
+        let mut image = image();
+        mbr_entry(&mut image, 0, 0xee, 1, SECTORS as u32 - 1);
+
+        let header = SECTOR_SIZE;
+        image[header..header + 8].copy_from_slice(b"EFI PART");
+        image[header + 72..header + 80].copy_from_slice(&2u64.to_le_bytes());
+        image[header + 80..header + 84].copy_from_slice(&4u32.to_le_bytes());
+        image[header + 84..header + 88].copy_from_slice(&128u32.to_le_bytes());
+
+        let mut entry = |index: usize, first: u64, last: u64| {
+            let entry = 2 * SECTOR_SIZE + index * 128;
+            image[entry] = 0xaf;
+            image[entry + 32..entry + 40].copy_from_slice(&first.to_le_bytes());
+            image[entry + 40..entry + 48].copy_from_slice(&last.to_le_bytes());
+        };
+        entry(0, 34, 49);
+        entry(2, 40, 1000);
+        entry(3, 64, 127);
+
+        assert_eq!(scan_image(image), [(1, 34, 16), (4, 64, 64)]);
+    }
+}
@@ -0,0 +1,63 @@
+use alloc::vec::Vec;
+use spin::RwLock;
+
+use super::{BlockDevice, BlockError};
+
+/// A block device backed by kernel memory, for boot images and testing.
+pub struct RamDisk {
+    sector_size: usize,
+    data: RwLock<Vec<u8>>,
+}
+
+impl RamDisk {
+    pub fn new(sector_size: usize, sector_count: u64) -> Self {
+        Self::from_image(
+            sector_size,
+            alloc::vec![0u8; sector_size * sector_count as usize],
+        )
+    }
+
+    /// Wraps a disk image; a trailing partial sector is dropped.
+    pub fn from_image(sector_size: usize, mut image: Vec<u8>) -> Self {
+        image.truncate(image.len() / sector_size * sector_size);
+        Self {
+            sector_size,
+            data: RwLock::new(image),
+        }
+    }
+
+    fn range(&self, start: u64, len: usize) -> Result<core::ops::Range<usize>, BlockError> {
+        if !len.is_multiple_of(self.sector_size) {
+            return Err(BlockError::Unaligned);
+        }
+
+        let begin = start as usize * self.sector_size;
+        if begin + len > self.data.read().len() {
+            return Err(BlockError::OutOfRange);
+        }
+
+        Ok(begin..begin + len)
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> u64 {
+        (self.data.read().len() / self.sector_size) as u64
+    }
+
+    fn read_sectors(&self, start: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let range = self.range(start, buf.len())?;
+        buf.copy_from_slice(&self.data.read()[range]);
+        Ok(())
+    }
+
+    fn write_sectors(&self, start: u64, buf: &[u8]) -> Result<(), BlockError> {
+        let range = self.range(start, buf.len())?;
+        self.data.write()[range].copy_from_slice(buf);
+        Ok(())
+    }
+}
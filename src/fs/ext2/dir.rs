@@ -0,0 +1,244 @@
+use alloc::{string::String, vec::Vec};
+
+use super::{Ext2Volume, RawInode, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG, read_u16, read_u32};
+
+const DIR_ENTRY_HEADER: usize = 8;
+
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+const FT_FIFO: u8 = 5;
+const FT_SYMLINK: u8 = 7;
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub ino: u32,
+    pub name: String,
+}
+
+fn entry_len(name_len: usize) -> usize {
+    (DIR_ENTRY_HEADER + name_len).next_multiple_of(4)
+}
+
+fn file_type(mode: u16) -> u8 {
+    match mode & S_IFMT {
+        S_IFREG => FT_REG_FILE,
+        S_IFDIR => FT_DIR,
+        S_IFIFO => FT_FIFO,
+        S_IFLNK => FT_SYMLINK,
+        _ => 0,
+    }
+}
+
+impl Ext2Volume {
+    fn dir_blocks(&self, dir: &mut RawInode) -> Vec<(usize, Vec<u8>)> {
+        let block_size = self.block_size();
+        let mut blocks = Vec::new();
+
+        for index in 0..(dir.size() as usize).div_ceil(block_size) {
+            let mut block = alloc::vec![0u8; block_size];
+            if self.read_data(dir, index * block_size, &mut block) != block_size {
+                break;
+            }
+            blocks.push((index, block));
+        }
+
+        blocks
+    }
+
+    pub fn read_dir(&self, dir: &mut RawInode) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+
+        for (_, block) in self.dir_blocks(dir) {
+            let mut offset = 0;
+            while offset + DIR_ENTRY_HEADER <= block.len() {
+                let ino = read_u32(&block, offset);
+                let rec_len = read_u16(&block, offset + 4) as usize;
+                let name_len = match self.filetype {
+                    true => block[offset + 6] as usize,
+                    false => read_u16(&block, offset + 6) as usize,
+                };
+                if rec_len < DIR_ENTRY_HEADER || offset + rec_len > block.len() {
+                    break;
+                }
+
+                let name = &block[offset + DIR_ENTRY_HEADER..];
+                if ino != 0 && name_len <= rec_len - DIR_ENTRY_HEADER {
+                    entries.push(DirEntry {
+                        ino,
+                        name: String::from_utf8_lossy(&name[..name_len]).into(),
+                    });
+                }
+                offset += rec_len;
+            }
+        }
+
+        entries
+    }
+
+    pub fn find_entry(&self, dir: &mut RawInode, name: &str) -> Option<DirEntry> {
+        self.read_dir(dir)
+            .into_iter()
+            .find(|entry| entry.name == name)
+    }
+
+    /// Finds the entry `name` in `dir`, returning its byte offset in the
+    /// directory, its record length and the offset of the entry before it
+    /// in the same block, if any.
+    fn locate_entry(
+        &self,
+        dir: &mut RawInode,
+        name: &str,
+    ) -> Option<(usize, usize, Option<usize>)> {
+        let block_size = self.block_size();
+
+        for (index, block) in self.dir_blocks(dir) {
+            let mut offset = 0;
+            let mut previous = None;
+            while offset + DIR_ENTRY_HEADER <= block_size {
+                let ino = read_u32(&block, offset);
+                let rec_len = read_u16(&block, offset + 4) as usize;
+                let name_len = match self.filetype {
+                    true => block[offset + 6] as usize,
+                    false => read_u16(&block, offset + 6) as usize,
+                };
+                if rec_len < DIR_ENTRY_HEADER || offset + rec_len > block_size {
+                    break;
+                }
+
+                let start = offset + DIR_ENTRY_HEADER;
+                if ino != 0
+                    && name_len <= rec_len - DIR_ENTRY_HEADER
+                    && &block[start..start + name_len] == name.as_bytes()
+                {
+                    let position = index * block_size;
+                    return Some((
+                        position + offset,
+                        rec_len,
+                        previous.map(|previous| position + previous),
+                    ));
+                }
+                previous = Some(offset);
+                offset += rec_len;
+            }
+        }
+
+        None
+    }
+
+    /// Unlinks the entry `name` from `dir`, handing its space to the entry
+    /// before it, or clearing it if it starts a block.
+    pub fn remove_entry(&self, dir: &mut RawInode, name: &str) -> Option<()> {
+        let (position, rec_len, previous) = self.locate_entry(dir, name)?;
+        let written = match previous {
+            Some(previous) => {
+                let merged = (position - previous + rec_len) as u16;
+                self.write_data(dir, previous + 4, &merged.to_le_bytes()) == 2
+            }
+            None => self.write_data(dir, position, &0u32.to_le_bytes()) == 4,
+        };
+        written.then_some(())
+    }
+
+    /// Points the existing entry `name` of `dir` at `ino`, whose mode is
+    /// `mode`.
+    pub fn set_entry(&self, dir: &mut RawInode, name: &str, ino: u32, mode: u16) -> Option<()> {
+        let (position, _, _) = self.locate_entry(dir, name)?;
+        if self.write_data(dir, position, &ino.to_le_bytes()) != 4 {
+            return None;
+        }
+        if self.filetype && self.write_data(dir, position + 7, &[file_type(mode)]) != 1 {
+            return None;
+        }
+        Some(())
+    }
+
+    fn encode_entry(&self, buf: &mut [u8], ino: u32, rec_len: usize, name: &str, mode: u16) {
+        buf[0..4].copy_from_slice(&ino.to_le_bytes());
+        buf[4..6].copy_from_slice(&(rec_len as u16).to_le_bytes());
+        if self.filetype {
+            buf[6] = name.len() as u8;
+            buf[7] = file_type(mode);
+        } else {
+            buf[6..8].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        }
+        buf[DIR_ENTRY_HEADER..DIR_ENTRY_HEADER + name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    /// Links `ino` into `dir` under `name`, growing the directory by a block
+    /// if no entry has enough slack.
+    pub fn add_entry(&self, dir: &mut RawInode, name: &str, ino: u32, mode: u16) -> Option<()> {
+        if name.is_empty() || name.len() > 255 {
+            return None;
+        }
+
+        let block_size = self.block_size();
+        let needed = entry_len(name.len());
+
+        for (index, mut block) in self.dir_blocks(dir) {
+            let mut offset = 0;
+            while offset + DIR_ENTRY_HEADER <= block_size {
+                let entry_ino = read_u32(&block, offset);
+                let rec_len = read_u16(&block, offset + 4) as usize;
+                if rec_len < DIR_ENTRY_HEADER || offset + rec_len > block_size {
+                    break;
+                }
+                let used = match entry_ino {
+                    0 => 0,
+                    _ => entry_len(match self.filetype {
+                        true => block[offset + 6] as usize,
+                        false => read_u16(&block, offset + 6) as usize,
+                    }),
+                };
+
+                if rec_len >= used + needed {
+                    let start = if used == 0 {
+                        self.encode_entry(&mut block[offset..], ino, rec_len, name, mode);
+                        offset
+                    } else {
+                        block[offset + 4..offset + 6].copy_from_slice(&(used as u16).to_le_bytes());
+                        let start = offset + used;
+                        self.encode_entry(&mut block[start..], ino, rec_len - used, name, mode);
+                        start
+                    };
+                    let end = start + needed;
+                    let position = index * block_size;
+                    return (self.write_data(dir, position + offset, &block[offset..end])
+                        == end - offset)
+                        .then_some(());
+                }
+
+                offset += rec_len;
+            }
+        }
+
+        let position = dir.size() as usize;
+        let mut block = alloc::vec![0u8; block_size];
+        self.encode_entry(&mut block, ino, block_size, name, mode);
+        if self.write_data(dir, position, &block) != block_size {
+            return None;
+        }
+        dir.set_size(position as u64 + block_size as u64);
+        Some(())
+    }
+
+    /// Writes the `.` and `..` entries of a new directory.
+    pub fn init_dir(&self, dir: &mut RawInode, ino: u32, parent: u32) -> Option<()> {
+        let block_size = self.block_size();
+        let mut block = alloc::vec![0u8; block_size];
+        let dot_len = entry_len(1);
+        self.encode_entry(&mut block, ino, dot_len, ".", S_IFDIR);
+        self.encode_entry(
+            &mut block[dot_len..],
+            parent,
+            block_size - dot_len,
+            "..",
+            S_IFDIR,
+        );
+
+        if self.write_data(dir, 0, &block) != block_size {
+            return None;
+        }
+        dir.set_size(block_size as u64);
+        Some(())
+    }
+}
@@ -0,0 +1,715 @@
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{any::Any, time::Duration};
+use spin::{Mutex, RwLock};
+
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode},
+        vfs::{
+            inode::{FileInfo, Inode, InodeRef, InodeTy, Metadata},
+            pipe::Pipe,
+            stat_struct::StatVfs,
+        },
+    },
+    syscall::errno::{EBUSY, EINVAL, EIO, ENOENT, ENOTEMPTY, EROFS, EXDEV},
+};
+
+use super::{
+    Ext2Volume, ROOT_INO, RawInode, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG, dir::DirEntry,
+};
+
+/// Symlink targets shorter than this are stored in the block map itself.
+const FAST_SYMLINK_MAX: u64 = 60;
+
+pub struct Ext2FS {
+    path: String,
+    volume: Arc<Ext2Volume>,
+    ino: u32,
+    this: Weak<RwLock<Ext2FS>>,
+    parent: Option<InodeRef>,
+    children: Mutex<BTreeMap<String, InodeRef>>,
+    inode: Mutex<RawInode>,
+    /// The pipe behind a FIFO, created on first open.
+    fifo: Mutex<Option<Arc<Pipe>>>,
+}
+
+/// Inode times are 32-bit seconds since the Unix epoch.
+#[cfg(not(test))]
+fn now() -> u32 {
+    crate::time::now().as_secs() as u32
+}
+
+/// The host running the tests has no clock of ours to read.
+#[cfg(test)]
+fn now() -> u32 {
+    0
+}
+
+fn inode_type(mode: u16) -> InodeTy {
+    match mode & S_IFMT {
+        S_IFDIR => InodeTy::Dir,
+        S_IFLNK => InodeTy::Symlink,
+        S_IFIFO => InodeTy::Fifo,
+        _ => InodeTy::File,
+    }
+}
+
+impl Ext2FS {
+    pub fn new_root(volume: Arc<Ext2Volume>) -> Option<InodeRef> {
+        let inode = volume.read_inode(ROOT_INO)?;
+        if inode.mode() & S_IFMT != S_IFDIR {
+            return None;
+        }
+        Some(Self::with_inode(volume, ROOT_INO, inode))
+    }
+
+    fn with_inode(volume: Arc<Ext2Volume>, ino: u32, inode: RawInode) -> InodeRef {
+        Arc::new_cyclic(|this| {
+            RwLock::new(Self {
+                path: String::new(),
+                volume,
+                ino,
+                this: this.clone(),
+                parent: None,
+                children: Mutex::new(BTreeMap::new()),
+                inode: Mutex::new(inode),
+                fifo: Mutex::new(None),
+            })
+        })
+    }
+
+    fn this(&self) -> Option<InodeRef> {
+        self.this.upgrade().map(|this| this as InodeRef)
+    }
+
+    fn file_type(&self) -> u16 {
+        self.inode.lock().mode() & S_IFMT
+    }
+
+    fn attach(&self, name: &str, ino: u32, inode: RawInode) -> InodeRef {
+        let node = Self::with_inode(self.volume.clone(), ino, inode);
+        node.write()
+            .when_mounted(self.path.clone() + name + "/", self.this());
+        self.children.lock().insert(name.into(), node.clone());
+        node
+    }
+
+    /// `node` if it is an inode of this volume rather than something
+    /// mounted on top of it.
+    fn same_volume<'a>(&self, node: &'a dyn Inode) -> Option<&'a Ext2FS> {
+        (node as &dyn Any)
+            .downcast_ref::<Ext2FS>()
+            .filter(|node| Arc::ptr_eq(&node.volume, &self.volume))
+    }
+
+    fn is_empty_dir(&self) -> bool {
+        self.volume
+            .read_dir(&mut self.inode.lock())
+            .iter()
+            .all(|entry| entry.name == "." || entry.name == "..")
+    }
+
+    /// Drops the link this directory, whose inode is `dir`, had to `node`.
+    /// The inode itself is freed once the last reference to `node` goes.
+    fn unlinked(&self, node: &Ext2FS, dir: &mut RawInode, time: u32) -> Option<()> {
+        let mut inode = node.inode.lock();
+        if inode.mode() & S_IFMT == S_IFDIR {
+            // Its `.` goes with it, and with its `..` our link count drops.
+            inode.set_links_count(0);
+            dir.set_links_count(dir.links_count().saturating_sub(1));
+        } else {
+            let links = inode.links_count();
+            inode.set_links_count(links.saturating_sub(1));
+        }
+        inode.set_ctime(time);
+        self.volume.write_inode(node.ino, &inode)
+    }
+
+    /// Allocates and links a new inode of `mode` named `name` in this
+    /// directory, owned by `uid`:`gid`.
+    fn create_inode(&self, name: &str, mode: u16, uid: u32, gid: u32) -> Option<(u32, RawInode)> {
+        let volume = &self.volume;
+        let is_dir = mode & S_IFMT == S_IFDIR;
+
+        let mut dir = self.inode.lock();
+        let ino = volume.allocate_inode(self.ino, is_dir)?;
+        let mut inode = RawInode::new(mode, uid, gid);
+        let time = now();
+        inode.set_atime(time);
+        inode.set_ctime(time);
+        inode.set_mtime(time);
+
+        let linked = (|| {
+            if is_dir {
+                inode.set_links_count(2);
+                volume.init_dir(&mut inode, ino, self.ino)?;
+            } else {
+                inode.set_links_count(1);
+            }
+            volume.write_inode(ino, &inode)?;
+            volume.add_entry(&mut dir, name, ino, mode)
+        })();
+
+        if linked.is_none() {
+            volume.free_blocks(&mut inode);
+            volume.free_inode(ino, is_dir);
+            return None;
+        }
+
+        if is_dir {
+            let links = dir.links_count();
+            dir.set_links_count(links + 1);
+        }
+        dir.set_ctime(time);
+        dir.set_mtime(time);
+        volume.write_inode(self.ino, &dir)?;
+
+        Some((ino, inode))
+    }
+}
+
+impl Inode for Ext2FS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        if father.is_some() {
+            self.parent = father;
+        }
+    }
+
+    fn when_umounted(&mut self) {
+        for node in self.children.lock().values() {
+            node.write().when_umounted();
+        }
+        self.flush();
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        self.inode.lock().size() as usize
+    }
+
+    fn mount(&self, node: InodeRef, name: String) {
+        self.children.lock().insert(name, node);
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        let mut inode = self.inode.lock();
+        let size = inode.size() as usize;
+        if inode.mode() & S_IFMT != S_IFREG || offset >= size {
+            return 0;
+        }
+
+        let len = buf.len().min(size - offset);
+        self.volume.read_data(&mut inode, offset, &mut buf[..len])
+    }
+
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        let mut inode = self.inode.lock();
+        if self.volume.is_read_only() || inode.mode() & S_IFMT != S_IFREG {
+            return 0;
+        }
+
+        let written = self.volume.write_data(&mut inode, offset, buf);
+        let end = (offset + written) as u64;
+        if end > inode.size() {
+            inode.set_size(end);
+        }
+        let time = now();
+        inode.set_ctime(time);
+        inode.set_mtime(time);
+        self.volume.write_inode(self.ino, &inode);
+
+        written
+    }
+
+    fn flush(&self) {
+        self.volume.sync();
+    }
+
+    fn statfs(&self) -> Option<StatVfs> {
+        Some(self.volume.statfs())
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        match name.as_str() {
+            "." => return self.this(),
+            ".." => return self.parent.clone().or_else(|| self.this()),
+            _ => {}
+        }
+
+        if self.file_type() != S_IFDIR {
+            return None;
+        }
+        if let Some(node) = self.children.lock().get(&name).cloned() {
+            return Some(node);
+        }
+
+        let DirEntry { ino, name } = self.volume.find_entry(&mut self.inode.lock(), &name)?;
+        let inode = self.volume.read_inode(ino)?;
+        Some(self.attach(&name, ino, inode))
+    }
+
+    fn create(&self, name: String, ty: InodeTy) -> Option<InodeRef> {
+        let mode = match ty {
+            InodeTy::Dir => 0o755,
+            _ => 0o644,
+        };
+        self.create_owned(name, ty, 0, 0, mode)
+    }
+
+    fn create_owned(
+        &self,
+        name: String,
+        ty: InodeTy,
+        uid: u32,
+        gid: u32,
+        mode: u16,
+    ) -> Option<InodeRef> {
+        if let Some(node) = self.open(name.clone()) {
+            return Some(node);
+        }
+        if self.volume.is_read_only() || self.file_type() != S_IFDIR {
+            return None;
+        }
+
+        let file_type = match ty {
+            InodeTy::File => S_IFREG,
+            InodeTy::Dir => S_IFDIR,
+            InodeTy::Fifo => S_IFIFO,
+            _ => return None,
+        };
+
+        let (ino, inode) = self.create_inode(&name, file_type | mode & 0o7777, uid, gid)?;
+        Some(self.attach(&name, ino, inode))
+    }
+
+    fn unlink(&self, name: String, _dir: bool) -> Result<(), usize> {
+        if self.volume.is_read_only() {
+            return Err(EROFS);
+        }
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+
+        {
+            let node = node.read();
+            let node = self.same_volume(&*node).ok_or(EBUSY)?;
+            if node.file_type() == S_IFDIR && !node.is_empty_dir() {
+                return Err(ENOTEMPTY);
+            }
+
+            let time = now();
+            let mut dir = self.inode.lock();
+            self.volume.remove_entry(&mut dir, &name).ok_or(EIO)?;
+            self.unlinked(node, &mut dir, time).ok_or(EIO)?;
+            dir.set_ctime(time);
+            dir.set_mtime(time);
+            self.volume.write_inode(self.ino, &dir).ok_or(EIO)?;
+        }
+
+        self.children.lock().remove(&name);
+        Ok(())
+    }
+
+    fn rename(&self, name: String, new_parent: &InodeRef, new_name: String) -> Result<(), usize> {
+        if self.volume.is_read_only() {
+            return Err(EROFS);
+        }
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+        let new_parent = new_parent.read();
+        let target = self.same_volume(&*new_parent).ok_or(EXDEV)?;
+        let replaced = target.open(new_name.clone());
+        if replaced
+            .as_ref()
+            .is_some_and(|replaced| Arc::ptr_eq(replaced, &node))
+        {
+            return Ok(());
+        }
+
+        {
+            let moved = node.read();
+            let moved = self.same_volume(&*moved).ok_or(EBUSY)?;
+            let mode = moved.inode.lock().mode();
+            // A directory moving elsewhere takes its `..` link along.
+            let reparented = mode & S_IFMT == S_IFDIR && target.ino != self.ino;
+            let time = now();
+
+            {
+                let mut dir = target.inode.lock();
+                match &replaced {
+                    Some(replaced) => {
+                        let replaced = replaced.read();
+                        let replaced = self.same_volume(&*replaced).ok_or(EBUSY)?;
+                        if replaced.file_type() == S_IFDIR && !replaced.is_empty_dir() {
+                            return Err(ENOTEMPTY);
+                        }
+                        self.volume
+                            .set_entry(&mut dir, &new_name, moved.ino, mode)
+                            .ok_or(EIO)?;
+                        target.unlinked(replaced, &mut dir, time).ok_or(EIO)?;
+                    }
+                    None => self
+                        .volume
+                        .add_entry(&mut dir, &new_name, moved.ino, mode)
+                        .ok_or(EIO)?,
+                }
+                if reparented {
+                    let links = dir.links_count();
+                    dir.set_links_count(links + 1);
+                }
+                dir.set_ctime(time);
+                dir.set_mtime(time);
+                self.volume.write_inode(target.ino, &dir).ok_or(EIO)?;
+            }
+
+            {
+                let mut dir = self.inode.lock();
+                self.volume.remove_entry(&mut dir, &name).ok_or(EIO)?;
+                if reparented {
+                    let links = dir.links_count();
+                    dir.set_links_count(links.saturating_sub(1));
+                }
+                dir.set_ctime(time);
+                dir.set_mtime(time);
+                self.volume.write_inode(self.ino, &dir).ok_or(EIO)?;
+            }
+
+            let mut inode = moved.inode.lock();
+            if reparented {
+                self.volume
+                    .set_entry(&mut inode, "..", target.ino, S_IFDIR)
+                    .ok_or(EIO)?;
+            }
+            inode.set_ctime(time);
+            self.volume.write_inode(moved.ino, &inode).ok_or(EIO)?;
+        }
+
+        self.children.lock().remove(&name);
+        target
+            .children
+            .lock()
+            .insert(new_name.clone(), node.clone());
+        node.write()
+            .when_mounted(target.path.clone() + &new_name + "/", target.this());
+        Ok(())
+    }
+
+    /// Cuts the file down, freeing its blocks, or extends it with a hole.
+    fn truncate(&self, _fd: usize, len: usize) -> Result<(), usize> {
+        if self.volume.is_read_only() {
+            return Err(EROFS);
+        }
+        let mut inode = self.inode.lock();
+        if inode.mode() & S_IFMT != S_IFREG {
+            return Err(EINVAL);
+        }
+
+        let len = len as u64;
+        if len < inode.size() {
+            self.volume.truncate_data(&mut inode, len).ok_or(EIO)?;
+        }
+        inode.set_size(len);
+        let time = now();
+        inode.set_ctime(time);
+        inode.set_mtime(time);
+        self.volume.write_inode(self.ino, &inode).ok_or(EIO)
+    }
+
+    fn on_open(&self, mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        if self.file_type() != S_IFIFO {
+            return Ok(None);
+        }
+
+        let pipe = self.fifo.lock().get_or_insert_with(Pipe::new).clone();
+        pipe.open_fifo(mode.into(), flags.contains(OpenFlags::NONBLOCK))
+            .map(Some)
+    }
+
+    fn chmod(&self, mode: u16) -> Result<(), usize> {
+        if self.volume.is_read_only() {
+            return Err(EROFS);
+        }
+        let mut inode = self.inode.lock();
+        let mode = inode.mode() & S_IFMT | mode & 0o7777;
+        inode.set_mode(mode);
+        inode.set_ctime(now());
+        self.volume.write_inode(self.ino, &inode).ok_or(EIO)
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), usize> {
+        if self.volume.is_read_only() {
+            return Err(EROFS);
+        }
+        let mut inode = self.inode.lock();
+        inode.set_owner(uid, gid);
+        inode.set_ctime(now());
+        self.volume.write_inode(self.ino, &inode).ok_or(EIO)
+    }
+
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> usize {
+        usize::MAX
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        if self.file_type() != S_IFDIR {
+            return Vec::new();
+        }
+
+        let entries = self.volume.read_dir(&mut self.inode.lock());
+        let mut vec = Vec::new();
+        for entry in entries.iter() {
+            let ty = match entry.name.as_str() {
+                "." | ".." => InodeTy::Dir,
+                _ => match self.volume.read_inode(entry.ino) {
+                    Some(inode) => inode_type(inode.mode()),
+                    None => continue,
+                },
+            };
+            vec.push(FileInfo::new(entry.name.clone(), ty));
+        }
+
+        for (name, node) in self.children.lock().iter() {
+            if !entries.iter().any(|entry| &entry.name == name) {
+                vec.push(FileInfo::new(name.clone(), node.read().inode_type()));
+            }
+        }
+
+        vec
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        inode_type(self.inode.lock().mode())
+    }
+
+    fn read_link(&self) -> Option<String> {
+        let mut inode = self.inode.lock();
+        if inode.mode() & S_IFMT != S_IFLNK {
+            return None;
+        }
+
+        let size = inode.size();
+        let mut target = alloc::vec![0u8; size as usize];
+        if size < FAST_SYMLINK_MAX && inode.sectors() == 0 {
+            target.copy_from_slice(&inode.block_bytes()[..size as usize]);
+        } else if self.volume.read_data(&mut inode, 0, &mut target) != target.len() {
+            return None;
+        }
+
+        String::from_utf8(target).ok()
+    }
+
+    fn mode(&self) -> u16 {
+        self.inode.lock().mode() & 0o7777
+    }
+
+    fn metadata(&self, _fd: usize) -> Metadata {
+        let inode = self.inode.lock();
+        Metadata {
+            ino: self.ino as u64,
+            mode: inode.mode() as u32,
+            nlink: inode.links_count() as u64,
+            uid: inode.uid(),
+            gid: inode.gid(),
+            size: inode.size(),
+            blksize: self.volume.block_size() as u64,
+            blocks: inode.sectors() as u64,
+            atime: Duration::from_secs(inode.atime() as u64),
+            mtime: Duration::from_secs(inode.mtime() as u64),
+            ctime: Duration::from_secs(inode.ctime() as u64),
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for Ext2FS {
+    /// An unlinked inode lives on while it is open; whoever lets go of it
+    /// last gives its blocks and number back.
+    fn drop(&mut self) {
+        let inode = self.inode.get_mut();
+        if inode.links_count() != 0 || self.volume.is_read_only() {
+            return;
+        }
+
+        let dir = inode.mode() & S_IFMT == S_IFDIR;
+        self.volume.free_blocks(inode);
+        inode.set_dtime(now());
+        self.volume.write_inode(self.ino, inode);
+        self.volume.free_inode(self.ino, dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, sync::Arc, vec::Vec};
+
+    use super::{Ext2FS, Ext2Volume, S_IFDIR, S_IFMT};
+    use crate::block::{BUFFER_CACHE, BlockDeviceRef, RamDisk};
+    use crate::fs::vfs::inode::{InodeRef, InodeTy};
+    use crate::syscall::errno::ENOTEMPTY;
+
+    /// `mke2fs -t ext2 -b 1024 -N 32 -m 0` on 128 blocks: one group, 1 KiB
+    /// blocks so that small files reach the indirect blocks.
+    const IMAGE: &[u8] = include_bytes!("testdata/mke2fs-128k.img");
+
+    fn mount() -> (BlockDeviceRef, Arc<Ext2Volume>, InodeRef) {
+        let disk: BlockDeviceRef = Arc::new(RamDisk::from_image(512, IMAGE.to_vec()));
+        let volume = Ext2Volume::new(disk.clone()).unwrap();
+        let root = Ext2FS::new_root(volume.clone()).unwrap();
+        (disk, volume, root)
+    }
+
+    fn read(node: &InodeRef, offset: usize, len: usize) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; len];
+        let read = node.read().read_at(0, offset, &mut buf);
+        buf.truncate(read);
+        buf
+    }
+
+    /// The inode number the on-disk `..` entry of `dir` points at.
+    fn dot_dot(volume: &Ext2Volume, dir: &InodeRef) -> u32 {
+        let ino = dir.read().metadata(0).ino as u32;
+        let mut inode = volume.read_inode(ino).unwrap();
+        volume.find_entry(&mut inode, "..").unwrap().ino
+    }
+
+    #[test]
+    fn truncate_frees_blocks_and_reads_zeros_past_the_old_end() {
+        let (disk, volume, root) = mount();
+        let file = root.read().create("file".into(), InodeTy::File).unwrap();
+        let (free, _) = volume.free_counts();
+
+        // 12 direct blocks, then 8 more behind the indirect block.
+        assert_eq!(file.read().write_at(0, 0, &[0xaa; 20 * 1024]), 20 * 1024);
+        assert_eq!(volume.free_counts().0, free - 21);
+
+        file.read().truncate(0, 5000).unwrap();
+        assert_eq!(volume.free_counts().0, free - 5);
+        assert_eq!(file.read().metadata(0).blocks, 10);
+
+        file.read().truncate(0, 8192).unwrap();
+        assert_eq!(volume.free_counts().0, free - 5);
+        assert_eq!(read(&file, 0, 5000), [0xaa; 5000]);
+        assert_eq!(read(&file, 5000, 8192), [0; 3192]);
+
+        file.read().truncate(0, 0).unwrap();
+        assert_eq!(volume.free_counts().0, free);
+        assert_eq!(file.read().metadata(0).blocks, 0);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn truncate_frees_double_indirect_blocks() {
+        let (disk, volume, root) = mount();
+        let file = root.read().create("sparse".into(), InodeTy::File).unwrap();
+        let (free, _) = volume.free_counts();
+
+        // Past 12 direct and 256 indirect blocks, leaving a hole before.
+        let offset = (12 + 256 + 1) * 1024;
+        file.read().write_at(0, 0, &[1; 1024]);
+        file.read().write_at(0, offset, &[2; 1]);
+        assert_eq!(volume.free_counts().0, free - 4);
+
+        file.read().truncate(0, 1024).unwrap();
+        assert_eq!(volume.free_counts().0, free - 1);
+        assert_eq!(read(&file, 0, 2048), [1; 1024]);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn unlinked_files_are_freed_on_last_close() {
+        let (disk, volume, root) = mount();
+        let counts = volume.free_counts();
+
+        let file = root.read().create("file".into(), InodeTy::File).unwrap();
+        file.read().write_at(0, 0, &[0x55; 3000]);
+        root.read().unlink("file".into(), false).unwrap();
+
+        assert!(root.read().open("file".into()).is_none());
+        assert_eq!(read(&file, 0, 3000), [0x55; 3000]);
+        assert_ne!(volume.free_counts(), counts);
+
+        drop(file);
+        assert_eq!(volume.free_counts(), counts);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn only_empty_directories_are_removed() {
+        let (disk, volume, root) = mount();
+        let counts = volume.free_counts();
+        let links = root.read().metadata(0).nlink;
+
+        let dir = root.read().create("dir".into(), InodeTy::Dir).unwrap();
+        dir.read().create("file".into(), InodeTy::File).unwrap();
+        assert_eq!(root.read().metadata(0).nlink, links + 1);
+        assert_eq!(root.read().unlink("dir".into(), true), Err(ENOTEMPTY));
+
+        dir.read().unlink("file".into(), false).unwrap();
+        root.read().unlink("dir".into(), true).unwrap();
+        drop(dir);
+        assert_eq!(root.read().metadata(0).nlink, links);
+        assert_eq!(volume.free_counts(), counts);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn rename_moves_directories_between_parents() {
+        let (disk, volume, root) = mount();
+        let from = root.read().create("from".into(), InodeTy::Dir).unwrap();
+        let to = root.read().create("to".into(), InodeTy::Dir).unwrap();
+        let moved = from.read().create("dir".into(), InodeTy::Dir).unwrap();
+
+        from.read()
+            .rename("dir".into(), &to, "moved".into())
+            .unwrap();
+
+        assert!(from.read().open("dir".into()).is_none());
+        let found = to.read().open("moved".into()).unwrap();
+        assert!(Arc::ptr_eq(&found, &moved));
+        assert_eq!(moved.read().get_path(), String::from("to/moved/"));
+        assert_eq!(dot_dot(&volume, &moved), to.read().metadata(0).ino as u32);
+        assert_eq!(from.read().metadata(0).nlink, 2);
+        assert_eq!(to.read().metadata(0).nlink, 3);
+        assert_eq!(moved.read().metadata(0).mode as u16 & S_IFMT, S_IFDIR);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn rename_replaces_the_target() {
+        let (disk, volume, root) = mount();
+        let counts = volume.free_counts();
+
+        let old = root.read().create("old".into(), InodeTy::File).unwrap();
+        old.read().write_at(0, 0, b"old");
+        let new = root.read().create("new".into(), InodeTy::File).unwrap();
+        new.read().write_at(0, 0, b"new");
+
+        root.read()
+            .rename("old".into(), &root, "new".into())
+            .unwrap();
+        drop(new);
+
+        let found = root.read().open("new".into()).unwrap();
+        assert!(Arc::ptr_eq(&found, &old));
+        assert_eq!(read(&found, 0, 16), b"old");
+        assert!(root.read().open("old".into()).is_none());
+
+        root.read().unlink("new".into(), false).unwrap();
+        drop((found, old));
+        assert_eq!(volume.free_counts(), counts);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+}
@@ -0,0 +1,688 @@
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use crate::block::{BUFFER_CACHE, BlockDeviceRef};
+
+use super::vfs::{
+    inode::InodeRef,
+    stat_struct::{EXT2_SUPER_MAGIC, StatVfs},
+};
+
+mod dir;
+mod inode;
+
+pub use inode::Ext2FS;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+const ROOT_INO: u32 = 2;
+
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+const RO_COMPAT_BTREE_DIR: u32 = 0x0004;
+const RO_COMPAT_SUPPORTED: u32 =
+    RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE | RO_COMPAT_BTREE_DIR;
+
+const GROUP_DESC_SIZE: u64 = 32;
+const DIRECT_BLOCKS: usize = 12;
+const RAW_INODE_SIZE: usize = 128;
+
+pub const S_IFMT: u16 = 0o170000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFIFO: u16 = 0o010000;
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// The first 128 bytes of an on-disk inode, common to every revision.
+#[derive(Clone)]
+pub struct RawInode {
+    raw: [u8; RAW_INODE_SIZE],
+}
+
+impl RawInode {
+    fn new(mode: u16, uid: u32, gid: u32) -> Self {
+        let mut inode = Self {
+            raw: [0; RAW_INODE_SIZE],
+        };
+        inode.set_mode(mode);
+        inode.set_owner(uid, gid);
+        inode
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, 0)
+    }
+
+    fn set_mode(&mut self, mode: u16) {
+        self.raw[0..2].copy_from_slice(&mode.to_le_bytes());
+    }
+
+    pub fn uid(&self) -> u32 {
+        read_u16(&self.raw, 2) as u32 | (read_u16(&self.raw, 120) as u32) << 16
+    }
+
+    pub fn gid(&self) -> u32 {
+        read_u16(&self.raw, 24) as u32 | (read_u16(&self.raw, 122) as u32) << 16
+    }
+
+    /// The high halves of the IDs live in the Linux-specific `osd2` area.
+    fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.raw[2..4].copy_from_slice(&(uid as u16).to_le_bytes());
+        self.raw[120..122].copy_from_slice(&((uid >> 16) as u16).to_le_bytes());
+        self.raw[24..26].copy_from_slice(&(gid as u16).to_le_bytes());
+        self.raw[122..124].copy_from_slice(&((gid >> 16) as u16).to_le_bytes());
+    }
+
+    /// File size; the high half lives in `i_dir_acl` for regular files.
+    pub fn size(&self) -> u64 {
+        let high = if self.mode() & S_IFMT == S_IFREG {
+            read_u32(&self.raw, 108) as u64
+        } else {
+            0
+        };
+        read_u32(&self.raw, 4) as u64 | high << 32
+    }
+
+    fn set_size(&mut self, size: u64) {
+        self.raw[4..8].copy_from_slice(&(size as u32).to_le_bytes());
+        if self.mode() & S_IFMT == S_IFREG {
+            self.raw[108..112].copy_from_slice(&((size >> 32) as u32).to_le_bytes());
+        }
+    }
+
+    pub fn atime(&self) -> u32 {
+        read_u32(&self.raw, 8)
+    }
+
+    pub fn ctime(&self) -> u32 {
+        read_u32(&self.raw, 12)
+    }
+
+    pub fn mtime(&self) -> u32 {
+        read_u32(&self.raw, 16)
+    }
+
+    fn set_atime(&mut self, time: u32) {
+        self.raw[8..12].copy_from_slice(&time.to_le_bytes());
+    }
+
+    fn set_ctime(&mut self, time: u32) {
+        self.raw[12..16].copy_from_slice(&time.to_le_bytes());
+    }
+
+    fn set_mtime(&mut self, time: u32) {
+        self.raw[16..20].copy_from_slice(&time.to_le_bytes());
+    }
+
+    fn set_dtime(&mut self, time: u32) {
+        self.raw[20..24].copy_from_slice(&time.to_le_bytes());
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, 26)
+    }
+
+    fn set_links_count(&mut self, count: u16) {
+        self.raw[26..28].copy_from_slice(&count.to_le_bytes());
+    }
+
+    /// Allocated space in 512-byte sectors.
+    pub fn sectors(&self) -> u32 {
+        read_u32(&self.raw, 28)
+    }
+
+    fn set_sectors(&mut self, sectors: u32) {
+        self.raw[28..32].copy_from_slice(&sectors.to_le_bytes());
+    }
+
+    fn block(&self, index: usize) -> u32 {
+        read_u32(&self.raw, 40 + index * 4)
+    }
+
+    fn set_block(&mut self, index: usize, block: u32) {
+        self.raw[40 + index * 4..44 + index * 4].copy_from_slice(&block.to_le_bytes());
+    }
+
+    /// Bytes of the block map, which hold the target of a fast symlink.
+    fn block_bytes(&self) -> &[u8] {
+        &self.raw[40..100]
+    }
+}
+
+/// A mounted ext2 volume.
+pub struct Ext2Volume {
+    device: BlockDeviceRef,
+    block_size: u64,
+    blocks_count: u32,
+    inodes_count: u32,
+    first_data_block: u32,
+    blocks_per_group: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    group_count: u32,
+    group_table: u64,
+    first_ino: u32,
+    filetype: bool,
+    read_only: bool,
+    alloc_lock: Mutex<()>,
+}
+
+/// Mounts the ext2 filesystem on `device`, returning its root directory.
+pub fn mount(device: BlockDeviceRef) -> Option<InodeRef> {
+    let volume = Ext2Volume::new(device)?;
+    Ext2FS::new_root(volume)
+}
+
+impl Ext2Volume {
+    pub fn new(device: BlockDeviceRef) -> Option<Arc<Self>> {
+        let mut sb = [0u8; 1024];
+        if BUFFER_CACHE
+            .read(&device, SUPERBLOCK_OFFSET, &mut sb)
+            .ok()?
+            != sb.len()
+        {
+            return None;
+        }
+        if read_u16(&sb, 56) != EXT2_MAGIC {
+            return None;
+        }
+
+        let inodes_count = read_u32(&sb, 0);
+        let blocks_count = read_u32(&sb, 4);
+        let first_data_block = read_u32(&sb, 20);
+        let block_size = 1024u64.checked_shl(read_u32(&sb, 24))?;
+        let blocks_per_group = read_u32(&sb, 32);
+        let inodes_per_group = read_u32(&sb, 40);
+        let rev_level = read_u32(&sb, 76);
+
+        let (first_ino, inode_size, incompat, ro_compat) = match rev_level {
+            0 => (11, RAW_INODE_SIZE as u64, 0, 0),
+            _ => (
+                read_u32(&sb, 84),
+                read_u16(&sb, 88) as u64,
+                read_u32(&sb, 96),
+                read_u32(&sb, 100),
+            ),
+        };
+
+        if block_size > 65536
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || first_ino <= ROOT_INO
+            || inode_size < RAW_INODE_SIZE as u64
+        {
+            return None;
+        }
+        if incompat & !INCOMPAT_SUPPORTED != 0 {
+            log::warn!("ext2: unsupported incompatible features {:#x}", incompat);
+            return None;
+        }
+
+        let read_only = ro_compat & !RO_COMPAT_SUPPORTED != 0;
+        if read_only {
+            log::warn!(
+                "ext2: unsupported read-only features {:#x}, mounting read-only",
+                ro_compat
+            );
+        }
+
+        let group_count = blocks_count
+            .checked_sub(first_data_block)?
+            .div_ceil(blocks_per_group);
+        let volume = Self {
+            device,
+            block_size,
+            blocks_count,
+            inodes_count,
+            first_data_block,
+            blocks_per_group,
+            inodes_per_group,
+            inode_size,
+            group_count,
+            group_table: (first_data_block as u64 + 1) * block_size,
+            first_ino,
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            read_only,
+            alloc_lock: Mutex::new(()),
+        };
+
+        log::info!(
+            "Mounted ext2 volume: {} blocks of {} bytes, {} inodes",
+            blocks_count,
+            block_size,
+            inodes_count
+        );
+
+        Some(Arc::new(volume))
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size as usize
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Option<()> {
+        let len = BUFFER_CACHE.read(&self.device, offset, buf).ok()?;
+        (len == buf.len()).then_some(())
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Option<()> {
+        if self.read_only {
+            return None;
+        }
+        BUFFER_CACHE.write(&self.device, offset, buf).ok()?;
+        Some(())
+    }
+
+    fn read_block(&self, block: u32, offset: usize, buf: &mut [u8]) -> Option<()> {
+        self.read(block as u64 * self.block_size + offset as u64, buf)
+    }
+
+    fn write_block(&self, block: u32, offset: usize, buf: &[u8]) -> Option<()> {
+        self.write(block as u64 * self.block_size + offset as u64, buf)
+    }
+
+    fn read_field_u32(&self, offset: u64) -> Option<u32> {
+        let mut buf = [0u8; 4];
+        self.read(offset, &mut buf)?;
+        Some(u32::from_le_bytes(buf))
+    }
+
+    /// Adds `delta` to the little-endian counter at `offset`.
+    fn adjust_counter(&self, offset: u64, width: usize, delta: i32) -> Option<()> {
+        let mut buf = [0u8; 4];
+        self.read(offset, &mut buf[..width])?;
+        let value = (u32::from_le_bytes(buf) as i64 + delta as i64) as u32;
+        self.write(offset, &value.to_le_bytes()[..width])
+    }
+
+    fn group_desc(&self, group: u32) -> u64 {
+        self.group_table + group as u64 * GROUP_DESC_SIZE
+    }
+
+    fn inode_offset(&self, ino: u32) -> Option<u64> {
+        if ino == 0 || ino > self.inodes_count {
+            return None;
+        }
+        let group = (ino - 1) / self.inodes_per_group;
+        let index = (ino - 1) % self.inodes_per_group;
+        let table = self.read_field_u32(self.group_desc(group) + 8)?;
+        Some(table as u64 * self.block_size + index as u64 * self.inode_size)
+    }
+
+    pub fn read_inode(&self, ino: u32) -> Option<RawInode> {
+        let mut raw = [0u8; RAW_INODE_SIZE];
+        self.read(self.inode_offset(ino)?, &mut raw)?;
+        Some(RawInode { raw })
+    }
+
+    fn write_inode(&self, ino: u32, inode: &RawInode) -> Option<()> {
+        self.write(self.inode_offset(ino)?, &inode.raw)
+    }
+
+    /// Finds and sets a clear bit in the bitmap of some group, starting the
+    /// search at `goal` and never handing out the first `reserved` bits of
+    /// the volume. Returns the group and bit index.
+    fn allocate_bit(
+        &self,
+        goal: u32,
+        reserved: u32,
+        bitmap_field: u64,
+        free_field: u64,
+    ) -> Option<(u32, u32)> {
+        let per_group = match bitmap_field {
+            0 => self.blocks_per_group,
+            _ => self.inodes_per_group,
+        };
+
+        for index in 0..self.group_count {
+            let group = (goal + index) % self.group_count;
+            let desc = self.group_desc(group);
+
+            let mut free = [0u8; 2];
+            self.read(desc + free_field, &mut free)?;
+            if u16::from_le_bytes(free) == 0 {
+                continue;
+            }
+
+            let bitmap_block = self.read_field_u32(desc + bitmap_field)?;
+            let mut bitmap = alloc::vec![0u8; (per_group as usize).div_ceil(8)];
+            self.read_block(bitmap_block, 0, &mut bitmap)?;
+
+            let first = reserved.saturating_sub(group * per_group).min(per_group);
+            let Some(bit) =
+                (first..per_group).find(|&bit| bitmap[bit as usize / 8] & (1 << (bit % 8)) == 0)
+            else {
+                continue;
+            };
+
+            let byte = bitmap[bit as usize / 8] | (1 << (bit % 8));
+            self.write_block(bitmap_block, bit as usize / 8, &[byte])?;
+            self.adjust_counter(desc + free_field, 2, -1)?;
+            return Some((group, bit));
+        }
+
+        None
+    }
+
+    fn free_bit(&self, group: u32, bit: u32, bitmap_field: u64, free_field: u64) -> Option<()> {
+        let desc = self.group_desc(group);
+        let bitmap_block = self.read_field_u32(desc + bitmap_field)?;
+
+        let mut byte = [0u8];
+        self.read_block(bitmap_block, bit as usize / 8, &mut byte)?;
+        byte[0] &= !(1 << (bit % 8));
+        self.write_block(bitmap_block, bit as usize / 8, &byte)?;
+        self.adjust_counter(desc + free_field, 2, 1)
+    }
+
+    /// Allocates a zeroed block, preferring the group of `goal`.
+    fn allocate_block(&self, goal: u32) -> Option<u32> {
+        let _guard = self.alloc_lock.lock();
+
+        let goal_group = goal.saturating_sub(self.first_data_block) / self.blocks_per_group;
+        let (group, bit) = self.allocate_bit(goal_group.min(self.group_count - 1), 0, 0, 12)?;
+        let block = self.first_data_block + group * self.blocks_per_group + bit;
+        if block >= self.blocks_count {
+            self.free_bit(group, bit, 0, 12);
+            return None;
+        }
+        self.adjust_counter(SUPERBLOCK_OFFSET + 12, 4, -1)?;
+
+        self.write_block(block, 0, &alloc::vec![0u8; self.block_size()])?;
+        Some(block)
+    }
+
+    fn free_block(&self, block: u32) -> Option<()> {
+        let _guard = self.alloc_lock.lock();
+
+        let index = block.checked_sub(self.first_data_block)?;
+        self.free_bit(
+            index / self.blocks_per_group,
+            index % self.blocks_per_group,
+            0,
+            12,
+        )?;
+        self.adjust_counter(SUPERBLOCK_OFFSET + 12, 4, 1)
+    }
+
+    /// Allocates an inode number in the group of `parent`, skipping the
+    /// reserved inodes.
+    fn allocate_inode(&self, parent: u32, dir: bool) -> Option<u32> {
+        let _guard = self.alloc_lock.lock();
+
+        let goal = (parent - 1) / self.inodes_per_group;
+        let (group, bit) = self.allocate_bit(goal, self.first_ino - 1, 4, 14)?;
+        let ino = group * self.inodes_per_group + bit + 1;
+
+        self.adjust_counter(SUPERBLOCK_OFFSET + 16, 4, -1)?;
+        if dir {
+            self.adjust_counter(self.group_desc(group) + 16, 2, 1)?;
+        }
+        Some(ino)
+    }
+
+    fn free_inode(&self, ino: u32, dir: bool) -> Option<()> {
+        let _guard = self.alloc_lock.lock();
+
+        let group = (ino - 1) / self.inodes_per_group;
+        self.free_bit(group, (ino - 1) % self.inodes_per_group, 4, 14)?;
+        self.adjust_counter(SUPERBLOCK_OFFSET + 16, 4, 1)?;
+        if dir {
+            self.adjust_counter(self.group_desc(group) + 16, 2, -1)?;
+        }
+        Some(())
+    }
+
+    /// Maps the logical block `index` of `inode` to a disk block, allocating
+    /// it and any missing indirect blocks when `allocate` is set. Returns 0
+    /// for holes.
+    fn bmap(&self, inode: &mut RawInode, index: usize, allocate: bool) -> Option<u32> {
+        let per_block = self.block_size() / 4;
+
+        let (slot, path) = if index < DIRECT_BLOCKS {
+            (index, Vec::new())
+        } else {
+            let mut rest = index - DIRECT_BLOCKS;
+            let mut level = 1;
+            let mut span = per_block;
+            while rest >= span {
+                rest -= span;
+                level += 1;
+                span *= per_block;
+                if level > 3 {
+                    return None;
+                }
+            }
+
+            let mut path = Vec::new();
+            for _ in 0..level {
+                span /= per_block;
+                path.push(rest / span);
+                rest %= span;
+            }
+            (DIRECT_BLOCKS + level - 1, path)
+        };
+
+        let mut block = inode.block(slot);
+        if block == 0 {
+            if !allocate {
+                return Some(0);
+            }
+            block = self.allocate_block(self.goal(inode))?;
+            inode.set_block(slot, block);
+            inode.set_sectors(inode.sectors() + (self.block_size / 512) as u32);
+        }
+
+        for entry in path {
+            let offset = entry * 4;
+            let mut next = self.read_field_u32(block as u64 * self.block_size + offset as u64)?;
+            if next == 0 {
+                if !allocate {
+                    return Some(0);
+                }
+                next = self.allocate_block(block)?;
+                self.write_block(block, offset, &next.to_le_bytes())?;
+                inode.set_sectors(inode.sectors() + (self.block_size / 512) as u32);
+            }
+            block = next;
+        }
+
+        Some(block)
+    }
+
+    fn goal(&self, inode: &RawInode) -> u32 {
+        (0..DIRECT_BLOCKS)
+            .map(|index| inode.block(index))
+            .find(|&block| block != 0)
+            .unwrap_or(self.first_data_block)
+    }
+
+    /// Frees every block of `inode`, including indirect blocks.
+    fn free_blocks(&self, inode: &mut RawInode) {
+        for slot in 0..DIRECT_BLOCKS + 3 {
+            let block = inode.block(slot);
+            if block != 0 {
+                let depth = slot.saturating_sub(DIRECT_BLOCKS - 1);
+                self.free_tree(block, depth);
+                inode.set_block(slot, 0);
+            }
+        }
+        inode.set_sectors(0);
+    }
+
+    /// Frees the tree of blocks at `block`, returning how many it held.
+    fn free_tree(&self, block: u32, depth: usize) -> u32 {
+        let mut freed = 1;
+        if depth > 0 {
+            let mut table = alloc::vec![0u8; self.block_size()];
+            if self.read_block(block, 0, &mut table).is_some() {
+                for entry in table.chunks_exact(4) {
+                    let child = read_u32(entry, 0);
+                    if child != 0 {
+                        freed += self.free_tree(child, depth - 1);
+                    }
+                }
+            }
+        }
+        self.free_block(block);
+        freed
+    }
+
+    /// Frees the blocks of the tree at `block` past its first `keep` data
+    /// blocks. Returns how many blocks went and whether `block` was one.
+    fn trim_tree(&self, block: u32, depth: usize, keep: usize) -> (u32, bool) {
+        if keep == 0 {
+            return (self.free_tree(block, depth), true);
+        }
+
+        let span = (self.block_size() / 4).pow(depth as u32 - 1);
+        let mut table = alloc::vec![0u8; self.block_size()];
+        if self.read_block(block, 0, &mut table).is_none() {
+            return (0, false);
+        }
+
+        let mut freed = 0;
+        for (index, entry) in table.chunks_exact_mut(4).enumerate() {
+            let child = read_u32(entry, 0);
+            if child == 0 || (index + 1) * span <= keep {
+                continue;
+            }
+            let (count, gone) = self.trim_tree(child, depth - 1, keep.saturating_sub(index * span));
+            freed += count;
+            if gone {
+                entry.fill(0);
+            }
+        }
+        self.write_block(block, 0, &table);
+        (freed, false)
+    }
+
+    /// Frees the blocks of `inode` past its first `size` bytes and zeroes
+    /// the rest of the last one, so that growing the file again reads zeros.
+    fn truncate_data(&self, inode: &mut RawInode, size: u64) -> Option<()> {
+        let tail = (size % self.block_size) as usize;
+        if tail != 0 {
+            let block = self.bmap(inode, (size / self.block_size) as usize, false)?;
+            if block != 0 {
+                let zero = alloc::vec![0u8; self.block_size() - tail];
+                self.write_block(block, tail, &zero)?;
+            }
+        }
+
+        let keep = size.div_ceil(self.block_size) as usize;
+        let per_block = self.block_size() / 4;
+        let mut start = 0;
+        let mut freed = 0;
+        for slot in 0..DIRECT_BLOCKS + 3 {
+            let depth = slot.saturating_sub(DIRECT_BLOCKS - 1);
+            let span = per_block.pow(depth as u32);
+            let block = inode.block(slot);
+            if block != 0 && start + span > keep {
+                let (count, gone) = self.trim_tree(block, depth, keep.saturating_sub(start));
+                freed += count;
+                if gone {
+                    inode.set_block(slot, 0);
+                }
+            }
+            start += span;
+        }
+
+        let sectors = freed * (self.block_size / 512) as u32;
+        inode.set_sectors(inode.sectors().saturating_sub(sectors));
+        Some(())
+    }
+
+    fn read_data(&self, inode: &mut RawInode, offset: usize, buf: &mut [u8]) -> usize {
+        let block_size = self.block_size();
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done;
+            let block_offset = position % block_size;
+            let count = (buf.len() - done).min(block_size - block_offset);
+
+            let Some(block) = self.bmap(inode, position / block_size, false) else {
+                break;
+            };
+            let chunk = &mut buf[done..done + count];
+            if block == 0 {
+                chunk.fill(0);
+            } else if self.read_block(block, block_offset, chunk).is_none() {
+                break;
+            }
+            done += count;
+        }
+
+        done
+    }
+
+    fn write_data(&self, inode: &mut RawInode, offset: usize, buf: &[u8]) -> usize {
+        let block_size = self.block_size();
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done;
+            let block_offset = position % block_size;
+            let count = (buf.len() - done).min(block_size - block_offset);
+
+            let Some(block) = self.bmap(inode, position / block_size, true) else {
+                break;
+            };
+            if self
+                .write_block(block, block_offset, &buf[done..done + count])
+                .is_none()
+            {
+                break;
+            }
+            done += count;
+        }
+
+        done
+    }
+
+    /// Free blocks and inodes as recorded in the superblock.
+    pub fn free_counts(&self) -> (u32, u32) {
+        let blocks = self.read_field_u32(SUPERBLOCK_OFFSET + 12).unwrap_or(0);
+        let inodes = self.read_field_u32(SUPERBLOCK_OFFSET + 16).unwrap_or(0);
+        (blocks, inodes)
+    }
+
+    pub fn statfs(&self) -> StatVfs {
+        let (free_blocks, free_inodes) = self.free_counts();
+        let reserved = self.read_field_u32(SUPERBLOCK_OFFSET + 8).unwrap_or(0);
+        StatVfs {
+            f_type: EXT2_SUPER_MAGIC,
+            f_bsize: self.block_size as i64,
+            f_blocks: self.blocks_count as u64,
+            f_bfree: free_blocks as u64,
+            f_bavail: free_blocks.saturating_sub(reserved) as u64,
+            f_files: self.inodes_count as u64,
+            f_ffree: free_inodes as u64,
+            f_namelen: 255,
+            ..Default::default()
+        }
+    }
+
+    /// Writes back every dirty block of the volume.
+    pub fn sync(&self) {
+        if let Err(err) = BUFFER_CACHE.sync(&self.device) {
+            log::warn!("Failed to sync ext2 volume: {:?}", err);
+        }
+    }
+}
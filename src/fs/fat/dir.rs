@@ -0,0 +1,553 @@
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{FatVolume, read_u16, read_u32};
+use crate::syscall::errno::{EEXIST, EINVAL, EIO, ENAMETOOLONG, ENOSPC};
+
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_FREE: u8 = 0xe5;
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+/// Numeric tails run from `~1` to `~999999`, the longest that fits in the
+/// base name.
+const MAX_NUMERIC_TAIL: u32 = 999_999;
+
+/// 1980-01-01, the FAT epoch; the kernel has no wall clock yet.
+const DEFAULT_DATE: u16 = 0x0021;
+
+/// Where the entries of a directory live on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirRegion {
+    /// The fixed-size root directory of a FAT16 volume.
+    FixedRoot,
+    Chain(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub attr: u8,
+    pub cluster: u32,
+    pub size: u32,
+    /// Disk offset of the 8.3 entry.
+    pub position: u64,
+    /// Disk offsets of the long name entries followed by the 8.3 entry.
+    pub slots: Vec<u64>,
+    pub write_date: u16,
+    pub write_time: u16,
+    pub access_date: u16,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+fn short_name_to_string(raw: &[u8], case: u8) -> String {
+    let mut base = String::from_utf8_lossy(&raw[0..8]).trim_end().to_string();
+    let mut ext = String::from_utf8_lossy(&raw[8..11]).trim_end().to_string();
+
+    if base.starts_with('\u{5}') {
+        base.replace_range(0..1, "\u{e5}");
+    }
+    if case & CASE_LOWER_BASE != 0 {
+        base = base.to_lowercase();
+    }
+    if case & CASE_LOWER_EXT != 0 {
+        ext = ext.to_lowercase();
+    }
+
+    if ext.is_empty() { base } else { base + "." + &ext }
+}
+
+/// The first `BASE~N.EXT` alias of `short_name` that isn't `taken`, keeping
+/// as much of its `base_len`-byte base name as the tail leaves room for.
+fn numeric_tail(
+    short_name: &[u8; 11],
+    base_len: usize,
+    taken: &BTreeSet<[u8; 11]>,
+) -> Option<[u8; 11]> {
+    (1..=MAX_NUMERIC_TAIL)
+        .map(|tail| {
+            let suffix = alloc::format!("~{}", tail);
+            let keep = base_len.min(8 - suffix.len());
+            let mut candidate = [b' '; 11];
+            candidate[..keep].copy_from_slice(&short_name[..keep]);
+            candidate[keep..keep + suffix.len()].copy_from_slice(suffix.as_bytes());
+            candidate[8..].copy_from_slice(&short_name[8..]);
+            candidate
+        })
+        .find(|candidate| !taken.contains(candidate))
+}
+
+impl FatVolume {
+    /// Disk ranges holding the entries of `region`, in order.
+    pub(super) fn dir_extents(&self, region: DirRegion) -> Vec<(u64, usize)> {
+        match region {
+            DirRegion::FixedRoot => alloc::vec![self.root_region()],
+            DirRegion::Chain(start) => self
+                .chain(start)
+                .into_iter()
+                .map(|cluster| (self.cluster_offset(cluster), self.cluster_size()))
+                .collect(),
+        }
+    }
+
+    /// Raw 32-byte slots of a directory together with their disk offsets.
+    fn dir_slots(&self, region: DirRegion) -> Vec<(u64, [u8; ENTRY_SIZE])> {
+        let mut slots = Vec::new();
+        for (offset, len) in self.dir_extents(region) {
+            let mut buf = alloc::vec![0u8; len];
+            if self.read(offset, &mut buf).is_none() {
+                break;
+            }
+            for (index, raw) in buf.chunks_exact(ENTRY_SIZE).enumerate() {
+                let position = offset + (index * ENTRY_SIZE) as u64;
+                slots.push((position, raw.try_into().unwrap()));
+            }
+        }
+        slots
+    }
+
+    pub fn read_dir(&self, region: DirRegion) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        let mut long_name: Vec<(u8, [u16; LFN_CHARS])> = Vec::new();
+        let mut long_slots = Vec::new();
+        let mut checksum = 0;
+
+        for (position, raw) in self.dir_slots(region) {
+            match raw[0] {
+                ENTRY_END => break,
+                ENTRY_FREE => {
+                    long_name.clear();
+                    long_slots.clear();
+                    continue;
+                }
+                _ => {}
+            }
+
+            if raw[11] == ATTR_LONG_NAME {
+                if raw[0] & LFN_LAST != 0 {
+                    long_name.clear();
+                    long_slots.clear();
+                    checksum = raw[13];
+                }
+                let mut chars = [0u16; LFN_CHARS];
+                for (index, &offset) in LFN_OFFSETS.iter().enumerate() {
+                    chars[index] = read_u16(&raw, offset);
+                }
+                long_name.push((raw[0] & !LFN_LAST, chars));
+                long_slots.push(position);
+                continue;
+            }
+
+            if raw[11] & ATTR_VOLUME_ID != 0 {
+                long_name.clear();
+                long_slots.clear();
+                continue;
+            }
+
+            let name = if !long_name.is_empty() && lfn_checksum(&raw[0..11]) == checksum {
+                long_name.sort_by_key(|(sequence, _)| *sequence);
+                let units = long_name
+                    .iter()
+                    .flat_map(|(_, chars)| chars.iter().copied())
+                    .take_while(|&unit| unit != 0 && unit != 0xffff)
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&units)
+            } else {
+                long_slots.clear();
+                short_name_to_string(&raw[0..11], raw[12])
+            };
+
+            let mut slots = core::mem::take(&mut long_slots);
+            slots.push(position);
+            long_name.clear();
+
+            let cluster = ((read_u16(&raw, 20) as u32) << 16) | read_u16(&raw, 26) as u32;
+            entries.push(DirEntry {
+                name,
+                attr: raw[11],
+                cluster,
+                size: read_u32(&raw, 28),
+                position,
+                slots,
+                write_time: read_u16(&raw, 22),
+                write_date: read_u16(&raw, 24),
+                access_date: read_u16(&raw, 18),
+            });
+        }
+
+        entries
+    }
+
+    pub fn find_entry(&self, region: DirRegion, name: &str) -> Option<DirEntry> {
+        self.read_dir(region)
+            .into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Updates the first cluster and size fields of the 8.3 entry at `position`.
+    pub fn update_entry(&self, position: u64, cluster: u32, size: u32) -> Option<()> {
+        self.write(position + 20, &((cluster >> 16) as u16).to_le_bytes())?;
+        self.write(position + 26, &(cluster as u16).to_le_bytes())?;
+        self.write(position + 28, &size.to_le_bytes())
+    }
+
+    /// Frees the long name and 8.3 slots of `entry`.
+    pub fn remove_entry(&self, entry: &DirEntry) -> Option<()> {
+        for &slot in entry.slots.iter() {
+            self.write(slot, &[ENTRY_FREE])?;
+        }
+        Some(())
+    }
+
+    /// Re-creates `entry` as `name` in `region`, keeping its attributes,
+    /// first cluster, size and dates, and frees its old slots. `name` may
+    /// differ from the old name only in case.
+    pub fn move_entry(
+        &self,
+        entry: &DirEntry,
+        region: DirRegion,
+        name: &str,
+    ) -> Result<DirEntry, usize> {
+        if self
+            .find_entry(region, name)
+            .is_some_and(|found| found.position != entry.position)
+        {
+            return Err(EEXIST);
+        }
+        let mut raw = [0u8; ENTRY_SIZE];
+        self.read(entry.position, &mut raw).ok_or(EIO)?;
+
+        let moved = self.write_entry(region, name, entry.attr, entry.cluster)?;
+        // Everything past the name and its case flags.
+        self.write(moved.position + 13, &raw[13..]).ok_or(EIO)?;
+        self.remove_entry(entry).ok_or(EIO)?;
+
+        Ok(DirEntry {
+            size: entry.size,
+            write_date: entry.write_date,
+            write_time: entry.write_time,
+            access_date: entry.access_date,
+            ..moved
+        })
+    }
+
+    /// Finds `count` consecutive free slots in `region`, growing the
+    /// directory by a cluster if it is full.
+    fn free_slots(&self, region: DirRegion, count: usize) -> Option<Vec<u64>> {
+        loop {
+            let slots = self.dir_slots(region);
+            let mut run = Vec::new();
+            for (position, raw) in slots.iter() {
+                if raw[0] == ENTRY_END || raw[0] == ENTRY_FREE {
+                    run.push(*position);
+                    if run.len() == count {
+                        return Some(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+
+            let DirRegion::Chain(start) = region else {
+                return None;
+            };
+            let last = *self.chain(start).last()?;
+            self.allocate_cluster(Some(last))?;
+        }
+    }
+
+    /// The 8.3 names in use in `region`.
+    fn short_names(&self, region: DirRegion) -> BTreeSet<[u8; 11]> {
+        self.dir_slots(region)
+            .iter()
+            .take_while(|(_, raw)| raw[0] != ENTRY_END)
+            .filter(|(_, raw)| raw[0] != ENTRY_FREE && raw[11] != ATTR_LONG_NAME)
+            .map(|(_, raw)| raw[0..11].try_into().unwrap())
+            .collect()
+    }
+
+    /// Builds the 8.3 alias of `name`, returning it and whether the name
+    /// fits in 8.3 without a long name entry. Fails once every numeric tail
+    /// is taken.
+    fn short_name(&self, region: DirRegion, name: &str) -> Option<([u8; 11], bool)> {
+        let (base, ext) = match name.rfind('.') {
+            Some(index) if index > 0 => (&name[..index], &name[index + 1..]),
+            _ => (name, ""),
+        };
+
+        let convert = |part: &str, len: usize| -> (Vec<u8>, bool) {
+            let mut lossy = false;
+            let mut out = Vec::new();
+            for c in part.chars() {
+                let c = match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase() as u8,
+                    '$' | '%' | '\'' | '-' | '_' | '@' | '~' | '`' | '!' | '(' | ')' | '{'
+                    | '}' | '^' | '#' | '&' => c as u8,
+                    '.' | ' ' => {
+                        lossy = true;
+                        continue;
+                    }
+                    _ => {
+                        lossy = true;
+                        b'_'
+                    }
+                };
+                if out.len() == len {
+                    lossy = true;
+                    break;
+                }
+                out.push(c);
+            }
+            (out, lossy)
+        };
+
+        let (base_bytes, base_lossy) = convert(base, 8);
+        let (ext_bytes, ext_lossy) = convert(ext, 3);
+        let exact = !base_lossy
+            && !ext_lossy
+            && !base_bytes.is_empty()
+            && name.chars().all(|c| !c.is_ascii_lowercase());
+
+        let mut short_name = [b' '; 11];
+        short_name[8..8 + ext_bytes.len()].copy_from_slice(&ext_bytes);
+        short_name[..base_bytes.len()].copy_from_slice(&base_bytes);
+
+        let taken = self.short_names(region);
+        if exact && !taken.contains(&short_name) {
+            return Some((short_name, true));
+        }
+
+        numeric_tail(&short_name, base_bytes.len(), &taken).map(|alias| (alias, false))
+    }
+
+    /// Creates an entry named `name` in `region` pointing at `cluster`.
+    /// FAT names are case-insensitive, so a name differing from an existing
+    /// one only in case is taken.
+    pub fn create_entry(
+        &self,
+        region: DirRegion,
+        name: &str,
+        attr: u8,
+        cluster: u32,
+    ) -> Result<DirEntry, usize> {
+        if self.find_entry(region, name).is_some() {
+            return Err(EEXIST);
+        }
+        self.write_entry(region, name, attr, cluster)
+    }
+
+    fn write_entry(
+        &self,
+        region: DirRegion,
+        name: &str,
+        attr: u8,
+        cluster: u32,
+    ) -> Result<DirEntry, usize> {
+        let units = name.encode_utf16().collect::<Vec<_>>();
+        if units.len() > 255 {
+            return Err(ENAMETOOLONG);
+        }
+        if units.is_empty() || name == "." || name == ".." {
+            return Err(EINVAL);
+        }
+
+        // Like Linux, treat running out of numeric tails as a clash.
+        let (short_name, exact) = self.short_name(region, name).ok_or(EEXIST)?;
+        let lfn_count = if exact {
+            0
+        } else {
+            units.len().div_ceil(LFN_CHARS)
+        };
+
+        let slots = self.free_slots(region, lfn_count + 1).ok_or(ENOSPC)?;
+        let checksum = lfn_checksum(&short_name);
+
+        for (index, &position) in slots[..lfn_count].iter().enumerate() {
+            let sequence = lfn_count - index;
+            let mut raw = [0u8; ENTRY_SIZE];
+            raw[0] = sequence as u8 | if index == 0 { LFN_LAST } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+
+            for (char_index, &offset) in LFN_OFFSETS.iter().enumerate() {
+                let unit_index = (sequence - 1) * LFN_CHARS + char_index;
+                let unit = match unit_index.cmp(&units.len()) {
+                    core::cmp::Ordering::Less => units[unit_index],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => 0xffff,
+                };
+                raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            self.write(position, &raw).ok_or(EIO)?;
+        }
+
+        let position = slots[lfn_count];
+        let mut raw = [0u8; ENTRY_SIZE];
+        raw[0..11].copy_from_slice(&short_name);
+        raw[11] = attr;
+        raw[16..18].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        raw[18..20].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[24..26].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        self.write(position, &raw).ok_or(EIO)?;
+
+        Ok(DirEntry {
+            name: name.to_string(),
+            attr,
+            cluster,
+            size: 0,
+            position,
+            slots,
+            write_date: DEFAULT_DATE,
+            write_time: 0,
+            access_date: DEFAULT_DATE,
+        })
+    }
+
+    /// Writes the `.` and `..` entries of a freshly allocated directory cluster.
+    pub fn init_dir(&self, cluster: u32, parent_cluster: u32) -> Option<()> {
+        let offset = self.cluster_offset(cluster);
+        let entries: [(&[u8], u32); 2] = [(b".", cluster), (b"..", parent_cluster)];
+        for (index, (name, target)) in entries.into_iter().enumerate() {
+            let mut raw = [0u8; ENTRY_SIZE];
+            raw[0..11].fill(b' ');
+            raw[..name.len()].copy_from_slice(name);
+            raw[11] = ATTR_DIRECTORY;
+            raw[16..18].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+            raw[20..22].copy_from_slice(&((target >> 16) as u16).to_le_bytes());
+            raw[24..26].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+            raw[26..28].copy_from_slice(&(target as u16).to_le_bytes());
+            self.write(offset + (index * ENTRY_SIZE) as u64, &raw)?;
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeSet, format, vec::Vec};
+
+    use super::{ATTR_ARCHIVE, DirRegion, MAX_NUMERIC_TAIL, numeric_tail};
+    use crate::block::BUFFER_CACHE;
+    use crate::fs::fat::{FatType, FatVolume, mkfs::mkfs};
+    use crate::syscall::errno::EEXIST;
+
+    /// Creates each of `names` in the root directory, returning their 8.3
+    /// names and how many slots each took.
+    fn create(names: &[&str]) -> Vec<([u8; 11], usize)> {
+        let disk = mkfs(FatType::Fat16);
+        let volume = FatVolume::new(disk.clone()).unwrap();
+
+        let created = names
+            .iter()
+            .map(|name| {
+                let entry = volume
+                    .create_entry(DirRegion::FixedRoot, name, ATTR_ARCHIVE, 0)
+                    .unwrap();
+                let mut short_name = [0u8; 11];
+                volume.read(entry.position, &mut short_name).unwrap();
+                (short_name, entry.slots.len())
+            })
+            .collect();
+
+        BUFFER_CACHE.invalidate(&disk);
+        created
+    }
+
+    #[test]
+    fn keeps_names_that_fit_in_8_3() {
+        assert_eq!(create(&["NOTES.TXT"]), [(*b"NOTES   TXT", 1)]);
+    }
+
+    #[test]
+    fn numbers_aliases_of_long_names() {
+        let created = create(&[
+            "Long File Name.txt",
+            "Long File Name 2.txt",
+            "readme.txt",
+            "Read Me.txt",
+        ]);
+        let short_names = created
+            .iter()
+            .map(|(short_name, _)| short_name)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            short_names,
+            [
+                b"LONGFI~1TXT",
+                b"LONGFI~2TXT",
+                b"README~1TXT",
+                b"README~2TXT"
+            ]
+        );
+        assert_eq!(created[0].1, 3);
+    }
+
+    #[test]
+    fn refuses_names_that_differ_only_in_case() {
+        let disk = mkfs(FatType::Fat16);
+        let volume = FatVolume::new(disk.clone()).unwrap();
+        volume
+            .create_entry(DirRegion::FixedRoot, "readme.txt", ATTR_ARCHIVE, 0)
+            .unwrap();
+
+        // FAT names are case-insensitive, so this is the same file.
+        assert_eq!(
+            volume
+                .create_entry(DirRegion::FixedRoot, "README.TXT", ATTR_ARCHIVE, 0)
+                .map(|entry| entry.position),
+            Err(EEXIST)
+        );
+        assert_eq!(volume.read_dir(DirRegion::FixedRoot).len(), 1);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_numeric_tail() {
+        let mut taken = (1..=MAX_NUMERIC_TAIL)
+            .map(|tail| {
+                let suffix = format!("~{}", tail);
+                let keep = 4.min(8 - suffix.len());
+                let name = format!("{:<8}TXT", format!("{}{}", &"FILE"[..keep], suffix));
+                name.into_bytes().try_into().unwrap()
+            })
+            .collect::<BTreeSet<[u8; 11]>>();
+
+        assert_eq!(numeric_tail(b"FILE    TXT", 4, &taken), None);
+
+        taken.remove(b"FIL~4242TXT");
+        assert_eq!(
+            numeric_tail(b"FILE    TXT", 4, &taken),
+            Some(*b"FIL~4242TXT")
+        );
+    }
+}
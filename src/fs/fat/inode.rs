@@ -0,0 +1,778 @@
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use spin::{Mutex, RwLock};
+
+use crate::{
+    fs::vfs::{
+        inode::{FileInfo, Inode, InodeRef, InodeTy},
+        stat_struct::StatVfs,
+    },
+    syscall::errno::{EBUSY, EFBIG, EINVAL, EIO, ENOENT, ENOSPC, ENOTEMPTY, EPERM, EXDEV},
+};
+
+use super::{
+    FatType, FatVolume,
+    dir::{ATTR_ARCHIVE, ATTR_DIRECTORY, ATTR_READ_ONLY, DirEntry, DirRegion},
+};
+
+struct FatNode {
+    cluster: u32,
+    size: u32,
+    attr: u8,
+    /// Disk offset of the 8.3 entry, `None` for the root directory.
+    entry: Option<u64>,
+    /// The entry is gone and the clusters are freed on the last drop.
+    unlinked: bool,
+}
+
+pub struct FatFS {
+    path: String,
+    volume: Arc<FatVolume>,
+    this: Weak<RwLock<FatFS>>,
+    parent: Option<InodeRef>,
+    children: Mutex<BTreeMap<String, InodeRef>>,
+    node: Mutex<FatNode>,
+}
+
+impl FatFS {
+    pub fn new_root(volume: Arc<FatVolume>) -> InodeRef {
+        let cluster = match volume.fat_type() {
+            FatType::Fat16 => 0,
+            FatType::Fat32 => volume.root_cluster,
+        };
+        Self::with_node(
+            volume,
+            FatNode {
+                cluster,
+                size: 0,
+                attr: ATTR_DIRECTORY,
+                entry: None,
+                unlinked: false,
+            },
+        )
+    }
+
+    fn from_entry(volume: Arc<FatVolume>, entry: &DirEntry) -> InodeRef {
+        Self::with_node(
+            volume,
+            FatNode {
+                cluster: entry.cluster,
+                size: entry.size,
+                attr: entry.attr,
+                entry: Some(entry.position),
+                unlinked: false,
+            },
+        )
+    }
+
+    fn with_node(volume: Arc<FatVolume>, node: FatNode) -> InodeRef {
+        Arc::new_cyclic(|this| {
+            RwLock::new(Self {
+                path: String::new(),
+                volume,
+                this: this.clone(),
+                parent: None,
+                children: Mutex::new(BTreeMap::new()),
+                node: Mutex::new(node),
+            })
+        })
+    }
+
+    fn this(&self) -> Option<InodeRef> {
+        self.this.upgrade().map(|this| this as InodeRef)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.node.lock().attr & ATTR_DIRECTORY != 0
+    }
+
+    fn region(&self) -> DirRegion {
+        let node = self.node.lock();
+        match node.entry {
+            None if self.volume.fat_type() == FatType::Fat16 => DirRegion::FixedRoot,
+            _ => DirRegion::Chain(node.cluster),
+        }
+    }
+
+    /// The cluster `..` entries below this directory point at, which is 0
+    /// for the root.
+    fn dir_cluster(&self) -> u32 {
+        match &*self.node.lock() {
+            FatNode { entry: None, .. } => 0,
+            node => node.cluster,
+        }
+    }
+
+    /// `node` if it lives on this volume rather than being mounted on it.
+    fn same_volume<'a>(&self, node: &'a dyn Inode) -> Option<&'a FatFS> {
+        (node as &dyn Any)
+            .downcast_ref::<FatFS>()
+            .filter(|node| Arc::ptr_eq(&node.volume, &self.volume))
+    }
+
+    fn is_empty_dir(&self) -> bool {
+        self.volume
+            .read_dir(self.region())
+            .iter()
+            .all(|entry| entry.name == "." || entry.name == "..")
+    }
+
+    fn uncache(&self, name: &str) {
+        self.children
+            .lock()
+            .retain(|child, _| !child.eq_ignore_ascii_case(name));
+    }
+
+    fn cached(&self, name: &str) -> Option<InodeRef> {
+        let children = self.children.lock();
+        children.get(name).cloned().or_else(|| {
+            children
+                .iter()
+                .find(|(child, _)| child.eq_ignore_ascii_case(name))
+                .map(|(_, node)| node.clone())
+        })
+    }
+
+    fn attach(&self, entry: &DirEntry) -> InodeRef {
+        let node = Self::from_entry(self.volume.clone(), entry);
+        node.write()
+            .when_mounted(self.path.clone() + &entry.name + "/", self.this());
+        self.children.lock().insert(entry.name.clone(), node.clone());
+        node
+    }
+}
+
+impl Inode for FatFS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        if father.is_some() {
+            self.parent = father;
+        }
+    }
+
+    fn when_umounted(&mut self) {
+        for node in self.children.lock().values() {
+            node.write().when_umounted();
+        }
+        self.flush();
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        self.node.lock().size as usize
+    }
+
+    fn mount(&self, node: InodeRef, name: String) {
+        self.children.lock().insert(name, node);
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        let node = self.node.lock();
+        if node.attr & ATTR_DIRECTORY != 0 || offset >= node.size as usize {
+            return 0;
+        }
+
+        let len = buf.len().min(node.size as usize - offset);
+        self.volume.read_data(node.cluster, offset, &mut buf[..len])
+    }
+
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        let mut node = self.node.lock();
+        // FAT file sizes are 32-bit.
+        if node.attr & (ATTR_DIRECTORY | ATTR_READ_ONLY) != 0
+            || offset.saturating_add(buf.len()) > u32::MAX as usize
+        {
+            return 0;
+        }
+
+        let mut cluster = node.cluster;
+        let written = self.volume.write_data(&mut cluster, offset, buf);
+        let size = node.size.max((offset + written) as u32);
+
+        if (cluster != node.cluster || size != node.size)
+            && !node.unlinked
+            && let Some(position) = node.entry
+        {
+            self.volume.update_entry(position, cluster, size);
+        }
+        node.cluster = cluster;
+        node.size = size;
+
+        written
+    }
+
+    fn flush(&self) {
+        self.volume.sync();
+    }
+
+    fn statfs(&self) -> Option<StatVfs> {
+        Some(self.volume.statfs())
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        match name.as_str() {
+            "." => return self.this(),
+            ".." => return self.parent.clone().or_else(|| self.this()),
+            _ => {}
+        }
+
+        if !self.is_dir() {
+            return None;
+        }
+        if let Some(node) = self.cached(&name) {
+            return Some(node);
+        }
+
+        let entry = self.volume.find_entry(self.region(), &name)?;
+        Some(self.attach(&entry))
+    }
+
+    fn create(&self, name: String, ty: InodeTy) -> Option<InodeRef> {
+        if let Some(node) = self.open(name.clone()) {
+            return Some(node);
+        }
+        if !self.is_dir() || self.node.lock().unlinked {
+            return None;
+        }
+
+        let region = self.region();
+        let entry = match ty {
+            InodeTy::File => self
+                .volume
+                .create_entry(region, &name, ATTR_ARCHIVE, 0)
+                .ok()?,
+            InodeTy::Dir => {
+                let parent_cluster = self.dir_cluster();
+                let cluster = self.volume.allocate_cluster(None)?;
+                let entry = self.volume.init_dir(cluster, parent_cluster).and_then(|_| {
+                    self.volume
+                        .create_entry(region, &name, ATTR_DIRECTORY, cluster)
+                        .ok()
+                });
+                if entry.is_none() {
+                    self.volume.free_chain(cluster);
+                }
+                entry?
+            }
+            _ => return None,
+        };
+
+        Some(self.attach(&entry))
+    }
+
+    fn unlink(&self, name: String, _dir: bool) -> Result<(), usize> {
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+
+        {
+            let node = node.read();
+            let node = self.same_volume(&*node).ok_or(EBUSY)?;
+            if node.is_dir() && !node.is_empty_dir() {
+                return Err(ENOTEMPTY);
+            }
+
+            let entry = self.volume.find_entry(self.region(), &name).ok_or(ENOENT)?;
+            self.volume.remove_entry(&entry).ok_or(EIO)?;
+            node.node.lock().unlinked = true;
+        }
+
+        self.uncache(&name);
+        Ok(())
+    }
+
+    fn rename(&self, name: String, new_parent: &InodeRef, new_name: String) -> Result<(), usize> {
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+        let new_parent = new_parent.read();
+        let target = self.same_volume(&*new_parent).ok_or(EXDEV)?;
+        let replaced = target.open(new_name.clone());
+        let parent_cluster = target.dir_cluster();
+
+        {
+            let moved = node.read();
+            let moved = self.same_volume(&*moved).ok_or(EBUSY)?;
+            let entry = self.volume.find_entry(self.region(), &name).ok_or(ENOENT)?;
+
+            match &replaced {
+                // Names differing only in case are the same entry.
+                Some(replaced) if Arc::ptr_eq(replaced, &node) => {
+                    if entry.name == new_name {
+                        return Ok(());
+                    }
+                }
+                Some(replaced) => {
+                    let replaced = replaced.read();
+                    let replaced = self.same_volume(&*replaced).ok_or(EBUSY)?;
+                    if replaced.is_dir() && !replaced.is_empty_dir() {
+                        return Err(ENOTEMPTY);
+                    }
+                    let old = self
+                        .volume
+                        .find_entry(target.region(), &new_name)
+                        .ok_or(EIO)?;
+                    self.volume.remove_entry(&old).ok_or(EIO)?;
+                    replaced.node.lock().unlinked = true;
+                }
+                None => {}
+            }
+
+            let entry = self.volume.move_entry(&entry, target.region(), &new_name)?;
+            let mut node = moved.node.lock();
+            node.entry = Some(entry.position);
+            if node.attr & ATTR_DIRECTORY != 0 && target.region() != self.region() {
+                let parent = self
+                    .volume
+                    .find_entry(DirRegion::Chain(node.cluster), "..")
+                    .ok_or(EIO)?;
+                self.volume
+                    .update_entry(parent.position, parent_cluster, 0)
+                    .ok_or(EIO)?;
+            }
+        }
+
+        self.uncache(&name);
+        target.uncache(&new_name);
+        target
+            .children
+            .lock()
+            .insert(new_name.clone(), node.clone());
+        node.write()
+            .when_mounted(target.path.clone() + &new_name + "/", target.this());
+        Ok(())
+    }
+
+    /// Cuts the file down, freeing its clusters, or extends it with zeros.
+    fn truncate(&self, _fd: usize, len: usize) -> Result<(), usize> {
+        let mut node = self.node.lock();
+        if node.attr & ATTR_DIRECTORY != 0 {
+            return Err(EINVAL);
+        }
+        if node.attr & ATTR_READ_ONLY != 0 {
+            return Err(EPERM);
+        }
+        // FAT file sizes are 32-bit.
+        if len > u32::MAX as usize {
+            return Err(EFBIG);
+        }
+
+        let size = node.size as usize;
+        let mut cluster = node.cluster;
+        let reached = if len < size {
+            let keep = len.div_ceil(self.volume.cluster_size());
+            cluster = self.volume.truncate_chain(cluster, keep);
+            len
+        } else {
+            self.volume.zero_fill(&mut cluster, size, len)
+        };
+
+        node.cluster = cluster;
+        node.size = reached as u32;
+        if !node.unlinked
+            && let Some(position) = node.entry
+        {
+            self.volume
+                .update_entry(position, cluster, node.size)
+                .ok_or(EIO)?;
+        }
+
+        match reached == len {
+            true => Ok(()),
+            false => Err(ENOSPC),
+        }
+    }
+
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> usize {
+        usize::MAX
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        if !self.is_dir() {
+            return Vec::new();
+        }
+
+        let mut vec = alloc::vec![
+            FileInfo::new(".".into(), InodeTy::Dir),
+            FileInfo::new("..".into(), InodeTy::Dir),
+        ];
+
+        let entries = self.volume.read_dir(self.region());
+        for entry in entries.iter() {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            let ty = if entry.is_dir() {
+                InodeTy::Dir
+            } else {
+                InodeTy::File
+            };
+            vec.push(FileInfo::new(entry.name.clone(), ty));
+        }
+
+        for (name, node) in self.children.lock().iter() {
+            if !entries.iter().any(|entry| &entry.name == name) {
+                vec.push(FileInfo::new(name.clone(), node.read().inode_type()));
+            }
+        }
+
+        vec
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        if self.is_dir() {
+            InodeTy::Dir
+        } else {
+            InodeTy::File
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        let attr = self.node.lock().attr;
+        match (attr & ATTR_DIRECTORY != 0, attr & ATTR_READ_ONLY != 0) {
+            (true, false) => 0o755,
+            (true, true) => 0o555,
+            (false, false) => 0o644,
+            (false, true) => 0o444,
+        }
+    }
+}
+
+impl Drop for FatFS {
+    /// A removed file keeps its clusters while it is open; whoever lets go
+    /// of it last frees them.
+    fn drop(&mut self) {
+        let node = self.node.get_mut();
+        if node.unlinked {
+            self.volume.free_chain(node.cluster);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use super::FatFS;
+    use crate::block::{BUFFER_CACHE, BlockDeviceRef};
+    use crate::fs::fat::{FatType, FatVolume, dir::DirRegion, mkfs::mkfs};
+    use crate::fs::vfs::inode::{InodeRef, InodeTy};
+    use crate::syscall::errno::ENOTEMPTY;
+
+    const TYPES: [FatType; 2] = [FatType::Fat16, FatType::Fat32];
+
+    fn mount(ty: FatType) -> (BlockDeviceRef, Arc<FatVolume>, InodeRef) {
+        remount(mkfs(ty))
+    }
+
+    /// Mounts `disk` afresh, reading everything back from the disk itself.
+    fn remount(disk: BlockDeviceRef) -> (BlockDeviceRef, Arc<FatVolume>, InodeRef) {
+        BUFFER_CACHE.invalidate(&disk);
+        let volume = FatVolume::new(disk.clone()).unwrap();
+        let root = FatFS::new_root(volume.clone());
+        (disk, volume, root)
+    }
+
+    fn root_region(volume: &FatVolume) -> DirRegion {
+        match volume.fat_type() {
+            FatType::Fat16 => DirRegion::FixedRoot,
+            FatType::Fat32 => DirRegion::Chain(volume.root_cluster),
+        }
+    }
+
+    fn fat(volume: &FatVolume, copy: u64) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; (volume.fat_size * volume.bytes_per_sector) as usize];
+        let offset = (volume.reserved_sectors + copy * volume.fat_size) * volume.bytes_per_sector;
+        volume.read(offset, &mut buf).unwrap();
+        buf
+    }
+
+    fn read(node: &InodeRef) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; node.read().size(0)];
+        node.read().read_at(0, 0, &mut buf);
+        buf
+    }
+
+    fn free(volume: &FatVolume) -> u64 {
+        volume.statfs().f_bfree
+    }
+
+    #[test]
+    fn truncate_frees_clusters_and_zero_fills() {
+        let (disk, volume, root) = mount(FatType::Fat16);
+        let file = root.read().create("file".into(), InodeTy::File).unwrap();
+        let clusters = free(&volume);
+
+        file.read().write_at(0, 0, &[0xaa; 3 * 512]);
+        assert_eq!(free(&volume), clusters - 3);
+
+        file.read().truncate(0, 600).unwrap();
+        assert_eq!(free(&volume), clusters - 2);
+        file.read().truncate(0, 2000).unwrap();
+        assert_eq!(free(&volume), clusters - 4);
+        let data = read(&file);
+        assert_eq!(data[..600], [0xaa; 600]);
+        assert_eq!(data[600..], [0; 1400]);
+
+        file.read().truncate(0, 0).unwrap();
+        assert_eq!(free(&volume), clusters);
+        let entry = volume.find_entry(DirRegion::FixedRoot, "file").unwrap();
+        assert_eq!((entry.cluster, entry.size), (0, 0));
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn unlinked_files_are_freed_on_last_close() {
+        let (disk, volume, root) = mount(FatType::Fat16);
+        let clusters = free(&volume);
+
+        let file = root.read().create("file".into(), InodeTy::File).unwrap();
+        file.read().write_at(0, 0, &[0x55; 1000]);
+        root.read().unlink("FILE".into(), false).unwrap();
+
+        assert!(root.read().open("file".into()).is_none());
+        assert!(volume.read_dir(DirRegion::FixedRoot).is_empty());
+        assert_eq!(read(&file), [0x55; 1000]);
+        assert_eq!(free(&volume), clusters - 2);
+
+        drop(file);
+        assert_eq!(free(&volume), clusters);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn only_empty_directories_are_removed() {
+        let (disk, volume, root) = mount(FatType::Fat16);
+        let clusters = free(&volume);
+
+        let dir = root.read().create("dir".into(), InodeTy::Dir).unwrap();
+        dir.read().create("file".into(), InodeTy::File).unwrap();
+        assert_eq!(root.read().unlink("dir".into(), true), Err(ENOTEMPTY));
+
+        dir.read().unlink("file".into(), false).unwrap();
+        root.read().unlink("dir".into(), true).unwrap();
+        drop(dir);
+        assert_eq!(free(&volume), clusters);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn rename_moves_directories_between_parents() {
+        let (disk, volume, root) = mount(FatType::Fat16);
+        let from = root.read().create("from".into(), InodeTy::Dir).unwrap();
+        let to = root.read().create("to".into(), InodeTy::Dir).unwrap();
+        let moved = from.read().create("dir".into(), InodeTy::Dir).unwrap();
+        moved.read().write_at(0, 0, b"ignored");
+
+        from.read()
+            .rename("dir".into(), &to, "A long name".into())
+            .unwrap();
+
+        assert!(from.read().open("dir".into()).is_none());
+        let found = to.read().open("a long NAME".into()).unwrap();
+        assert!(Arc::ptr_eq(&found, &moved));
+
+        let to_cluster = volume
+            .find_entry(DirRegion::FixedRoot, "to")
+            .unwrap()
+            .cluster;
+        let entry = volume
+            .find_entry(DirRegion::Chain(to_cluster), "A long name")
+            .unwrap();
+        assert!(entry.is_dir());
+        assert_eq!(entry.slots.len(), 2);
+        let parent = volume
+            .find_entry(DirRegion::Chain(entry.cluster), "..")
+            .unwrap();
+        assert_eq!(parent.cluster, to_cluster);
+
+        let from_cluster = volume
+            .find_entry(DirRegion::FixedRoot, "from")
+            .unwrap()
+            .cluster;
+        let names = volume
+            .read_dir(DirRegion::Chain(from_cluster))
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, [".", ".."]);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn rename_replaces_the_target_and_keeps_the_data() {
+        let (disk, volume, root) = mount(FatType::Fat16);
+        let clusters = free(&volume);
+
+        let old = root.read().create("old".into(), InodeTy::File).unwrap();
+        old.read().write_at(0, 0, b"old");
+        let new = root.read().create("new".into(), InodeTy::File).unwrap();
+        new.read().write_at(0, 0, &[0; 700]);
+
+        root.read()
+            .rename("old".into(), &root, "new".into())
+            .unwrap();
+        drop(new);
+
+        let found = root.read().open("new".into()).unwrap();
+        assert!(Arc::ptr_eq(&found, &old));
+        assert_eq!(read(&found), b"old");
+        assert_eq!(free(&volume), clusters - 1);
+
+        // The size and cluster moved along with the name.
+        let entry = volume.find_entry(DirRegion::FixedRoot, "new").unwrap();
+        assert_eq!(entry.size, 3);
+        let names = volume
+            .read_dir(DirRegion::FixedRoot)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["new"]);
+
+        BUFFER_CACHE.invalidate(&disk);
+    }
+
+    #[test]
+    fn mounts_blank_volumes() {
+        for ty in TYPES {
+            let (disk, volume, root) = mount(ty);
+
+            assert_eq!(volume.fat_type(), ty);
+            assert!(volume.read_dir(root_region(&volume)).is_empty());
+            assert!(root.read().open("anything".into()).is_none());
+            // The FAT32 root directory takes up a cluster of its own.
+            let used = if ty == FatType::Fat32 { 1 } else { 0 };
+            assert_eq!(free(&volume), volume.cluster_count as u64 - used);
+
+            BUFFER_CACHE.invalidate(&disk);
+        }
+    }
+
+    #[test]
+    fn flushed_writes_survive_a_remount() {
+        for ty in TYPES {
+            let (disk, _, root) = mount(ty);
+            let data = (0..5000).map(|byte| byte as u8).collect::<Vec<_>>();
+
+            let dir = root.read().create("docs".into(), InodeTy::Dir).unwrap();
+            let file = dir
+                .read()
+                .create("Quarterly Report.txt".into(), InodeTy::File)
+                .unwrap();
+            assert_eq!(file.read().write_at(0, 0, &data), data.len());
+            root.read().flush();
+            drop((dir, file, root));
+
+            let (disk, volume, root) = remount(disk);
+            let dir = root.read().open("DOCS".into()).unwrap();
+            let file = dir.read().open("quarterly REPORT.TXT".into()).unwrap();
+            assert_eq!(read(&file), data);
+
+            let docs = volume.find_entry(root_region(&volume), "docs").unwrap();
+            let entry = volume
+                .find_entry(DirRegion::Chain(docs.cluster), "Quarterly Report.txt")
+                .unwrap();
+            let mut short_name = [0u8; 11];
+            volume.read(entry.position, &mut short_name).unwrap();
+            assert_eq!(&short_name, b"QUARTE~1TXT");
+            assert_eq!((entry.size, entry.slots.len()), (5000, 3));
+
+            BUFFER_CACHE.invalidate(&disk);
+        }
+    }
+
+    #[test]
+    fn directories_point_back_at_their_parents() {
+        for ty in TYPES {
+            let (disk, volume, root) = mount(ty);
+            let outer = root.read().create("outer".into(), InodeTy::Dir).unwrap();
+            outer.read().create("inner".into(), InodeTy::Dir).unwrap();
+
+            let outer = volume.find_entry(root_region(&volume), "OUTER").unwrap();
+            let inner = volume
+                .find_entry(DirRegion::Chain(outer.cluster), "inner")
+                .unwrap();
+            assert!(outer.is_dir() && inner.is_dir());
+
+            let dots = |cluster| {
+                volume
+                    .read_dir(DirRegion::Chain(cluster))
+                    .into_iter()
+                    .map(|entry| (entry.name, entry.cluster))
+                    .collect::<Vec<_>>()
+            };
+            // `..` is 0 right below the root, even on FAT32.
+            assert_eq!(
+                dots(outer.cluster),
+                [
+                    (".".into(), outer.cluster),
+                    ("..".into(), 0),
+                    ("inner".into(), inner.cluster)
+                ]
+            );
+            assert_eq!(
+                dots(inner.cluster),
+                [(".".into(), inner.cluster), ("..".into(), outer.cluster)]
+            );
+
+            BUFFER_CACHE.invalidate(&disk);
+        }
+    }
+
+    #[test]
+    fn allocates_clusters_in_every_fat() {
+        for ty in TYPES {
+            let (disk, volume, root) = mount(ty);
+            let clusters = free(&volume);
+
+            let file = root.read().create("file".into(), InodeTy::File).unwrap();
+            file.read().write_at(0, 0, &[0x11; 4 * 512 + 1]);
+            let entry = volume.find_entry(root_region(&volume), "file").unwrap();
+            let chain = volume.chain(entry.cluster);
+            assert_eq!(chain.len(), 5);
+            assert_eq!(free(&volume), clusters - 5);
+            assert_eq!(fat(&volume, 0), fat(&volume, 1));
+
+            root.read().flush();
+            let (disk, volume, _) = remount(disk);
+            assert_eq!(volume.chain(entry.cluster), chain);
+            assert_eq!(free(&volume), clusters - 5);
+            if ty == FatType::Fat32 {
+                let (free_count, next_free) = volume.read_fsinfo().unwrap();
+                assert_eq!(free_count as u64, clusters - 5);
+                assert_eq!(next_free, chain[4] + 1);
+            }
+
+            BUFFER_CACHE.invalidate(&disk);
+        }
+    }
+
+    #[test]
+    fn create_finds_existing_names_whatever_their_case() {
+        for ty in TYPES {
+            let (disk, volume, root) = mount(ty);
+
+            let file = root
+                .read()
+                .create("readme.txt".into(), InodeTy::File)
+                .unwrap();
+            let again = root
+                .read()
+                .create("README.TXT".into(), InodeTy::File)
+                .unwrap();
+            assert!(Arc::ptr_eq(&file, &again));
+            assert_eq!(volume.read_dir(root_region(&volume)).len(), 1);
+
+            BUFFER_CACHE.invalidate(&disk);
+        }
+    }
+}
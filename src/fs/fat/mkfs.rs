@@ -0,0 +1,217 @@
+//! Blank volumes for the tests. They come from the host's `mkfs.fat` when
+//! there is one, and are otherwise written out the way mkfs.fat 4.2 lays
+//! them out with alignment off (`-a`), FAT sizing formula included.
+
+extern crate std;
+
+use alloc::{format, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{fs, process::Command};
+
+use super::FatType;
+use crate::block::{BlockDeviceRef, RamDisk};
+
+const SECTOR_SIZE: usize = 512;
+const NUM_FATS: usize = 2;
+const MEDIA: u8 = 0xf8;
+const VOLUME_ID: u32 = 0x1234_abcd;
+
+const SECTORS_PER_TRACK: u16 = 32;
+const HEADS: u16 = 64;
+
+const FAT32_INFO_SECTOR: usize = 1;
+const FAT32_BACKUP_BOOT: usize = 6;
+
+/// The geometry of a test volume. Both types use 512-byte clusters so
+/// the tests can count them the same way.
+struct Format {
+    ty: FatType,
+    size_kib: usize,
+    sectors_per_cluster: usize,
+    reserved_sectors: usize,
+    root_entries: usize,
+}
+
+impl Format {
+    fn new(ty: FatType) -> Self {
+        match ty {
+            // 32481 clusters.
+            FatType::Fat16 => Self {
+                ty,
+                size_kib: 16 * 1024,
+                sectors_per_cluster: 1,
+                reserved_sectors: 1,
+                root_entries: 512,
+            },
+            // 68528 clusters, just past the FAT32 minimum.
+            FatType::Fat32 => Self {
+                ty,
+                size_kib: 34 * 1024,
+                sectors_per_cluster: 1,
+                reserved_sectors: 32,
+                root_entries: 0,
+            },
+        }
+    }
+}
+
+/// A blank volume of type `ty`.
+pub fn mkfs(ty: FatType) -> BlockDeviceRef {
+    let format = Format::new(ty);
+    let image = mkfs_fat(&format).unwrap_or_else(|| format_image(&format));
+    Arc::new(RamDisk::from_image(SECTOR_SIZE, image))
+}
+
+/// Runs the host's mkfs.fat, if it has one.
+fn mkfs_fat(format: &Format) -> Option<Vec<u8>> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "kernel-test-{}-{}.img",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let bits = match format.ty {
+        FatType::Fat16 => "16",
+        FatType::Fat32 => "32",
+    };
+    let args = [
+        "-a".into(),
+        "-C".into(),
+        "-F".into(),
+        bits.into(),
+        "-S".into(),
+        SECTOR_SIZE.to_string(),
+        "-s".into(),
+        format.sectors_per_cluster.to_string(),
+        "-R".into(),
+        format.reserved_sectors.to_string(),
+        "-f".into(),
+        NUM_FATS.to_string(),
+        "-i".into(),
+        format!("{:08x}", VOLUME_ID),
+        path.to_string_lossy().into_owned(),
+        format.size_kib.to_string(),
+    ];
+    let root_entries = ["-r".into(), format.root_entries.to_string()];
+    let args = match format.ty {
+        FatType::Fat16 => [&root_entries[..], &args[..]].concat(),
+        FatType::Fat32 => args.to_vec(),
+    };
+
+    let status = ["mkfs.fat", "/sbin/mkfs.fat", "/usr/sbin/mkfs.fat"]
+        .iter()
+        .find_map(|program| {
+            Command::new(program)
+                .args(&args)
+                .stdout(std::process::Stdio::null())
+                .status()
+                .ok()
+        })?;
+    let image = fs::read(&path).ok();
+    let _ = fs::remove_file(&path);
+    assert!(status.success(), "mkfs.fat {:?} failed", args);
+    image
+}
+
+fn put_u16(image: &mut [u8], offset: usize, value: u16) {
+    image[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(image: &mut [u8], offset: usize, value: u32) {
+    image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Sectors per FAT and the resulting cluster count, as mkfs.fat works
+/// them out for `data_sectors` sectors after the reserved ones and the
+/// root directory.
+fn fat_length(format: &Format, data_sectors: usize) -> (usize, usize) {
+    let entry_size = match format.ty {
+        FatType::Fat16 => 2,
+        FatType::Fat32 => 4,
+    };
+    let cluster_bytes = format.sectors_per_cluster * SECTOR_SIZE;
+    let clusters = (data_sectors * SECTOR_SIZE + NUM_FATS * 2 * entry_size)
+        / (cluster_bytes + NUM_FATS * entry_size);
+    let fat_length = ((clusters + 2) * entry_size).div_ceil(SECTOR_SIZE);
+    let clusters = (data_sectors - NUM_FATS * fat_length) / format.sectors_per_cluster;
+    (fat_length, clusters)
+}
+
+fn format_image(format: &Format) -> Vec<u8> {
+    let total = format.size_kib * 1024 / SECTOR_SIZE;
+    let root_sectors = (format.root_entries * 32).div_ceil(SECTOR_SIZE);
+    let (fat_length, clusters) = fat_length(format, total - format.reserved_sectors - root_sectors);
+    let mut image = alloc::vec![0u8; total * SECTOR_SIZE];
+
+    let boot = &mut image[..SECTOR_SIZE];
+    boot[3..11].copy_from_slice(b"mkfs.fat");
+    put_u16(boot, 11, SECTOR_SIZE as u16);
+    boot[13] = format.sectors_per_cluster as u8;
+    put_u16(boot, 14, format.reserved_sectors as u16);
+    boot[16] = NUM_FATS as u8;
+    put_u16(boot, 17, format.root_entries as u16);
+    if total < 0x10000 {
+        put_u16(boot, 19, total as u16);
+    } else {
+        put_u32(boot, 32, total as u32);
+    }
+    boot[21] = MEDIA;
+    put_u16(boot, 24, SECTORS_PER_TRACK);
+    put_u16(boot, 26, HEADS);
+    boot[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+    // The extended BPB follows the FAT32 fields where there are some.
+    let ebpb = match format.ty {
+        FatType::Fat16 => {
+            boot[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+            put_u16(boot, 22, fat_length as u16);
+            36
+        }
+        FatType::Fat32 => {
+            boot[0..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+            put_u32(boot, 36, fat_length as u32);
+            put_u32(boot, 44, 2);
+            put_u16(boot, 48, FAT32_INFO_SECTOR as u16);
+            put_u16(boot, 50, FAT32_BACKUP_BOOT as u16);
+            64
+        }
+    };
+    boot[ebpb] = 0x80;
+    boot[ebpb + 2] = 0x29;
+    put_u32(boot, ebpb + 3, VOLUME_ID);
+    boot[ebpb + 7..ebpb + 18].copy_from_slice(b"NO NAME    ");
+    boot[ebpb + 18..ebpb + 26].copy_from_slice(match format.ty {
+        FatType::Fat16 => b"FAT16   ",
+        FatType::Fat32 => b"FAT32   ",
+    });
+
+    for fat in 0..NUM_FATS {
+        let offset = (format.reserved_sectors + fat * fat_length) * SECTOR_SIZE;
+        match format.ty {
+            FatType::Fat16 => {
+                image[offset..offset + 4].copy_from_slice(&[MEDIA, 0xff, 0xff, 0xff]);
+            }
+            FatType::Fat32 => {
+                put_u32(&mut image, offset, 0x0fff_ff00 | MEDIA as u32);
+                put_u32(&mut image, offset + 4, 0x0fff_ffff);
+                // The root directory takes cluster 2.
+                put_u32(&mut image, offset + 8, 0x0fff_fff8);
+            }
+        }
+    }
+
+    if format.ty == FatType::Fat32 {
+        let info = &mut image[FAT32_INFO_SECTOR * SECTOR_SIZE..][..SECTOR_SIZE];
+        put_u32(info, 0, 0x4161_5252);
+        put_u32(info, 484, 0x6141_7272);
+        put_u32(info, 488, clusters as u32 - 1);
+        put_u32(info, 492, 2);
+        info[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+        // The backup boot sector and FSInfo.
+        image.copy_within(0..2 * SECTOR_SIZE, FAT32_BACKUP_BOOT * SECTOR_SIZE);
+    }
+
+    image
+}
@@ -0,0 +1,450 @@
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use crate::block::{BUFFER_CACHE, BlockDeviceRef};
+
+use super::vfs::{
+    inode::InodeRef,
+    stat_struct::{MSDOS_SUPER_MAGIC, StatVfs},
+};
+
+mod dir;
+mod inode;
+#[cfg(test)]
+mod mkfs;
+
+pub use inode::FatFS;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x41615252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x61417272;
+const FSINFO_UNKNOWN: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// A mounted FAT16 or FAT32 volume.
+pub struct FatVolume {
+    device: BlockDeviceRef,
+    ty: FatType,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    reserved_sectors: u64,
+    num_fats: u64,
+    fat_size: u64,
+    root_dir_sectors: u64,
+    first_data_sector: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    fsinfo_sector: u64,
+    next_free: Mutex<u32>,
+    free_count: Mutex<u32>,
+    alloc_lock: Mutex<()>,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Mounts the FAT filesystem on `device`, returning its root directory.
+pub fn mount(device: BlockDeviceRef) -> Option<InodeRef> {
+    let volume = FatVolume::new(device)?;
+    Some(FatFS::new_root(volume))
+}
+
+impl FatVolume {
+    pub fn new(device: BlockDeviceRef) -> Option<Arc<Self>> {
+        let mut boot = [0u8; 512];
+        if BUFFER_CACHE.read(&device, 0, &mut boot).ok()? != boot.len() {
+            return None;
+        }
+        if boot[510] != 0x55 || boot[511] != 0xaa {
+            return None;
+        }
+
+        let bytes_per_sector = read_u16(&boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = read_u16(&boot, 14) as u64;
+        let num_fats = boot[16] as u64;
+        let root_entry_count = read_u16(&boot, 17) as u64;
+        let total_sectors = match read_u16(&boot, 19) {
+            0 => read_u32(&boot, 32) as u64,
+            count => count as u64,
+        };
+        let fat_size = match read_u16(&boot, 22) {
+            0 => read_u32(&boot, 36) as u64,
+            size => size as u64,
+        };
+
+        if !bytes_per_sector.is_power_of_two()
+            || bytes_per_sector < 512
+            || sectors_per_cluster == 0
+            || num_fats == 0
+        {
+            return None;
+        }
+
+        let root_dir_sectors = (root_entry_count * 32).div_ceil(bytes_per_sector);
+        let first_data_sector = reserved_sectors + num_fats * fat_size + root_dir_sectors;
+        let cluster_count = (total_sectors.checked_sub(first_data_sector)? / sectors_per_cluster) as u32;
+
+        let ty = match cluster_count {
+            0..4085 => {
+                log::warn!("FAT12 volumes are not supported");
+                return None;
+            }
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+
+        let (root_cluster, fsinfo_sector) = match ty {
+            FatType::Fat16 => (0, 0),
+            FatType::Fat32 => (read_u32(&boot, 44), read_u16(&boot, 48) as u64),
+        };
+
+        let volume = Self {
+            device,
+            ty,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            fat_size,
+            root_dir_sectors,
+            first_data_sector,
+            cluster_count,
+            root_cluster,
+            fsinfo_sector,
+            next_free: Mutex::new(2),
+            free_count: Mutex::new(FSINFO_UNKNOWN),
+            alloc_lock: Mutex::new(()),
+        };
+
+        if let Some((free_count, next_free)) = volume.read_fsinfo() {
+            if free_count <= volume.cluster_count {
+                *volume.free_count.lock() = free_count;
+            }
+            if volume.is_valid_cluster(next_free) {
+                *volume.next_free.lock() = next_free;
+            }
+        }
+
+        log::info!(
+            "Mounted {:?} volume: {} clusters of {} bytes",
+            ty,
+            cluster_count,
+            volume.cluster_size()
+        );
+
+        Some(Arc::new(volume))
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.ty
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Option<()> {
+        let len = BUFFER_CACHE.read(&self.device, offset, buf).ok()?;
+        (len == buf.len()).then_some(())
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Option<()> {
+        BUFFER_CACHE.write(&self.device, offset, buf).ok()?;
+        Some(())
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        (self.first_data_sector + (cluster as u64 - 2) * self.sectors_per_cluster)
+            * self.bytes_per_sector
+    }
+
+    /// Byte range of the fixed FAT16 root directory.
+    fn root_region(&self) -> (u64, usize) {
+        let sector = self.reserved_sectors + self.num_fats * self.fat_size;
+        (
+            sector * self.bytes_per_sector,
+            (self.root_dir_sectors * self.bytes_per_sector) as usize,
+        )
+    }
+
+    fn fat_entry_offset(&self, cluster: u32, fat: u64) -> u64 {
+        let entry_size = match self.ty {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        (self.reserved_sectors + fat * self.fat_size) * self.bytes_per_sector
+            + cluster as u64 * entry_size
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Option<u32> {
+        let offset = self.fat_entry_offset(cluster, 0);
+        match self.ty {
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                self.read(offset, &mut buf)?;
+                Some(u16::from_le_bytes(buf) as u32)
+            }
+            FatType::Fat32 => {
+                let mut buf = [0u8; 4];
+                self.read(offset, &mut buf)?;
+                Some(u32::from_le_bytes(buf) & 0x0fff_ffff)
+            }
+        }
+    }
+
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Option<()> {
+        for fat in 0..self.num_fats {
+            let offset = self.fat_entry_offset(cluster, fat);
+            match self.ty {
+                FatType::Fat16 => self.write(offset, &(value as u16).to_le_bytes())?,
+                FatType::Fat32 => {
+                    let mut buf = [0u8; 4];
+                    self.read(offset, &mut buf)?;
+                    let value = (u32::from_le_bytes(buf) & 0xf000_0000) | (value & 0x0fff_ffff);
+                    self.write(offset, &value.to_le_bytes())?;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.ty {
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+
+    fn chain(&self, start: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut cluster = start;
+        while self.is_valid_cluster(cluster) && clusters.len() <= self.cluster_count as usize {
+            clusters.push(cluster);
+            match self.fat_entry(cluster) {
+                Some(next) => cluster = next,
+                None => break,
+            }
+        }
+        clusters
+    }
+
+    /// Allocates a zeroed cluster and links it after `prev` if given.
+    fn allocate_cluster(&self, prev: Option<u32>) -> Option<u32> {
+        let _guard = self.alloc_lock.lock();
+
+        let start = *self.next_free.lock();
+        let cluster = (0..self.cluster_count)
+            .map(|index| 2 + (start - 2 + index) % self.cluster_count)
+            .find(|&cluster| self.fat_entry(cluster) == Some(0))?;
+
+        self.set_fat_entry(cluster, self.end_of_chain())?;
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster)?;
+        }
+        *self.next_free.lock() = cluster + 1;
+        let mut free_count = self.free_count.lock();
+        if *free_count != FSINFO_UNKNOWN {
+            *free_count -= 1;
+        }
+        drop(free_count);
+
+        let zero = alloc::vec![0u8; self.cluster_size()];
+        self.write(self.cluster_offset(cluster), &zero)?;
+
+        Some(cluster)
+    }
+
+    /// Returns every cluster of the chain starting at `start` to the free pool.
+    fn free_chain(&self, start: u32) {
+        let _guard = self.alloc_lock.lock();
+
+        let chain = self.chain(start);
+        for &cluster in chain.iter() {
+            self.set_fat_entry(cluster, 0);
+        }
+
+        let mut free_count = self.free_count.lock();
+        if *free_count != FSINFO_UNKNOWN {
+            *free_count += chain.len() as u32;
+        }
+    }
+
+    /// Cuts the chain starting at `start` down to its first `keep`
+    /// clusters, returning the new start, which is 0 once nothing is left.
+    fn truncate_chain(&self, start: u32, keep: usize) -> u32 {
+        if keep == 0 {
+            self.free_chain(start);
+            return 0;
+        }
+
+        let chain = self.chain(start);
+        if let Some(&rest) = chain.get(keep) {
+            self.set_fat_entry(chain[keep - 1], self.end_of_chain());
+            self.free_chain(rest);
+        }
+        start
+    }
+
+    /// Grows the data of the chain at `start` from `size` to `len` bytes of
+    /// zeros, since FAT files can't have holes. Returns the size reached.
+    fn zero_fill(&self, start: &mut u32, size: usize, len: usize) -> usize {
+        let cluster_size = self.cluster_size();
+        let mut chain = self.chain(*start);
+        let mut done = size;
+
+        while done < len {
+            let index = done / cluster_size;
+            if index == chain.len() {
+                // New clusters come zeroed.
+                let Some(cluster) = self.allocate_cluster(chain.last().copied()) else {
+                    break;
+                };
+                if chain.is_empty() {
+                    *start = cluster;
+                }
+                chain.push(cluster);
+                done = ((index + 1) * cluster_size).min(len);
+                continue;
+            }
+
+            let cluster_offset = done % cluster_size;
+            let count = (len - done).min(cluster_size - cluster_offset);
+            let disk_offset = self.cluster_offset(chain[index]) + cluster_offset as u64;
+            if self.write(disk_offset, &alloc::vec![0u8; count]).is_none() {
+                break;
+            }
+            done += count;
+        }
+
+        done
+    }
+
+    fn read_data(&self, start: u32, offset: usize, buf: &mut [u8]) -> usize {
+        let cluster_size = self.cluster_size();
+        let chain = self.chain(start);
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done;
+            let Some(&cluster) = chain.get(position / cluster_size) else {
+                break;
+            };
+            let cluster_offset = position % cluster_size;
+            let count = (buf.len() - done).min(cluster_size - cluster_offset);
+
+            let disk_offset = self.cluster_offset(cluster) + cluster_offset as u64;
+            if self.read(disk_offset, &mut buf[done..done + count]).is_none() {
+                break;
+            }
+            done += count;
+        }
+
+        done
+    }
+
+    /// Writes `buf` at `offset` into the chain starting at `start`, growing
+    /// the chain as needed. `start` is updated when the chain was empty.
+    fn write_data(&self, start: &mut u32, offset: usize, buf: &[u8]) -> usize {
+        let cluster_size = self.cluster_size();
+        let needed = (offset + buf.len()).div_ceil(cluster_size);
+
+        let mut chain = self.chain(*start);
+        while chain.len() < needed {
+            let Some(cluster) = self.allocate_cluster(chain.last().copied()) else {
+                break;
+            };
+            if chain.is_empty() {
+                *start = cluster;
+            }
+            chain.push(cluster);
+        }
+
+        let mut done = 0;
+        while done < buf.len() {
+            let position = offset + done;
+            let Some(&cluster) = chain.get(position / cluster_size) else {
+                break;
+            };
+            let cluster_offset = position % cluster_size;
+            let count = (buf.len() - done).min(cluster_size - cluster_offset);
+
+            let disk_offset = self.cluster_offset(cluster) + cluster_offset as u64;
+            if self.write(disk_offset, &buf[done..done + count]).is_none() {
+                break;
+            }
+            done += count;
+        }
+
+        done
+    }
+
+    /// Free clusters, counted from the FAT when FSInfo did not know.
+    fn free_clusters(&self) -> u32 {
+        let _guard = self.alloc_lock.lock();
+        let mut free_count = self.free_count.lock();
+        if *free_count == FSINFO_UNKNOWN {
+            *free_count = (2..self.cluster_count + 2)
+                .filter(|&cluster| self.fat_entry(cluster) == Some(0))
+                .count() as u32;
+        }
+        *free_count
+    }
+
+    pub fn statfs(&self) -> StatVfs {
+        let free = self.free_clusters() as u64;
+        StatVfs {
+            f_type: MSDOS_SUPER_MAGIC,
+            f_bsize: self.cluster_size() as i64,
+            f_blocks: self.cluster_count as u64,
+            f_bfree: free,
+            f_bavail: free,
+            f_namelen: 255,
+            ..Default::default()
+        }
+    }
+
+    fn read_fsinfo(&self) -> Option<(u32, u32)> {
+        if self.ty != FatType::Fat32 || self.fsinfo_sector == 0 {
+            return None;
+        }
+
+        let mut sector = [0u8; 512];
+        self.read(self.fsinfo_sector * self.bytes_per_sector, &mut sector)?;
+        if read_u32(&sector, 0) != FSINFO_LEAD_SIGNATURE
+            || read_u32(&sector, 484) != FSINFO_STRUCT_SIGNATURE
+        {
+            return None;
+        }
+
+        Some((read_u32(&sector, 488), read_u32(&sector, 492)))
+    }
+
+    /// Writes back the FSInfo hints and every dirty block of the volume.
+    pub fn sync(&self) {
+        if self.read_fsinfo().is_some() {
+            let offset = self.fsinfo_sector * self.bytes_per_sector;
+            let free_count = *self.free_count.lock();
+            let next_free = *self.next_free.lock();
+            self.write(offset + 488, &free_count.to_le_bytes());
+            self.write(offset + 492, &next_free.to_le_bytes());
+        }
+
+        if let Err(err) = BUFFER_CACHE.sync(&self.device) {
+            log::warn!("Failed to sync FAT volume: {:?}", err);
+        }
+    }
+}
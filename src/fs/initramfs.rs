@@ -0,0 +1,245 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::vfs::{
+    inode::{InodeRef, InodeTy},
+    tmpfs::TmpFS,
+};
+
+const CPIO_NEWC_MAGIC: &[u8] = b"070701";
+const CPIO_CRC_MAGIC: &[u8] = b"070702";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+enum EntryKind<'a> {
+    Dir,
+    File(&'a [u8]),
+    Symlink(String),
+    HardLink(String, &'a [u8]),
+}
+
+struct Entry<'a> {
+    path: String,
+    mode: u16,
+    kind: EntryKind<'a>,
+}
+
+/// Unpacks a newc cpio or ustar archive into the directory `root`.
+pub fn unpack(archive: &[u8], root: InodeRef) -> Option<usize> {
+    let entries = if archive.starts_with(CPIO_NEWC_MAGIC) || archive.starts_with(CPIO_CRC_MAGIC) {
+        parse_cpio(archive)?
+    } else if archive.len() >= TAR_BLOCK_SIZE && &archive[257..262] == TAR_MAGIC {
+        parse_tar(archive)?
+    } else {
+        log::warn!("Initramfs is neither a newc cpio nor a ustar archive");
+        return None;
+    };
+
+    let count = entries.len();
+    for entry in entries {
+        install(&root, entry);
+    }
+
+    Some(count)
+}
+
+fn parse_hex(field: &[u8]) -> Option<usize> {
+    usize::from_str_radix(str::from_utf8(field).ok()?, 16).ok()
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let field = str::from_utf8(field).ok()?;
+    let field = field.trim_matches(|c: char| c == '\0' || c == ' ');
+    if field.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(field, 8).ok()
+}
+
+fn parse_name(field: &[u8]) -> Option<String> {
+    let len = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..len]).ok().map(|name| name.to_string())
+}
+
+fn parse_cpio(archive: &[u8]) -> Option<Vec<Entry<'_>>> {
+    let mut entries = Vec::new();
+    let mut links: BTreeMap<usize, String> = BTreeMap::new();
+    let mut offset = 0;
+
+    while offset + CPIO_HEADER_SIZE <= archive.len() {
+        let header = &archive[offset..offset + CPIO_HEADER_SIZE];
+        if !header.starts_with(CPIO_NEWC_MAGIC) && !header.starts_with(CPIO_CRC_MAGIC) {
+            return None;
+        }
+
+        let field = |index: usize| parse_hex(&header[6 + index * 8..6 + (index + 1) * 8]);
+        let ino = field(0)?;
+        let mode = field(1)? as u32;
+        let nlink = field(4)?;
+        let file_size = field(6)?;
+        let name_size = field(11)?;
+
+        let name_start = offset + CPIO_HEADER_SIZE;
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let data_end = data_start + file_size;
+        if data_end > archive.len() {
+            return None;
+        }
+
+        let path = parse_name(&archive[name_start..name_start + name_size])?;
+        if path == CPIO_TRAILER {
+            break;
+        }
+        let data = &archive[data_start..data_end];
+        offset = data_end.next_multiple_of(4);
+
+        let kind = match mode & S_IFMT {
+            S_IFDIR => EntryKind::Dir,
+            S_IFLNK => EntryKind::Symlink(str::from_utf8(data).ok()?.to_string()),
+            S_IFREG if nlink > 1 && links.contains_key(&ino) => {
+                EntryKind::HardLink(links[&ino].clone(), data)
+            }
+            S_IFREG => {
+                if nlink > 1 {
+                    links.insert(ino, path.clone());
+                }
+                EntryKind::File(data)
+            }
+            _ => {
+                log::warn!("Initramfs: skipping special file {}", path);
+                continue;
+            }
+        };
+
+        entries.push(Entry {
+            path,
+            mode: (mode & 0o7777) as u16,
+            kind,
+        });
+    }
+
+    Some(entries)
+}
+
+fn parse_tar(archive: &[u8]) -> Option<Vec<Entry<'_>>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    while offset + TAR_BLOCK_SIZE <= archive.len() {
+        let header = &archive[offset..offset + TAR_BLOCK_SIZE];
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if &header[257..262] != TAR_MAGIC {
+            return None;
+        }
+
+        let mut path = parse_name(&header[0..100])?;
+        let prefix = parse_name(&header[345..500])?;
+        if !prefix.is_empty() {
+            path = prefix + "/" + &path;
+        }
+        let mode = parse_octal(&header[100..108])?;
+        let size = parse_octal(&header[124..136])?;
+        let link_name = parse_name(&header[157..257])?;
+
+        let data_start = offset + TAR_BLOCK_SIZE;
+        let data_end = data_start + size;
+        if data_end > archive.len() {
+            return None;
+        }
+        let data = &archive[data_start..data_end];
+        offset = data_start + size.next_multiple_of(TAR_BLOCK_SIZE);
+
+        let kind = match header[156] {
+            b'0' | b'\0' | b'7' => EntryKind::File(data),
+            b'5' => EntryKind::Dir,
+            b'2' => EntryKind::Symlink(link_name),
+            b'1' => EntryKind::HardLink(link_name, &[]),
+            other => {
+                log::warn!("Initramfs: skipping tar entry {} of type {}", path, other);
+                continue;
+            }
+        };
+
+        entries.push(Entry {
+            path,
+            mode: (mode & 0o7777) as u16,
+            kind,
+        });
+    }
+
+    Some(entries)
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect()
+}
+
+fn lookup(root: &InodeRef, path: &str) -> Option<InodeRef> {
+    let mut node = root.clone();
+    for name in split_path(path) {
+        let child = node.read().open(name.to_string())?;
+        node = child;
+    }
+    Some(node)
+}
+
+fn attach(parent: &InodeRef, name: &str, node: InodeRef) {
+    parent.read().mount(node.clone(), name.to_string());
+    let path = parent.read().get_path() + name + "/";
+    node.write().when_mounted(path, Some(parent.clone()));
+}
+
+fn install(root: &InodeRef, entry: Entry) {
+    let names = split_path(&entry.path);
+    let Some((&name, parents)) = names.split_last() else {
+        return;
+    };
+
+    let mut parent = root.clone();
+    for &dir in parents {
+        let child = parent.read().create(dir.to_string(), InodeTy::Dir);
+        match child {
+            Some(child) => parent = child,
+            None => return,
+        }
+    }
+
+    if let EntryKind::Dir = entry.kind
+        && parent.read().open(name.to_string()).is_some()
+    {
+        return;
+    }
+
+    let node = match entry.kind {
+        EntryKind::Dir => TmpFS::new_dir(entry.mode),
+        EntryKind::File(data) => TmpFS::new_file(entry.mode, Vec::from(data)),
+        EntryKind::Symlink(target) => TmpFS::new_symlink(target),
+        EntryKind::HardLink(target, data) => {
+            // newc archives store the contents of a hard-linked file with its last link.
+            if let Some(node) = lookup(root, &target) {
+                if !data.is_empty() {
+                    node.read().write_at(0, 0, data);
+                }
+                parent.read().mount(node, name.to_string());
+            }
+            return;
+        }
+    };
+
+    attach(&parent, name, node);
+}
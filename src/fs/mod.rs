@@ -1,31 +1,86 @@
 use alloc::{
     collections::btree_map::BTreeMap,
-    string::{String, ToString},
+    string::ToString,
+    sync::Arc,
 };
 use spin::{Lazy, Mutex};
 use vfs::{
     acpi::AcpiFS,
+    devfs,
     fb::FbFS,
     inode::{InodeRef, mount_to},
-    root::RootFS,
+    procfs::{self, ProcFile, ProcRoot},
+    tmpfs::TmpFS,
 };
 
-use crate::task::process::ProcessId;
+use crate::{
+    block::{BlockDeviceRef, RamDisk, register_block_device},
+    task::process::ProcessId,
+};
 
+pub mod ext2;
+pub mod fat;
+pub mod initramfs;
+pub mod mount;
 pub mod operation;
+pub mod poll;
 pub mod user;
 pub mod vfs;
 
-pub static ROOT: Lazy<Mutex<InodeRef>> = Lazy::new(|| Mutex::new(RootFS::new()));
+pub static ROOT: Lazy<Mutex<InodeRef>> = Lazy::new(|| Mutex::new(TmpFS::new()));
+
+pub static USER_FS_MANAGER: Mutex<BTreeMap<ProcessId, Arc<user::UserServer>>> =
+    Mutex::new(BTreeMap::new());
 
-pub static USER_FS_MANAGER: Mutex<BTreeMap<ProcessId, usize>> = Mutex::new(BTreeMap::new());
-pub static PATH_TO_PID: Mutex<BTreeMap<String, ProcessId>> = Mutex::new(BTreeMap::new());
+/// Mounts the ext2 image passed as the `/rootfs.img` module as the root
+/// filesystem, returning the RAM disk holding it.
+fn mount_root_image() -> Option<BlockDeviceRef> {
+    let image = crate::module::get_module("/rootfs.img")?;
+    let device: BlockDeviceRef = Arc::new(RamDisk::from_image(512, image.to_vec()));
+
+    match ext2::mount(device.clone()) {
+        Some(root) => {
+            *ROOT.lock() = root;
+            Some(device)
+        }
+        None => {
+            log::warn!("/rootfs.img is not an ext2 image");
+            None
+        }
+    }
+}
+
+fn register_filesystems() {
+    mount::register_filesystem("vfat", |source| mount::mount_block(source, fat::mount));
+    mount::register_filesystem("msdos", |source| mount::mount_block(source, fat::mount));
+    mount::register_filesystem("ext2", |source| mount::mount_block(source, ext2::mount));
+    mount::register_filesystem("tmpfs", |_| Ok(TmpFS::new()));
+    mount::register_filesystem("userfs", user::mount);
+}
 
 pub fn init() {
+    register_filesystems();
+
+    let root_image = mount_root_image();
     ROOT.lock().write().when_mounted("/".to_string(), None);
+    match root_image {
+        Some(_) => mount::record("/dev/ram0", "/", "ext2", ROOT.lock().clone()),
+        None => mount::record("rootfs", "/", "tmpfs", ROOT.lock().clone()),
+    }
 
-    let dev_fs = RootFS::new();
+    if root_image.is_none()
+        && let Some(archive) = crate::module::get_module("/initramfs")
+    {
+        match initramfs::unpack(archive, ROOT.lock().clone()) {
+            Some(count) => log::info!("Unpacked {} initramfs entries", count),
+            None => log::warn!("Failed to unpack initramfs"),
+        }
+    }
+
+    let dev_fs: InodeRef = devfs::DEV_FS.clone();
     mount_to(dev_fs.clone(), ROOT.lock().clone(), "dev".to_string());
+    mount::record("devfs", "/dev", "devfs", dev_fs.clone());
+    devfs::init();
 
     let acpi_fs = AcpiFS::new();
     mount_to(acpi_fs.clone(), dev_fs.clone(), "kernel.acpi".to_string());
@@ -33,6 +88,16 @@ pub fn init() {
     let fb_fs = FbFS::new();
     mount_to(fb_fs.clone(), dev_fs.clone(), "kernel.fb".to_string());
 
-    let pipe_fs = RootFS::new();
-    mount_to(pipe_fs.clone(), ROOT.lock().clone(), "pipe".to_string());
+    let proc_fs = ProcRoot::new();
+    mount_to(proc_fs.clone(), ROOT.lock().clone(), "proc".to_string());
+    mount_to(ProcFile::new(mount::mounts), proc_fs.clone(), "mounts".to_string());
+    mount_to(ProcFile::new(procfs::meminfo), proc_fs.clone(), "meminfo".to_string());
+    mount_to(ProcFile::new(procfs::cpuinfo), proc_fs.clone(), "cpuinfo".to_string());
+    mount_to(ProcFile::new(procfs::interrupts), proc_fs.clone(), "interrupts".to_string());
+    mount_to(ProcFile::new(procfs::uptime), proc_fs.clone(), "uptime".to_string());
+    mount::record("proc", "/proc", "proc", proc_fs.clone());
+
+    if let Some(device) = root_image {
+        register_block_device("ram0", device);
+    }
 }
@@ -0,0 +1,326 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::{
+    block::{BlockDeviceRef, find_block_device},
+    syscall::errno::{EBUSY, EINVAL, ENODEV, ENOENT, ENOTBLK, ENOTDIR},
+};
+
+use super::{
+    operation::{get_cwd, is_path_busy, kernel_open},
+    vfs::{
+        inode::{InodeRef, InodeTy, mount_to},
+        stat_struct::{
+            DEVFS_SUPER_MAGIC, EXT2_SUPER_MAGIC, FUSE_SUPER_MAGIC, MSDOS_SUPER_MAGIC,
+            PROC_SUPER_MAGIC, StatVfs, TMPFS_MAGIC,
+        },
+    },
+};
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MountFlags: usize {
+        const RDONLY = 1;
+        const NOSUID = 2;
+        const NODEV = 4;
+        const NOEXEC = 8;
+        const REMOUNT = 32;
+    }
+}
+
+/// Detach the mount even if it is still in use.
+pub const MNT_DETACH: usize = 2;
+
+/// Creates the root directory of a new instance of a filesystem type.
+pub type MountFn = fn(source: &str) -> Result<InodeRef, usize>;
+
+static FILESYSTEMS: Mutex<BTreeMap<String, MountFn>> = Mutex::new(BTreeMap::new());
+static MOUNT_TABLE: Mutex<Vec<MountEntry>> = Mutex::new(Vec::new());
+
+pub struct MountEntry {
+    pub source: String,
+    pub target: String,
+    pub fstype: String,
+    pub flags: MountFlags,
+    /// Reported as `st_dev` for everything on the mount.
+    pub dev: u64,
+    root: InodeRef,
+    /// The directory the mount is attached to and the node it hides. Kernel
+    /// mounts have none and cannot be unmounted.
+    attached: Option<(InodeRef, InodeRef)>,
+}
+
+pub fn register_filesystem(name: &str, mount: MountFn) {
+    FILESYSTEMS.lock().insert(name.to_string(), mount);
+}
+
+/// Mounts a block-device-backed filesystem using `mount` on the device named
+/// by `source`.
+pub fn mount_block(
+    source: &str,
+    mount: fn(BlockDeviceRef) -> Option<InodeRef>,
+) -> Result<InodeRef, usize> {
+    let device = find_block_device(source).ok_or(ENOTBLK)?;
+    mount(device).ok_or(EINVAL)
+}
+
+/// Turns `path` into an absolute path without `.`, `..` or a trailing slash.
+fn normalize(path: &str) -> String {
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        get_cwd() + "/" + path
+    };
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    alloc::format!("/{}", components.join("/"))
+}
+
+fn is_below(path: &str, target: &str) -> bool {
+    target == "/" || path == target || path.starts_with(&(target.to_string() + "/"))
+}
+
+/// Hands out anonymous device numbers (major 0), as Linux does for mounts
+/// without a backing device number.
+fn next_dev() -> u64 {
+    static NEXT_MINOR: AtomicU64 = AtomicU64::new(1);
+    NEXT_MINOR.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Records a filesystem mounted by the kernel itself.
+pub fn record(source: &str, target: &str, fstype: &str, root: InodeRef) {
+    MOUNT_TABLE.lock().push(MountEntry {
+        source: source.to_string(),
+        target: target.to_string(),
+        fstype: fstype.to_string(),
+        flags: MountFlags::empty(),
+        dev: next_dev(),
+        root,
+        attached: None,
+    });
+}
+
+pub fn mount(source: &str, target: &str, fstype: &str, flags: MountFlags) -> Result<(), usize> {
+    let target = normalize(target);
+
+    if flags.contains(MountFlags::REMOUNT) {
+        let mut table = MOUNT_TABLE.lock();
+        let entry = table
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.target == target)
+            .ok_or(EINVAL)?;
+        entry.flags = flags - MountFlags::REMOUNT;
+        return Ok(());
+    }
+
+    let mount_fn = *FILESYSTEMS.lock().get(fstype).ok_or(ENODEV)?;
+
+    let (parent_path, name) = target.rsplit_once('/').ok_or(EINVAL)?;
+    if name.is_empty() {
+        return Err(EBUSY);
+    }
+
+    let covered = kernel_open(target.clone()).ok_or(ENOENT)?;
+    if covered.read().inode_type() != InodeTy::Dir {
+        return Err(ENOTDIR);
+    }
+    let parent = kernel_open(parent_path.to_string() + "/").ok_or(ENOENT)?;
+
+    let root = mount_fn(source)?;
+    mount_to(root.clone(), parent.clone(), name.to_string());
+
+    MOUNT_TABLE.lock().push(MountEntry {
+        source: source.to_string(),
+        target,
+        fstype: fstype.to_string(),
+        flags,
+        dev: next_dev(),
+        root,
+        attached: Some((parent, covered)),
+    });
+
+    Ok(())
+}
+
+pub fn umount(target: &str, flags: usize) -> Result<(), usize> {
+    let target = normalize(target);
+    let mut table = MOUNT_TABLE.lock();
+
+    let index = table
+        .iter()
+        .rposition(|entry| entry.target == target)
+        .ok_or(EINVAL)?;
+    if table[index].attached.is_none() {
+        return Err(EBUSY);
+    }
+
+    if flags & MNT_DETACH == 0 {
+        let nested = table
+            .iter()
+            .skip(index + 1)
+            .any(|entry| is_below(&entry.target, &target));
+        if nested || is_path_busy(&(target.clone() + "/")) {
+            return Err(EBUSY);
+        }
+    }
+
+    let entry = table.remove(index);
+    drop(table);
+
+    detach(entry);
+    Ok(())
+}
+
+/// Puts back what the mount covered.
+fn detach(entry: MountEntry) {
+    entry.root.write().when_umounted();
+    if let Some((parent, covered)) = entry.attached {
+        let name = entry
+            .target
+            .rsplit_once('/')
+            .map(|(_, name)| name)
+            .unwrap_or("");
+        parent.read().mount(covered, name.to_string());
+    }
+}
+
+/// Detaches every mount of `fstype` from `source`, busy or not, for when
+/// the source goes away.
+pub fn umount_source(fstype: &str, source: &str) {
+    loop {
+        let mut table = MOUNT_TABLE.lock();
+        let Some(index) = table.iter().rposition(|entry| {
+            entry.fstype == fstype && entry.source == source && entry.attached.is_some()
+        }) else {
+            return;
+        };
+        let entry = table.remove(index);
+        drop(table);
+        detach(entry);
+    }
+}
+
+/// Runs `f` on the innermost mount containing `path`.
+fn with_mount<T>(path: &str, f: impl FnOnce(&MountEntry) -> T) -> Option<T> {
+    let path = path.trim_end_matches('/');
+    let path = if path.is_empty() { "/" } else { path };
+
+    MOUNT_TABLE
+        .lock()
+        .iter()
+        .filter(|entry| is_below(path, &entry.target))
+        .max_by_key(|entry| entry.target.len())
+        .map(f)
+}
+
+/// Whether `inode` is the root of a mount, which can't be removed or
+/// renamed away.
+pub fn is_mount_root(inode: &InodeRef) -> bool {
+    MOUNT_TABLE
+        .lock()
+        .iter()
+        .any(|entry| Arc::ptr_eq(&entry.root, inode))
+}
+
+/// Flags of the innermost mount containing `path`.
+pub fn mount_flags(path: &str) -> MountFlags {
+    with_mount(path, |entry| entry.flags).unwrap_or(MountFlags::empty())
+}
+
+/// Device number of the innermost mount containing `path`.
+pub fn mount_dev(path: &str) -> u64 {
+    with_mount(path, |entry| entry.dev).unwrap_or(0)
+}
+
+/// `f_flags` bit marking the flags as valid.
+const ST_VALID: i64 = 0x20;
+
+fn magic(fstype: &str) -> i64 {
+    match fstype {
+        "ext2" => EXT2_SUPER_MAGIC,
+        "vfat" | "msdos" => MSDOS_SUPER_MAGIC,
+        "tmpfs" => TMPFS_MAGIC,
+        "proc" => PROC_SUPER_MAGIC,
+        "devfs" => DEVFS_SUPER_MAGIC,
+        "userfs" => FUSE_SUPER_MAGIC,
+        _ => 0,
+    }
+}
+
+/// Usage of the filesystem `inode` lives on, as reported by `statfs`.
+pub fn statfs(inode: &InodeRef) -> StatVfs {
+    let inode = inode.read();
+    let mut stat = inode.statfs().unwrap_or_default();
+
+    let path = inode.get_path();
+    let mount = match path.starts_with('/') {
+        true => with_mount(&path, |entry| {
+            (magic(&entry.fstype), entry.flags, entry.dev)
+        }),
+        false => None,
+    };
+    if let Some((magic, flags, dev)) = mount {
+        if stat.f_type == 0 {
+            stat.f_type = magic;
+        }
+        // The low mount flags share their values with the ST_* ones.
+        stat.f_flags = (flags - MountFlags::REMOUNT).bits() as i64;
+        stat.f_fsid = [dev as i32, (dev >> 32) as i32];
+    }
+
+    stat.f_flags |= ST_VALID;
+    if stat.f_frsize == 0 {
+        stat.f_frsize = stat.f_bsize;
+    }
+    if stat.f_namelen == 0 {
+        stat.f_namelen = 255;
+    }
+    stat
+}
+
+/// The mount table in the format of `/proc/mounts`.
+pub fn mounts() -> String {
+    let mut content = String::new();
+    for entry in MOUNT_TABLE.lock().iter() {
+        let mut options = String::from(match entry.flags.contains(MountFlags::RDONLY) {
+            true => "ro",
+            false => "rw",
+        });
+        for (flag, name) in [
+            (MountFlags::NOSUID, ",nosuid"),
+            (MountFlags::NODEV, ",nodev"),
+            (MountFlags::NOEXEC, ",noexec"),
+        ] {
+            if entry.flags.contains(flag) {
+                options.push_str(name);
+            }
+        }
+
+        content += &alloc::format!(
+            "{} {} {} {} 0 0\n",
+            entry.source,
+            entry.target,
+            entry.fstype,
+            options
+        );
+    }
+    content
+}
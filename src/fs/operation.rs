@@ -4,9 +4,16 @@ use core::{
 };
 
 use crate::{
-    fs::vfs::{inode::mount_to, pipe::PipeFS},
+    fs::vfs::pipe::{Pipe, PipeEnd},
     ref_to_mut,
-    task::process::ProcessId,
+    syscall::errno::{
+        EACCES, EBADF, EBUSY, EEXIST, EINVAL, EISDIR, ELOOP, ENOENT, ENOTDIR, EPERM, EROFS,
+    },
+    task::{
+        credentials::{Access, Credentials, S_ISGID, S_ISUID, S_ISVTX},
+        get_current_process,
+        process::ProcessId,
+    },
 };
 use alloc::{
     collections::BTreeMap,
@@ -14,45 +21,67 @@ use alloc::{
     sync::Arc,
     vec::Vec,
 };
+use bitflags::bitflags;
 use spin::Mutex;
 
 use crate::task::get_current_process_id;
 
 use super::{
-    PATH_TO_PID, ROOT,
-    user::UserFS,
+    ROOT,
+    mount::{MountFlags, is_mount_root, mount_dev, mount_flags},
     vfs::{
         inode::{FileInfo, InodeRef, InodeTy},
-        stat_struct::Stat,
+        stat_struct::{S_IFDIR, S_IFMT, Stat},
     },
 };
 
 static FILE_DESCRIPTOR_MANAGERS: Mutex<BTreeMap<ProcessId, Arc<FileDescriptorManager>>> =
     Mutex::new(BTreeMap::new());
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpenMode {
     Read = 0,
     Write = 1,
     ReadWrite = 2,
 }
 
-impl From<usize> for OpenMode {
-    fn from(mode: usize) -> Self {
+impl OpenMode {
+    fn access(self) -> Access {
+        match self {
+            Self::Read => Access::READ,
+            Self::Write => Access::WRITE,
+            Self::ReadWrite => Access::READ | Access::WRITE,
+        }
+    }
+}
+
+impl TryFrom<usize> for OpenMode {
+    type Error = usize;
+
+    fn try_from(mode: usize) -> Result<Self, usize> {
         match mode {
-            0 => Self::Read,
-            1 => Self::Write,
-            2 => Self::ReadWrite,
-            _ => panic!("Unknown open mode!!!"),
+            0 => Ok(Self::Read),
+            1 => Ok(Self::Write),
+            2 => Ok(Self::ReadWrite),
+            _ => Err(EINVAL),
         }
     }
 }
 
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: usize {
+        const NOCTTY = 0o400;
+        const NONBLOCK = 0o4000;
+    }
+}
+
 type FileDescriptor = usize;
 type FileTuple = (InodeRef, OpenMode, usize);
 
 struct FileDescriptorManager {
     file_descriptors: BTreeMap<FileDescriptor, FileTuple>,
-    file_descriptor_to_paths: BTreeMap<FileDescriptor, String>,
+    file_descriptor_flags: BTreeMap<FileDescriptor, OpenFlags>,
     file_descriptor_allocator: AtomicUsize,
     cwd: Mutex<InodeRef>,
 }
@@ -61,7 +90,7 @@ impl FileDescriptorManager {
     pub fn new(file_descriptors: BTreeMap<FileDescriptor, FileTuple>) -> Self {
         Self {
             file_descriptors,
-            file_descriptor_to_paths: BTreeMap::new(),
+            file_descriptor_flags: BTreeMap::new(),
             file_descriptor_allocator: AtomicUsize::new(3), // 0, 1, and 2 are reserved for stdin, stdout, and stderr
             cwd: Mutex::new(ROOT.lock().clone()),
         }
@@ -80,17 +109,16 @@ impl FileDescriptorManager {
         new_fd
     }
 
-    pub fn add_fd_to_path(&self, fd: FileDescriptor, path: String) {
-        ref_to_mut(self)
-            .file_descriptor_to_paths
-            .insert(fd, path.clone());
+    pub fn set_flags(&self, fd: FileDescriptor, flags: OpenFlags) {
+        ref_to_mut(self).file_descriptor_flags.insert(fd, flags);
     }
 
     pub fn change_cwd(&self, path: String) {
-        if let Some(inode) = get_inode_by_path(path) {
-            if inode.read().inode_type() == InodeTy::Dir {
-                *self.cwd.lock() = inode;
-            }
+        if let Ok(inode) = get_inode_by_path(path)
+            && inode.read().inode_type() == InodeTy::Dir
+            && check_access(&inode, Access::EXECUTE).is_ok()
+        {
+            *self.cwd.lock() = inode;
         }
     }
 
@@ -117,6 +145,13 @@ pub fn init_file_descriptor_manager_for_fork(this: ProcessId) {
         .insert(this, parent_file_descriptor_manager.clone());
 }
 
+/// Drops the file table of an exiting process, closing its files unless a
+/// forked process still shares the table.
+pub fn remove_file_descriptor_manager(pid: ProcessId) {
+    let manager = FILE_DESCRIPTOR_MANAGERS.lock().remove(&pid);
+    drop(manager);
+}
+
 pub fn init_file_descriptor_manager_with_stdin_stdout(
     pid: ProcessId,
     stdin: InodeRef,
@@ -132,28 +167,141 @@ pub fn init_file_descriptor_manager_with_stdin_stdout(
     file_descriptor_managers.insert(pid, Arc::new(FileDescriptorManager::new(file_descriptors)));
 }
 
-fn get_inode_by_path(path: String) -> Option<InodeRef> {
-    let root = ROOT.lock().clone();
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+fn get_inode_by_path(path: String) -> Result<InodeRef, usize> {
+    resolve_path(&path, MAX_SYMLINK_DEPTH, true)
+}
+
+/// Walks `path` from the root as the calling process, which needs search
+/// permission on every directory on the way. A symlink in the last
+/// component is only followed if `follow_last` is set.
+fn resolve_path(path: &str, depth: usize, follow_last: bool) -> Result<InodeRef, usize> {
+    let credentials = get_current_process().read().credentials.clone();
+    resolve_path_as(path, depth, follow_last, &credentials)
+}
 
-    let path = path.split("/");
+fn resolve_path_as(
+    path: &str,
+    depth: usize,
+    follow_last: bool,
+    credentials: &Credentials,
+) -> Result<InodeRef, usize> {
+    // Root may search any directory, so spare asking for metadata.
+    let searcher = (!credentials.is_root()).then_some(credentials);
+    walk(path, depth, follow_last, searcher)
+}
 
-    let node = root;
+fn walk(
+    path: &str,
+    depth: usize,
+    follow_last: bool,
+    searcher: Option<&Credentials>,
+) -> Result<InodeRef, usize> {
+    let mut node = ROOT.lock().clone();
+    let mut path_nodes = path
+        .split("/")
+        .filter(|path_node| !path_node.is_empty())
+        .peekable();
+
+    while let Some(path_node) = path_nodes.next() {
+        if let Some(credentials) = searcher
+            && !credentials.may(&node.read().metadata(0), Access::EXECUTE)
+        {
+            return Err(EACCES);
+        }
+        let child = node.read().open(String::from(path_node)).ok_or(ENOENT)?;
 
-    for path_node in path {
-        if path_node.len() > 0 {
-            if let Some(child) = node.read().open(String::from(path_node)) {
-                core::mem::drop(core::mem::replace(ref_to_mut(&node), child));
-            } else {
-                return None;
+        let link = child.read().read_link();
+        if let Some(target) = link
+            && (follow_last || path_nodes.peek().is_some())
+        {
+            if depth == 0 {
+                return Err(ELOOP);
             }
+
+            let rest = path_nodes.collect::<Vec<_>>().join("/");
+            let target = if target.starts_with("/") {
+                target
+            } else {
+                node.read().get_path() + &target
+            };
+            return walk(
+                &alloc::format!("{}/{}", target, rest),
+                depth - 1,
+                follow_last,
+                searcher,
+            );
         }
+
+        node = child;
     }
 
-    Some(node.clone())
+    Ok(node)
+}
+
+fn is_read_only(inode: &InodeRef) -> bool {
+    mount_flags(&inode.read().get_path()).contains(MountFlags::RDONLY)
+}
+
+/// Fails with `EACCES` unless the calling process may access `inode` as
+/// `access`.
+fn check_access(inode: &InodeRef, access: Access) -> Result<(), usize> {
+    // Asking a user filesystem for metadata may block, so do it before
+    // taking the process lock.
+    let metadata = inode.read().metadata(0);
+    match get_current_process()
+        .read()
+        .credentials
+        .may(&metadata, access)
+    {
+        true => Ok(()),
+        false => Err(EACCES),
+    }
 }
 
+/// Fails unless the calling process may remove `inode` from `parent`: it
+/// needs write and search permission there, and in a sticky directory must
+/// own either the directory or the entry.
+fn check_removable(parent: &InodeRef, inode: &InodeRef) -> Result<(), usize> {
+    let parent = parent.read().metadata(0);
+    let inode = inode.read().metadata(0);
+    let process = get_current_process();
+    let process = process.read();
+    let credentials = &process.credentials;
+    if !credentials.may(&parent, Access::WRITE | Access::EXECUTE) {
+        return Err(EACCES);
+    }
+    if parent.mode & S_ISVTX != 0 && !credentials.owns(&parent) && !credentials.owns(&inode) {
+        return Err(EPERM);
+    }
+    Ok(())
+}
+
+/// Whether `inode` is `ancestor` or lies somewhere below it.
+fn is_within(inode: &InodeRef, ancestor: &InodeRef) -> bool {
+    let mut node = inode.clone();
+    loop {
+        if Arc::ptr_eq(&node, ancestor) {
+            return true;
+        }
+        let parent = node.read().open("..".into());
+        match parent {
+            Some(parent) if !Arc::ptr_eq(&parent, &node) => node = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// Looks the absolute `path` up on behalf of `credentials`, which need
+/// search permission on every directory on the way.
+pub fn open_as(path: &str, credentials: &Credentials) -> Result<InodeRef, usize> {
+    resolve_path_as(path, MAX_SYMLINK_DEPTH, true, credentials)
+}
+
+/// Looks `path` up for the kernel itself, without permission checks.
 pub fn kernel_open(path: String) -> Option<InodeRef> {
-    get_inode_by_path(path)
+    walk(&path, MAX_SYMLINK_DEPTH, true, None).ok()
 }
 
 pub fn get_inode_by_fd(file_descriptor: usize) -> Option<InodeRef> {
@@ -166,32 +314,51 @@ pub fn get_inode_by_fd(file_descriptor: usize) -> Option<InodeRef> {
     Some(inode.clone())
 }
 
-pub fn get_path_by_fd(file_descriptor: usize) -> Option<String> {
+/// Everything `file_descriptor` stands for, to install it elsewhere.
+pub fn get_file_by_fd(file_descriptor: usize) -> Option<(InodeRef, OpenMode, OpenFlags)> {
     let current_file_descriptor_manager = get_file_descriptor_manager()?;
+    let (inode, mode, _) = current_file_descriptor_manager
+        .file_descriptors
+        .get(&file_descriptor)?;
+    Some((inode.clone(), *mode, get_flags_by_fd(file_descriptor)))
+}
+
+pub fn get_flags_by_fd(file_descriptor: usize) -> OpenFlags {
+    get_file_descriptor_manager()
+        .and_then(|manager| manager.file_descriptor_flags.get(&file_descriptor).copied())
+        .unwrap_or(OpenFlags::empty())
+}
 
+pub fn set_flags_by_fd(file_descriptor: usize, flags: OpenFlags) -> Option<()> {
+    let current_file_descriptor_manager = get_file_descriptor_manager()?;
     current_file_descriptor_manager
-        .file_descriptor_to_paths
-        .get(&file_descriptor)
-        .cloned()
+        .file_descriptors
+        .get(&file_descriptor)?;
+    current_file_descriptor_manager.set_flags(file_descriptor, flags);
+    Some(())
+}
+
+/// Puts an inode that has no path, such as an epoll instance, into the
+/// file table.
+pub fn install(inode: InodeRef, mode: OpenMode, flags: OpenFlags) -> Option<FileDescriptor> {
+    let current_file_descriptor_manager = get_file_descriptor_manager()?;
+    let file_descriptor = current_file_descriptor_manager.add_inode(inode, mode);
+    current_file_descriptor_manager.set_flags(file_descriptor, flags);
+    Some(file_descriptor)
 }
 
-pub fn pipe(fd: &mut [FileDescriptor]) -> Option<usize> {
+pub fn pipe(fd: &mut [FileDescriptor], flags: OpenFlags) -> Option<usize> {
     assert_eq!(fd.len(), 2);
 
     let current_file_descriptor_manager = get_file_descriptor_manager()?;
 
-    let pipe_inode = PipeFS::new();
-    let pipe_fs_inode = kernel_open("/pipe".to_string())?;
-    mount_to(
-        pipe_inode.clone(),
-        pipe_fs_inode.clone(),
-        alloc::format!("pipe{}", get_current_process_id().0),
-    );
-
+    let pipe = Pipe::new();
     let read_descriptor =
-        current_file_descriptor_manager.add_inode(pipe_inode.clone(), OpenMode::Read);
+        current_file_descriptor_manager.add_inode(pipe.open(PipeEnd::Read), OpenMode::Read);
     let write_descriptor =
-        current_file_descriptor_manager.add_inode(pipe_inode.clone(), OpenMode::Write);
+        current_file_descriptor_manager.add_inode(pipe.open(PipeEnd::Write), OpenMode::Write);
+    current_file_descriptor_manager.set_flags(read_descriptor, flags);
+    current_file_descriptor_manager.set_flags(write_descriptor, flags);
 
     fd[0] = read_descriptor;
     fd[1] = write_descriptor;
@@ -199,25 +366,8 @@ pub fn pipe(fd: &mut [FileDescriptor]) -> Option<usize> {
     return Some(0);
 }
 
-pub fn open(path: String, open_mode: OpenMode) -> Option<usize> {
-    let current_file_descriptor_manager = get_file_descriptor_manager()?;
-
-    if path.starts_with(':') {
-        let mut path = path.clone();
-        let c = path.remove(0);
-        assert_eq!(c, ':');
-        let (fs_name, user_path) = path.split_once(':')?;
-
-        if let Some(&pid) = PATH_TO_PID.lock().get(fs_name) {
-            let inode = UserFS::new(pid);
-            inode.write().when_mounted(user_path.to_string(), None);
-            inode.read().open(user_path.to_string());
-            let file_descriptor =
-                current_file_descriptor_manager.add_inode(inode.clone(), open_mode);
-            current_file_descriptor_manager.add_fd_to_path(file_descriptor, user_path.to_string());
-            return Some(file_descriptor);
-        }
-    }
+pub fn open(path: String, open_mode: OpenMode, flags: OpenFlags) -> Result<usize, usize> {
+    let current_file_descriptor_manager = get_file_descriptor_manager().ok_or(EBADF)?;
 
     let inode = if path.starts_with("/") {
         get_inode_by_path(path.clone())?
@@ -229,9 +379,18 @@ pub fn open(path: String, open_mode: OpenMode) -> Option<usize> {
         ))?
     };
 
+    if !matches!(open_mode, OpenMode::Read) && is_read_only(&inode) {
+        return Err(EROFS);
+    }
+    check_access(&inode, open_mode.access())?;
+
+    let opened = inode.read().on_open(open_mode, flags)?;
+    let inode = opened.unwrap_or(inode);
+
     let file_descriptor = current_file_descriptor_manager.add_inode(inode, open_mode);
+    current_file_descriptor_manager.set_flags(file_descriptor, flags);
 
-    Some(file_descriptor)
+    Ok(file_descriptor)
 }
 
 pub fn read(fd: FileDescriptor, buf: &mut [u8]) -> usize {
@@ -242,6 +401,8 @@ pub fn read(fd: FileDescriptor, buf: &mut [u8]) -> usize {
     let current_file_descriptor_manager = current_file_descriptor_manager.unwrap();
 
     if let Some((inode, mode, offset)) = current_file_descriptor_manager.file_descriptors.get(&fd) {
+        // Keep the inode alive even if the descriptor is closed while blocked.
+        let inode = inode.clone();
         match mode {
             OpenMode::Read | OpenMode::ReadWrite => inode.read().read_at(fd, *offset, buf),
 
@@ -257,6 +418,7 @@ pub fn write(fd: FileDescriptor, buf: &[u8]) -> usize {
         if let Some((inode, mode, offset)) =
             current_file_descriptor_manager.file_descriptors.get(&fd)
         {
+            let inode = inode.clone();
             match mode {
                 OpenMode::Write | OpenMode::ReadWrite => inode.read().write_at(fd, *offset, buf),
 
@@ -283,9 +445,9 @@ pub fn lseek(fd: FileDescriptor, offset: usize) -> Option<()> {
 
 pub fn close(fd: FileDescriptor) -> Option<()> {
     let current_file_descriptor_manager = get_file_descriptor_manager()?;
-    ref_to_mut(current_file_descriptor_manager.as_ref())
-        .file_descriptors
-        .remove(&fd)?;
+    let manager = ref_to_mut(current_file_descriptor_manager.as_ref());
+    manager.file_descriptor_flags.remove(&fd);
+    manager.file_descriptors.remove(&fd)?;
     Some(())
 }
 
@@ -301,26 +463,54 @@ pub fn fsize(fd: FileDescriptor) -> Option<usize> {
     Some(size)
 }
 
-pub fn fstat(fd: FileDescriptor, buf_addr: usize) -> Option<usize> {
-    let current_file_descriptor_manager = get_file_descriptor_manager()?;
+fn write_stat(inode: &InodeRef, fd: FileDescriptor, buf_addr: usize) {
+    let inode = inode.read();
+    let mut metadata = inode.metadata(fd);
+    if metadata.dev == 0 {
+        metadata.dev = mount_dev(&inode.get_path());
+    }
 
-    let (inode, _, _) = ref_to_mut(current_file_descriptor_manager.as_ref())
-        .file_descriptors
-        .get_mut(&fd)?;
+    let stat_struct = Stat::from(metadata);
+    let stat_buf = &*stat_struct;
 
-    let size = fsize(fd)?;
+    unsafe { core::slice::from_raw_parts_mut(buf_addr as *mut u8, stat_buf.len()) }
+        .copy_from_slice(stat_buf);
+}
 
-    let mut stat_strcut = Stat::default();
-    stat_strcut.st_size = size as u64;
+pub fn fstat(fd: FileDescriptor, buf_addr: usize) -> Option<usize> {
+    let inode = get_inode_by_fd(fd)?;
+    write_stat(&inode, fd, buf_addr);
+    Some(0)
+}
 
-    let stat_buf = &*stat_strcut;
+fn write_statfs(inode: &InodeRef, buf_addr: usize) {
+    let statfs = super::mount::statfs(inode);
+    let statfs_buf = &*statfs;
 
-    unsafe { core::slice::from_raw_parts_mut(buf_addr as *mut u8, stat_buf.len()) }
-        .copy_from_slice(stat_buf);
+    unsafe { core::slice::from_raw_parts_mut(buf_addr as *mut u8, statfs_buf.len()) }
+        .copy_from_slice(statfs_buf);
+}
+
+pub fn fstatfs(fd: FileDescriptor, buf_addr: usize) -> Option<usize> {
+    let inode = get_inode_by_fd(fd)?;
+    write_statfs(&inode, buf_addr);
+    Some(0)
+}
 
+pub fn statfs(path: &str, buf_addr: usize) -> Option<usize> {
+    let inode = get_inode_by_path(path.to_string()).ok()?;
+    write_statfs(&inode, buf_addr);
     Some(0)
 }
 
+/// Stats the absolute `path`, following a symlink in its last component
+/// only if `follow` is set.
+pub fn stat(path: &str, follow: bool, buf_addr: usize) -> Result<(), usize> {
+    let inode = resolve_path(path, MAX_SYMLINK_DEPTH, follow)?;
+    write_stat(&inode, 0, buf_addr);
+    Ok(())
+}
+
 pub fn list_dir(fd: FileDescriptor) -> Vec<FileInfo> {
     if let Some(current_file_descriptor_manager) = get_file_descriptor_manager() {
         let current = current_file_descriptor_manager.get_cwd();
@@ -341,6 +531,70 @@ pub fn list_dir(fd: FileDescriptor) -> Vec<FileInfo> {
     }
 }
 
+/// `d_type` values of `linux_dirent64`.
+fn dirent_type(ty: InodeTy) -> u8 {
+    match ty {
+        InodeTy::Fifo => 1,
+        InodeTy::CharDevice => 2,
+        InodeTy::Dir => 4,
+        InodeTy::BlockDevice => 6,
+        InodeTy::File => 8,
+        InodeTy::Symlink => 10,
+    }
+}
+
+/// Fills `buf` with `linux_dirent64` records for the directory open as `fd`,
+/// starting at the entry the descriptor's offset points to and advancing
+/// it past the entries written. Returns the number of bytes used.
+pub fn getdents64(fd: FileDescriptor, buf: &mut [u8]) -> Result<usize, usize> {
+    const HEADER_SIZE: usize = 19;
+
+    let current_file_descriptor_manager = get_file_descriptor_manager().ok_or(EBADF)?;
+    let (inode, _, offset) = current_file_descriptor_manager
+        .file_descriptors
+        .get(&fd)
+        .ok_or(EBADF)?;
+    let (inode, start) = (inode.clone(), *offset);
+    if inode.read().inode_type() != InodeTy::Dir {
+        return Err(ENOTDIR);
+    }
+
+    let mut entries = inode.read().list(fd);
+    entries.sort();
+
+    let mut written = 0;
+    let mut index = start;
+    for entry in entries.iter().skip(start) {
+        let record_len = (HEADER_SIZE + entry.name.len() + 1).next_multiple_of(8);
+        if written + record_len > buf.len() {
+            if written == 0 {
+                return Err(EINVAL);
+            }
+            break;
+        }
+
+        let ino = inode
+            .read()
+            .open(entry.name.clone())
+            .map(|child| child.read().metadata(fd).ino)
+            .unwrap_or(index as u64 + 1);
+
+        let record = &mut buf[written..written + record_len];
+        record.fill(0);
+        record[0..8].copy_from_slice(&ino.to_ne_bytes());
+        record[8..16].copy_from_slice(&(index as i64 + 1).to_ne_bytes());
+        record[16..18].copy_from_slice(&(record_len as u16).to_ne_bytes());
+        record[18] = dirent_type(entry.ty);
+        record[HEADER_SIZE..HEADER_SIZE + entry.name.len()].copy_from_slice(entry.name.as_bytes());
+
+        written += record_len;
+        index += 1;
+    }
+
+    lseek(fd, index).ok_or(EBADF)?;
+    Ok(written)
+}
+
 pub fn change_cwd(path: String) {
     if let Some(current_file_descriptor_manager) = get_file_descriptor_manager() {
         if path.starts_with("/") {
@@ -353,6 +607,31 @@ pub fn change_cwd(path: String) {
     }
 }
 
+/// Whether any process has a file open or its working directory at or
+/// below `prefix`.
+pub fn is_path_busy(prefix: &str) -> bool {
+    FILE_DESCRIPTOR_MANAGERS.lock().values().any(|manager| {
+        manager.get_cwd().starts_with(prefix)
+            || manager
+                .file_descriptors
+                .values()
+                .any(|(inode, _, _)| inode.read().get_path().starts_with(prefix))
+    })
+}
+
+/// The open files of `pid`, for `/proc/<pid>/fd`.
+pub fn open_files(pid: ProcessId) -> Vec<(FileDescriptor, InodeRef)> {
+    let Some(manager) = FILE_DESCRIPTOR_MANAGERS.lock().get(&pid).cloned() else {
+        return Vec::new();
+    };
+
+    manager
+        .file_descriptors
+        .iter()
+        .map(|(&fd, (inode, _, _))| (fd, inode.clone()))
+        .collect()
+}
+
 pub fn get_cwd() -> String {
     if let Some(current_file_descriptor_manager) = get_file_descriptor_manager() {
         current_file_descriptor_manager.get_cwd()
@@ -361,31 +640,254 @@ pub fn get_cwd() -> String {
     }
 }
 
-pub fn create(path: String, ty: InodeTy, mode: OpenMode) -> Option<FileDescriptor> {
-    if let Some(current_file_descriptor_manager) = get_file_descriptor_manager() {
-        if path.starts_with("/") {
-            let mut name = String::new();
-            let parent_path = {
-                let mut path = path.clone();
-                while !path.ends_with("/") {
-                    name.push(path.pop().unwrap());
-                }
-                path
-            };
-            let parent = get_inode_by_path(parent_path)?;
-            let inode = parent.read().create(name.clone(), ty)?;
-            let file_descriptor = current_file_descriptor_manager.add_inode(inode, mode);
-            Some(file_descriptor)
-        } else {
-            let cwd = current_file_descriptor_manager.get_cwd();
-            let parent = get_inode_by_path(cwd.clone())?;
-            let inode = parent.read().create(path.clone(), ty)?;
-            let file_descriptor = current_file_descriptor_manager.add_inode(inode, mode);
-            Some(file_descriptor)
-        }
+/// Stands for the current working directory in the `*at` syscalls.
+pub const AT_FDCWD: usize = -100isize as usize;
+
+/// Resolves `path` against the directory open as `dirfd`, or against the
+/// working directory for [`AT_FDCWD`].
+pub fn absolute_path(dirfd: usize, path: &str) -> Option<String> {
+    if path.starts_with('/') {
+        return Some(path.to_string());
+    }
+
+    let base = if dirfd == AT_FDCWD {
+        get_cwd()
     } else {
-        None
+        let inode = get_inode_by_fd(dirfd)?;
+        if inode.read().inode_type() != InodeTy::Dir {
+            return None;
+        }
+        inode.read().get_path()
+    };
+    Some(base + path)
+}
+
+/// Splits the absolute `path` into the directory holding it and the name
+/// of the entry.
+fn parent_and_name(path: &str) -> Result<(InodeRef, &str), usize> {
+    let (parent_path, name) = path.trim_end_matches('/').rsplit_once('/').ok_or(ENOENT)?;
+    let parent = get_inode_by_path(parent_path.to_string() + "/")?;
+    if parent.read().inode_type() != InodeTy::Dir {
+        return Err(ENOTDIR);
+    }
+    Ok((parent, name))
+}
+
+/// Creates a special file of type `ty` at the absolute `path`, owned by the
+/// calling process and with the permission bits of `mode` its umask leaves.
+pub fn mknod(path: &str, ty: InodeTy, mode: u16) -> Result<(), usize> {
+    let (parent, name) = parent_and_name(path)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(EEXIST);
+    }
+    if parent.read().open(name.to_string()).is_some() {
+        return Err(EEXIST);
+    }
+    if is_read_only(&parent) {
+        return Err(EROFS);
+    }
+    check_access(&parent, Access::WRITE | Access::EXECUTE)?;
+
+    let (uid, gid, umask) = {
+        let process = get_current_process();
+        let process = process.read();
+        let credentials = &process.credentials;
+        (
+            credentials.user.effective,
+            credentials.group.effective,
+            process.umask,
+        )
+    };
+    parent
+        .read()
+        .create_owned(name.to_string(), ty, uid, gid, mode & 0o7777 & !umask)
+        .ok_or(EPERM)?;
+    Ok(())
+}
+
+/// Removes the entry at the absolute `path`, which must be a directory if
+/// and only if `dir` is set.
+pub fn unlink(path: &str, dir: bool) -> Result<(), usize> {
+    let (parent, name) = parent_and_name(path)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(if dir { EINVAL } else { EISDIR });
+    }
+
+    let inode = parent.read().open(name.to_string()).ok_or(ENOENT)?;
+    match (inode.read().inode_type() == InodeTy::Dir, dir) {
+        (true, false) => return Err(EISDIR),
+        (false, true) => return Err(ENOTDIR),
+        _ => {}
+    }
+    if is_mount_root(&inode) {
+        return Err(EBUSY);
+    }
+    if is_read_only(&parent) {
+        return Err(EROFS);
+    }
+    check_removable(&parent, &inode)?;
+
+    parent.read().unlink(name.to_string(), dir)
+}
+
+/// Moves the entry at the absolute path `from` to `to`.
+pub fn rename(from: &str, to: &str) -> Result<(), usize> {
+    let (from_parent, from_name) = parent_and_name(from)?;
+    let (to_parent, to_name) = parent_and_name(to)?;
+    for name in [from_name, to_name] {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(EBUSY);
+        }
+    }
+
+    let inode = from_parent
+        .read()
+        .open(from_name.to_string())
+        .ok_or(ENOENT)?;
+    if is_read_only(&from_parent) || is_read_only(&to_parent) {
+        return Err(EROFS);
+    }
+    check_removable(&from_parent, &inode)?;
+    let replaced = to_parent.read().open(to_name.to_string());
+    match &replaced {
+        Some(replaced) => check_removable(&to_parent, replaced)?,
+        None => check_access(&to_parent, Access::WRITE | Access::EXECUTE)?,
+    }
+
+    if is_mount_root(&inode) || replaced.as_ref().is_some_and(is_mount_root) {
+        return Err(EBUSY);
+    }
+
+    let is_dir = inode.read().inode_type() == InodeTy::Dir;
+    if let Some(replaced) = replaced
+        && !Arc::ptr_eq(&replaced, &inode)
+    {
+        match (is_dir, replaced.read().inode_type() == InodeTy::Dir) {
+            (true, false) => return Err(ENOTDIR),
+            (false, true) => return Err(EISDIR),
+            _ => {}
+        }
+    }
+    if is_dir && is_within(&to_parent, &inode) {
+        return Err(EINVAL);
+    }
+
+    from_parent
+        .read()
+        .rename(from_name.to_string(), &to_parent, to_name.to_string())
+}
+
+/// Sets the size of the file at the absolute `path`.
+pub fn truncate(path: &str, len: usize) -> Result<(), usize> {
+    let inode = get_inode_by_path(path.to_string())?;
+    if inode.read().inode_type() == InodeTy::Dir {
+        return Err(EISDIR);
+    }
+    if is_read_only(&inode) {
+        return Err(EROFS);
+    }
+    check_access(&inode, Access::WRITE)?;
+    inode.read().truncate(0, len)
+}
+
+/// Sets the permission bits of `inode`, which the calling process must own.
+fn change_mode(inode: &InodeRef, mode: u32) -> Result<(), usize> {
+    if is_read_only(inode) {
+        return Err(EROFS);
+    }
+    let metadata = inode.read().metadata(0);
+    let mut mode = mode & 0o7777;
+    {
+        let process = get_current_process();
+        let process = process.read();
+        let credentials = &process.credentials;
+        if !credentials.owns(&metadata) {
+            return Err(EPERM);
+        }
+        // Otherwise anyone could make a file run as a group they are not in.
+        if !credentials.is_root() && !credentials.in_group(metadata.gid) {
+            mode &= !S_ISGID;
+        }
     }
+    inode.read().chmod(mode as u16)
+}
+
+/// Gives `inode` to `uid` and `gid`, keeping whichever is `None`. Only root
+/// may give files away; owners may move them to one of their groups.
+fn change_owner(inode: &InodeRef, uid: Option<u32>, gid: Option<u32>) -> Result<(), usize> {
+    if is_read_only(inode) {
+        return Err(EROFS);
+    }
+    let metadata = inode.read().metadata(0);
+    let uid = uid.unwrap_or(metadata.uid);
+    let gid = gid.unwrap_or(metadata.gid);
+    {
+        let process = get_current_process();
+        let process = process.read();
+        let credentials = &process.credentials;
+        if !credentials.is_root()
+            && (uid != metadata.uid
+                || !credentials.owns(&metadata)
+                || gid != metadata.gid && !credentials.in_group(gid))
+        {
+            return Err(EPERM);
+        }
+    }
+
+    let inode = inode.read();
+    inode.chown(uid, gid)?;
+    // Set-ID bits must not carry over to the new owner.
+    let set_id = S_ISUID | S_ISGID;
+    if (uid, gid) != (metadata.uid, metadata.gid)
+        && metadata.mode & set_id != 0
+        && metadata.mode & S_IFMT != S_IFDIR
+    {
+        inode.chmod((metadata.mode & 0o7777 & !set_id) as u16)?;
+    }
+    Ok(())
+}
+
+/// Changes the mode of the file at the absolute `path`.
+pub fn chmod(path: &str, mode: u32) -> Result<(), usize> {
+    let inode = get_inode_by_path(path.to_string())?;
+    change_mode(&inode, mode)
+}
+
+pub fn fchmod(fd: FileDescriptor, mode: u32) -> Result<(), usize> {
+    let inode = get_inode_by_fd(fd).ok_or(EBADF)?;
+    change_mode(&inode, mode)
+}
+
+/// Changes the owner of the file at the absolute `path`, following a
+/// symlink in its last component only if `follow` is set.
+pub fn chown(path: &str, follow: bool, uid: Option<u32>, gid: Option<u32>) -> Result<(), usize> {
+    let inode = resolve_path(path, MAX_SYMLINK_DEPTH, follow)?;
+    change_owner(&inode, uid, gid)
+}
+
+pub fn fchown(fd: FileDescriptor, uid: Option<u32>, gid: Option<u32>) -> Result<(), usize> {
+    let inode = get_inode_by_fd(fd).ok_or(EBADF)?;
+    change_owner(&inode, uid, gid)
+}
+
+/// Sets the size of the file open as `fd`, which must be writable.
+pub fn ftruncate(fd: FileDescriptor, len: usize) -> Result<(), usize> {
+    let current_file_descriptor_manager = get_file_descriptor_manager().ok_or(EBADF)?;
+    let (inode, mode, _) = current_file_descriptor_manager
+        .file_descriptors
+        .get(&fd)
+        .ok_or(EBADF)?;
+    if *mode == OpenMode::Read {
+        return Err(EINVAL);
+    }
+    let inode = inode.clone();
+    inode.read().truncate(fd, len)
+}
+
+pub fn fsync(fd: FileDescriptor) -> Option<()> {
+    let inode = get_inode_by_fd(fd)?;
+    inode.read().flush();
+    Some(())
 }
 
 pub fn get_type(fd: FileDescriptor) -> Option<InodeTy> {
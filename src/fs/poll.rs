@@ -0,0 +1,194 @@
+use alloc::{sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use core::time::Duration;
+
+use super::operation::get_inode_by_fd;
+use crate::{
+    acpi::hpet::HPET,
+    syscall::errno::{EBADF, EINTR},
+    task::{
+        signal::has_pending_signal,
+        timer::TIMER,
+        wait_queue::{WaitQueue, deadline_passed, set_current_blocking},
+    },
+};
+
+bitflags! {
+    /// Readiness events, with the values shared by `poll` and `epoll`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PollEvents: u32 {
+        const IN = 0x001;
+        const PRI = 0x002;
+        const OUT = 0x004;
+        const ERR = 0x008;
+        const HUP = 0x010;
+        const NVAL = 0x020;
+        const RDNORM = 0x040;
+        const WRNORM = 0x100;
+    }
+}
+
+/// Events reported whether or not they were asked for.
+pub const ALWAYS_REPORTED: PollEvents = PollEvents::ERR
+    .union(PollEvents::HUP)
+    .union(PollEvents::NVAL);
+
+/// The wait queues a poller sleeps on, filled in by `Inode::poll`.
+#[derive(Default)]
+pub struct PollTable {
+    queues: Vec<Arc<WaitQueue>>,
+}
+
+impl PollTable {
+    /// Wakes the poller when `queue` is woken.
+    pub fn register(&mut self, queue: &Arc<WaitQueue>) {
+        queue.register();
+        self.queues.push(queue.clone());
+    }
+
+    fn clear(&mut self) {
+        for queue in self.queues.drain(..) {
+            queue.unregister();
+        }
+    }
+}
+
+/// Calls `scan` until it reports something ready, `timeout` runs out or a
+/// signal arrives. `scan` polls every file of interest against the table,
+/// so any of them changing state wakes the caller up to scan again.
+pub fn poll_wait(
+    timeout: Option<Duration>,
+    mut scan: impl FnMut(&mut PollTable) -> usize,
+) -> Result<usize, usize> {
+    let deadline = match timeout {
+        Some(timeout) if timeout.is_zero() => Some(0),
+        Some(timeout) => {
+            let deadline = HPET.estimate(timeout);
+            TIMER.lock().add_deadline(deadline);
+            Some(deadline)
+        }
+        None => None,
+    };
+
+    let mut table = PollTable::default();
+    loop {
+        set_current_blocking(true);
+
+        let ready = scan(&mut table);
+        let result = if ready > 0 {
+            Some(Ok(ready))
+        } else if has_pending_signal() {
+            Some(Err(EINTR))
+        } else if deadline_passed(deadline) {
+            Some(Ok(0))
+        } else {
+            None
+        };
+
+        if let Some(result) = result {
+            table.clear();
+            set_current_blocking(false);
+            return result;
+        }
+
+        crate::syscall::op::sys_yield();
+        table.clear();
+    }
+}
+
+/// Readiness of the file open as `fd`, limited to `events` and the ones
+/// that are always reported.
+pub fn poll_fd(fd: usize, events: PollEvents, table: &mut PollTable) -> PollEvents {
+    match get_inode_by_fd(fd) {
+        Some(inode) => inode.read().poll(fd, table) & (events | ALWAYS_REPORTED),
+        None => PollEvents::NVAL,
+    }
+}
+
+/// `struct pollfd`.
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+pub fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> Result<usize, usize> {
+    poll_wait(timeout, |table| {
+        let mut ready = 0;
+        for pollfd in fds.iter_mut() {
+            pollfd.revents = 0;
+            if pollfd.fd < 0 {
+                continue;
+            }
+
+            let events = PollEvents::from_bits_truncate(pollfd.events as u16 as u32);
+            let revents = poll_fd(pollfd.fd as usize, events, table);
+            pollfd.revents = revents.bits() as i16;
+            if !revents.is_empty() {
+                ready += 1;
+            }
+        }
+        ready
+    })
+}
+
+/// What makes an fd count as ready in the read, write and except sets.
+const SELECT_EVENTS: [PollEvents; 3] = [
+    PollEvents::IN.union(PollEvents::HUP).union(PollEvents::ERR),
+    PollEvents::OUT.union(PollEvents::ERR),
+    PollEvents::PRI,
+];
+
+fn is_set(set: &[u64], fd: usize) -> bool {
+    set.get(fd / 64)
+        .is_some_and(|word| word & (1 << (fd % 64)) != 0)
+}
+
+/// Waits on the read, write and except `fd_set`s, leaving only the ready
+/// fds set in them.
+pub fn select(
+    nfds: usize,
+    sets: &mut [Option<&mut [u64]>; 3],
+    timeout: Option<Duration>,
+) -> Result<usize, usize> {
+    let mut wanted = Vec::new();
+    for fd in 0..nfds {
+        let requested =
+            [0, 1, 2].map(|index| sets[index].as_deref().is_some_and(|set| is_set(set, fd)));
+        if requested.contains(&true) {
+            if get_inode_by_fd(fd).is_none() {
+                return Err(EBADF);
+            }
+            wanted.push((fd, requested));
+        }
+    }
+
+    let mut results = alloc::vec![[false; 3]; wanted.len()];
+    let ready = poll_wait(timeout, |table| {
+        let mut ready = 0;
+        for ((fd, requested), result) in wanted.iter().zip(results.iter_mut()) {
+            let revents = poll_fd(*fd, PollEvents::all(), table);
+            for index in 0..3 {
+                result[index] = requested[index] && revents.intersects(SELECT_EVENTS[index]);
+                ready += result[index] as usize;
+            }
+        }
+        ready
+    })?;
+
+    for set in sets.iter_mut().flatten() {
+        set.fill(0);
+    }
+    for ((fd, _), result) in wanted.iter().zip(results.iter()) {
+        for (set, &ready) in sets.iter_mut().zip(result.iter()) {
+            if let Some(set) = set
+                && ready
+            {
+                set[fd / 64] |= 1 << (fd % 64);
+            }
+        }
+    }
+
+    Ok(ready)
+}
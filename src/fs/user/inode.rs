@@ -0,0 +1,475 @@
+use core::any::Any;
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec};
+use spin::{Mutex, RwLock};
+
+use super::{UserRequest, UserResponse, UserServer, protocol::*};
+use crate::{
+    fs::{
+        USER_FS_MANAGER,
+        operation::{OpenFlags, OpenMode},
+        vfs::{
+            inode::{FileInfo, Inode, InodeRef, InodeTy, Metadata},
+            stat_struct::{FUSE_SUPER_MAGIC, StatVfs},
+        },
+    },
+    syscall::errno::{EIO, ENOSYS, EPERM, EXDEV},
+    task::process::ProcessId,
+};
+
+/// How much of a directory one `USER_READDIR` asks for.
+const READDIR_BUFFER_SIZE: usize = 4096;
+
+/// A file the server opened, released when the last descriptor for it is
+/// closed.
+struct Handle {
+    server: Arc<UserServer>,
+    id: u64,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let _ = self.server.post(UserRequest {
+            cmd: USER_CLOSE,
+            arg: self.id,
+            ..Default::default()
+        });
+    }
+}
+
+/// What the nodes of one mounted server have in common.
+#[derive(Default)]
+struct Tree {
+    /// The directory the root is mounted in, reached by `..`.
+    above: Mutex<Option<InodeRef>>,
+    /// Filesystems mounted over directories of the server, by path there.
+    mounts: Mutex<BTreeMap<String, InodeRef>>,
+}
+
+/// A file on a filesystem server, named by its path there. Opening it on a
+/// server with [`UserCaps::HANDLES`] gives a copy holding the handle.
+pub struct UserFS {
+    path: String,
+    pid: ProcessId,
+    /// The path on the server.
+    file: String,
+    ty: InodeTy,
+    tree: Arc<Tree>,
+    handle: Option<Handle>,
+}
+
+impl UserFS {
+    /// A single file of the server run by `pid`. Asks nothing of the
+    /// server, so the server itself may call it.
+    pub fn new(pid: ProcessId, file: &str, ty: InodeTy) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            pid,
+            file: String::from(file),
+            ty,
+            tree: Arc::new(Tree::default()),
+            handle: None,
+        }))
+    }
+
+    /// The root directory of the server run by `pid`, to be mounted.
+    pub fn root(pid: ProcessId) -> InodeRef {
+        Self::new(pid, "/", InodeTy::Dir)
+    }
+
+    /// Another node of the same server.
+    fn relative(&self, file: String, path: String, ty: InodeTy) -> Self {
+        Self {
+            path,
+            pid: self.pid,
+            file,
+            ty,
+            tree: self.tree.clone(),
+            handle: None,
+        }
+    }
+
+    fn server(&self) -> Result<Arc<UserServer>, usize> {
+        USER_FS_MANAGER.lock().get(&self.pid).cloned().ok_or(EIO)
+    }
+
+    /// The server, if it supports all of `caps`.
+    fn server_with(&self, caps: UserCaps) -> Result<Arc<UserServer>, usize> {
+        let server = self.server()?;
+        match server.caps().contains(caps) {
+            true => Ok(server),
+            false => Err(EPERM),
+        }
+    }
+
+    fn handle(&self) -> u64 {
+        self.handle.as_ref().map_or(NO_HANDLE, |handle| handle.id)
+    }
+
+    /// The path of the entry `name` in this directory.
+    fn child(&self, name: &str) -> String {
+        match self.file.ends_with('/') {
+            true => self.file.clone() + name,
+            false => self.file.clone() + "/" + name,
+        }
+    }
+
+    /// The directory above, which for the root is the one it is mounted in.
+    fn parent(&self) -> Option<InodeRef> {
+        let Some((file, _)) = self.file.trim_end_matches('/').rsplit_once('/') else {
+            return self.tree.above.lock().clone();
+        };
+        let file = match file.is_empty() {
+            true => String::from("/"),
+            false => String::from(file),
+        };
+        let path = match self.path.trim_end_matches('/').rsplit_once('/') {
+            Some((path, _)) => String::from(path) + "/",
+            None => String::new(),
+        };
+        let node = self.relative(file, path, InodeTy::Dir);
+        Some(Arc::new(RwLock::new(node)))
+    }
+
+    fn call(&self, request: UserRequest) -> Result<UserResponse, usize> {
+        self.server()?.call(at(request, &self.file))
+    }
+
+    /// Stats `file`, with this node's handle if it is this node's file.
+    fn stat(&self, file: &str) -> Result<UserStat, usize> {
+        let server = self.server()?;
+        if !server.caps().contains(UserCaps::STAT) {
+            return Err(ENOSYS);
+        }
+
+        let mut stat = UserStat::default();
+        let request = UserRequest {
+            cmd: USER_STAT,
+            buf_addr: stat.as_mut_ptr() as u64,
+            buf_size: stat.len() as u64,
+            arg: match file == self.file {
+                true => self.handle(),
+                false => NO_HANDLE,
+            },
+            ..Default::default()
+        };
+        result(server.call(at(request, file)))?;
+        Ok(stat)
+    }
+
+    /// Reads the directory with `USER_READDIR`, one buffer at a time.
+    fn read_dir(&self, server: &UserServer) -> Vec<FileInfo> {
+        let mut entries = Vec::new();
+        let mut buffer = vec![0u8; READDIR_BUFFER_SIZE];
+        let mut cookie = 0;
+        loop {
+            let request = UserRequest {
+                cmd: USER_READDIR,
+                offset: cookie,
+                buf_addr: buffer.as_mut_ptr() as u64,
+                buf_size: buffer.len() as u64,
+                arg: self.handle(),
+                ..Default::default()
+            };
+            let len = match result(server.call(at(request, &self.file))) {
+                Ok(len) if len > 0 => len.min(buffer.len()),
+                _ => break,
+            };
+            match UserDirent::parse(&buffer[..len], &mut entries) {
+                Some(next) => cookie = next,
+                None => break,
+            }
+        }
+        entries
+    }
+
+    /// Reads the raw `Vec<String>` a version 0 server answers `USER_LIST`
+    /// with. Every entry is reported as a regular file.
+    fn list_names(&self) -> Vec<FileInfo> {
+        let Ok(response) = self.call(request(USER_LIST, 0, 0, 0)) else {
+            return Vec::new();
+        };
+        if response.ret_val <= 0 {
+            return Vec::new();
+        }
+
+        let vec = unsafe {
+            Vec::from_raw_parts(
+                response.ret_val as usize as *mut String,
+                response.ret_val2 as usize,
+                response.ret_val3 as usize,
+            )
+        };
+        vec.into_iter()
+            .map(|name| FileInfo {
+                name,
+                ty: InodeTy::File,
+            })
+            .collect()
+    }
+}
+
+fn request(cmd: u64, offset: usize, buf_addr: usize, buf_size: usize) -> UserRequest {
+    UserRequest {
+        cmd,
+        offset: offset as u64,
+        buf_addr: buf_addr as u64,
+        buf_size: buf_size as u64,
+        ..Default::default()
+    }
+}
+
+/// `request` about the file at `path` on the server.
+fn at(mut request: UserRequest, path: &str) -> UserRequest {
+    request.path_addr = path.as_ptr() as u64;
+    request.path_len = path.len() as u64;
+    request
+}
+
+/// A server's reply as a result, with negative values taken as errnos.
+fn result(response: Result<UserResponse, usize>) -> Result<usize, usize> {
+    match response?.ret_val {
+        err if err < 0 => Err(err.unsigned_abs() as usize),
+        ret_val => Ok(ret_val as usize),
+    }
+}
+
+/// A syscall return value from a server's reply.
+fn ret_val(response: Result<UserResponse, usize>) -> usize {
+    match response {
+        Ok(response) => response.ret_val as usize,
+        Err(err) => err.wrapping_neg(),
+    }
+}
+
+impl Inode for UserFS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        *self.tree.above.lock() = father;
+    }
+
+    fn when_umounted(&mut self) {
+        self.tree.above.lock().take();
+        self.tree.mounts.lock().clear();
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// Unmounting puts back the node that was covered, which is just the
+    /// server's own directory again.
+    fn mount(&self, node: InodeRef, name: String) {
+        let file = self.child(&name);
+        let covered = (&*node.read() as &dyn Any)
+            .downcast_ref::<UserFS>()
+            .is_some_and(|node| node.pid == self.pid && node.file == file);
+
+        let mut mounts = self.tree.mounts.lock();
+        match covered {
+            true => mounts.remove(&file),
+            false => mounts.insert(file, node),
+        };
+    }
+
+    /// Entries are looked up with `USER_STAT`. Servers that can't stat are
+    /// taken to have every path, each a directory, as every file looked
+    /// before the protocol had types.
+    fn open(&self, name: String) -> Option<InodeRef> {
+        let node = match name.as_str() {
+            "." => self.relative(self.file.clone(), self.path.clone(), self.ty),
+            ".." => return self.parent(),
+            _ => {
+                let file = self.child(&name);
+                if let Some(node) = self.tree.mounts.lock().get(&file) {
+                    return Some(node.clone());
+                }
+                let ty = match self.stat(&file) {
+                    Ok(stat) => stat.inode_type(),
+                    Err(ENOSYS) => InodeTy::Dir,
+                    Err(_) => return None,
+                };
+                self.relative(file, self.path.clone() + &name + "/", ty)
+            }
+        };
+        Some(Arc::new(RwLock::new(node)))
+    }
+
+    fn create(&self, name: String, ty: InodeTy) -> Option<InodeRef> {
+        let (cmd, caps, mode) = match ty {
+            InodeTy::File => (USER_CREATE, UserCaps::CREATE, 0o644),
+            InodeTy::Dir => (USER_MKDIR, UserCaps::MKDIR, 0o755),
+            _ => return None,
+        };
+        let server = self.server_with(caps).ok()?;
+
+        let file = self.child(&name);
+        let request = UserRequest {
+            cmd,
+            arg: mode,
+            ..Default::default()
+        };
+        result(server.call(at(request, &file))).ok()?;
+
+        let node = self.relative(file, self.path.clone() + &name + "/", ty);
+        Some(Arc::new(RwLock::new(node)))
+    }
+
+    fn unlink(&self, name: String, dir: bool) -> Result<(), usize> {
+        let server = self.server_with(UserCaps::UNLINK)?;
+        let request = UserRequest {
+            cmd: USER_UNLINK,
+            arg: dir as u64,
+            ..Default::default()
+        };
+        result(server.call(at(request, &self.child(&name)))).map(|_| ())
+    }
+
+    fn rename(&self, name: String, new_parent: &InodeRef, new_name: String) -> Result<(), usize> {
+        let new_parent = new_parent.read();
+        let new_parent = (&*new_parent as &dyn Any)
+            .downcast_ref::<UserFS>()
+            .filter(|parent| parent.pid == self.pid)
+            .ok_or(EXDEV)?;
+        let server = self.server_with(UserCaps::RENAME)?;
+
+        let to = new_parent.child(&new_name);
+        let request = request(USER_RENAME, 0, to.as_ptr() as usize, to.len());
+        result(server.call(at(request, &self.child(&name)))).map(|_| ())
+    }
+
+    fn truncate(&self, _fd: usize, len: usize) -> Result<(), usize> {
+        let server = self.server_with(UserCaps::TRUNCATE)?;
+        let request = UserRequest {
+            cmd: USER_TRUNCATE,
+            offset: len as u64,
+            arg: self.handle(),
+            ..Default::default()
+        };
+        result(server.call(at(request, &self.file))).map(|_| ())
+    }
+
+    /// Opens the file on the server, giving a node that holds the handle.
+    /// Version 0 servers are only told about the open.
+    fn on_open(&self, mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        let server = self.server()?;
+        if !server.caps().contains(UserCaps::HANDLES) {
+            let _ = server.call(request(
+                USER_OPEN,
+                0,
+                self.file.as_ptr() as usize,
+                self.file.len(),
+            ));
+            return Ok(None);
+        }
+
+        let request = UserRequest {
+            cmd: USER_OPEN,
+            arg: mode as u64 | flags.bits() as u64,
+            ..Default::default()
+        };
+        let id = result(server.call(at(request, &self.file)))? as u64;
+
+        let mut node = self.relative(self.file.clone(), self.path.clone(), self.ty);
+        node.handle = Some(Handle { server, id });
+        Ok(Some(Arc::new(RwLock::new(node))))
+    }
+
+    /// The server writes straight into the caller's buffer, lent to it for
+    /// the length of the request.
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let response = self.server().and_then(|server| {
+            let grant = server.lend(buf.as_mut_ptr() as usize, buf.len(), true)?;
+            let mut request = request(USER_READ, offset, grant.addr(), buf.len());
+            request.arg = self.handle();
+            server.call(at(request, &self.file))
+        });
+        ret_val(response)
+    }
+
+    /// Like [`UserFS::read_at`], with the buffer lent read-only.
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let response = self.server().and_then(|server| {
+            let grant = server.lend(buf.as_ptr() as usize, buf.len(), false)?;
+            let mut request = request(USER_WRITE, offset, grant.addr(), buf.len());
+            request.arg = self.handle();
+            server.call(at(request, &self.file))
+        });
+        ret_val(response)
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        let mut request = request(USER_SIZE, 0, 0, 0);
+        request.arg = self.handle();
+        ret_val(self.call(request))
+    }
+
+    /// The server fills in a `struct statfs`; fields it leaves zero are
+    /// completed from the mount table as for any other filesystem.
+    fn statfs(&self) -> Option<StatVfs> {
+        let mut stat = StatVfs::default();
+        let response = self
+            .server()
+            .and_then(|server| {
+                server.call(request(
+                    USER_STATFS,
+                    0,
+                    stat.as_mut_ptr() as usize,
+                    stat.len(),
+                ))
+            })
+            .ok()?;
+
+        if response.ret_val < 0 {
+            return None;
+        }
+        if stat.f_type == 0 {
+            stat.f_type = FUSE_SUPER_MAGIC;
+        }
+        Some(stat)
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        match self.server_with(UserCaps::READDIR) {
+            Ok(server) => self.read_dir(&server),
+            Err(_) => self.list_names(),
+        }
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        self.ty
+    }
+
+    /// What the server reports, or what is known without asking for servers
+    /// that can't stat.
+    fn metadata(&self, fd: usize) -> Metadata {
+        if let Ok(stat) = self.stat(&self.file) {
+            return stat.into();
+        }
+
+        let size = self.size(fd) as u64;
+        Metadata {
+            ino: self as *const Self as usize as u64,
+            mode: self.ty.mode_bits() | self.mode() as u32,
+            nlink: 1,
+            size,
+            blksize: 4096,
+            blocks: size.div_ceil(512),
+            ..Default::default()
+        }
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        let buffer = [cmd, arg];
+        let mut request = request(USER_IOCTL, 0, buffer.as_ptr() as usize, buffer.len());
+        request.arg = self.handle();
+        ret_val(self.call(request))
+    }
+}
@@ -0,0 +1,374 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+};
+use spin::{Mutex, RwLock};
+use x86_64::{VirtAddr, structures::paging::PhysFrame};
+
+use crate::{
+    memory::{DmaManager, Grant, GrantWindow, MappingType, MemoryManager},
+    service,
+    syscall::errno::{EEXIST, EINVAL, EIO, ENODEV},
+    task::{
+        get_current_process,
+        process::{Process, ProcessId},
+        wait_queue::WaitQueue,
+    },
+};
+
+use super::{
+    USER_FS_MANAGER,
+    mount::{self, MountFlags},
+    vfs::inode::InodeRef,
+};
+use protocol::{InitInfo, USER_INIT, USERFS_VERSION, UserCaps};
+
+mod inode;
+pub mod protocol;
+
+pub use inode::UserFS;
+
+/// Slots in each direction of a ring.
+pub const RING_ENTRIES: usize = 64;
+/// What a server must leave unmapped at the address it registers with.
+pub const RING_SIZE: usize = size_of::<UserRing>().next_multiple_of(DmaManager::UNIT_SIZE);
+
+/// `SYS_USERFS_WAIT` flag: sleep until there is a request to serve.
+pub const USERFS_WAIT_BLOCK: usize = 1;
+
+/// A command for a server. Read and write buffers are the client's own
+/// pages, lent into the server; other buffers and the path live in kernel
+/// memory the server can reach.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserRequest {
+    /// Echoed back in the response.
+    pub id: u64,
+    pub cmd: u64,
+    pub offset: u64,
+    pub buf_addr: u64,
+    pub buf_size: u64,
+    /// The file the command is about.
+    pub path_addr: u64,
+    pub path_len: u64,
+    pub arg: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserResponse {
+    pub id: u64,
+    pub ret_val: i64,
+    pub ret_val2: i64,
+    pub ret_val3: i64,
+}
+
+/// The page(s) shared between the kernel and a server. The kernel produces
+/// requests and the server responses; each side only moves its own head and
+/// the other side's tail. Indices run freely and are taken modulo
+/// [`RING_ENTRIES`].
+#[repr(C)]
+pub struct UserRing {
+    pub request_head: AtomicU32,
+    pub request_tail: AtomicU32,
+    pub response_head: AtomicU32,
+    pub response_tail: AtomicU32,
+    pub requests: [UserRequest; RING_ENTRIES],
+    pub responses: [UserResponse; RING_ENTRIES],
+}
+
+struct ServerState {
+    /// The server exited; its ring pages are gone with its address space.
+    dead: bool,
+    next_id: u64,
+    /// What the server agreed to in `USER_INIT`, once it was asked.
+    caps: Option<UserCaps>,
+    /// Requests in flight, and their response once it arrived.
+    pending: BTreeMap<u64, Option<UserResponse>>,
+}
+
+/// The kernel's end of a filesystem server's ring.
+pub struct UserServer {
+    pid: ProcessId,
+    process: Weak<RwLock<Process>>,
+    ring: usize,
+    /// Where client buffers are lent into the server.
+    grants: Arc<Mutex<GrantWindow>>,
+    state: Mutex<ServerState>,
+    /// The server sleeps here until there are requests.
+    requests: WaitQueue,
+    /// Clients sleep here until their response arrives or the ring has room.
+    responses: WaitQueue,
+}
+
+impl UserServer {
+    /// Must only be used while the server is alive, under `state`.
+    fn ring(&self) -> &UserRing {
+        unsafe { &*(self.ring as *const UserRing) }
+    }
+
+    /// Queues `request`, returning its id, or `None` if the ring is full.
+    /// The response is kept for [`UserServer::call`] only if `reply` is set.
+    fn submit(&self, mut request: UserRequest, reply: bool) -> Result<Option<u64>, usize> {
+        let mut state = self.state.lock();
+        if state.dead {
+            return Err(EIO);
+        }
+
+        let ring = self.ring();
+        let head = ring.request_head.load(Ordering::Relaxed);
+        let tail = ring.request_tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) as usize >= RING_ENTRIES {
+            return Ok(None);
+        }
+
+        request.id = state.next_id;
+        state.next_id += 1;
+        if reply {
+            state.pending.insert(request.id, None);
+        }
+
+        let slot = &ring.requests[head as usize % RING_ENTRIES];
+        unsafe { (slot as *const UserRequest as *mut UserRequest).write_volatile(request) };
+        ring.request_head
+            .store(head.wrapping_add(1), Ordering::Release);
+        drop(state);
+
+        self.requests.wake_all();
+        Ok(Some(request.id))
+    }
+
+    /// Queues `request`, waiting for room in the ring if need be.
+    fn send(&self, request: UserRequest, reply: bool) -> Result<u64, usize> {
+        let mut error = None;
+        let mut id = None;
+        self.responses
+            .wait_until(|| match self.submit(request, reply) {
+                Ok(Some(submitted)) => {
+                    id = Some(submitted);
+                    Some(0)
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    error = Some(err);
+                    Some(0)
+                }
+            });
+        match error {
+            Some(err) => Err(err),
+            None => Ok(id.unwrap()),
+        }
+    }
+
+    /// Sends `request` without waiting for the response. Only for requests
+    /// that point at no buffers.
+    fn post(&self, request: UserRequest) -> Result<(), usize> {
+        self.send(request, false).map(|_| ())
+    }
+
+    /// Sends `request` and waits for the response. The wait can't be
+    /// interrupted, since the server may still be using buffers the request
+    /// points at.
+    fn call(&self, request: UserRequest) -> Result<UserResponse, usize> {
+        let id = self.send(request, true)?;
+
+        let mut response = Err(EIO);
+        self.responses.wait_until(|| {
+            let mut state = self.state.lock();
+            if let Some(Some(reply)) = state.pending.get(&id) {
+                response = Ok(*reply);
+                state.pending.remove(&id);
+                return Some(0);
+            }
+            if state.dead {
+                state.pending.remove(&id);
+                return Some(0);
+            }
+            None
+        });
+        response
+    }
+
+    /// Collects the responses the server has posted and wakes their clients.
+    fn harvest(&self) {
+        let mut state = self.state.lock();
+        if state.dead {
+            return;
+        }
+
+        let ring = self.ring();
+        let head = ring.response_head.load(Ordering::Acquire);
+        let mut tail = ring.response_tail.load(Ordering::Relaxed);
+        while tail != head {
+            let slot = &ring.responses[tail as usize % RING_ENTRIES];
+            let response = unsafe { (slot as *const UserResponse).read_volatile() };
+            // Ignore ids nobody is waiting for.
+            if let Some(entry) = state.pending.get_mut(&response.id) {
+                *entry = Some(response);
+            }
+            tail = tail.wrapping_add(1);
+        }
+        ring.response_tail.store(tail, Ordering::Release);
+        drop(state);
+
+        // Also wakes clients waiting for the server to make room.
+        self.responses.wake_all();
+    }
+
+    /// What the server supports, asked for with `USER_INIT` the first time.
+    /// Servers from before the handshake support none of it.
+    fn caps(&self) -> UserCaps {
+        if let Some(caps) = self.state.lock().caps {
+            return caps;
+        }
+
+        let info = InitInfo::new(USERFS_VERSION, UserCaps::all());
+        let response = self.call(UserRequest {
+            cmd: USER_INIT,
+            buf_addr: info.as_ptr() as u64,
+            buf_size: info.len() as u64,
+            ..Default::default()
+        });
+        let caps = match response {
+            Ok(response) if response.ret_val > 0 => {
+                UserCaps::from_bits_truncate(response.ret_val2 as u64)
+            }
+            Ok(_) => UserCaps::empty(),
+            Err(_) => return UserCaps::empty(),
+        };
+        self.state.lock().caps = Some(caps);
+        caps
+    }
+
+    /// Maps `len` bytes of the caller's memory at `addr` into the server.
+    fn lend(&self, addr: usize, len: usize, writable: bool) -> Result<Grant, usize> {
+        let process = self.process.upgrade().ok_or(EIO)?;
+        Grant::new(process, self.grants.clone(), addr, len, writable)
+    }
+
+    fn queued_requests(&self) -> Option<usize> {
+        let state = self.state.lock();
+        if state.dead {
+            return None;
+        }
+        let ring = self.ring();
+        let head = ring.request_head.load(Ordering::Acquire);
+        let tail = ring.request_tail.load(Ordering::Acquire);
+        Some(head.wrapping_sub(tail) as usize)
+    }
+}
+
+/// Sets up a ring for the calling process and maps it at `ring_addr`.
+pub fn register_server(ring_addr: usize) -> Result<Arc<UserServer>, usize> {
+    if ring_addr == 0 || !ring_addr.is_multiple_of(DmaManager::UNIT_SIZE) {
+        return Err(EINVAL);
+    }
+
+    let process = get_current_process();
+    let pid = process.read().id;
+    // A server registering several names shares one ring.
+    if let Some(server) = USER_FS_MANAGER.lock().get(&pid) {
+        return Ok(server.clone());
+    }
+
+    let (physical, virtual_address) = DmaManager::allocate(RING_SIZE);
+    unsafe { core::ptr::write_bytes(virtual_address.as_mut_ptr::<u8>(), 0, RING_SIZE) };
+    MemoryManager::map_range_to(
+        VirtAddr::new(ring_addr as u64),
+        PhysFrame::containing_address(physical),
+        RING_SIZE as u64,
+        MappingType::UserData.flags(),
+        &mut process.write().page_table,
+    )
+    .map_err(|_| EINVAL)?;
+
+    let server = Arc::new(UserServer {
+        pid,
+        process: Arc::downgrade(&process),
+        ring: virtual_address.as_u64() as usize,
+        grants: Arc::new(Mutex::new(GrantWindow::new())),
+        state: Mutex::new(ServerState {
+            dead: false,
+            next_id: 1,
+            caps: None,
+            pending: BTreeMap::new(),
+        }),
+        requests: WaitQueue::new(),
+        responses: WaitQueue::new(),
+    });
+    USER_FS_MANAGER.lock().insert(pid, server.clone());
+    Ok(server)
+}
+
+/// Publishes the calling server as `name` in the name service, the source
+/// to mount it from. A name that is an absolute path is also mounted there
+/// right away.
+pub fn register_name(name: &str) -> Result<(), usize> {
+    let pid = get_current_process().read().id;
+    if !USER_FS_MANAGER.lock().contains_key(&pid) {
+        return Err(EINVAL);
+    }
+
+    match service::register(name, None) {
+        Ok(()) => {}
+        Err(EEXIST) if service::provider(name) == Some(pid) => return Ok(()),
+        Err(err) => return Err(err),
+    }
+
+    if name.starts_with('/')
+        && let Err(err) = mount::mount(name, name, "userfs", MountFlags::empty())
+    {
+        let _ = service::unregister(name);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Gives the root of the server registered as `source`, for `mount`.
+pub fn mount(source: &str) -> Result<InodeRef, usize> {
+    let pid = service::provider(source).ok_or(ENODEV)?;
+    if !USER_FS_MANAGER.lock().contains_key(&pid) {
+        return Err(ENODEV);
+    }
+    Ok(UserFS::root(pid))
+}
+
+/// Picks up the calling server's responses and, with
+/// [`USERFS_WAIT_BLOCK`], sleeps until there are requests. Returns how many
+/// requests are queued.
+pub fn server_wait(flags: usize) -> Result<usize, usize> {
+    let pid = get_current_process().read().id;
+    let server = USER_FS_MANAGER.lock().get(&pid).cloned().ok_or(EINVAL)?;
+    server.harvest();
+
+    if flags & USERFS_WAIT_BLOCK == 0 {
+        return server.queued_requests().ok_or(EIO);
+    }
+    let queued = server
+        .requests
+        .wait_interruptible(|| server.queued_requests().filter(|&queued| queued > 0));
+    match queued {
+        err if err > isize::MAX as usize => Err(err.wrapping_neg()),
+        queued => Ok(queued),
+    }
+}
+
+/// Fails every request still waiting on a server that exited, and takes
+/// down the mounts of `names`, which it was registered as.
+pub fn server_exited(pid: ProcessId, names: &[String]) {
+    let Some(server) = USER_FS_MANAGER.lock().remove(&pid) else {
+        return;
+    };
+    let mut state = server.state.lock();
+    state.dead = true;
+    drop(state);
+    server.responses.wake_all();
+
+    for name in names {
+        mount::umount_source("userfs", name);
+    }
+    log::warn!("User filesystem server {} exited", server.pid.0);
+}
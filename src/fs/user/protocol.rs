@@ -0,0 +1,225 @@
+use core::{
+    mem,
+    ops::{Deref, DerefMut},
+    slice,
+    time::Duration,
+};
+
+use alloc::{string::String, vec::Vec};
+use bitflags::bitflags;
+
+use crate::fs::vfs::{
+    inode::{FileInfo, InodeTy, Metadata},
+    stat_struct::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT},
+};
+
+// Commands every server understands. `USER_READ`, `USER_WRITE`, `USER_SIZE`
+// and `USER_IOCTL` name the file by path; servers with
+// [`UserCaps::HANDLES`] also find the handle in `arg`, or [`NO_HANDLE`].
+pub const USER_READ: u64 = 1;
+pub const USER_WRITE: u64 = 2;
+/// Without [`UserCaps::HANDLES`] the path is in the buffer and the reply is
+/// ignored. With it, the path is in the path fields, `arg` holds the
+/// `O_*` flags and the reply is the new handle.
+pub const USER_OPEN: u64 = 3;
+pub const USER_SIZE: u64 = 4;
+/// Replies with a `Vec<String>` allocated on the kernel heap, as raw parts.
+pub const USER_LIST: u64 = 5;
+/// The buffer holds the command and its argument.
+pub const USER_IOCTL: u64 = 6;
+/// The buffer is a `struct statfs` to fill in.
+pub const USER_STATFS: u64 = 7;
+
+// Version 1.
+/// Sent before anything else. The buffer is an [`InitInfo`] with the
+/// kernel's version and capabilities; the reply is the server's version in
+/// `ret_val` and its capabilities in `ret_val2`. Servers that fail it or
+/// answer 0 are treated as version 0.
+pub const USER_INIT: u64 = 8;
+/// `arg` is the handle to release. Nobody waits for the reply.
+pub const USER_CLOSE: u64 = 9;
+/// The buffer is a [`UserStat`] to fill in; `arg` is a handle or
+/// [`NO_HANDLE`].
+pub const USER_STAT: u64 = 10;
+/// Creates the regular file at the path; `arg` holds the permission bits.
+pub const USER_CREATE: u64 = 11;
+/// Creates the directory at the path; `arg` holds the permission bits.
+pub const USER_MKDIR: u64 = 12;
+/// Removes the path, a directory if `arg` is 1.
+pub const USER_UNLINK: u64 = 13;
+/// Moves the path to the one in the buffer.
+pub const USER_RENAME: u64 = 14;
+/// Sets the size of the path or handle in `arg` to `offset`.
+pub const USER_TRUNCATE: u64 = 15;
+/// Fills the buffer with [`UserDirent`] records for the directory at the
+/// path, from the entry whose cookie is `offset` on; 0 is the first. The
+/// reply is the number of bytes used, 0 once there are no more.
+pub const USER_READDIR: u64 = 16;
+
+/// The newest version of the protocol the kernel speaks.
+pub const USERFS_VERSION: u32 = 1;
+
+/// `arg` for commands that accept a handle when the file is not open.
+pub const NO_HANDLE: u64 = u64::MAX;
+
+bitflags! {
+    /// What a version 1 server supports beyond the version 0 commands.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UserCaps: u64 {
+        /// `USER_OPEN` returns handles, released with `USER_CLOSE`.
+        const HANDLES = 1 << 0;
+        const STAT = 1 << 1;
+        const CREATE = 1 << 2;
+        const MKDIR = 1 << 3;
+        const UNLINK = 1 << 4;
+        const RENAME = 1 << 5;
+        const TRUNCATE = 1 << 6;
+        const READDIR = 1 << 7;
+    }
+}
+
+/// How a `USER_LIST` reply's `ret_val`s describe the vector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetVecStruct {
+    pub addr: usize,
+    pub len: usize,
+    pub cap: usize,
+}
+
+/// What the kernel offers in `USER_INIT`.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct InitInfo {
+    pub version: u32,
+    __pad0: u32,
+    pub caps: u64,
+}
+
+impl InitInfo {
+    pub fn new(version: u32, caps: UserCaps) -> Self {
+        Self {
+            version,
+            caps: caps.bits(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A server's answer to `USER_STAT`. `mode` carries the file type bits as
+/// well as the permissions; times are since the Unix epoch.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct UserStat {
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: u64,
+    pub atime_nsec: u64,
+    pub mtime: u64,
+    pub mtime_nsec: u64,
+    pub ctime: u64,
+    pub ctime_nsec: u64,
+}
+
+const _: () = assert!(mem::size_of::<UserStat>() == 104);
+
+impl UserStat {
+    pub fn inode_type(&self) -> InodeTy {
+        inode_type(self.mode)
+    }
+}
+
+impl From<UserStat> for Metadata {
+    fn from(stat: UserStat) -> Self {
+        Self {
+            ino: stat.ino,
+            mode: stat.mode,
+            nlink: stat.nlink as u64,
+            uid: stat.uid,
+            gid: stat.gid,
+            rdev: stat.rdev,
+            size: stat.size,
+            blksize: stat.blksize,
+            blocks: stat.blocks,
+            atime: Duration::new(stat.atime, stat.atime_nsec as u32),
+            mtime: Duration::new(stat.mtime, stat.mtime_nsec as u32),
+            ctime: Duration::new(stat.ctime, stat.ctime_nsec as u32),
+            ..Default::default()
+        }
+    }
+}
+
+/// The header of a `USER_READDIR` record. The name follows it, and the
+/// record is padded to a multiple of 8 bytes.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct UserDirent {
+    pub ino: u64,
+    /// The cookie to continue after this entry with.
+    pub next: u64,
+    /// The `S_IF*` bits of the entry's type.
+    pub ty: u32,
+    pub name_len: u32,
+}
+
+const _: () = assert!(mem::size_of::<UserDirent>() == 24);
+
+impl UserDirent {
+    /// Decodes the records in `buf`, appending them to `entries` and
+    /// returning the cookie after the last one.
+    pub fn parse(mut buf: &[u8], entries: &mut Vec<FileInfo>) -> Option<u64> {
+        let mut next = None;
+        while buf.len() >= mem::size_of::<Self>() {
+            let mut header = Self::default();
+            header.copy_from_slice(&buf[..mem::size_of::<Self>()]);
+            let name_end = mem::size_of::<Self>() + header.name_len as usize;
+            let name = buf.get(mem::size_of::<Self>()..name_end)?;
+
+            entries.push(FileInfo::new(
+                String::from_utf8_lossy(name).into_owned(),
+                inode_type(header.ty),
+            ));
+            next = Some(header.next);
+            buf = buf.get(name_end.next_multiple_of(8)..).unwrap_or_default();
+        }
+        next
+    }
+}
+
+fn inode_type(mode: u32) -> InodeTy {
+    match mode & S_IFMT {
+        S_IFDIR => InodeTy::Dir,
+        S_IFLNK => InodeTy::Symlink,
+        S_IFBLK => InodeTy::BlockDevice,
+        S_IFIFO => InodeTy::Fifo,
+        S_IFCHR => InodeTy::CharDevice,
+        _ => InodeTy::File,
+    }
+}
+
+macro_rules! impl_as_bytes {
+    ($($ty:ty),*) => {$(
+        impl Deref for $ty {
+            type Target = [u8];
+            fn deref(&self) -> &[u8] {
+                let len = mem::size_of::<$ty>();
+                unsafe { slice::from_raw_parts(self as *const $ty as *const u8, len) }
+            }
+        }
+
+        impl DerefMut for $ty {
+            fn deref_mut(&mut self) -> &mut [u8] {
+                let len = mem::size_of::<$ty>();
+                unsafe { slice::from_raw_parts_mut(self as *mut $ty as *mut u8, len) }
+            }
+        }
+    )*};
+}
+
+impl_as_bytes!(RetVecStruct, InitInfo, UserStat, UserDirent);
@@ -0,0 +1,80 @@
+use alloc::{string::String, sync::Arc};
+use spin::RwLock;
+
+use crate::block::{BUFFER_CACHE, BlockDeviceRef};
+
+use super::inode::{Inode, InodeRef, InodeTy};
+
+const BLKSSZGET: usize = 0x1268;
+const BLKFLSBUF: usize = 0x1261;
+const BLKGETSIZE64: usize = 0x80081272;
+
+pub struct BlockFS {
+    path: String,
+    device: BlockDeviceRef,
+}
+
+impl BlockFS {
+    pub fn new(device: BlockDeviceRef) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            device,
+        }))
+    }
+
+    pub fn device(&self) -> BlockDeviceRef {
+        self.device.clone()
+    }
+}
+
+impl Inode for BlockFS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {
+        self.flush();
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        self.device.size() as usize
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        BUFFER_CACHE
+            .read(&self.device, offset as u64, buf)
+            .unwrap_or(0)
+    }
+
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        BUFFER_CACHE
+            .write(&self.device, offset as u64, buf)
+            .unwrap_or(0)
+    }
+
+    fn flush(&self) {
+        if let Err(err) = BUFFER_CACHE.sync(&self.device) {
+            log::warn!("Failed to sync {}: {:?}", self.path, err);
+        }
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        match cmd {
+            BLKSSZGET => unsafe { *(arg as *mut u32) = self.device.sector_size() as u32 },
+            BLKGETSIZE64 => unsafe { *(arg as *mut u64) = self.device.size() },
+            BLKFLSBUF => self.flush(),
+            _ => return usize::MAX,
+        }
+
+        0
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::BlockDevice
+    }
+}
@@ -0,0 +1,501 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use spin::{Mutex, RwLock};
+
+use super::inode::{Inode, InodeRef, InodeTy};
+use crate::{
+    fs::{
+        operation::{self, OpenFlags, OpenMode},
+        poll::{PollEvents, PollTable},
+    },
+    memory::{Transfer, check_unmapped},
+    syscall::errno::{EAGAIN, EBADF, EFAULT, EMSGSIZE, ENOENT, EPIPE},
+    task::wait_queue::WaitQueue,
+};
+
+/// The most data bytes one message carries.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+pub const MAX_MESSAGE_FDS: usize = 16;
+pub const MAX_MESSAGE_REGIONS: usize = 16;
+/// Messages queued in each direction before senders wait.
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// Flag for sending and receiving: fail with `EAGAIN` instead of waiting.
+pub const CHANNEL_NONBLOCK: usize = 1;
+
+/// A message as user space describes it. When sending, the fields describe
+/// what to send; fds stay open in the sender, while regions are unmapped
+/// from it. When receiving, they describe the room there is, `map_addr`
+/// and `map_len` giving unmapped space for the regions, and are rewritten
+/// with what arrived. A message that does not fit is left queued, and the
+/// room it needs is written back instead.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelMessage {
+    /// Non-zero in calls, to be quoted in the reply.
+    pub txid: u64,
+    pub data: u64,
+    pub data_len: u64,
+    /// An array of fds.
+    pub fds: u64,
+    pub fd_count: u64,
+    /// An array of [`MemoryRegion`]s.
+    pub regions: u64,
+    pub region_count: u64,
+    pub map_addr: u64,
+    pub map_len: u64,
+}
+
+/// Page-aligned memory moved along with a message.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryRegion {
+    pub addr: u64,
+    pub len: u64,
+}
+
+/// Borrows `len` items at `addr` in user memory.
+fn user_slice<'a, T>(addr: u64, len: usize) -> Result<&'a mut [T], usize> {
+    match (addr, len) {
+        (_, 0) => Ok(&mut []),
+        (0, _) => Err(EFAULT),
+        (addr, len) => Ok(unsafe { core::slice::from_raw_parts_mut(addr as *mut T, len) }),
+    }
+}
+
+/// A message in flight, owned by the kernel.
+struct Message {
+    txid: u64,
+    data: Vec<u8>,
+    files: Vec<(InodeRef, OpenMode, OpenFlags)>,
+    /// The regions with the addresses they were taken from.
+    regions: Vec<(usize, Transfer)>,
+}
+
+impl Message {
+    /// Collects what `header` describes from the current process.
+    fn gather(header: &ChannelMessage) -> Result<Self, usize> {
+        let (data_len, fd_count, region_count) = (
+            header.data_len as usize,
+            header.fd_count as usize,
+            header.region_count as usize,
+        );
+        if data_len > MAX_MESSAGE_SIZE
+            || fd_count > MAX_MESSAGE_FDS
+            || region_count > MAX_MESSAGE_REGIONS
+        {
+            return Err(EMSGSIZE);
+        }
+
+        let data = user_slice::<u8>(header.data, data_len)?.to_vec();
+        let files = user_slice::<u64>(header.fds, fd_count)?
+            .iter()
+            .map(|&fd| operation::get_file_by_fd(fd as usize).ok_or(EBADF))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Last, as taking regions is the only step with side effects.
+        let mut message = Self {
+            txid: 0,
+            data,
+            files,
+            regions: Vec::with_capacity(region_count),
+        };
+        for region in user_slice::<MemoryRegion>(header.regions, region_count)? {
+            let addr = region.addr as usize;
+            match Transfer::take(addr, region.len as usize) {
+                Ok(transfer) => message.regions.push((addr, transfer)),
+                Err(err) => {
+                    message.give_back();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(message)
+    }
+
+    /// Maps the regions back where they were taken from, after a failed
+    /// send.
+    fn give_back(self) {
+        for (addr, transfer) in self.regions {
+            let _ = transfer.place(addr);
+        }
+    }
+
+    fn map_len(&self) -> usize {
+        self.regions
+            .iter()
+            .map(|(_, transfer)| transfer.len())
+            .sum()
+    }
+
+    /// Checks the message fits the room in `header`, writing the room it
+    /// needs into `header` if not.
+    fn fits(&self, header: &mut ChannelMessage) -> Result<(), usize> {
+        let map_len = self.map_len();
+        if self.data.len() > header.data_len as usize
+            || self.files.len() > header.fd_count as usize
+            || self.regions.len() > header.region_count as usize
+            || map_len > header.map_len as usize
+        {
+            header.data_len = self.data.len() as u64;
+            header.fd_count = self.files.len() as u64;
+            header.region_count = self.regions.len() as u64;
+            header.map_len = map_len as u64;
+            return Err(EMSGSIZE);
+        }
+        Ok(())
+    }
+
+    /// Like [`Message::fits`], and also checks the space for the regions is
+    /// free in the current process.
+    fn check(&self, header: &mut ChannelMessage) -> Result<(), usize> {
+        self.fits(header)?;
+        match self.map_len() {
+            0 => Ok(()),
+            map_len => check_unmapped(header.map_addr as usize, map_len),
+        }
+    }
+
+    /// Hands the message to the current process, returning the data length.
+    fn deliver(self, header: &mut ChannelMessage) -> Result<usize, usize> {
+        self.check(header)?;
+
+        let data_len = self.data.len();
+        user_slice::<u8>(header.data, data_len)?.copy_from_slice(&self.data);
+
+        let fds = user_slice::<u64>(header.fds, self.files.len())?;
+        let regions = user_slice::<MemoryRegion>(header.regions, self.regions.len())?;
+        header.txid = self.txid;
+        header.data_len = data_len as u64;
+        header.fd_count = fds.len() as u64;
+        header.region_count = regions.len() as u64;
+
+        for (slot, (inode, mode, flags)) in fds.iter_mut().zip(self.files) {
+            *slot = operation::install(inode, mode, flags).ok_or(EBADF)? as u64;
+        }
+
+        let mut addr = header.map_addr as usize;
+        for (slot, (_, transfer)) in regions.iter_mut().zip(self.regions) {
+            let len = transfer.len();
+            transfer.place(addr)?;
+            *slot = MemoryRegion {
+                addr: addr as u64,
+                len: len as u64,
+            };
+            addr += len;
+        }
+        header.map_len = (addr - header.map_addr as usize) as u64;
+
+        Ok(data_len)
+    }
+}
+
+/// The messages travelling towards one end.
+struct Queue {
+    messages: Mutex<VecDeque<Message>>,
+    /// The end waiting for messages.
+    readers: Arc<WaitQueue>,
+    /// The other end waiting for room.
+    writers: Arc<WaitQueue>,
+}
+
+impl Queue {
+    fn new() -> Self {
+        Self {
+            messages: Mutex::new(VecDeque::new()),
+            readers: Arc::new(WaitQueue::new()),
+            writers: Arc::new(WaitQueue::new()),
+        }
+    }
+}
+
+/// A call waiting for its reply.
+struct Call {
+    /// The end that made the call.
+    caller: usize,
+    /// The room the caller has for the reply.
+    room: ChannelMessage,
+    reply: Option<Message>,
+}
+
+/// A bidirectional message channel between two ends, 0 and 1.
+pub struct Channel {
+    id: usize,
+    /// `queues[end]` holds the messages for `end` to receive.
+    queues: [Queue; 2],
+    open: [AtomicBool; 2],
+    next_txid: AtomicU64,
+    calls: Mutex<BTreeMap<u64, Call>>,
+    /// Callers wait here for their replies.
+    replies: WaitQueue,
+}
+
+impl Channel {
+    /// Creates a channel and returns its two ends.
+    pub fn pair() -> [InodeRef; 2] {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        let channel = Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            queues: [Queue::new(), Queue::new()],
+            open: [AtomicBool::new(true), AtomicBool::new(true)],
+            next_txid: AtomicU64::new(1),
+            calls: Mutex::new(BTreeMap::new()),
+            replies: WaitQueue::new(),
+        });
+
+        [0, 1].map(|end| -> InodeRef {
+            Arc::new(RwLock::new(ChannelFS {
+                path: alloc::format!("channel:[{}]", channel.id),
+                channel: channel.clone(),
+                end,
+            }))
+        })
+    }
+
+    fn peer_open(&self, end: usize) -> bool {
+        self.open[1 - end].load(Ordering::SeqCst)
+    }
+
+    fn close(&self, end: usize) {
+        self.open[end].store(false, Ordering::SeqCst);
+        // Nobody will receive or collect these any more.
+        let messages = core::mem::take(&mut *self.queues[end].messages.lock());
+        let mut calls = self.calls.lock();
+        let abandoned = calls
+            .extract_if(.., |_, call| call.caller == end)
+            .collect::<Vec<_>>();
+        drop(calls);
+        drop((messages, abandoned));
+
+        for queue in &self.queues {
+            queue.readers.wake_all();
+            queue.writers.wake_all();
+        }
+        self.replies.wake_all();
+    }
+
+    fn poll(&self, end: usize, table: &mut PollTable) -> PollEvents {
+        let (incoming, outgoing) = (&self.queues[end], &self.queues[1 - end]);
+        table.register(&incoming.readers);
+        table.register(&outgoing.writers);
+
+        let mut events = PollEvents::empty();
+        if !incoming.messages.lock().is_empty() {
+            events |= PollEvents::IN | PollEvents::RDNORM;
+        }
+        if outgoing.messages.lock().len() < CHANNEL_CAPACITY {
+            events |= PollEvents::OUT | PollEvents::WRNORM;
+        }
+        if !self.peer_open(end) {
+            events |= PollEvents::HUP;
+        }
+        events
+    }
+
+    /// Queues `message` for the other end of `end`.
+    fn push(&self, end: usize, message: Message, nonblock: bool) -> Result<(), usize> {
+        let queue = &self.queues[1 - end];
+        let mut message = Some(message);
+        let result = queue.writers.wait_interruptible(|| {
+            if !self.peer_open(end) {
+                return Some(EPIPE.wrapping_neg());
+            }
+            let mut messages = queue.messages.lock();
+            if messages.len() < CHANNEL_CAPACITY {
+                messages.extend(message.take());
+                Some(0)
+            } else if nonblock {
+                Some(EAGAIN.wrapping_neg())
+            } else {
+                None
+            }
+        });
+
+        if let Some(message) = message {
+            message.give_back();
+            return Err(result.wrapping_neg());
+        }
+        queue.readers.wake_all();
+        Ok(())
+    }
+
+    pub fn send(&self, end: usize, header: &ChannelMessage, nonblock: bool) -> Result<(), usize> {
+        self.push(end, Message::gather(header)?, nonblock)
+    }
+
+    /// Takes the next message for `end`, returning its data length.
+    pub fn receive(
+        &self,
+        end: usize,
+        header: &mut ChannelMessage,
+        nonblock: bool,
+    ) -> Result<usize, usize> {
+        let queue = &self.queues[end];
+        let mut received = None;
+        let result = queue.readers.wait_interruptible(|| {
+            let mut messages = queue.messages.lock();
+            let Some(message) = messages.front() else {
+                return if !self.peer_open(end) {
+                    Some(EPIPE.wrapping_neg())
+                } else if nonblock {
+                    Some(EAGAIN.wrapping_neg())
+                } else {
+                    None
+                };
+            };
+            if let Err(err) = message.check(header) {
+                return Some(err.wrapping_neg());
+            }
+            received = messages.pop_front();
+            Some(0)
+        });
+
+        let Some(message) = received else {
+            return Err(result.wrapping_neg());
+        };
+        queue.writers.wake_all();
+        message.deliver(header)
+    }
+
+    /// Sends `request` and waits for the reply to arrive in `reply`. The
+    /// reply skips the queue and goes straight to the caller.
+    pub fn call(
+        &self,
+        end: usize,
+        request: &ChannelMessage,
+        reply: &mut ChannelMessage,
+    ) -> Result<usize, usize> {
+        let mut message = Message::gather(request)?;
+        let txid = self.next_txid.fetch_add(1, Ordering::Relaxed);
+        message.txid = txid;
+        self.calls.lock().insert(
+            txid,
+            Call {
+                caller: end,
+                room: *reply,
+                reply: None,
+            },
+        );
+        if let Err(err) = self.push(end, message, false) {
+            self.calls.lock().remove(&txid);
+            return Err(err);
+        }
+
+        let mut answer = None;
+        let result = self.replies.wait_interruptible(|| {
+            let mut calls = self.calls.lock();
+            if let Some(call) = calls.get_mut(&txid)
+                && call.reply.is_some()
+            {
+                answer = call.reply.take();
+            } else if self.peer_open(end) {
+                return None;
+            }
+            calls.remove(&txid);
+            Some(0)
+        });
+
+        match answer {
+            Some(answer) => answer.deliver(reply),
+            None => {
+                let call = self.calls.lock().remove(&txid);
+                drop(call);
+                Err(match result {
+                    0 => EPIPE,
+                    err => err.wrapping_neg(),
+                })
+            }
+        }
+    }
+
+    /// Answers the call `header.txid` made from the other end.
+    pub fn reply(&self, end: usize, header: &ChannelMessage) -> Result<(), usize> {
+        let mut message = Message::gather(header)?;
+        message.txid = header.txid;
+
+        let mut calls = self.calls.lock();
+        let result = match calls.get_mut(&header.txid) {
+            Some(call) if call.caller != end && call.reply.is_none() => {
+                let mut room = call.room;
+                match message.fits(&mut room) {
+                    Ok(()) => {
+                        call.reply = Some(message);
+                        self.replies.wake_all();
+                        return Ok(());
+                    }
+                    Err(err) => err,
+                }
+            }
+            _ => ENOENT,
+        };
+        drop(calls);
+
+        message.give_back();
+        Err(result)
+    }
+}
+
+/// One end of a channel.
+pub struct ChannelFS {
+    path: String,
+    channel: Arc<Channel>,
+    end: usize,
+}
+
+impl ChannelFS {
+    pub fn send(&self, header: &ChannelMessage, nonblock: bool) -> Result<(), usize> {
+        self.channel.send(self.end, header, nonblock)
+    }
+
+    pub fn receive(&self, header: &mut ChannelMessage, nonblock: bool) -> Result<usize, usize> {
+        self.channel.receive(self.end, header, nonblock)
+    }
+
+    pub fn call(
+        &self,
+        request: &ChannelMessage,
+        reply: &mut ChannelMessage,
+    ) -> Result<usize, usize> {
+        self.channel.call(self.end, request, reply)
+    }
+
+    pub fn reply(&self, header: &ChannelMessage) -> Result<(), usize> {
+        self.channel.reply(self.end, header)
+    }
+}
+
+impl Drop for ChannelFS {
+    fn drop(&mut self) {
+        self.channel.close(self.end);
+    }
+}
+
+impl Inode for ChannelFS {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::File
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        self.channel.poll(self.end, table)
+    }
+
+    fn mode(&self) -> u16 {
+        0o600
+    }
+}
@@ -0,0 +1,284 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use spin::{Lazy, RwLock};
+
+use super::{
+    inode::{FileInfo, Inode, InodeRef, InodeTy, Metadata, mount_to},
+    input,
+};
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode},
+        poll::{PollEvents, PollTable},
+    },
+    ref_to_mut,
+    syscall::errno::{EEXIST, ENOSPC, ENOTTY},
+};
+
+pub static DEV_FS: Lazy<Arc<RwLock<DevFS>>> = Lazy::new(|| {
+    let inode = Arc::new(RwLock::new(DevFS {
+        nodes: BTreeMap::new(),
+        numbers: BTreeMap::new(),
+        path: String::new(),
+    }));
+    let this: InodeRef = inode.clone();
+    inode.write().nodes.insert(".".into(), this);
+    inode
+});
+
+/// Encodes a device number the way Linux reports it in `st_rdev`.
+pub fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    (major & 0xfff) << 8 | (major & !0xfff) << 32 | (minor & 0xff) | (minor & !0xff) << 12
+}
+
+/// Publishes `device` as `/dev/<name>` with the given device number.
+pub fn register_device(
+    name: &str,
+    major: u32,
+    minor: u32,
+    device: InodeRef,
+) -> Result<InodeRef, usize> {
+    let mut dev_fs = DEV_FS.write();
+    if dev_fs.nodes.contains_key(name) || dev_fs.numbers.contains_key(&(major, minor)) {
+        return Err(EEXIST);
+    }
+    dev_fs.numbers.insert((major, minor), name.to_string());
+    drop(dev_fs);
+
+    let node = DeviceNode::new(major, minor, device);
+    mount_to(node.clone(), DEV_FS.clone(), name.to_string());
+
+    log::info!("Registered device /dev/{} ({}:{})", name, major, minor);
+    Ok(node)
+}
+
+pub fn unregister_device(name: &str) -> Option<InodeRef> {
+    let mut dev_fs = DEV_FS.write();
+    dev_fs.numbers.retain(|_, node_name| node_name != name);
+    let node = dev_fs.nodes.remove(name)?;
+    drop(dev_fs);
+
+    node.write().when_umounted();
+    Some(node)
+}
+
+/// The device registered under `major:minor`.
+pub fn find_device(major: u32, minor: u32) -> Option<InodeRef> {
+    let dev_fs = DEV_FS.read();
+    let name = dev_fs.numbers.get(&(major, minor))?;
+    dev_fs.nodes.get(name).cloned()
+}
+
+/// Registers the devices every system has.
+pub fn init() {
+    let devices = [
+        ("null", 1, 3, MemDevice::Null),
+        ("zero", 1, 5, MemDevice::Zero),
+        ("full", 1, 7, MemDevice::Full),
+        ("random", 1, 8, MemDevice::Random),
+        ("urandom", 1, 9, MemDevice::Random),
+    ];
+    for (name, major, minor, device) in devices {
+        let _ = register_device(name, major, minor, Arc::new(RwLock::new(device)));
+    }
+
+    // The mouse takes the number of Linux's `psaux`.
+    let _ = register_device("ps2mouse", 10, 1, input::MOUSE.device());
+    let _ = register_device("ps2keyboard", 10, 2, input::KEYBOARD.device());
+}
+
+/// `/dev`. Besides devices with a number, it holds nodes mounted into it
+/// directly, such as block devices and `kernel.fb`.
+pub struct DevFS {
+    nodes: BTreeMap<String, InodeRef>,
+    numbers: BTreeMap<(u32, u32), String>,
+    path: String,
+}
+
+impl Inode for DevFS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        if let Some(father) = father {
+            self.nodes.insert("..".into(), father.clone());
+        }
+    }
+
+    fn when_umounted(&mut self) {
+        for (name, node) in self.nodes.iter() {
+            if name != "." && name != ".." {
+                node.write().when_umounted();
+            }
+        }
+    }
+
+    fn mount(&self, node: InodeRef, name: String) {
+        ref_to_mut(self).nodes.insert(name, node);
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        self.nodes.get(&name).cloned()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Dir
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        let mut vec = Vec::new();
+        for (name, inode) in self.nodes.iter() {
+            vec.push(FileInfo::new(name.clone(), inode.read().inode_type()));
+        }
+        vec
+    }
+}
+
+/// A registered device as it appears in `/dev`: the driver's inode plus its
+/// device number.
+struct DeviceNode {
+    path: String,
+    major: u32,
+    minor: u32,
+    device: InodeRef,
+}
+
+impl DeviceNode {
+    fn new(major: u32, minor: u32, device: InodeRef) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            major,
+            minor,
+            device,
+        }))
+    }
+}
+
+impl Inode for DeviceNode {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {
+        self.device.write().when_umounted();
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, fd: usize) -> usize {
+        self.device.read().size(fd)
+    }
+
+    fn read_at(&self, fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        self.device.read().read_at(fd, offset, buf)
+    }
+
+    fn write_at(&self, fd: usize, offset: usize, buf: &[u8]) -> usize {
+        self.device.read().write_at(fd, offset, buf)
+    }
+
+    fn flush(&self) {
+        self.device.read().flush();
+    }
+
+    fn on_open(&self, mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        self.device.read().on_open(mode, flags)
+    }
+
+    fn poll(&self, fd: usize, table: &mut PollTable) -> PollEvents {
+        self.device.read().poll(fd, table)
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        self.device.read().ioctl(cmd, arg)
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        match self.device.read().inode_type() {
+            InodeTy::BlockDevice => InodeTy::BlockDevice,
+            _ => InodeTy::CharDevice,
+        }
+    }
+
+    fn metadata(&self, fd: usize) -> Metadata {
+        let device = self.device.read();
+        Metadata {
+            ino: self as *const Self as usize as u64,
+            mode: self.inode_type().mode_bits() | device.mode() as u32,
+            rdev: makedev(self.major, self.minor),
+            ..device.metadata(fd)
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        self.device.read().mode()
+    }
+}
+
+/// The devices Linux keeps under major 1.
+enum MemDevice {
+    Null,
+    Zero,
+    /// Reads like `zero`, but every write fails with `ENOSPC`.
+    Full,
+    /// Both `random` and `urandom`; the pool is seeded before any user
+    /// code runs, so neither ever blocks.
+    Random,
+}
+
+impl Inode for MemDevice {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        String::new()
+    }
+
+    fn read_at(&self, _fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
+        match self {
+            MemDevice::Null => return 0,
+            MemDevice::Zero | MemDevice::Full => buf.fill(0),
+            MemDevice::Random => crate::random::fill(buf),
+        }
+        buf.len()
+    }
+
+    fn write_at(&self, _fd: usize, _offset: usize, buf: &[u8]) -> usize {
+        match self {
+            MemDevice::Full => ENOSPC.wrapping_neg(),
+            MemDevice::Random => {
+                for chunk in buf.chunks(8) {
+                    let mut sample = [0u8; 8];
+                    sample[..chunk.len()].copy_from_slice(chunk);
+                    crate::random::add_entropy(u64::from_ne_bytes(sample));
+                }
+                buf.len()
+            }
+            _ => buf.len(),
+        }
+    }
+
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> usize {
+        ENOTTY.wrapping_neg()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o666
+    }
+}
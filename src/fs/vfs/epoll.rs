@@ -0,0 +1,144 @@
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+};
+use core::time::Duration;
+use spin::{Mutex, RwLock};
+
+use super::inode::{Inode, InodeRef, InodeTy};
+use crate::{
+    fs::poll::{ALWAYS_REPORTED, PollEvents, PollTable, poll_wait},
+    syscall::errno::{EEXIST, EINVAL, ENOENT},
+};
+
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+/// Report the next event only, then disable the fd until `EPOLL_CTL_MOD`.
+const EPOLLONESHOT: u32 = 1 << 30;
+/// Accepted, but events are always reported level-triggered.
+const EPOLLET: u32 = 1 << 31;
+
+/// `struct epoll_event`, which is packed on x86_64.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+struct Interest {
+    /// Weak, so a watched file still closes when its last fd does.
+    inode: Weak<RwLock<dyn Inode>>,
+    event: EpollEvent,
+}
+
+/// An epoll instance: a set of watched fds waited on together.
+pub struct EpollFS {
+    path: String,
+    interests: Mutex<BTreeMap<usize, Interest>>,
+}
+
+impl EpollFS {
+    pub fn new() -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::from("anon_inode:[eventpoll]"),
+            interests: Mutex::new(BTreeMap::new()),
+        }))
+    }
+
+    /// Adds, changes or removes the interest in `fd`, which is open as
+    /// `inode`.
+    pub fn control(
+        &self,
+        op: usize,
+        fd: usize,
+        inode: &InodeRef,
+        event: EpollEvent,
+    ) -> Result<(), usize> {
+        let mut interests = self.interests.lock();
+        interests.retain(|_, interest| interest.inode.strong_count() > 0);
+
+        let event = EpollEvent {
+            events: event.events | ALWAYS_REPORTED.bits(),
+            ..event
+        };
+
+        match op {
+            EPOLL_CTL_ADD => {
+                if interests.contains_key(&fd) {
+                    return Err(EEXIST);
+                }
+                let inode = Arc::downgrade(inode);
+                interests.insert(fd, Interest { inode, event });
+            }
+            EPOLL_CTL_MOD => interests.get_mut(&fd).ok_or(ENOENT)?.event = event,
+            EPOLL_CTL_DEL => {
+                interests.remove(&fd).ok_or(ENOENT)?;
+            }
+            _ => return Err(EINVAL),
+        }
+        Ok(())
+    }
+
+    /// Waits for watched fds to become ready and fills `events` with them.
+    pub fn wait(
+        &self,
+        events: &mut [EpollEvent],
+        timeout: Option<Duration>,
+    ) -> Result<usize, usize> {
+        poll_wait(timeout, |table| self.collect(events, table))
+    }
+
+    fn collect(&self, events: &mut [EpollEvent], table: &mut PollTable) -> usize {
+        let mut count = 0;
+        for (&fd, interest) in self.interests.lock().iter_mut() {
+            if count == events.len() {
+                break;
+            }
+            let Some(inode) = interest.inode.upgrade() else {
+                continue;
+            };
+
+            let wanted = PollEvents::from_bits_truncate(interest.event.events);
+            let ready = inode.read().poll(fd, table) & wanted;
+            if ready.is_empty() {
+                continue;
+            }
+
+            events[count] = EpollEvent {
+                events: ready.bits(),
+                data: interest.event.data,
+            };
+            count += 1;
+
+            if interest.event.events & EPOLLONESHOT != 0 {
+                interest.event.events &= EPOLLONESHOT | EPOLLET;
+            }
+        }
+        count
+    }
+}
+
+impl Inode for EpollFS {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::File
+    }
+
+    fn mode(&self) -> u16 {
+        0o600
+    }
+}
@@ -89,3 +89,23 @@ impl Inode for FbFS {
         ret
     }
 }
+
+/// The framebuffer limine set up, for the kernel's own console.
+pub struct FramebufferInfo {
+    pub addr: usize,
+    pub width: usize,
+    pub height: usize,
+    pub pitch: usize,
+    pub bpp: usize,
+}
+
+pub fn framebuffer_info() -> Option<FramebufferInfo> {
+    let fb = FB_REQUEST.get_response()?.framebuffers().next()?;
+    Some(FramebufferInfo {
+        addr: fb.addr() as usize,
+        width: fb.width() as usize,
+        height: fb.height() as usize,
+        pitch: fb.pitch() as usize,
+        bpp: fb.bpp() as usize,
+    })
+}
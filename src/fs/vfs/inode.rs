@@ -1,12 +1,61 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{any::Any, time::Duration};
 use spin::RwLock;
 
+use super::stat_struct::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG, StatVfs};
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode},
+        poll::{PollEvents, PollTable},
+    },
+    syscall::errno::{EINVAL, EPERM},
+};
+
 pub type InodeRef = Arc<RwLock<dyn Inode>>;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum InodeTy {
     Dir = 0,
     File = 1,
+    Symlink = 2,
+    BlockDevice = 3,
+    Fifo = 4,
+    CharDevice = 5,
+}
+
+impl InodeTy {
+    /// The file type bits of `st_mode`.
+    pub fn mode_bits(self) -> u32 {
+        match self {
+            InodeTy::Dir => S_IFDIR,
+            InodeTy::File => S_IFREG,
+            InodeTy::Symlink => S_IFLNK,
+            InodeTy::BlockDevice => S_IFBLK,
+            InodeTy::Fifo => S_IFIFO,
+            InodeTy::CharDevice => S_IFCHR,
+        }
+    }
+}
+
+/// What `stat` reports about an inode. Times are since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metadata {
+    /// Left zero by filesystems; filled in from the mount table.
+    pub dev: u64,
+    pub ino: u64,
+    /// Permission and file type bits.
+    pub mode: u32,
+    pub nlink: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    /// In 512-byte units.
+    pub blocks: u64,
+    pub atime: Duration,
+    pub mtime: Duration,
+    pub ctime: Duration,
 }
 
 #[repr(C)]
@@ -22,7 +71,7 @@ impl FileInfo {
     }
 }
 
-pub trait Inode: Sync + Send {
+pub trait Inode: Any + Sync + Send {
     fn when_mounted(&mut self, path: String, father: Option<InodeRef>);
     fn when_umounted(&mut self);
 
@@ -41,15 +90,73 @@ pub trait Inode: Sync + Send {
     fn write_at(&self, fd: usize, _offset: usize, _buf: &[u8]) -> usize {
         0
     }
-    fn flush(&self) {
-        unimplemented!()
+    fn flush(&self) {}
+    /// Usage of the whole filesystem the inode belongs to. Fields left zero
+    /// are filled in from the mount table.
+    fn statfs(&self) -> Option<StatVfs> {
+        None
     }
 
     fn open(&self, _name: String) -> Option<InodeRef> {
         unimplemented!()
     }
     fn create(&self, _name: String, _ty: InodeTy) -> Option<InodeRef> {
-        unimplemented!()
+        None
+    }
+    /// Creates `name` owned by `uid`:`gid` with the permission bits `mode`.
+    /// Filesystems that keep no owners refuse the change and keep their
+    /// defaults.
+    fn create_owned(
+        &self,
+        name: String,
+        ty: InodeTy,
+        uid: u32,
+        gid: u32,
+        mode: u16,
+    ) -> Option<InodeRef> {
+        let node = self.create(name, ty)?;
+        {
+            let node = node.read();
+            let _ = node.chown(uid, gid);
+            let _ = node.chmod(mode);
+        }
+        Some(node)
+    }
+    /// Removes the entry `name`, which must be a directory if `dir` is set
+    /// and must not be one otherwise.
+    fn unlink(&self, _name: String, _dir: bool) -> Result<(), usize> {
+        Err(EPERM)
+    }
+    /// Moves the entry `name` to `new_name` in `new_parent`, replacing
+    /// what was there.
+    fn rename(
+        &self,
+        _name: String,
+        _new_parent: &InodeRef,
+        _new_name: String,
+    ) -> Result<(), usize> {
+        Err(EPERM)
+    }
+    fn truncate(&self, _fd: usize, _len: usize) -> Result<(), usize> {
+        Err(EINVAL)
+    }
+    /// Sets the permission bits, including the set-ID and sticky bits.
+    fn chmod(&self, _mode: u16) -> Result<(), usize> {
+        Err(EPERM)
+    }
+    fn chown(&self, _uid: u32, _gid: u32) -> Result<(), usize> {
+        Err(EPERM)
+    }
+    /// Called when the inode is opened. Returning an inode puts that inode
+    /// into the file table instead, which is how FIFOs hand out pipe ends.
+    fn on_open(&self, _mode: OpenMode, _flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        Ok(None)
+    }
+    /// Reports which events the inode is ready for, registering on `table`
+    /// the wait queues woken when that changes. Files that never block are
+    /// always ready.
+    fn poll(&self, _fd: usize, _table: &mut PollTable) -> PollEvents {
+        PollEvents::IN | PollEvents::RDNORM | PollEvents::OUT | PollEvents::WRNORM
     }
     fn ioctl(&self, _cmd: usize, _arg: usize) -> usize {
         unimplemented!()
@@ -61,6 +168,41 @@ pub trait Inode: Sync + Send {
     fn inode_type(&self) -> InodeTy {
         InodeTy::File
     }
+
+    fn read_link(&self) -> Option<String> {
+        None
+    }
+
+    /// Defaults suit in-memory inodes, which are numbered by address.
+    fn metadata(&self, fd: usize) -> Metadata {
+        let ty = self.inode_type();
+        let size = self.size(fd) as u64;
+        let (uid, gid) = self.owner();
+        Metadata {
+            ino: self as *const Self as *const () as usize as u64,
+            mode: ty.mode_bits() | self.mode() as u32,
+            uid,
+            gid,
+            nlink: if ty == InodeTy::Dir { 2 } else { 1 },
+            size,
+            blksize: 4096,
+            blocks: size.div_ceil(512),
+            ..Default::default()
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        match self.inode_type() {
+            InodeTy::Dir => 0o755,
+            InodeTy::File | InodeTy::BlockDevice | InodeTy::Fifo | InodeTy::CharDevice => 0o644,
+            InodeTy::Symlink => 0o777,
+        }
+    }
+
+    /// The user and group owning the inode.
+    fn owner(&self) -> (u32, u32) {
+        (0, 0)
+    }
 }
 
 pub fn mount_to(node: InodeRef, to: InodeRef, name: String) {
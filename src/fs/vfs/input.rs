@@ -0,0 +1,111 @@
+use alloc::{collections::VecDeque, string::String, sync::Arc};
+use spin::{Lazy, Mutex, RwLock};
+
+use super::inode::{Inode, InodeRef, InodeTy};
+use crate::{
+    fs::{
+        operation::{OpenFlags, get_flags_by_fd},
+        poll::{PollEvents, PollTable},
+    },
+    syscall::errno::{EAGAIN, ENOTTY},
+    task::wait_queue::WaitQueue,
+};
+
+/// Bytes queued beyond this are dropped until the driver catches up.
+const INPUT_CAPACITY: usize = 4096;
+
+/// Scancodes from the PS/2 keyboard, read by the userspace driver through
+/// `/dev/ps2keyboard`.
+pub static KEYBOARD: Lazy<Arc<InputQueue>> = Lazy::new(InputQueue::new);
+/// Packet bytes from the PS/2 mouse, read through `/dev/ps2mouse`.
+pub static MOUSE: Lazy<Arc<InputQueue>> = Lazy::new(InputQueue::new);
+
+/// Bytes an interrupt handler hands to whoever reads the device.
+pub struct InputQueue {
+    bytes: Mutex<VecDeque<u8>>,
+    readable: Arc<WaitQueue>,
+}
+
+impl InputQueue {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            bytes: Mutex::new(VecDeque::new()),
+            readable: Arc::new(WaitQueue::new()),
+        })
+    }
+
+    /// Queues `byte` and wakes the readers. Safe in interrupt handlers:
+    /// readers only take the lock inside syscalls, which run with
+    /// interrupts off.
+    pub fn push(&self, byte: u8) {
+        {
+            let mut bytes = self.bytes.lock();
+            if bytes.len() < INPUT_CAPACITY {
+                bytes.push_back(byte);
+            }
+        }
+        self.readable.wake_all();
+    }
+
+    /// The device reading from this queue.
+    pub fn device(self: &Arc<Self>) -> InodeRef {
+        Arc::new(RwLock::new(InputDevice {
+            queue: self.clone(),
+        }))
+    }
+}
+
+struct InputDevice {
+    queue: Arc<InputQueue>,
+}
+
+impl Inode for InputDevice {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        String::new()
+    }
+
+    /// Blocks until at least one byte is queued, unless the descriptor is
+    /// non-blocking.
+    fn read_at(&self, fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+
+        self.queue.readable.wait_interruptible(|| {
+            let mut bytes = self.queue.bytes.lock();
+            if bytes.is_empty() {
+                return nonblock.then_some(EAGAIN.wrapping_neg());
+            }
+            let count = buf.len().min(bytes.len());
+            for (byte, queued) in buf.iter_mut().zip(bytes.drain(..count)) {
+                *byte = queued;
+            }
+            Some(count)
+        })
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        table.register(&self.queue.readable);
+        match self.queue.bytes.lock().is_empty() {
+            true => PollEvents::empty(),
+            false => PollEvents::IN | PollEvents::RDNORM,
+        }
+    }
+
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> usize {
+        ENOTTY.wrapping_neg()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o600
+    }
+}
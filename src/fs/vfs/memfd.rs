@@ -0,0 +1,234 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use spin::{Mutex, RwLock};
+use x86_64::structures::paging::PhysFrame;
+
+use super::inode::{Inode, InodeRef, InodeTy};
+use crate::{
+    memory::{
+        FRAME_ALLOCATOR, allocate_zeroed, convert_physical_to_virtual, share, unshare, writers,
+    },
+    syscall::errno::{EBUSY, EINVAL, ENXIO, EPERM},
+};
+
+pub const MFD_CLOEXEC: usize = 1;
+pub const MFD_ALLOW_SEALING: usize = 2;
+
+const PAGE_SIZE: usize = 4096;
+
+bitflags! {
+    /// The `F_SEAL_*` restrictions a memfd can be sealed with.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Seals: u32 {
+        /// No more seals can be added.
+        const SEAL = 1;
+        const SHRINK = 2;
+        const GROW = 4;
+        /// Neither `write` nor writable shared mappings.
+        const WRITE = 8;
+    }
+}
+
+struct Contents {
+    /// One reference on each frame belongs to the memfd; mappings hold the
+    /// others.
+    frames: Vec<PhysFrame>,
+    size: usize,
+    seals: Seals,
+}
+
+impl Contents {
+    fn resize(&mut self, size: usize) -> Result<(), usize> {
+        let pages = size.div_ceil(PAGE_SIZE);
+        if size > self.size {
+            if self.seals.contains(Seals::GROW) {
+                return Err(EPERM);
+            }
+            let added = allocate_zeroed(pages.saturating_sub(self.frames.len()))?;
+            for &frame in &added {
+                share(frame, false);
+            }
+            self.frames.extend(added);
+        } else if size < self.size {
+            if self.seals.contains(Seals::SHRINK) {
+                return Err(EPERM);
+            }
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            for frame in self.frames.drain(pages..) {
+                unshare(frame, false, &mut frame_allocator);
+            }
+            drop(frame_allocator);
+            // Growing again must bring back zeroes, also in mappings.
+            if let Some(&last) = self.frames.last()
+                && !size.is_multiple_of(PAGE_SIZE)
+            {
+                let page = Self::page(last);
+                page[size % PAGE_SIZE..].fill(0);
+            }
+        }
+        self.size = size;
+        Ok(())
+    }
+
+    fn page(frame: PhysFrame) -> &'static mut [u8] {
+        let address = convert_physical_to_virtual(frame.start_address());
+        unsafe { core::slice::from_raw_parts_mut(address.as_mut_ptr(), PAGE_SIZE) }
+    }
+
+    /// Runs `f` on each piece of the `len` bytes from `offset`, which must
+    /// lie within the frames, with its offset into those bytes.
+    fn each_piece(&self, offset: usize, len: usize, mut f: impl FnMut(usize, &mut [u8])) {
+        let mut done = 0;
+        while done < len {
+            let position = offset + done;
+            let page = Self::page(self.frames[position / PAGE_SIZE]);
+            let start = position % PAGE_SIZE;
+            let count = (PAGE_SIZE - start).min(len - done);
+            f(done, &mut page[start..start + count]);
+            done += count;
+        }
+    }
+}
+
+/// An anonymous file living in memory, from `memfd_create`. Its pages can
+/// be mapped into several processes at once.
+pub struct MemFS {
+    path: String,
+    contents: Mutex<Contents>,
+}
+
+impl MemFS {
+    pub fn new(name: &str, allow_sealing: bool) -> InodeRef {
+        let seals = match allow_sealing {
+            true => Seals::empty(),
+            false => Seals::SEAL,
+        };
+        Arc::new(RwLock::new(Self {
+            path: alloc::format!("memfd:{}", name),
+            contents: Mutex::new(Contents {
+                frames: Vec::new(),
+                size: 0,
+                seals,
+            }),
+        }))
+    }
+
+    pub fn seals(&self) -> Seals {
+        self.contents.lock().seals
+    }
+
+    pub fn add_seals(&self, seals: Seals) -> Result<(), usize> {
+        let mut contents = self.contents.lock();
+        if contents.seals.contains(Seals::SEAL) {
+            return Err(EPERM);
+        }
+        if seals.contains(Seals::WRITE) && contents.frames.iter().any(|&frame| writers(frame) > 0) {
+            return Err(EBUSY);
+        }
+        contents.seals |= seals;
+        Ok(())
+    }
+
+    /// Takes a reference on each frame behind the `len` bytes at `offset`,
+    /// both page aligned, to map them shared.
+    pub fn share_frames(
+        &self,
+        offset: usize,
+        len: usize,
+        writable: bool,
+    ) -> Result<Vec<PhysFrame>, usize> {
+        let contents = self.contents.lock();
+        if writable && contents.seals.contains(Seals::WRITE) {
+            return Err(EPERM);
+        }
+        let first = offset / PAGE_SIZE;
+        let frames = contents
+            .frames
+            .get(first..first + len / PAGE_SIZE)
+            .ok_or(ENXIO)?;
+        for &frame in frames {
+            share(frame, writable);
+        }
+        Ok(frames.to_vec())
+    }
+
+    /// Copies the `len` bytes at `offset` into fresh frames, for a private
+    /// mapping. Bytes past the end read as zero.
+    pub fn copy_frames(&self, offset: usize, len: usize) -> Result<Vec<PhysFrame>, usize> {
+        let frames = allocate_zeroed(len / PAGE_SIZE)?;
+        let contents = self.contents.lock();
+        let available = contents.size.saturating_sub(offset).min(len);
+        contents.each_piece(offset, available, |done, piece| {
+            let target = Contents::page(frames[done / PAGE_SIZE]);
+            let start = done % PAGE_SIZE;
+            target[start..start + piece.len()].copy_from_slice(piece);
+        });
+        Ok(frames)
+    }
+}
+
+impl Drop for MemFS {
+    fn drop(&mut self) {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        for frame in self.contents.get_mut().frames.drain(..) {
+            unshare(frame, false, &mut frame_allocator);
+        }
+    }
+}
+
+impl Inode for MemFS {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        self.contents.lock().size
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        let contents = self.contents.lock();
+        let len = contents.size.saturating_sub(offset).min(buf.len());
+        contents.each_piece(offset, len, |done, piece| {
+            buf[done..done + piece.len()].copy_from_slice(piece);
+        });
+        len
+    }
+
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        let mut contents = self.contents.lock();
+        if contents.seals.contains(Seals::WRITE) {
+            return EPERM.wrapping_neg();
+        }
+        let Some(end) = offset.checked_add(buf.len()) else {
+            return EINVAL.wrapping_neg();
+        };
+        if end > contents.size
+            && let Err(err) = contents.resize(end)
+        {
+            return err.wrapping_neg();
+        }
+        contents.each_piece(offset, buf.len(), |done, piece| {
+            piece.copy_from_slice(&buf[done..done + piece.len()]);
+        });
+        buf.len()
+    }
+
+    fn truncate(&self, _fd: usize, len: usize) -> Result<(), usize> {
+        self.contents.lock().resize(len)
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::File
+    }
+
+    fn mode(&self) -> u16 {
+        0o600
+    }
+}
@@ -1,7 +1,15 @@
 pub mod stat_struct;
 
 pub mod acpi;
+pub mod block;
+pub mod channel;
+pub mod devfs;
+pub mod epoll;
 pub mod fb;
 pub mod inode;
+pub mod input;
+pub mod memfd;
 pub mod pipe;
+pub mod procfs;
 pub mod root;
+pub mod tmpfs;
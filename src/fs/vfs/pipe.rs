@@ -1,25 +1,283 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::{Mutex, RwLock};
 
 use super::inode::{Inode, InodeRef};
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode, get_flags_by_fd},
+        poll::{PollEvents, PollTable},
+    },
+    syscall::errno::{EAGAIN, EBADF, ENXIO, EPIPE},
+    task::{
+        signal::{SIGPIPE, send_signal_to_current},
+        wait_queue::WaitQueue,
+    },
+};
 
+pub const PIPE_CAPACITY: usize = 64 * 1024;
+/// Writes of at most this many bytes are never interleaved with other writes.
+pub const PIPE_BUF: usize = 4096;
+
+struct RingBuffer {
+    data: Box<[u8]>,
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            data: alloc::vec![0u8; capacity].into_boxed_slice(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn free(&self) -> usize {
+        self.data.len() - self.len
+    }
+
+    fn push(&mut self, buf: &[u8]) -> usize {
+        let count = buf.len().min(self.free());
+        for (index, &byte) in buf[..count].iter().enumerate() {
+            let position = (self.head + self.len + index) % self.data.len();
+            self.data[position] = byte;
+        }
+        self.len += count;
+        count
+    }
+
+    fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.len);
+        for (index, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.data[(self.head + index) % self.data.len()];
+        }
+        self.head = (self.head + count) % self.data.len();
+        self.len -= count;
+        count
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeEnd {
+    Read,
+    Write,
+    /// Both ends at once, for a FIFO opened with `O_RDWR`.
+    ReadWrite,
+}
+
+impl From<OpenMode> for PipeEnd {
+    fn from(mode: OpenMode) -> Self {
+        match mode {
+            OpenMode::Read => Self::Read,
+            OpenMode::Write => Self::Write,
+            OpenMode::ReadWrite => Self::ReadWrite,
+        }
+    }
+}
+
+impl PipeEnd {
+    fn readable(self) -> bool {
+        self != PipeEnd::Write
+    }
+
+    fn writable(self) -> bool {
+        self != PipeEnd::Read
+    }
+}
+
+/// The buffer shared by the two ends of a pipe or FIFO.
+pub struct Pipe {
+    id: usize,
+    buffer: Mutex<RingBuffer>,
+    readers: AtomicUsize,
+    writers: AtomicUsize,
+    /// How many times each side was ever opened, so a FIFO opener notices a
+    /// counterpart that came and went while it slept.
+    reader_opens: AtomicUsize,
+    writer_opens: AtomicUsize,
+    /// FIFO openers waiting for the other side.
+    open_queue: WaitQueue,
+    /// Readers waiting for data or for the last writer to go away.
+    read_queue: Arc<WaitQueue>,
+    /// Writers waiting for space or for the last reader to go away.
+    write_queue: Arc<WaitQueue>,
+}
+
+impl Pipe {
+    pub fn new() -> Arc<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            buffer: Mutex::new(RingBuffer::new(PIPE_CAPACITY)),
+            readers: AtomicUsize::new(0),
+            writers: AtomicUsize::new(0),
+            reader_opens: AtomicUsize::new(0),
+            writer_opens: AtomicUsize::new(0),
+            open_queue: WaitQueue::new(),
+            read_queue: Arc::new(WaitQueue::new()),
+            write_queue: Arc::new(WaitQueue::new()),
+        })
+    }
+
+    /// Opens a new end of the pipe. The end counts as a reader or writer
+    /// until its inode is dropped.
+    pub fn open(self: &Arc<Self>, end: PipeEnd) -> InodeRef {
+        if end.readable() {
+            self.readers.fetch_add(1, Ordering::SeqCst);
+            self.reader_opens.fetch_add(1, Ordering::SeqCst);
+        }
+        if end.writable() {
+            self.writers.fetch_add(1, Ordering::SeqCst);
+            self.writer_opens.fetch_add(1, Ordering::SeqCst);
+        }
+        self.open_queue.wake_all();
+
+        Arc::new(RwLock::new(PipeFS {
+            path: alloc::format!("pipe:[{}]", self.id),
+            pipe: self.clone(),
+            end,
+        }))
+    }
+
+    /// Opens an end of a FIFO. Unless `nonblock` is set, a reader waits for
+    /// a writer and a writer for a reader; a non-blocking writer fails with
+    /// `ENXIO` when there is no reader.
+    pub fn open_fifo(self: &Arc<Self>, end: PipeEnd, nonblock: bool) -> Result<InodeRef, usize> {
+        let (others, other_opens) = match end {
+            PipeEnd::Read => (&self.writers, &self.writer_opens),
+            PipeEnd::Write => (&self.readers, &self.reader_opens),
+            PipeEnd::ReadWrite => return Ok(self.open(end)),
+        };
+
+        if end == PipeEnd::Write && nonblock && others.load(Ordering::SeqCst) == 0 {
+            return Err(ENXIO);
+        }
+
+        let seen = other_opens.load(Ordering::SeqCst);
+        let inode = self.open(end);
+        if nonblock {
+            return Ok(inode);
+        }
+
+        let result = self.open_queue.wait_interruptible(|| {
+            (others.load(Ordering::SeqCst) > 0 || other_opens.load(Ordering::SeqCst) != seen)
+                .then_some(0)
+        });
+        match result {
+            0 => Ok(inode),
+            err => Err(err.wrapping_neg()),
+        }
+    }
+
+    fn close(&self, end: PipeEnd) {
+        if end.readable() && self.readers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.write_queue.wake_all();
+        }
+        if end.writable() && self.writers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.read_queue.wake_all();
+        }
+    }
+
+    pub fn poll(&self, end: PipeEnd, table: &mut PollTable) -> PollEvents {
+        let mut events = PollEvents::empty();
+        if end.readable() {
+            table.register(&self.read_queue);
+            if self.buffer.lock().len > 0 {
+                events |= PollEvents::IN | PollEvents::RDNORM;
+            }
+            if self.writers.load(Ordering::SeqCst) == 0 {
+                events |= PollEvents::HUP;
+            }
+        }
+        if end.writable() {
+            table.register(&self.write_queue);
+            if self.buffer.lock().free() >= PIPE_BUF {
+                events |= PollEvents::OUT | PollEvents::WRNORM;
+            }
+            if self.readers.load(Ordering::SeqCst) == 0 {
+                events |= PollEvents::ERR;
+            }
+        }
+        events
+    }
+
+    pub fn read(&self, buf: &mut [u8], nonblock: bool) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+
+        let read = self.read_queue.wait_interruptible(|| {
+            let mut buffer = self.buffer.lock();
+            if buffer.len > 0 {
+                Some(buffer.pop(buf))
+            } else if self.writers.load(Ordering::SeqCst) == 0 {
+                Some(0)
+            } else if nonblock {
+                Some(EAGAIN.wrapping_neg())
+            } else {
+                None
+            }
+        });
+
+        if (read as isize) > 0 {
+            self.write_queue.wake_all();
+        }
+        read
+    }
+
+    pub fn write(&self, buf: &[u8], nonblock: bool) -> usize {
+        let atomic = buf.len() <= PIPE_BUF;
+        let mut written = 0;
+
+        while written < buf.len() {
+            let result = self.write_queue.wait_interruptible(|| {
+                if self.readers.load(Ordering::SeqCst) == 0 {
+                    return Some(EPIPE.wrapping_neg());
+                }
+
+                let mut buffer = self.buffer.lock();
+                let free = buffer.free();
+                if free >= buf.len() - written || (!atomic && free > 0) {
+                    Some(buffer.push(&buf[written..]))
+                } else if nonblock {
+                    Some(EAGAIN.wrapping_neg())
+                } else {
+                    None
+                }
+            });
+
+            if (result as isize) < 0 {
+                if result == EPIPE.wrapping_neg() {
+                    send_signal_to_current(SIGPIPE);
+                }
+                return if written > 0 { written } else { result };
+            }
+
+            written += result;
+            self.read_queue.wake_all();
+        }
+
+        written
+    }
+}
+
+/// One end of a pipe.
 pub struct PipeFS {
     path: String,
-    buffer: Arc<Mutex<Vec<u8>>>,
+    pipe: Arc<Pipe>,
+    end: PipeEnd,
 }
 
-impl PipeFS {
-    pub fn new() -> InodeRef {
-        let inode = Arc::new(RwLock::new(Self {
-            path: String::new(),
-            buffer: Arc::new(Mutex::new(Vec::new())),
-        }));
-        inode
+impl Drop for PipeFS {
+    fn drop(&mut self) {
+        self.pipe.close(self.end);
     }
 }
 
 impl Inode for PipeFS {
-    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
         self.path.clear();
         self.path.push_str(path.as_str());
     }
@@ -31,23 +289,30 @@ impl Inode for PipeFS {
     }
 
     fn inode_type(&self) -> super::inode::InodeTy {
-        super::inode::InodeTy::File
+        super::inode::InodeTy::Fifo
     }
 
     fn read_at(&self, fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
-        while self.buffer.lock().is_empty() {
-            crate::syscall::op::sys_yield();
+        if !self.end.readable() {
+            return EBADF.wrapping_neg();
         }
-        buf.copy_from_slice(self.buffer.lock().as_slice());
-        self.buffer.lock().clear();
-        buf.len()
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+        self.pipe.read(buf, nonblock)
     }
 
     fn write_at(&self, fd: usize, _offset: usize, buf: &[u8]) -> usize {
-        for &byte in buf {
-            self.buffer.lock().push(0);
+        if !self.end.writable() {
+            return EBADF.wrapping_neg();
         }
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+        self.pipe.write(buf, nonblock)
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        self.pipe.poll(self.end, table)
+    }
 
-        self.buffer.lock().len()
+    fn mode(&self) -> u16 {
+        0o600
     }
 }
@@ -0,0 +1,605 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+use spin::RwLock;
+use x86::cpuid::CpuId;
+use x86_64::structures::paging::PageTableFlags;
+
+use super::inode::{FileInfo, Inode, InodeRef, InodeTy};
+use crate::{
+    acpi::hpet::HPET,
+    fs::operation::open_files,
+    irq::InterruptIndex,
+    memory::{
+        ExtendedPageTable, FRAME_ALLOCATOR, HEAP_SIZE, HEAP_START, KERNEL_ALLOCATOR, MappedRegion,
+    },
+    smp::CPUS,
+    task::{
+        get_current_process_id,
+        process::{PROCESSES, Process, ProcessId},
+        stack::UserStack,
+        thread::ThreadState,
+    },
+};
+
+type Generator = Box<dyn Fn() -> String + Send + Sync>;
+
+/// A read-only file whose content is generated on every read.
+pub struct ProcFile {
+    path: String,
+    generate: Generator,
+}
+
+impl ProcFile {
+    pub fn new(generate: impl Fn() -> String + Send + Sync + 'static) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            generate: Box::new(generate),
+        }))
+    }
+}
+
+impl Inode for ProcFile {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        (self.generate)().len()
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        let content = (self.generate)();
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return 0;
+        }
+
+        let len = buf.len().min(content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        len
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::File
+    }
+
+    fn mode(&self) -> u16 {
+        0o444
+    }
+}
+
+/// A symbolic link whose target is worked out each time it is followed.
+pub struct ProcLink {
+    path: String,
+    target: Generator,
+}
+
+impl ProcLink {
+    pub fn new(target: impl Fn() -> String + Send + Sync + 'static) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            target: Box::new(target),
+        }))
+    }
+}
+
+impl Inode for ProcLink {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        (self.target)().len()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Symlink
+    }
+
+    fn read_link(&self) -> Option<String> {
+        Some((self.target)())
+    }
+}
+
+/// The `/proc` directory: the files mounted into it, `self`, and one
+/// directory per live process, made up whenever they are looked up.
+pub struct ProcRoot {
+    nodes: BTreeMap<String, InodeRef>,
+    path: String,
+}
+
+impl ProcRoot {
+    pub fn new() -> InodeRef {
+        let inode = Arc::new(RwLock::new(Self {
+            nodes: BTreeMap::new(),
+            path: String::new(),
+        }));
+        inode.write().nodes.insert(".".into(), inode.clone());
+        inode
+    }
+}
+
+impl Inode for ProcRoot {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        if let Some(father) = father {
+            self.nodes.insert("..".into(), father.clone());
+        }
+    }
+
+    fn when_umounted(&mut self) {
+        for (name, node) in self.nodes.iter() {
+            if name != "." && name != ".." {
+                node.write().when_umounted();
+            }
+        }
+    }
+
+    fn mount(&self, node: InodeRef, name: String) {
+        crate::ref_to_mut(self).nodes.insert(name, node);
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        if let Some(node) = self.nodes.get(&name) {
+            return Some(node.clone());
+        }
+
+        if name == "self" {
+            let root = self.path.clone();
+            let link = ProcLink::new(move || format!("{}{}", root, get_current_process_id().0));
+            return Some(attach(link, &self.path, &name));
+        }
+
+        let pid = ProcessId(name.parse().ok()?);
+        with_process(pid, |_| ())?;
+        let dir = ProcessDir::new(pid, self.nodes.get(".")?.clone());
+        Some(attach(dir, &self.path, &name))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Dir
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        let mut vec = Vec::new();
+        for (name, inode) in self.nodes.iter() {
+            vec.push(FileInfo::new(name.clone(), inode.read().inode_type()));
+        }
+        vec.push(FileInfo::new("self".into(), InodeTy::Symlink));
+        for process in PROCESSES.read().iter() {
+            let pid = process.read().id;
+            vec.push(FileInfo::new(pid.0.to_string(), InodeTy::Dir));
+        }
+        vec
+    }
+
+    fn mode(&self) -> u16 {
+        0o555
+    }
+}
+
+/// `/proc/<pid>`.
+struct ProcessDir {
+    pid: ProcessId,
+    path: String,
+    root: InodeRef,
+}
+
+impl ProcessDir {
+    const ENTRIES: [(&'static str, InodeTy); 5] = [
+        ("cmdline", InodeTy::File),
+        ("fd", InodeTy::Dir),
+        ("maps", InodeTy::File),
+        ("stat", InodeTy::File),
+        ("status", InodeTy::File),
+    ];
+
+    fn new(pid: ProcessId, root: InodeRef) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            pid,
+            path: String::new(),
+            root,
+        }))
+    }
+}
+
+impl Inode for ProcessDir {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        let pid = self.pid;
+        with_process(pid, |_| ())?;
+
+        let node = match name.as_str() {
+            "." => {
+                let this = ProcessDir::new(pid, self.root.clone());
+                this.write().when_mounted(self.path.clone(), None);
+                return Some(this);
+            }
+            ".." => return Some(self.root.clone()),
+            "cmdline" => ProcFile::new(move || cmdline(pid)),
+            "fd" => FdDir::new(pid, self.root.clone()),
+            "maps" => ProcFile::new(move || maps(pid)),
+            "stat" => ProcFile::new(move || stat(pid)),
+            "status" => ProcFile::new(move || status(pid)),
+            _ => return None,
+        };
+        Some(attach(node, &self.path, &name))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Dir
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        let mut vec = Vec::new();
+        vec.push(FileInfo::new(".".into(), InodeTy::Dir));
+        vec.push(FileInfo::new("..".into(), InodeTy::Dir));
+        for (name, ty) in Self::ENTRIES {
+            vec.push(FileInfo::new(name.into(), ty));
+        }
+        vec
+    }
+
+    fn mode(&self) -> u16 {
+        0o555
+    }
+}
+
+/// `/proc/<pid>/fd`, one link per open descriptor.
+struct FdDir {
+    pid: ProcessId,
+    path: String,
+    root: InodeRef,
+}
+
+impl FdDir {
+    fn new(pid: ProcessId, root: InodeRef) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            pid,
+            path: String::new(),
+            root,
+        }))
+    }
+}
+
+impl Inode for FdDir {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        match name.as_str() {
+            "." => {
+                let this = FdDir::new(self.pid, self.root.clone());
+                this.write().when_mounted(self.path.clone(), None);
+                Some(this)
+            }
+            ".." => {
+                let parent = self.path.trim_end_matches('/');
+                let parent = &parent[..parent.rfind('/')? + 1];
+                let dir = ProcessDir::new(self.pid, self.root.clone());
+                dir.write().when_mounted(parent.to_string(), None);
+                Some(dir)
+            }
+            _ => {
+                let fd = name.parse().ok()?;
+                let (_, inode) = open_files(self.pid)
+                    .into_iter()
+                    .find(|(open, _)| *open == fd)?;
+                let target = fd_target(&inode);
+                Some(attach(
+                    ProcLink::new(move || target.clone()),
+                    &self.path,
+                    &name,
+                ))
+            }
+        }
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Dir
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        let mut vec = Vec::new();
+        vec.push(FileInfo::new(".".into(), InodeTy::Dir));
+        vec.push(FileInfo::new("..".into(), InodeTy::Dir));
+        for (fd, _) in open_files(self.pid) {
+            vec.push(FileInfo::new(fd.to_string(), InodeTy::Symlink));
+        }
+        vec
+    }
+
+    fn mode(&self) -> u16 {
+        0o500
+    }
+}
+
+fn attach(node: InodeRef, dir: &str, name: &str) -> InodeRef {
+    node.write().when_mounted(format!("{}{}/", dir, name), None);
+    node
+}
+
+fn with_process<R>(pid: ProcessId, f: impl FnOnce(&Process) -> R) -> Option<R> {
+    let process = PROCESSES
+        .read()
+        .iter()
+        .find(|process| process.read().id == pid)?
+        .clone();
+    let process = process.read();
+    Some(f(&process))
+}
+
+/// What an open descriptor points at, as `readlink` on `/proc/<pid>/fd/N`
+/// reports it.
+fn fd_target(inode: &InodeRef) -> String {
+    let inode = inode.read();
+    let path = inode.get_path();
+    if path.is_empty() {
+        let ino = inode.metadata(0).ino;
+        return match inode.inode_type() {
+            InodeTy::Fifo => format!("pipe:[{}]", ino),
+            _ => format!("anon_inode:[{}]", ino),
+        };
+    }
+
+    match path.trim_end_matches('/') {
+        "" => "/".into(),
+        path => path.into(),
+    }
+}
+
+/// The short name Linux calls `comm`: the last path component of the
+/// executable, cut to 15 bytes.
+fn comm(process: &Process) -> &str {
+    let name = process.name.rsplit('/').next().unwrap_or(&process.name);
+    match name.char_indices().nth(15) {
+        Some((end, _)) => &name[..end],
+        None => name,
+    }
+}
+
+fn state(process: &Process) -> (char, &'static str) {
+    let running = process
+        .threads
+        .iter()
+        .any(|thread| thread.read().state != ThreadState::Blocked);
+    match running {
+        true => ('R', "running"),
+        false => ('S', "sleeping"),
+    }
+}
+
+/// The user mappings of a process. The kernel heap is mapped user-accessible
+/// into every address space, but it is not the process's memory.
+fn user_regions(process: &Process) -> Vec<MappedRegion> {
+    let heap = HEAP_START as u64..(HEAP_START + HEAP_SIZE) as u64;
+    process
+        .page_table
+        .user_regions()
+        .into_iter()
+        .filter(|region| {
+            !(heap.contains(&region.start.as_u64()) && region.end.as_u64() <= heap.end)
+        })
+        .collect()
+}
+
+fn mapped_bytes(regions: &[MappedRegion]) -> u64 {
+    regions.iter().map(|region| region.end - region.start).sum()
+}
+
+fn cmdline(pid: ProcessId) -> String {
+    with_process(pid, |process| format!("{}\0", process.name)).unwrap_or_default()
+}
+
+fn maps(pid: ProcessId) -> String {
+    let Some(regions) = with_process(pid, user_regions) else {
+        return String::new();
+    };
+
+    let stack_top = UserStack::end_address();
+    let mut out = String::new();
+    for region in regions {
+        let writable = region.flags.contains(PageTableFlags::WRITABLE);
+        let executable = !region.flags.contains(PageTableFlags::NO_EXECUTE);
+        let _ = write!(
+            out,
+            "{:08x}-{:08x} r{}{}p 00000000 00:00 0",
+            region.start.as_u64(),
+            region.end.as_u64(),
+            if writable { 'w' } else { '-' },
+            if executable { 'x' } else { '-' },
+        );
+        if region.start < stack_top && stack_top <= region.end {
+            out.push_str("          [stack]");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn status(pid: ProcessId) -> String {
+    with_process(pid, |process| {
+        let (state, state_name) = state(process);
+        let size = mapped_bytes(&user_regions(process)) / 1024;
+        format!(
+            "Name:\t{}\nState:\t{} ({})\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+             VmSize:\t{:8} kB\nVmRSS:\t{:8} kB\nThreads:\t{}\nSigPnd:\t{:016x}\n",
+            comm(process),
+            state,
+            state_name,
+            pid.0,
+            pid.0,
+            process.parent.map_or(0, |parent| parent.0),
+            size,
+            size,
+            process.threads.len(),
+            process.pending_signals,
+        )
+    })
+    .unwrap_or_default()
+}
+
+fn stat(pid: ProcessId) -> String {
+    with_process(pid, |process| {
+        let (state, _) = state(process);
+        let size = mapped_bytes(&user_regions(process));
+        // pid (comm) state ppid pgrp session tty_nr tpgid flags minflt
+        // cminflt majflt cmajflt utime stime cutime cstime priority nice
+        // num_threads itrealvalue starttime vsize rss, then the fields this
+        // kernel doesn't keep track of.
+        let mut out = format!(
+            "{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 0 0 0 0 20 0 {} 0 0 {} {}",
+            pid.0,
+            comm(process),
+            state,
+            process.parent.map_or(0, |parent| parent.0),
+            pid.0,
+            pid.0,
+            process.threads.len(),
+            size,
+            size / 4096,
+        );
+        for _ in 24..52 {
+            out.push_str(" 0");
+        }
+        out.push('\n');
+        out
+    })
+    .unwrap_or_default()
+}
+
+pub fn meminfo() -> String {
+    let (total, free) = FRAME_ALLOCATOR.lock().frame_counts();
+    let heap_used = KERNEL_ALLOCATOR.used_bytes();
+
+    let mut out = String::new();
+    let mut line = |name: &str, bytes: usize| {
+        let _ = writeln!(out, "{:<16}{:>8} kB", format!("{}:", name), bytes / 1024);
+    };
+    line("MemTotal", total * 4096);
+    line("MemFree", free * 4096);
+    line("MemAvailable", free * 4096);
+    line("KernelHeapTotal", HEAP_SIZE);
+    line("KernelHeapUsed", heap_used);
+    line("KernelHeapFree", HEAP_SIZE - heap_used);
+    out
+}
+
+pub fn cpuinfo() -> String {
+    let cpuid = CpuId::new();
+    let vendor = cpuid.get_vendor_info();
+    let features = cpuid.get_feature_info();
+    let brand = cpuid.get_processor_brand_string();
+
+    let mut flags = Vec::new();
+    if let Some(features) = &features {
+        let known = [
+            ("fpu", features.has_fpu()),
+            ("tsc", features.has_tsc()),
+            ("msr", features.has_msr()),
+            ("pae", features.has_pae()),
+            ("apic", features.has_apic()),
+            ("sse", features.has_sse()),
+            ("sse2", features.has_sse2()),
+            ("pni", features.has_sse3()),
+            ("sse4_1", features.has_sse41()),
+            ("sse4_2", features.has_sse42()),
+            ("x2apic", features.has_x2apic()),
+            ("avx", features.has_avx()),
+        ];
+        flags.extend(known.iter().filter(|(_, has)| *has).map(|(name, _)| *name));
+    }
+
+    let cpus = CPUS.read();
+    let count = cpus.iter_id().count();
+    let mut out = String::new();
+    for (processor, lapic_id) in cpus.iter_id().enumerate() {
+        let _ = writeln!(out, "processor\t: {}", processor);
+        if let Some(vendor) = &vendor {
+            let _ = writeln!(out, "vendor_id\t: {}", vendor.as_str());
+        }
+        if let Some(features) = &features {
+            let _ = writeln!(out, "cpu family\t: {}", features.family_id());
+            let _ = writeln!(out, "model\t\t: {}", features.model_id());
+        }
+        if let Some(brand) = &brand {
+            let _ = writeln!(out, "model name\t: {}", brand.as_str().trim());
+        }
+        if let Some(features) = &features {
+            let _ = writeln!(out, "stepping\t: {}", features.stepping_id());
+        }
+        let _ = writeln!(out, "apicid\t\t: {}", lapic_id);
+        let _ = writeln!(out, "cpu cores\t: {}", count);
+        let _ = writeln!(out, "flags\t\t: {}", flags.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn interrupts() -> String {
+    let mut out = String::from("          total\n");
+    for index in InterruptIndex::ALL {
+        let _ = writeln!(
+            out,
+            "{:>3}: {:>10}   {}",
+            index as u8,
+            index.count(),
+            index.name()
+        );
+    }
+    out
+}
+
+/// The second column is time spent idle, which isn't tracked.
+pub fn uptime() -> String {
+    let uptime = HPET.elapsed();
+    format!(
+        "{}.{:02} 0.00\n",
+        uptime.as_secs(),
+        uptime.subsec_millis() / 10
+    )
+}
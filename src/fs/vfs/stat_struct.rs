@@ -4,24 +4,65 @@ use core::{
     slice,
 };
 
+use super::inode::Metadata;
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFSOCK: u32 = 0o140000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFIFO: u32 = 0o010000;
+
+/// `struct stat` as laid out on Linux x86_64.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct Stat {
     pub st_dev: u64,
     pub st_ino: u64,
-    pub st_mode: u16,
-    pub st_nlink: u32,
+    pub st_nlink: u64,
+    pub st_mode: u32,
     pub st_uid: u32,
     pub st_gid: u32,
-    pub st_size: u64,
-    pub st_blksize: u32,
-    pub st_blocks: u64,
-    pub st_mtime: u64,
-    pub st_mtime_nsec: u32,
-    pub st_atime: u64,
-    pub st_atime_nsec: u32,
-    pub st_ctime: u64,
-    pub st_ctime_nsec: u32,
+    __pad0: u32,
+    pub st_rdev: u64,
+    pub st_size: i64,
+    pub st_blksize: i64,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: i64,
+    __unused: [i64; 3],
+}
+
+const _: () = assert!(mem::size_of::<Stat>() == 144);
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            st_dev: metadata.dev,
+            st_ino: metadata.ino,
+            st_nlink: metadata.nlink,
+            st_mode: metadata.mode,
+            st_uid: metadata.uid,
+            st_gid: metadata.gid,
+            st_rdev: metadata.rdev,
+            st_size: metadata.size as i64,
+            st_blksize: metadata.blksize as i64,
+            st_blocks: metadata.blocks as i64,
+            st_atime: metadata.atime.as_secs() as i64,
+            st_atime_nsec: metadata.atime.subsec_nanos() as i64,
+            st_mtime: metadata.mtime.as_secs() as i64,
+            st_mtime_nsec: metadata.mtime.subsec_nanos() as i64,
+            st_ctime: metadata.ctime.as_secs() as i64,
+            st_ctime_nsec: metadata.ctime.subsec_nanos() as i64,
+            ..Default::default()
+        }
+    }
 }
 
 impl Deref for Stat {
@@ -37,15 +78,33 @@ impl DerefMut for Stat {
     }
 }
 
+pub const EXT2_SUPER_MAGIC: i64 = 0xef53;
+pub const MSDOS_SUPER_MAGIC: i64 = 0x4d44;
+pub const TMPFS_MAGIC: i64 = 0x01021994;
+pub const PROC_SUPER_MAGIC: i64 = 0x9fa0;
+pub const DEVFS_SUPER_MAGIC: i64 = 0x1373;
+pub const FUSE_SUPER_MAGIC: i64 = 0x65735546;
+
+/// `struct statfs` as laid out on Linux x86_64.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct StatVfs {
-    pub f_bsize: u32,
+    pub f_type: i64,
+    pub f_bsize: i64,
     pub f_blocks: u64,
     pub f_bfree: u64,
     pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_fsid: [i32; 2],
+    pub f_namelen: i64,
+    pub f_frsize: i64,
+    pub f_flags: i64,
+    pub f_spare: [i64; 4],
 }
 
+const _: () = assert!(mem::size_of::<StatVfs>() == 120);
+
 impl Deref for StatVfs {
     type Target = [u8];
     fn deref(&self) -> &[u8] {
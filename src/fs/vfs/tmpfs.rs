@@ -0,0 +1,364 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU16, AtomicU32, Ordering},
+};
+use spin::{Mutex, RwLock};
+
+use super::{
+    inode::{FileInfo, Inode, InodeRef, InodeTy},
+    pipe::Pipe,
+    stat_struct::{StatVfs, TMPFS_MAGIC},
+};
+use crate::{
+    fs::operation::{OpenFlags, OpenMode},
+    memory::FRAME_ALLOCATOR,
+    syscall::errno::{EBUSY, EINVAL, ENOENT, ENOMEM, ENOTDIR, ENOTEMPTY, EXDEV},
+};
+
+enum TmpData {
+    Dir(BTreeMap<String, InodeRef>),
+    File(Vec<u8>),
+    Symlink(String),
+    Fifo(Arc<Pipe>),
+}
+
+pub struct TmpFS {
+    path: String,
+    mode: AtomicU16,
+    uid: AtomicU32,
+    gid: AtomicU32,
+    data: Mutex<TmpData>,
+}
+
+impl TmpFS {
+    pub fn new() -> InodeRef {
+        Self::new_dir(0o755)
+    }
+
+    pub fn new_dir(mode: u16) -> InodeRef {
+        let inode = Self::with_data(mode, TmpData::Dir(BTreeMap::new()));
+        inode.read().mount(inode.clone(), ".".into());
+        inode
+    }
+
+    pub fn new_file(mode: u16, content: Vec<u8>) -> InodeRef {
+        Self::with_data(mode, TmpData::File(content))
+    }
+
+    pub fn new_symlink(target: String) -> InodeRef {
+        Self::with_data(0o777, TmpData::Symlink(target))
+    }
+
+    pub fn new_fifo(mode: u16) -> InodeRef {
+        Self::with_data(mode, TmpData::Fifo(Pipe::new()))
+    }
+
+    fn with_data(mode: u16, data: TmpData) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            mode: AtomicU16::new(mode),
+            uid: AtomicU32::new(0),
+            gid: AtomicU32::new(0),
+            data: Mutex::new(data),
+        }))
+    }
+
+    fn is_empty_dir(&self) -> bool {
+        match &*self.data.lock() {
+            TmpData::Dir(nodes) => nodes.keys().all(|name| name == "." || name == ".."),
+            _ => false,
+        }
+    }
+
+    /// Checks that the entry `node` may be removed from this filesystem:
+    /// other filesystems mounted here are busy, and directories must be
+    /// empty.
+    fn check_removable(node: &InodeRef) -> Result<(), usize> {
+        let node = node.read();
+        let node = (&*node as &dyn Any).downcast_ref::<TmpFS>().ok_or(EBUSY)?;
+        match node.inode_type() == InodeTy::Dir && !node.is_empty_dir() {
+            true => Err(ENOTEMPTY),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Inode for TmpFS {
+    fn when_mounted(&mut self, path: String, father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+        if let Some(father) = father
+            && let TmpData::Dir(nodes) = &mut *self.data.lock()
+        {
+            nodes.insert("..".into(), father.clone());
+        }
+    }
+
+    fn when_umounted(&mut self) {
+        if let TmpData::Dir(nodes) = &*self.data.lock() {
+            for (name, node) in nodes.iter() {
+                if name != "." && name != ".." {
+                    node.write().when_umounted();
+                }
+            }
+        }
+    }
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn size(&self, _fd: usize) -> usize {
+        match &*self.data.lock() {
+            TmpData::File(content) => content.len(),
+            TmpData::Symlink(target) => target.len(),
+            TmpData::Dir(_) | TmpData::Fifo(_) => 0,
+        }
+    }
+
+    fn mount(&self, node: InodeRef, name: String) {
+        if let TmpData::Dir(nodes) = &mut *self.data.lock() {
+            nodes.insert(name, node);
+        }
+    }
+
+    fn read_at(&self, _fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        if let TmpData::File(content) = &*self.data.lock() {
+            if offset >= content.len() {
+                return 0;
+            }
+            let len = buf.len().min(content.len() - offset);
+            buf[..len].copy_from_slice(&content[offset..offset + len]);
+            return len;
+        }
+
+        0
+    }
+
+    fn write_at(&self, _fd: usize, offset: usize, buf: &[u8]) -> usize {
+        if let TmpData::File(content) = &mut *self.data.lock() {
+            if content.len() < offset + buf.len() {
+                content.resize(offset + buf.len(), 0);
+            }
+            content[offset..offset + buf.len()].copy_from_slice(buf);
+            return buf.len();
+        }
+
+        0
+    }
+
+    fn flush(&self) {}
+
+    /// tmpfs has no size limit of its own, so it reports physical memory.
+    fn statfs(&self) -> Option<StatVfs> {
+        let (total, free) = FRAME_ALLOCATOR.lock().frame_counts();
+        Some(StatVfs {
+            f_type: TMPFS_MAGIC,
+            f_bsize: 4096,
+            f_blocks: total as u64,
+            f_bfree: free as u64,
+            f_bavail: free as u64,
+            f_namelen: 255,
+            ..Default::default()
+        })
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        match &*self.data.lock() {
+            TmpData::Dir(nodes) => nodes.get(&name).cloned(),
+            _ => None,
+        }
+    }
+
+    fn create(&self, name: String, ty: InodeTy) -> Option<InodeRef> {
+        if let Some(node) = self.open(name.clone()) {
+            return Some(node);
+        }
+
+        let node = match ty {
+            InodeTy::Dir => Self::new_dir(0o755),
+            InodeTy::File => Self::new_file(0o644, Vec::new()),
+            InodeTy::Fifo => Self::new_fifo(0o644),
+            _ => return None,
+        };
+
+        let this = self.open(".".into())?;
+        self.mount(node.clone(), name.clone());
+        node.write()
+            .when_mounted(self.path.clone() + &name + "/", Some(this));
+
+        Some(node)
+    }
+
+    fn unlink(&self, name: String, _dir: bool) -> Result<(), usize> {
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+        Self::check_removable(&node)?;
+        match &mut *self.data.lock() {
+            TmpData::Dir(nodes) => nodes.remove(&name).map(|_| ()).ok_or(ENOENT),
+            _ => Err(ENOTDIR),
+        }
+    }
+
+    fn rename(&self, name: String, new_parent: &InodeRef, new_name: String) -> Result<(), usize> {
+        let node = self.open(name.clone()).ok_or(ENOENT)?;
+        if !(&*node.read() as &dyn Any).is::<TmpFS>() {
+            return Err(EBUSY);
+        }
+        let new_parent = new_parent.read();
+        let new_parent = (&*new_parent as &dyn Any)
+            .downcast_ref::<TmpFS>()
+            .ok_or(EXDEV)?;
+
+        match new_parent.open(new_name.clone()) {
+            Some(replaced) if Arc::ptr_eq(&replaced, &node) => return Ok(()),
+            Some(replaced) => Self::check_removable(&replaced)?,
+            None => {}
+        }
+
+        // The parents may be the same directory, so never hold both locks.
+        if let TmpData::Dir(nodes) = &mut *self.data.lock() {
+            nodes.remove(&name);
+        }
+        new_parent.mount(node.clone(), new_name.clone());
+        node.write().when_mounted(
+            new_parent.path.clone() + &new_name + "/",
+            new_parent.open(".".into()),
+        );
+
+        Ok(())
+    }
+
+    /// Cuts the file down or extends it with zeros.
+    fn truncate(&self, _fd: usize, len: usize) -> Result<(), usize> {
+        match &mut *self.data.lock() {
+            TmpData::File(content) => {
+                content
+                    .try_reserve(len.saturating_sub(content.len()))
+                    .map_err(|_| ENOMEM)?;
+                content.resize(len, 0);
+                Ok(())
+            }
+            _ => Err(EINVAL),
+        }
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        let mut vec = Vec::new();
+        if let TmpData::Dir(nodes) = &*self.data.lock() {
+            for (name, inode) in nodes.iter() {
+                let ty = if name == "." || name == ".." {
+                    InodeTy::Dir
+                } else {
+                    inode.read().inode_type()
+                };
+                vec.push(FileInfo::new(name.clone(), ty));
+            }
+        }
+        vec
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        match &*self.data.lock() {
+            TmpData::Dir(_) => InodeTy::Dir,
+            TmpData::File(_) => InodeTy::File,
+            TmpData::Symlink(_) => InodeTy::Symlink,
+            TmpData::Fifo(_) => InodeTy::Fifo,
+        }
+    }
+
+    fn on_open(&self, mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        // Opening may block until the other side arrives, so don't hold the
+        // data lock while doing it.
+        let pipe = match &*self.data.lock() {
+            TmpData::Fifo(pipe) => pipe.clone(),
+            _ => return Ok(None),
+        };
+        pipe.open_fifo(mode.into(), flags.contains(OpenFlags::NONBLOCK))
+            .map(Some)
+    }
+
+    fn read_link(&self) -> Option<String> {
+        match &*self.data.lock() {
+            TmpData::Symlink(target) => Some(target.clone()),
+            _ => None,
+        }
+    }
+
+    fn chmod(&self, mode: u16) -> Result<(), usize> {
+        self.mode.store(mode & 0o7777, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<(), usize> {
+        self.uid.store(uid, Ordering::Relaxed);
+        self.gid.store(gid, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn mode(&self) -> u16 {
+        self.mode.load(Ordering::Relaxed)
+    }
+
+    fn owner(&self) -> (u32, u32) {
+        (
+            self.uid.load(Ordering::Relaxed),
+            self.gid.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use super::TmpFS;
+    use crate::fs::vfs::inode::{InodeRef, InodeTy};
+    use crate::syscall::errno::ENOTEMPTY;
+
+    fn read(node: &InodeRef) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; node.read().size(0)];
+        node.read().read_at(0, 0, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn truncate_cuts_and_zero_extends() {
+        let root = TmpFS::new();
+        let file = root.read().create("file".into(), InodeTy::File).unwrap();
+        file.read().write_at(0, 0, b"hello world");
+
+        file.read().truncate(0, 5).unwrap();
+        assert_eq!(read(&file), b"hello");
+        file.read().truncate(0, 8).unwrap();
+        assert_eq!(read(&file), b"hello\0\0\0");
+    }
+
+    #[test]
+    fn unlink_keeps_non_empty_directories() {
+        let root = TmpFS::new();
+        let dir = root.read().create("dir".into(), InodeTy::Dir).unwrap();
+        dir.read().create("file".into(), InodeTy::File).unwrap();
+
+        assert_eq!(root.read().unlink("dir".into(), true), Err(ENOTEMPTY));
+
+        dir.read().unlink("file".into(), false).unwrap();
+        root.read().unlink("dir".into(), true).unwrap();
+        assert!(root.read().open("dir".into()).is_none());
+    }
+
+    #[test]
+    fn rename_reparents_and_replaces() {
+        let root = TmpFS::new();
+        let from = root.read().create("from".into(), InodeTy::Dir).unwrap();
+        let to = root.read().create("to".into(), InodeTy::Dir).unwrap();
+        let dir = from.read().create("dir".into(), InodeTy::Dir).unwrap();
+        to.read().create("dir".into(), InodeTy::Dir).unwrap();
+
+        from.read().rename("dir".into(), &to, "dir".into()).unwrap();
+
+        assert!(from.read().open("dir".into()).is_none());
+        assert!(Arc::ptr_eq(&to.read().open("dir".into()).unwrap(), &dir));
+        assert!(Arc::ptr_eq(&dir.read().open("..".into()).unwrap(), &to));
+    }
+}
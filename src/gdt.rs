@@ -1,3 +1,5 @@
+use alloc::boxed::Box;
+use core::ops::Range;
 use spin::Lazy;
 use x86_64::VirtAddr;
 use x86_64::instructions::segmentation::{CS, SS, Segment};
@@ -9,18 +11,80 @@ use x86_64::structures::tss::TaskStateSegment;
 pub const DOUBLE_FAULT_IST_INDEX: usize = 0;
 const FAULT_STACK_SIZE: usize = 2048;
 
+/// The number of I/O ports, each with a bit in an [`IoBitmap`].
+pub const IO_PORTS: usize = 0x1_0000;
+const IO_BITMAP_SIZE: usize = IO_PORTS / 8;
+
+/// Which I/O ports a thread may access from ring 3. A set bit denies the
+/// port, as in the TSS.
+#[derive(Clone)]
+pub struct IoBitmap([u8; IO_BITMAP_SIZE]);
+
+impl IoBitmap {
+    /// A bitmap denying every port.
+    pub fn new() -> Box<Self> {
+        Box::new(Self([0xff; IO_BITMAP_SIZE]))
+    }
+
+    pub fn set(&mut self, ports: Range<usize>, allowed: bool) {
+        for port in ports {
+            let bit = 1 << (port % 8);
+            match allowed {
+                true => self.0[port / 8] &= !bit,
+                false => self.0[port / 8] |= bit,
+            }
+        }
+    }
+
+    /// Denies every allowed port for which `keep` is false.
+    pub fn retain(&mut self, keep: impl Fn(usize) -> bool) {
+        for port in 0..IO_PORTS {
+            if self.0[port / 8] & (1 << (port % 8)) == 0 && !keep(port) {
+                self.0[port / 8] |= 1 << (port % 8);
+            }
+        }
+    }
+
+    /// Whether no port is allowed.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0xff)
+    }
+}
+
+/// A TSS followed by its I/O permission bitmap, which has to lie within the
+/// TSS segment.
+#[repr(C)]
+struct TaskState {
+    tss: TaskStateSegment,
+    /// The CPU reads two bytes at a time, so the bitmap ends in an extra
+    /// byte of ones.
+    io_bitmap: [u8; IO_BITMAP_SIZE + 1],
+}
+
+/// Where the bitmap starts, and an offset past the segment limit, which
+/// denies every port.
+const IO_BITMAP_BASE: u16 = size_of::<TaskStateSegment>() as u16;
+const NO_IO_BITMAP: u16 = size_of::<TaskState>() as u16;
+
 pub struct CpuInfo {
     gdt: GlobalDescriptorTable,
-    tss: TaskStateSegment,
+    /// Boxed so the GDT's pointer to it stays valid wherever the `CpuInfo`
+    /// moves.
+    task_state: Box<TaskState>,
     selectors: Option<Selectors>,
     fault_stack: [u8; FAULT_STACK_SIZE],
 }
 
 impl Default for CpuInfo {
     fn default() -> Self {
+        let mut tss = TaskStateSegment::new();
+        tss.iomap_base = IO_BITMAP_BASE;
         Self {
             gdt: GlobalDescriptorTable::new(),
-            tss: TaskStateSegment::new(),
+            task_state: Box::new(TaskState {
+                tss,
+                io_bitmap: [0xff; IO_BITMAP_SIZE + 1],
+            }),
             selectors: None,
             fault_stack: [0; FAULT_STACK_SIZE],
         }
@@ -30,7 +94,19 @@ impl Default for CpuInfo {
 impl CpuInfo {
     #[inline]
     pub fn set_ring0_rsp(&mut self, rsp: VirtAddr) {
-        self.tss.privilege_stack_table[0] = rsp;
+        self.task_state.tss.privilege_stack_table[0] = rsp;
+    }
+
+    /// Lets ring 3 access the ports `bitmap` allows, or none without one.
+    pub fn set_io_bitmap(&mut self, bitmap: Option<&IoBitmap>) {
+        let task_state = &mut *self.task_state;
+        match bitmap {
+            Some(bitmap) => {
+                task_state.io_bitmap[..IO_BITMAP_SIZE].copy_from_slice(&bitmap.0);
+                task_state.tss.iomap_base = IO_BITMAP_BASE;
+            }
+            None => task_state.tss.iomap_base = NO_IO_BITMAP,
+        }
     }
 }
 
@@ -38,14 +114,16 @@ impl CpuInfo {
     pub fn init(&mut self) {
         let (mut gdt, mut selectors) = COMMON_GDT.clone();
 
-        self.tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = {
+        self.task_state.tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = {
             let stack_start = self.fault_stack.as_ptr() as u64;
             VirtAddr::new(stack_start + self.fault_stack.len() as u64)
         };
 
-        let tss_ref = unsafe { &*(&self.tss as *const _) };
-        let tss_selector = Some(gdt.append(Descriptor::tss_segment(tss_ref)));
-        selectors.tss_selector = tss_selector;
+        let task_state = unsafe { &*(&*self.task_state as *const TaskState) };
+        let descriptor = Descriptor::tss_segment_with_iomap(&task_state.tss, &task_state.io_bitmap)
+            .expect("I/O bitmap must directly follow the TSS");
+        selectors.tss_selector = Some(gdt.append(descriptor));
+        self.set_io_bitmap(None);
 
         self.gdt = gdt;
         self.selectors = Some(selectors);
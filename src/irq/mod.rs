@@ -1,4 +1,4 @@
-use alloc::string::ToString;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Lazy;
 use x86_64::VirtAddr;
 use x86_64::instructions::port::PortReadOnly;
@@ -22,8 +22,49 @@ pub enum InterruptIndex {
     Keyboard,
     Mouse,
     HpetTimer,
+    Serial,
 }
 
+impl InterruptIndex {
+    pub const ALL: [InterruptIndex; 7] = [
+        InterruptIndex::Timer,
+        InterruptIndex::ApicError,
+        InterruptIndex::ApicSpurious,
+        InterruptIndex::Keyboard,
+        InterruptIndex::Mouse,
+        InterruptIndex::HpetTimer,
+        InterruptIndex::Serial,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InterruptIndex::Timer => "LAPIC timer",
+            InterruptIndex::ApicError => "LAPIC error",
+            InterruptIndex::ApicSpurious => "Spurious",
+            InterruptIndex::Keyboard => "PS/2 keyboard",
+            InterruptIndex::Mouse => "PS/2 mouse",
+            InterruptIndex::HpetTimer => "HPET timer",
+            InterruptIndex::Serial => "COM1",
+        }
+    }
+
+    /// How many times the interrupt has fired, summed over all CPUs.
+    pub fn count(self) -> u64 {
+        INTERRUPT_COUNTS[self.slot()].load(Ordering::Relaxed)
+    }
+
+    fn record(self) {
+        INTERRUPT_COUNTS[self.slot()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn slot(self) -> usize {
+        (self as u8 - INTERRUPT_INDEX_OFFSET) as usize
+    }
+}
+
+static INTERRUPT_COUNTS: [AtomicU64; InterruptIndex::ALL.len()] =
+    [const { AtomicU64::new(0) }; InterruptIndex::ALL.len()];
+
 pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     let mut idt = InterruptDescriptorTable::new();
 
@@ -40,6 +81,7 @@ pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     idt[InterruptIndex::Keyboard as u8].set_handler_fn(keyboard_interrupt);
     idt[InterruptIndex::Mouse as u8].set_handler_fn(mouse_interrupt);
     idt[InterruptIndex::HpetTimer as u8].set_handler_fn(hpet_timer_interrupt);
+    idt[InterruptIndex::Serial as u8].set_handler_fn(serial_interrupt);
 
     unsafe {
         idt.double_fault
@@ -53,6 +95,8 @@ pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
 #[naked]
 pub extern "x86-interrupt" fn timer_interrupt(_frame: InterruptStackFrame) {
     fn timer_handler(context: VirtAddr) -> VirtAddr {
+        InterruptIndex::Timer.record();
+        crate::random::add_interrupt_entropy();
         crate::acpi::apic::end_of_interrupt();
         SCHEDULER.lock().schedule(context)
     }
@@ -73,16 +117,19 @@ pub extern "x86-interrupt" fn timer_interrupt(_frame: InterruptStackFrame) {
 }
 
 extern "x86-interrupt" fn lapic_error(_frame: InterruptStackFrame) {
+    InterruptIndex::ApicError.record();
     log::error!("Local APIC error!");
     crate::acpi::apic::end_of_interrupt();
 }
 
 extern "x86-interrupt" fn spurious_interrupt(_frame: InterruptStackFrame) {
+    InterruptIndex::ApicSpurious.record();
     log::debug!("Received spurious interrupt!");
     crate::acpi::apic::end_of_interrupt();
 }
 
 extern "x86-interrupt" fn hpet_timer_interrupt(_frame: InterruptStackFrame) {
+    InterruptIndex::HpetTimer.record();
     crate::acpi::apic::end_of_interrupt();
     TIMER.lock().wakeup();
 }
@@ -115,35 +162,27 @@ extern "x86-interrupt" fn double_fault(frame: InterruptStackFrame, error_code: u
 }
 
 extern "x86-interrupt" fn keyboard_interrupt(_frame: InterruptStackFrame) {
+    InterruptIndex::Keyboard.record();
+    crate::random::add_interrupt_entropy();
     crate::acpi::apic::end_of_interrupt();
     let scancode = unsafe { PortReadOnly::new(0x60).read() };
-    let fd = crate::fs::operation::open(
-        ":ps2:keyboard".to_string(),
-        crate::fs::operation::OpenMode::ReadWrite,
-    );
-
-    log::debug!("fd = {:?}", fd);
-
-    if let Some(fd) = fd {
-        let buf = &[scancode];
-        crate::fs::operation::write(fd, buf);
-        crate::fs::operation::close(fd);
-    }
+    crate::tty::keyboard::handle_scancode(scancode);
+    crate::fs::vfs::input::KEYBOARD.push(scancode);
 }
 
 extern "x86-interrupt" fn mouse_interrupt(_frame: InterruptStackFrame) {
+    InterruptIndex::Mouse.record();
+    crate::random::add_interrupt_entropy();
     crate::acpi::apic::end_of_interrupt();
     let packet = unsafe { PortReadOnly::new(0x60).read() };
-    let fd = crate::fs::operation::open(
-        ":ps2:mouse".to_string(),
-        crate::fs::operation::OpenMode::ReadWrite,
-    );
-
-    if let Some(fd) = fd {
-        let buf = &[packet];
-        crate::fs::operation::write(fd, buf);
-        crate::fs::operation::close(fd);
-    }
+    crate::fs::vfs::input::MOUSE.push(packet);
+}
+
+extern "x86-interrupt" fn serial_interrupt(_frame: InterruptStackFrame) {
+    InterruptIndex::Serial.record();
+    crate::random::add_interrupt_entropy();
+    crate::acpi::apic::end_of_interrupt();
+    crate::tty::serial::handle_interrupt();
 }
 
 extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
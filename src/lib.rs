@@ -24,6 +24,7 @@ extern "C" fn kmain() -> ! {
     log::info!("Reduct OS kernel starting...");
 
     acpi::init();
+    random::init();
 
     smp::CPUS.write().load(*BSP_LAPIC_ID);
     irq::IDT.load();
@@ -34,6 +35,7 @@ extern "C" fn kmain() -> ! {
     syscall::init();
 
     fs::init();
+    tty::init();
 
     module::load_all_module();
 
@@ -52,16 +54,21 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 }
 
 pub mod acpi;
+pub mod block;
 pub mod fs;
 pub mod gdt;
 pub mod irq;
 pub mod klog;
 pub mod memory;
 pub mod module;
+pub mod random;
 pub mod serial;
+pub mod service;
 pub mod smp;
 pub mod syscall;
 pub mod task;
+pub mod time;
+pub mod tty;
 
 pub fn addr_of<T>(reffer: &T) -> usize {
     reffer as *const T as usize
@@ -91,6 +91,11 @@ impl BitmapFrameAllocator {
         }
     }
 
+    /// How many frames the allocator manages, and how many of them are free.
+    pub fn frame_counts(&self) -> (usize, usize) {
+        (self.origin_frames, self.usable_frames)
+    }
+
     pub fn allocate_frames(&mut self, count: usize) -> Option<PhysFrame> {
         let index = self
             .bitmap
@@ -0,0 +1,154 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+use spin::{Mutex, RwLock};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::mapper::TranslateResult;
+use x86_64::structures::paging::{Mapper, Page, PageSize, PageTableFlags, Size4KiB, Translate};
+
+use super::{FRAME_ALLOCATOR, ref_current_page_table};
+use crate::syscall::errno::{EFAULT, ENOMEM};
+use crate::task::process::Process;
+
+/// Where grants are mapped in the receiving process, clear of the heap and
+/// the stacks.
+const GRANT_WINDOW_START: u64 = 0x7000_0000_0000;
+const GRANT_WINDOW_SIZE: u64 = 0x10_0000_0000;
+
+/// Marks page table entries for borrowed frames, which tearing down the
+/// borrower's address space must leave alone.
+pub const BORROWED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// The part of a process's address space grants are placed in.
+pub struct GrantWindow {
+    /// Start address to length in pages of every grant in place.
+    used: BTreeMap<u64, u64>,
+}
+
+impl Default for GrantWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GrantWindow {
+    pub const fn new() -> Self {
+        Self {
+            used: BTreeMap::new(),
+        }
+    }
+
+    fn allocate(&mut self, pages: u64) -> Option<u64> {
+        let size = pages * Size4KiB::SIZE;
+        let mut start = GRANT_WINDOW_START;
+        for (&used, &used_pages) in self.used.iter() {
+            if used - start >= size {
+                break;
+            }
+            start = used + used_pages * Size4KiB::SIZE;
+        }
+        if start + size > GRANT_WINDOW_START + GRANT_WINDOW_SIZE {
+            return None;
+        }
+        self.used.insert(start, pages);
+        Some(start)
+    }
+
+    fn free(&mut self, start: u64) {
+        self.used.remove(&start);
+    }
+}
+
+/// A buffer of the current address space lent to another process: the
+/// frames behind it are mapped into `process` until the grant is dropped,
+/// with no copy in between.
+pub struct Grant {
+    process: Arc<RwLock<Process>>,
+    window: Arc<Mutex<GrantWindow>>,
+    start: u64,
+    pages: u64,
+    offset: u64,
+}
+
+impl Grant {
+    /// Lends `len` bytes at `addr`, writable by the borrower if `writable`.
+    pub fn new(
+        process: Arc<RwLock<Process>>,
+        window: Arc<Mutex<GrantWindow>>,
+        addr: usize,
+        len: usize,
+        writable: bool,
+    ) -> Result<Self, usize> {
+        let first = Page::<Size4KiB>::containing_address(VirtAddr::new(addr as u64));
+        let last =
+            Page::<Size4KiB>::containing_address(VirtAddr::new((addr + len.max(1) - 1) as u64));
+        let pages = last - first + 1;
+
+        let start = window.lock().allocate(pages).ok_or(ENOMEM)?;
+        let grant = Self {
+            process,
+            window,
+            start,
+            pages,
+            offset: addr as u64 % Size4KiB::SIZE,
+        };
+
+        let mut flags = PageTableFlags::PRESENT
+            | PageTableFlags::USER_ACCESSIBLE
+            | PageTableFlags::NO_EXECUTE
+            | BORROWED;
+        if writable {
+            flags |= PageTableFlags::WRITABLE;
+        }
+
+        let source = ref_current_page_table();
+        interrupts::without_interrupts(|| {
+            let mut process = grant.process.write();
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            for (index, page) in Page::range_inclusive(first, last).enumerate() {
+                let frame = source.translate_page(page).map_err(|_| EFAULT)?;
+                let target =
+                    Page::containing_address(VirtAddr::new(start + index as u64 * Size4KiB::SIZE));
+                unsafe {
+                    process
+                        .page_table
+                        .map_to(target, frame, flags, &mut *frame_allocator)
+                        .map_err(|_| ENOMEM)?
+                        .ignore();
+                }
+            }
+            Ok::<(), usize>(())
+        })?;
+
+        Ok(grant)
+    }
+
+    /// Where the buffer appears in the borrower.
+    pub fn addr(&self) -> usize {
+        (self.start + self.offset) as usize
+    }
+}
+
+impl Drop for Grant {
+    /// Only borrowed entries are removed, in case lending failed part way
+    /// because the borrower had mapped something of its own there.
+    fn drop(&mut self) {
+        interrupts::without_interrupts(|| {
+            let mut process = self.process.write();
+            for index in 0..self.pages {
+                let address = VirtAddr::new(self.start + index * Size4KiB::SIZE);
+                let borrowed = matches!(
+                    process.page_table.translate(address),
+                    TranslateResult::Mapped { flags, .. } if flags.contains(BORROWED)
+                );
+                if !borrowed {
+                    continue;
+                }
+                let page = Page::<Size4KiB>::containing_address(address);
+                if let Ok((_, flush)) = process.page_table.unmap(page) {
+                    flush.flush();
+                }
+            }
+        });
+        self.window.lock().free(self.start);
+    }
+}
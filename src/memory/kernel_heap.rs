@@ -1,3 +1,5 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use good_memory_allocator::SpinLockedAllocator;
 use x86_64::{PhysAddr, VirtAddr};
 
@@ -7,7 +9,54 @@ pub const HEAP_START: usize = 0x1145_1400_0000;
 pub const HEAP_SIZE: usize = 256 * 1024 * 1024;
 
 #[global_allocator]
-pub static KERNEL_ALLOCATOR: SpinLockedAllocator = SpinLockedAllocator::empty();
+pub static KERNEL_ALLOCATOR: KernelAllocator = KernelAllocator::empty();
+
+/// The heap allocator, counting the bytes handed out for `/proc/meminfo`.
+pub struct KernelAllocator {
+    inner: SpinLockedAllocator,
+    used: AtomicUsize,
+}
+
+impl KernelAllocator {
+    const fn empty() -> Self {
+        Self {
+            inner: SpinLockedAllocator::empty(),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    unsafe fn init(&self, start: usize, size: usize) {
+        unsafe { self.inner.init(start, size) };
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            self.used.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        self.used.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            self.used.fetch_add(new_size, Ordering::Relaxed);
+            self.used.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
 
 pub fn init_heap() {
     let page_table_addr =
@@ -0,0 +1,155 @@
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame,
+    Size4KiB, Translate,
+};
+
+use super::shared::release;
+use super::transfer::pages;
+use super::{
+    BORROWED, BitmapFrameAllocator, ExtendedPageTable, FRAME_ALLOCATOR, HEAP_SIZE, HEAP_START,
+    convert_physical_to_virtual,
+};
+use crate::syscall::errno::{EINVAL, ENOMEM};
+use crate::task::get_current_process;
+
+/// Where mappings without a fixed address go, below the grant window.
+const MMAP_START: u64 = 0x6000_0000_0000;
+const MMAP_END: u64 = 0x7000_0000_0000;
+
+/// Allocates `count` frames filled with zeroes, or none at all.
+pub fn allocate_zeroed(count: usize) -> Result<Vec<PhysFrame>, usize> {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mut frames = Vec::with_capacity(count);
+    for _ in 0..count {
+        let Some(frame) = frame_allocator.allocate_frame() else {
+            for frame in frames {
+                unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+            return Err(ENOMEM);
+        };
+        let address = convert_physical_to_virtual(frame.start_address());
+        unsafe { core::ptr::write_bytes(address.as_mut_ptr::<u8>(), 0, 4096) };
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Finds `len` bytes of address space nothing is mapped in.
+pub fn find_free(len: usize) -> Result<usize, usize> {
+    let len = len as u64;
+    let regions = get_current_process().read().page_table.user_regions();
+
+    let mut start = MMAP_START;
+    for region in regions {
+        let (region_start, region_end) = (region.start.as_u64(), region.end.as_u64());
+        if region_end <= start {
+            continue;
+        }
+        if region_start >= start + len {
+            break;
+        }
+        start = region_end;
+    }
+    match start + len <= MMAP_END {
+        true => Ok(start as usize),
+        false => Err(ENOMEM),
+    }
+}
+
+/// Maps `frames` one after another at `addr` in the current process. Each
+/// frame comes with a reference the mapping takes over: ownership of a
+/// private frame, or a count on a [`super::SHARED`] one. If mapping fails,
+/// all of them are released again.
+pub fn map(addr: usize, frames: Vec<PhysFrame>, flags: PageTableFlags) -> Result<(), usize> {
+    let pages = pages(addr, frames.len() * 4096);
+
+    let process = get_current_process();
+    interrupts::without_interrupts(|| {
+        let mut process = process.write();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let pages = match pages {
+            Ok(pages) => pages,
+            Err(err) => {
+                for frame in frames {
+                    release(frame, flags, &mut frame_allocator);
+                }
+                return Err(err);
+            }
+        };
+        let mut frames = frames.into_iter();
+        for (page, frame) in pages.zip(&mut frames) {
+            let mapped = unsafe {
+                process
+                    .page_table
+                    .map_to(page, frame, flags, &mut *frame_allocator)
+            };
+            match mapped {
+                Ok(flush) => flush.flush(),
+                Err(_) => {
+                    release(frame, flags, &mut frame_allocator);
+                    for frame in frames {
+                        release(frame, flags, &mut frame_allocator);
+                    }
+                    let mapped = Page::range(pages.start, page);
+                    unmap_pages(&mut process.page_table, &mut frame_allocator, mapped);
+                    return Err(ENOMEM);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The pages [`unmap`] would clear in the `len` bytes at `addr`, if it may.
+pub fn unmappable(addr: usize, len: usize) -> Result<PageRange<Size4KiB>, usize> {
+    let pages = pages(addr, len.next_multiple_of(4096))?;
+    let heap = HEAP_START..HEAP_START + HEAP_SIZE;
+    if addr < heap.end && heap.start < addr + len {
+        return Err(EINVAL);
+    }
+    Ok(pages)
+}
+
+/// Removes the current process's mappings in the `len` bytes at `addr`,
+/// which may have holes. Borrowed pages are left to their lender.
+pub fn unmap(addr: usize, len: usize) -> Result<(), usize> {
+    unmap_range(unmappable(addr, len)?);
+    Ok(())
+}
+
+/// Like [`unmap`], for pages already checked with [`unmappable`].
+pub fn unmap_range(pages: PageRange<Size4KiB>) {
+    let process = get_current_process();
+    interrupts::without_interrupts(|| {
+        let mut process = process.write();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        unmap_pages(&mut process.page_table, &mut frame_allocator, pages);
+    });
+}
+
+fn unmap_pages(
+    page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut BitmapFrameAllocator,
+    pages: PageRange<Size4KiB>,
+) {
+    for page in pages {
+        let flags = match page_table.translate(page.start_address()) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(_),
+                flags,
+                ..
+            } if flags.contains(PageTableFlags::USER_ACCESSIBLE) && !flags.contains(BORROWED) => {
+                flags
+            }
+            _ => continue,
+        };
+        if let Ok((frame, flush)) = page_table.unmap(page) {
+            flush.flush();
+            release(frame, flags, frame_allocator);
+        }
+    }
+}
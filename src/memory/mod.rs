@@ -7,15 +7,23 @@ use x86_64::{PhysAddr, VirtAddr};
 mod bitmap;
 mod dma;
 mod frame;
+mod grant;
 mod kernel_heap;
 mod manager;
+mod mapping;
 mod page_table;
+mod shared;
+mod transfer;
 
 pub use dma::DmaManager;
 pub use frame::BitmapFrameAllocator;
-pub use kernel_heap::{KERNEL_ALLOCATOR, init_heap};
+pub use grant::{BORROWED, Grant, GrantWindow};
+pub use kernel_heap::{HEAP_SIZE, HEAP_START, KERNEL_ALLOCATOR, init_heap};
 pub use manager::{MappingType, MemoryManager};
+pub use mapping::{allocate_zeroed, find_free, map, unmap, unmap_range, unmappable};
 pub use page_table::*;
+pub use shared::{SHARED, share, unshare, writers};
+pub use transfer::{Transfer, USER_END, check_unmapped};
 
 #[used]
 #[unsafe(link_section = ".requests")]
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use x86_64::structures::paging::FrameAllocator;
 use x86_64::structures::paging::FrameDeallocator;
 use x86_64::structures::paging::PhysFrame;
@@ -7,6 +8,8 @@ use x86_64::{PhysAddr, VirtAddr};
 
 use super::FRAME_ALLOCATOR;
 use super::MappingType;
+use super::grant::BORROWED;
+use super::shared::{SHARED, release};
 use super::{BitmapFrameAllocator, PHYSICAL_MEMORY_OFFSET, convert_physical_to_virtual};
 
 pub trait ExtendedPageTable {
@@ -15,6 +18,15 @@ pub trait ExtendedPageTable {
     fn read_mapped_address(&self, buffer: &mut [u8], address: VirtAddr);
     unsafe fn deep_copy(&self) -> OffsetPageTable<'static>;
     unsafe fn free_user_page_table(&self);
+    fn user_regions(&self) -> Vec<MappedRegion>;
+}
+
+/// A run of contiguous user-accessible pages sharing the same flags.
+#[derive(Debug, Clone, Copy)]
+pub struct MappedRegion {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub flags: PageTableFlags,
 }
 
 impl ExtendedPageTable for OffsetPageTable<'_> {
@@ -61,6 +73,12 @@ impl ExtendedPageTable for OffsetPageTable<'_> {
         let mut frame_allocator = FRAME_ALLOCATOR.lock();
         free_from_recursion(&mut frame_allocator, self.physical_address(), 4);
     }
+
+    fn user_regions(&self) -> Vec<MappedRegion> {
+        let mut regions = Vec::new();
+        regions_from_recursion(&mut regions, self.level_4_table(), 4, 0);
+        regions
+    }
 }
 
 unsafe fn new_from_allocate(
@@ -128,10 +146,15 @@ unsafe fn free_from_recursion(
         }
 
         if page_table_level == 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            if entry.flags().contains(MappingType::UserCode.flags()) {
-                if let Ok(frame) = entry.frame() {
-                    frame_allocator.deallocate_frame(frame);
-                }
+            let flags = entry.flags();
+            let Ok(frame) = entry.frame() else {
+                continue;
+            };
+            // A shared frame outlives this mapping unless it was the last.
+            if flags.contains(SHARED) {
+                release(frame, flags, frame_allocator);
+            } else if flags.contains(MappingType::UserCode.flags()) && !flags.contains(BORROWED) {
+                frame_allocator.deallocate_frame(frame);
             }
         } else {
             free_from_recursion(frame_allocator, entry.addr(), page_table_level - 1);
@@ -141,6 +164,47 @@ unsafe fn free_from_recursion(
     frame_allocator.deallocate_frame(PhysFrame::containing_address(physical_address));
 }
 
+fn regions_from_recursion(
+    regions: &mut Vec<MappedRegion>,
+    page_table: &PageTable,
+    page_table_level: u8,
+    base: u64,
+) {
+    let entry_size = 4096u64 << (9 * (page_table_level as u64 - 1));
+
+    // Only the lower half belongs to user space.
+    let entries = if page_table_level == 4 { 256 } else { 512 };
+
+    for (index, entry) in page_table.iter().enumerate().take(entries) {
+        let flags = entry.flags();
+        if entry.is_unused() || !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            continue;
+        }
+
+        let start = base + index as u64 * entry_size;
+        if page_table_level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            let flags = flags
+                & (PageTableFlags::PRESENT
+                    | PageTableFlags::WRITABLE
+                    | PageTableFlags::USER_ACCESSIBLE
+                    | PageTableFlags::NO_EXECUTE);
+            match regions.last_mut() {
+                Some(last) if last.end.as_u64() == start && last.flags == flags => {
+                    last.end = VirtAddr::new(start + entry_size);
+                }
+                _ => regions.push(MappedRegion {
+                    start: VirtAddr::new(start),
+                    end: VirtAddr::new(start + entry_size),
+                    flags,
+                }),
+            }
+        } else {
+            let next = unsafe { &*convert_physical_to_virtual(entry.addr()).as_ptr() };
+            regions_from_recursion(regions, next, page_table_level - 1, start);
+        }
+    }
+}
+
 /// In syscall, we don't need to worry about page tables, because we are using the user page table.
 /// Use this function instead of `write` in syscall.
 pub fn write_for_syscall<T: Clone>(addr: VirtAddr, buf: &[T]) {
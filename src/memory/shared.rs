@@ -0,0 +1,70 @@
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameDeallocator, PageTableFlags, PhysFrame};
+
+use super::BitmapFrameAllocator;
+
+/// Marks page table entries for frames that may be mapped more than once.
+/// Such frames are counted in [`SHARED_FRAMES`] and only freed with their
+/// last reference.
+pub const SHARED: PageTableFlags = PageTableFlags::BIT_10;
+
+#[derive(Default)]
+struct References {
+    /// Mappings of the frame plus the object holding it, such as a memfd.
+    count: usize,
+    /// How many of the mappings are writable.
+    writers: usize,
+}
+
+/// Lock [`super::FRAME_ALLOCATOR`] first when both are needed.
+static SHARED_FRAMES: Mutex<BTreeMap<PhysFrame, References>> = Mutex::new(BTreeMap::new());
+
+/// Takes a reference to `frame`, through a writable mapping if `writable`.
+pub fn share(frame: PhysFrame, writable: bool) {
+    let mut frames = SHARED_FRAMES.lock();
+    let references = frames.entry(frame).or_default();
+    references.count += 1;
+    references.writers += writable as usize;
+}
+
+/// Drops a reference taken with [`share`], freeing the frame if it was the
+/// last one.
+pub fn unshare(frame: PhysFrame, writable: bool, frame_allocator: &mut BitmapFrameAllocator) {
+    let mut frames = SHARED_FRAMES.lock();
+    let Some(references) = frames.get_mut(&frame) else {
+        return;
+    };
+    references.count -= 1;
+    references.writers -= writable as usize;
+    if references.count == 0 {
+        frames.remove(&frame);
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+}
+
+/// How many writable mappings `frame` has.
+pub fn writers(frame: PhysFrame) -> usize {
+    SHARED_FRAMES
+        .lock()
+        .get(&frame)
+        .map_or(0, |references| references.writers)
+}
+
+/// Lets go of the frame a user page table entry with `flags` pointed at:
+/// a shared frame loses a reference, any other is freed.
+pub fn release(
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    frame_allocator: &mut BitmapFrameAllocator,
+) {
+    if flags.contains(SHARED) {
+        unshare(
+            frame,
+            flags.contains(PageTableFlags::WRITABLE),
+            frame_allocator,
+        );
+    } else {
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+}
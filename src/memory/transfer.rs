@@ -0,0 +1,143 @@
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{
+    Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+
+use super::shared::release;
+use super::{BORROWED, FRAME_ALLOCATOR, HEAP_SIZE, HEAP_START};
+use crate::syscall::errno::{EFAULT, EINVAL, ENOMEM};
+use crate::task::get_current_process;
+
+/// The end of the lower half, where user mappings live.
+pub const USER_END: u64 = 0x8000_0000_0000;
+
+/// The pages of `len` bytes at `addr`, both of which must be page aligned.
+pub(super) fn pages(addr: usize, len: usize) -> Result<PageRange<Size4KiB>, usize> {
+    let (addr, len) = (addr as u64, len as u64);
+    let end = addr.checked_add(len).ok_or(EINVAL)?;
+    if len == 0 || addr % Size4KiB::SIZE != 0 || len % Size4KiB::SIZE != 0 || end > USER_END {
+        return Err(EINVAL);
+    }
+    Ok(Page::range(
+        Page::containing_address(VirtAddr::new(addr)),
+        Page::containing_address(VirtAddr::new(end)),
+    ))
+}
+
+/// Fails unless nothing is mapped in the `len` bytes at `addr`.
+pub fn check_unmapped(addr: usize, len: usize) -> Result<(), usize> {
+    let pages = pages(addr, len)?;
+    let process = get_current_process();
+    let process = process.read();
+    for page in pages {
+        if !matches!(
+            process.page_table.translate(page.start_address()),
+            TranslateResult::NotMapped
+        ) {
+            return Err(EFAULT);
+        }
+    }
+    Ok(())
+}
+
+/// Pages taken out of the current address space to be placed in another
+/// one, so that memory changes hands without a copy. Frames that are never
+/// placed are released.
+pub struct Transfer {
+    frames: Vec<(PhysFrame, PageTableFlags)>,
+}
+
+impl Transfer {
+    /// Unmaps the `len` bytes at `addr`. Nothing is touched unless every
+    /// page is mapped and the process's own, which rules out the kernel
+    /// heap and borrowed pages.
+    pub fn take(addr: usize, len: usize) -> Result<Self, usize> {
+        let pages = pages(addr, len)?;
+        let heap = HEAP_START as u64..(HEAP_START + HEAP_SIZE) as u64;
+
+        let process = get_current_process();
+        interrupts::without_interrupts(|| {
+            let mut process = process.write();
+            let mut flags = Vec::new();
+            for page in pages {
+                if heap.contains(&page.start_address().as_u64()) {
+                    return Err(EFAULT);
+                }
+                match process.page_table.translate(page.start_address()) {
+                    TranslateResult::Mapped {
+                        frame: MappedFrame::Size4KiB(_),
+                        flags: page_flags,
+                        ..
+                    } if page_flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                        && !page_flags.contains(BORROWED) =>
+                    {
+                        flags.push(page_flags)
+                    }
+                    _ => return Err(EFAULT),
+                }
+            }
+
+            let mut frames = Vec::with_capacity(flags.len());
+            for (page, flags) in pages.zip(flags) {
+                let (frame, flush) = process.page_table.unmap(page).map_err(|_| EFAULT)?;
+                flush.flush();
+                frames.push((frame, flags));
+            }
+            Ok(Self { frames })
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len() * Size4KiB::SIZE as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Maps the pages at `addr` in the current address space, with the
+    /// flags they had in the old one. Check the range with
+    /// [`check_unmapped`] first.
+    pub fn place(mut self, addr: usize) -> Result<(), usize> {
+        let pages = pages(addr, self.len())?;
+        let frames = core::mem::take(&mut self.frames);
+
+        let process = get_current_process();
+        interrupts::without_interrupts(|| {
+            let mut process = process.write();
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            let mut frames = frames.into_iter();
+            for (page, (frame, flags)) in pages.zip(&mut frames) {
+                let mapped = unsafe {
+                    process
+                        .page_table
+                        .map_to(page, frame, flags, &mut *frame_allocator)
+                };
+                match mapped {
+                    Ok(flush) => flush.flush(),
+                    Err(_) => {
+                        release(frame, flags, &mut frame_allocator);
+                        for (frame, flags) in frames {
+                            release(frame, flags, &mut frame_allocator);
+                        }
+                        return Err(ENOMEM);
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        for (frame, flags) in self.frames.drain(..) {
+            release(frame, flags, &mut frame_allocator);
+        }
+    }
+}
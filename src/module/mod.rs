@@ -1,5 +1,10 @@
 use limine::{modules::InternalModule, request::ModuleRequest};
 
+use crate::{
+    syscall::errno::ENOENT,
+    task::{capability::Capabilities, credentials::Credentials},
+};
+
 #[used]
 #[unsafe(link_section = ".requests")]
 static MODULE_REQUEST: ModuleRequest = ModuleRequest::new().with_internal_modules(&[
@@ -10,32 +15,65 @@ static MODULE_REQUEST: ModuleRequest = ModuleRequest::new().with_internal_module
     &InternalModule::new().with_path(limine::cstr!("/drv/fsmd")),
     &InternalModule::new().with_path(limine::cstr!("/drv/nvmed")),
     &InternalModule::new().with_path(limine::cstr!("/usr/init")),
+    &InternalModule::new().with_path(limine::cstr!("/initramfs")),
+    &InternalModule::new().with_path(limine::cstr!("/rootfs.img")),
+    &InternalModule::new().with_path(limine::cstr!("/font.psf")),
 ]);
 
-fn load_module(module: &&limine::file::File) {
+const BOOT_DRIVERS: &[&str] = &["/drv/acpid", "/drv/pcid", "/drv/ps2d", "/drv/fbd", "/drv/fsmd"];
+
+fn load_module(
+    module: &limine::file::File,
+    capabilities: Capabilities,
+    credentials: Credentials,
+) -> Result<(), usize> {
     super::task::process::Process::create(
         unsafe { str::from_utf8_unchecked(module.path()) },
         unsafe { core::slice::from_raw_parts(module.addr() as *const u8, module.size() as usize) },
-    );
+        capabilities,
+        credentials,
+    )
+    .map(|_| ())
 }
 
+pub fn get_module(path: &str) -> Option<&'static [u8]> {
+    let module = MODULE_REQUEST
+        .get_response()?
+        .modules()
+        .iter()
+        .find(|module| module.path() == path.as_bytes())?;
+
+    Some(unsafe { core::slice::from_raw_parts(module.addr() as *const u8, module.size() as usize) })
+}
+
+/// Boot drivers are trusted with everything and run as root.
 pub fn load_all_module() {
-    for module in MODULE_REQUEST.get_response().unwrap().modules() {
-        if module.path() == "/drv/acpid".as_bytes()
-            || module.path() == "/drv/pcid".as_bytes()
-            || module.path() == "/drv/ps2d".as_bytes()
-            || module.path() == "/drv/fbd".as_bytes()
-            || module.path() == "/drv/fsmd".as_bytes()
-        {
-            load_module(module);
+    for path in BOOT_DRIVERS {
+        if let Err(err) = load_named_module(path, Capabilities::all(), &Credentials::root()) {
+            log::error!("Failed to start {}: errno {}", path, err);
         }
     }
 }
 
-pub fn load_named_module(path: &str) {
-    for module in MODULE_REQUEST.get_response().unwrap().modules() {
-        if module.path() == path.as_bytes() {
-            load_module(module);
-        }
+/// Starts the program at `path` with `capabilities` as `credentials`,
+/// preferring the file in the root filesystem over the limine module of the
+/// same name, which is only used if there is no such file.
+pub fn load_named_module(
+    path: &str,
+    capabilities: Capabilities,
+    credentials: &Credentials,
+) -> Result<(), usize> {
+    match crate::task::process::exec(path, capabilities.clone(), credentials) {
+        Err(ENOENT) => {}
+        result => return result.map(|_| ()),
     }
+
+    let module = MODULE_REQUEST
+        .get_response()
+        .ok_or(ENOENT)?
+        .modules()
+        .iter()
+        .find(|module| module.path() == path.as_bytes())
+        .ok_or(ENOENT)?;
+    load_module(module, capabilities, credentials.clone())
 }
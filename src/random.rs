@@ -0,0 +1,158 @@
+use spin::{Lazy, Mutex};
+use x86::cpuid::CpuId;
+
+use crate::acpi::hpet::HPET;
+
+/// Backs `/dev/random` and `/dev/urandom`.
+static POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool::new());
+
+static HAS_RDRAND: Lazy<bool> = Lazy::new(|| {
+    CpuId::new()
+        .get_feature_info()
+        .is_some_and(|features| features.has_rdrand())
+});
+
+static HAS_RDSEED: Lazy<bool> = Lazy::new(|| {
+    CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|features| features.has_rdseed())
+});
+
+/// Samples from RDSEED/RDRAND and the timestamps of interrupts are folded
+/// into a 256-bit key. Output is ChaCha20 keystream under that key, and the
+/// key is replaced by fresh keystream after every request, so earlier output
+/// can't be worked out from a later state of the pool.
+struct EntropyPool {
+    key: [u32; 8],
+    nonce: u64,
+    samples: usize,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            nonce: 0,
+            samples: 0,
+        }
+    }
+
+    fn mix(&mut self, sample: u64) {
+        let slot = self.samples % 4 * 2;
+        self.key[slot] ^= sample as u32;
+        self.key[slot + 1] ^= (sample >> 32) as u32;
+        self.samples += 1;
+
+        // Spread the sample over the whole key.
+        for _ in 0..2 {
+            quarter_round(&mut self.key, 0, 2, 4, 6);
+            quarter_round(&mut self.key, 1, 3, 5, 7);
+            quarter_round(&mut self.key, 0, 3, 4, 7);
+            quarter_round(&mut self.key, 1, 2, 5, 6);
+        }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        let mut counter = 0;
+        for chunk in buf.chunks_mut(64) {
+            let block = self.block(counter);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            counter += 1;
+        }
+
+        let block = self.block(counter);
+        for (word, bytes) in self.key.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        self.nonce += 1;
+    }
+
+    fn block(&self, counter: u64) -> [u8; 64] {
+        let mut input = [0u32; 16];
+        input[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+        input[4..12].copy_from_slice(&self.key);
+        input[12] = counter as u32;
+        input[13] = (counter >> 32) as u32;
+        input[14] = self.nonce as u32;
+        input[15] = (self.nonce >> 32) as u32;
+
+        let mut state = input;
+        for _ in 0..10 {
+            quarter_round(&mut state, 0, 4, 8, 12);
+            quarter_round(&mut state, 1, 5, 9, 13);
+            quarter_round(&mut state, 2, 6, 10, 14);
+            quarter_round(&mut state, 3, 7, 11, 15);
+            quarter_round(&mut state, 0, 5, 10, 15);
+            quarter_round(&mut state, 1, 6, 11, 12);
+            quarter_round(&mut state, 2, 7, 8, 13);
+            quarter_round(&mut state, 3, 4, 9, 14);
+        }
+
+        let mut output = [0u8; 64];
+        for (index, bytes) in output.chunks_exact_mut(4).enumerate() {
+            let word = state[index].wrapping_add(input[index]);
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        output
+    }
+}
+
+fn quarter_round(state: &mut [u32], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn timestamp() -> u64 {
+    unsafe { x86::time::rdtsc() }
+}
+
+fn hardware_sample() -> Option<u64> {
+    let mut sample = 0;
+    let ok = unsafe {
+        (*HAS_RDSEED && x86::random::rdseed64(&mut sample))
+            || (*HAS_RDRAND && x86::random::rdrand64(&mut sample))
+    };
+    ok.then_some(sample)
+}
+
+/// Seeds the pool. Needs the HPET.
+pub fn init() {
+    let mut pool = POOL.lock();
+    for _ in 0..16 {
+        if let Some(sample) = hardware_sample() {
+            pool.mix(sample);
+        }
+        pool.mix(timestamp() ^ HPET.ticks().rotate_left(32));
+    }
+
+    if !*HAS_RDSEED && !*HAS_RDRAND {
+        log::warn!("No hardware random number generator, seeding from timers only");
+    }
+}
+
+pub fn add_entropy(sample: u64) {
+    POOL.lock().mix(sample);
+}
+
+/// Mixes in the time of an interrupt. Skips the sample rather than spin if
+/// the pool is in use, since the interrupted code may be holding it.
+pub fn add_interrupt_entropy() {
+    if let Some(mut pool) = POOL.try_lock() {
+        pool.mix(timestamp());
+    }
+}
+
+pub fn fill(buf: &mut [u8]) {
+    let mut pool = POOL.lock();
+    if let Some(sample) = hardware_sample() {
+        pool.mix(sample);
+    }
+    pool.mix(timestamp());
+    pool.fill(buf);
+}
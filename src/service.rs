@@ -0,0 +1,200 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, RwLock};
+
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode, get_flags_by_fd},
+        poll::{PollEvents, PollTable},
+        vfs::inode::{Inode, InodeRef, InodeTy},
+    },
+    syscall::errno::{EAGAIN, EEXIST, EINVAL, ENOENT, EPERM},
+    task::{get_current_process_id, process::ProcessId, wait_queue::WaitQueue},
+};
+
+/// Lookup and watch flag: wait for the name to be registered.
+pub const SERVICE_WAIT: usize = 1;
+
+/// An open file handed to everyone who looks the name up, typically one
+/// end of a channel whose other end the provider serves.
+pub type Endpoint = (InodeRef, OpenMode, OpenFlags);
+
+/// Whether one registration is still in place.
+struct Presence {
+    gone: AtomicBool,
+    queue: Arc<WaitQueue>,
+}
+
+struct Service {
+    provider: ProcessId,
+    endpoint: Option<Endpoint>,
+    presence: Arc<Presence>,
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        self.presence.gone.store(true, Ordering::SeqCst);
+        self.presence.queue.wake_all();
+    }
+}
+
+/// The name service: processes publish endpoints under a name, and others
+/// look them up, wait for them to appear, or watch for their provider to go
+/// away. Names disappear with the process that registered them.
+static SERVICES: Mutex<BTreeMap<String, Service>> = Mutex::new(BTreeMap::new());
+/// Woken whenever a name is registered.
+static REGISTERED: WaitQueue = WaitQueue::new();
+
+/// Publishes `endpoint` as `name` for the calling process.
+pub fn register(name: &str, endpoint: Option<Endpoint>) -> Result<(), usize> {
+    if name.is_empty() {
+        return Err(EINVAL);
+    }
+
+    let mut services = SERVICES.lock();
+    if services.contains_key(name) {
+        return Err(EEXIST);
+    }
+    services.insert(
+        name.to_string(),
+        Service {
+            provider: get_current_process_id(),
+            endpoint,
+            presence: Arc::new(Presence {
+                gone: AtomicBool::new(false),
+                queue: Arc::new(WaitQueue::new()),
+            }),
+        },
+    );
+    drop(services);
+
+    REGISTERED.wake_all();
+    Ok(())
+}
+
+/// Withdraws `name`, which the calling process must have registered.
+pub fn unregister(name: &str) -> Result<(), usize> {
+    let mut services = SERVICES.lock();
+    match services.get(name) {
+        Some(service) if service.provider == get_current_process_id() => {}
+        Some(_) => return Err(EPERM),
+        None => return Err(ENOENT),
+    }
+    let service = services.remove(name);
+    drop(services);
+    drop(service);
+    Ok(())
+}
+
+/// The process providing `name`.
+pub fn provider(name: &str) -> Option<ProcessId> {
+    SERVICES.lock().get(name).map(|service| service.provider)
+}
+
+/// Runs `f` on the service `name`, waiting for it to be registered if
+/// `wait` is set.
+fn with_service<T>(name: &str, wait: bool, f: impl Fn(&Service) -> T) -> Result<T, usize> {
+    let mut found = None;
+    let result = REGISTERED.wait_interruptible(|| match SERVICES.lock().get(name) {
+        Some(service) => {
+            found = Some(f(service));
+            Some(0)
+        }
+        None if wait => None,
+        None => Some(ENOENT.wrapping_neg()),
+    });
+    found.ok_or(result.wrapping_neg())
+}
+
+/// Finds the provider of `name` and its endpoint, if it published one.
+pub fn lookup(name: &str, wait: bool) -> Result<(ProcessId, Option<Endpoint>), usize> {
+    with_service(name, wait, |service| {
+        (service.provider, service.endpoint.clone())
+    })
+}
+
+/// Opens a file that becomes readable, yielding the provider's pid, once
+/// the current registration of `name` is gone.
+pub fn watch(name: &str, wait: bool) -> Result<InodeRef, usize> {
+    let (provider, presence) = with_service(name, wait, |service| {
+        (service.provider, service.presence.clone())
+    })?;
+    Ok(Arc::new(RwLock::new(ServiceWatch {
+        path: alloc::format!("service:[{}]", name),
+        provider,
+        presence,
+    })))
+}
+
+/// Takes down every name `pid` registered, returning them.
+pub fn provider_exited(pid: ProcessId) -> Vec<String> {
+    let mut services = SERVICES.lock();
+    let removed = services
+        .extract_if(.., |_, service| service.provider == pid)
+        .collect::<Vec<_>>();
+    drop(services);
+
+    removed.into_iter().map(|(name, _)| name).collect()
+}
+
+/// What [`watch`] returns.
+pub struct ServiceWatch {
+    path: String,
+    provider: ProcessId,
+    presence: Arc<Presence>,
+}
+
+impl Inode for ServiceWatch {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::File
+    }
+
+    /// Waits for the registration to go, then reads the provider's pid.
+    fn read_at(&self, fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
+        let pid = self.provider.0.to_ne_bytes();
+        if buf.len() < pid.len() {
+            return EINVAL.wrapping_neg();
+        }
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+        let result = self.presence.queue.wait_interruptible(|| {
+            match self.presence.gone.load(Ordering::SeqCst) {
+                true => Some(0),
+                false if nonblock => Some(EAGAIN.wrapping_neg()),
+                false => None,
+            }
+        });
+        if result != 0 {
+            return result;
+        }
+        buf[..pid.len()].copy_from_slice(&pid);
+        pid.len()
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        table.register(&self.presence.queue);
+        match self.presence.gone.load(Ordering::SeqCst) {
+            true => PollEvents::IN | PollEvents::RDNORM | PollEvents::HUP,
+            false => PollEvents::empty(),
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        0o400
+    }
+}
@@ -0,0 +1,46 @@
+// Linux error numbers, returned negated from syscalls.
+
+pub const EPERM: usize = 1;
+pub const ENOENT: usize = 2;
+pub const ESRCH: usize = 3;
+pub const EINTR: usize = 4;
+pub const EIO: usize = 5;
+pub const ENXIO: usize = 6;
+pub const E2BIG: usize = 7;
+pub const ENOEXEC: usize = 8;
+pub const EBADF: usize = 9;
+pub const ECHILD: usize = 10;
+pub const EAGAIN: usize = 11;
+pub const ENOMEM: usize = 12;
+pub const EACCES: usize = 13;
+pub const EFAULT: usize = 14;
+pub const ENOTBLK: usize = 15;
+pub const EBUSY: usize = 16;
+pub const EEXIST: usize = 17;
+pub const EXDEV: usize = 18;
+pub const ENODEV: usize = 19;
+pub const ENOTDIR: usize = 20;
+pub const EISDIR: usize = 21;
+pub const EINVAL: usize = 22;
+pub const ENFILE: usize = 23;
+pub const EMFILE: usize = 24;
+pub const ENOTTY: usize = 25;
+pub const ETXTBSY: usize = 26;
+pub const EFBIG: usize = 27;
+pub const ENOSPC: usize = 28;
+pub const ESPIPE: usize = 29;
+pub const EROFS: usize = 30;
+pub const EMLINK: usize = 31;
+pub const EPIPE: usize = 32;
+pub const ERANGE: usize = 34;
+pub const ENAMETOOLONG: usize = 36;
+pub const ENOSYS: usize = 38;
+pub const ENOTEMPTY: usize = 39;
+pub const ELOOP: usize = 40;
+pub const EMSGSIZE: usize = 90;
+pub const ETIMEDOUT: usize = 110;
+
+/// Converts an error number into a syscall return value.
+pub fn errno(err: usize) -> isize {
+    -(err as isize)
+}
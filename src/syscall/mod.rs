@@ -59,6 +59,18 @@ const SYS_DIR_ITEMNUM: usize = 10006;
 const SYS_LOAD_DRIVER: usize = 10007;
 const SYS_DMA_ALLOCATE: usize = 10008;
 const SYS_DMA_DEALLOCATE: usize = 10009;
+const SYS_REGISTER_DEVICE: usize = 10010;
+const SYS_USERFS_WAIT: usize = 10011;
+const SYS_CHANNEL_CREATE: usize = 10012;
+const SYS_CHANNEL_SEND: usize = 10013;
+const SYS_CHANNEL_RECV: usize = 10014;
+const SYS_CHANNEL_CALL: usize = 10015;
+const SYS_CHANNEL_REPLY: usize = 10016;
+const SYS_SERVICE_REGISTER: usize = 10017;
+const SYS_SERVICE_UNREGISTER: usize = 10018;
+const SYS_SERVICE_LOOKUP: usize = 10019;
+const SYS_SERVICE_WATCH: usize = 10020;
+const SYS_RESTRICT_CAPABILITIES: usize = 10021;
 
 fn syscall_matcher(regs: &mut Context) {
     let arg1 = regs.rdi;
@@ -73,7 +85,27 @@ fn syscall_matcher(regs: &mut Context) {
     let ret = match syscall_num {
         SCHED_YIELD => sys_yield(),
         EXIT => sys_exit(arg1),
-        WAIT4 => sys_wait4(arg1),
+        WAIT4 => sys_wait4(arg1, arg2, arg3),
+        SETSID => sys_setsid(),
+        SETPGID => sys_setpgid(arg1, arg2),
+        GETPGID => sys_getpgid(arg1),
+        GETPGRP => sys_getpgid(0),
+        GETSID => sys_getsid(arg1),
+        GETUID => sys_getuid(),
+        GETEUID => sys_geteuid(),
+        GETGID => sys_getgid(),
+        GETEGID => sys_getegid(),
+        SETUID => sys_setuid(arg1),
+        SETGID => sys_setgid(arg1),
+        SETREUID => sys_setreuid(arg1, arg2),
+        SETREGID => sys_setregid(arg1, arg2),
+        SETRESUID => sys_setresuid(arg1, arg2, arg3),
+        SETRESGID => sys_setresgid(arg1, arg2, arg3),
+        GETRESUID => sys_getresuid(arg1, arg2, arg3),
+        GETRESGID => sys_getresgid(arg1, arg2, arg3),
+        GETGROUPS => sys_getgroups(arg1, arg2),
+        SETGROUPS => sys_setgroups(arg1, arg2),
+        UMASK => sys_umask(arg1),
         FORK => sys_fork(regs),
         VFORK => sys_fork(regs),
 
@@ -83,8 +115,49 @@ fn syscall_matcher(regs: &mut Context) {
         WRITE => sys_write(arg1, arg2, arg3),
         LSEEK => sys_lseek(arg1, arg2),
         FSTAT => sys_fstat(arg1, arg2),
+        STAT => sys_stat(arg1, arg2),
+        LSTAT => sys_lstat(arg1, arg2),
+        NEWFSTATAT => sys_newfstatat(arg1, arg2, arg3, arg4),
+        STATFS => sys_statfs(arg1, arg2),
+        FSTATFS => sys_fstatfs(arg1, arg2),
+        GETDENTS64 => sys_getdents64(arg1, arg2, arg3),
         PIPE => sys_pipe(arg1),
+        PIPE2 => sys_pipe2(arg1, arg2),
+        FCNTL => sys_fcntl(arg1, arg2, arg3),
         IOCTL => sys_ioctl(arg1, arg2, arg3),
+        FSYNC => sys_fsync(arg1),
+        FDATASYNC => sys_fsync(arg1),
+        SYNC => sys_sync(),
+        MOUNT => sys_mount(arg1, arg2, arg3, arg4),
+        UMOUNT2 => sys_umount2(arg1, arg2),
+        MKDIR => sys_mkdir(arg1, arg2),
+        MKDIRAT => sys_mkdirat(arg1, arg2, arg3),
+        UNLINK => sys_unlink(arg1),
+        UNLINKAT => sys_unlinkat(arg1, arg2, arg3),
+        RMDIR => sys_rmdir(arg1),
+        RENAME => sys_rename(arg1, arg2),
+        RENAMEAT => sys_renameat(arg1, arg2, arg3, arg4),
+        TRUNCATE => sys_truncate(arg1, arg2),
+        FTRUNCATE => sys_ftruncate(arg1, arg2),
+        CHMOD => sys_chmod(arg1, arg2),
+        FCHMOD => sys_fchmod(arg1, arg2),
+        FCHMODAT => sys_fchmodat(arg1, arg2, arg3),
+        CHOWN => sys_chown(arg1, arg2, arg3),
+        FCHOWN => sys_fchown(arg1, arg2, arg3),
+        LCHOWN => sys_lchown(arg1, arg2, arg3),
+        FCHOWNAT => sys_fchownat(arg1, arg2, arg3, arg4, arg5),
+        MEMFD_CREATE => sys_memfd_create(arg1, arg2),
+        MMAP => sys_mmap(arg1, arg2, arg3, arg4, arg5, arg6),
+        MUNMAP => sys_munmap(arg1, arg2),
+        MKNOD => sys_mknod(arg1, arg2, arg3),
+        MKNODAT => sys_mknodat(arg1, arg2, arg3, arg4),
+        POLL => sys_poll(arg1, arg2, arg3),
+        PPOLL => sys_ppoll(arg1, arg2, arg3, arg4),
+        SELECT => sys_select(arg1, arg2, arg3, arg4, arg5),
+        EPOLL_CREATE1 => sys_epoll_create1(arg1),
+        EPOLL_CTL => sys_epoll_ctl(arg1, arg2, arg3, arg4),
+        EPOLL_WAIT => sys_epoll_wait(arg1, arg2, arg3, arg4),
+        IOPERM => sys_ioperm(arg1, arg2, arg3),
 
         SYS_PUT_STRING => sys_putstring(arg1, arg2),
         SYS_MALLOC => sys_malloc(arg1, arg2),
@@ -93,14 +166,29 @@ fn syscall_matcher(regs: &mut Context) {
         SYS_LISTDIR => sys_listdir(arg1, arg2),
         SYS_FREE => sys_free(arg1, arg2, arg3),
         SYS_DIR_ITEMNUM => sys_dir_itemnum(arg1),
-        SYS_LOAD_DRIVER => sys_load_driver(arg1, arg2),
+        SYS_LOAD_DRIVER => sys_load_driver(arg1, arg2, arg3),
         SYS_DMA_ALLOCATE => sys_alloc_dma(arg1),
         SYS_DMA_DEALLOCATE => sys_dealloc_dma(arg1),
+        SYS_REGISTER_DEVICE => sys_register_device(arg1, arg2, arg3, arg4),
+        SYS_USERFS_WAIT => sys_userfs_wait(arg1),
+        SYS_CHANNEL_CREATE => sys_channel_create(arg1, arg2),
+        SYS_CHANNEL_SEND => sys_channel_send(arg1, arg2, arg3),
+        SYS_CHANNEL_RECV => sys_channel_recv(arg1, arg2, arg3),
+        SYS_CHANNEL_CALL => sys_channel_call(arg1, arg2, arg3),
+        SYS_CHANNEL_REPLY => sys_channel_reply(arg1, arg2),
+        SYS_SERVICE_REGISTER => sys_service_register(arg1, arg2),
+        SYS_SERVICE_UNREGISTER => sys_service_unregister(arg1),
+        SYS_SERVICE_LOOKUP => sys_service_lookup(arg1, arg2, arg3),
+        SYS_SERVICE_WATCH => sys_service_watch(arg1, arg2),
+        SYS_RESTRICT_CAPABILITIES => sys_restrict_capabilities(arg1),
 
         _ => -1,
     };
 
+    crate::task::signal::handle_pending_signals();
+
     regs.rax = ret as usize;
 }
 
+pub mod errno;
 pub mod op;
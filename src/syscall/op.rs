@@ -1,22 +1,58 @@
 use alloc::{string::ToString, sync::Arc};
+use core::{alloc::Layout, any::Any, time::Duration};
 use x86_64::{
     PhysAddr, VirtAddr,
-    structures::paging::{PhysFrame, Size4KiB},
+    structures::paging::{PageTableFlags, PhysFrame, Size4KiB},
 };
 
+use super::errno::{EACCES, EBADF, EFAULT, EINVAL, EMFILE, ENODEV, ENOENT, EPERM, ESRCH, errno};
 use crate::{
-    fs::{PATH_TO_PID, USER_FS_MANAGER, operation::OpenMode},
+    acpi::apic::LAPIC,
+    fs::{
+        USER_FS_MANAGER,
+        mount::MountFlags,
+        operation::{AT_FDCWD, OpenFlags, OpenMode},
+        poll::PollFd,
+        user::{self, UserFS},
+        vfs::{
+            channel::{CHANNEL_NONBLOCK, Channel, ChannelFS, ChannelMessage},
+            devfs,
+            epoll::{EPOLL_CTL_DEL, EpollEvent, EpollFS},
+            inode::InodeTy,
+            memfd::{MFD_ALLOW_SEALING, MFD_CLOEXEC, MemFS, Seals},
+            stat_struct::{S_IFIFO, S_IFMT, S_IFREG},
+        },
+    },
+    gdt::{IO_PORTS, IoBitmap},
     irq::InterruptIndex,
-    memory::{MappingType, MemoryManager, ref_current_page_table, write_for_syscall},
-    serial_print,
+    memory::{
+        BORROWED, DmaManager, MappingType, MemoryManager, PHYSICAL_MEMORY_OFFSET, SHARED,
+        ref_current_page_table, write_for_syscall,
+    },
+    service::{self, SERVICE_WAIT},
+    smp::CPUS,
     task::{
+        capability::{Capabilities, CapabilityGrant, Privileges},
         context::Context,
-        get_current_process_id, get_current_thread,
-        process::{PROCESSES, ProcessId},
+        credentials::{Credentials, Ids, NGROUPS_MAX},
+        get_current_process, get_current_process_id, get_current_thread,
+        process::{EXITED, PROCESSES, ProcessId, SharedProcess},
         scheduler::SCHEDULER,
+        thread::Thread,
     },
 };
 
+/// Borrows a NUL-terminated string from user memory.
+fn c_str(addr: usize) -> Option<&'static str> {
+    if addr == 0 {
+        return None;
+    }
+    let len = unsafe { core::ffi::CStr::from_ptr(addr as *const core::ffi::c_char) }
+        .to_bytes()
+        .len();
+    str::from_utf8(unsafe { core::slice::from_raw_parts(addr as *const u8, len) }).ok()
+}
+
 pub fn sys_yield() -> isize {
     unsafe {
         core::arch::asm!(
@@ -37,10 +73,13 @@ pub fn sys_exit(code: usize) -> isize {
         .and_then(|thread| thread.read().process.upgrade());
 
     if let Some(process) = process {
+        crate::fs::operation::remove_file_descriptor_manager(process.read().id);
+
         let mut scheduler = SCHEDULER.lock();
         for thread in process.read().threads.iter() {
             scheduler.remove(Arc::downgrade(thread));
         }
+        drop(scheduler);
         process.read().exit();
     }
 
@@ -50,53 +89,335 @@ pub fn sys_exit(code: usize) -> isize {
 pub fn sys_putstring(addr: usize, len: usize) -> isize {
     if let Ok(str) = unsafe { str::from_utf8(core::slice::from_raw_parts(addr as *const u8, len)) }
     {
-        serial_print!("{}", str);
+        crate::tty::console_write(str.as_bytes());
         return str.len() as isize;
     }
 
     0
 }
 
-pub fn sys_wait4(pid: usize) -> isize {
-    loop {
-        if PROCESSES
-            .read()
-            .iter()
-            .find(|process| process.read().id == ProcessId::from(pid as u64))
-            .is_none()
+/// Also report children that stopped, not only those that exited.
+const WUNTRACED: usize = 2;
+
+/// Waits for the process `pid` to exit, or with `WUNTRACED` to stop. Exit
+/// codes aren't kept, so `status` is only written for stops.
+pub fn sys_wait4(pid: usize, status: usize, options: usize) -> isize {
+    let pid = ProcessId::from(pid as u64);
+    let result = EXITED.wait_interruptible(|| {
+        let processes = PROCESSES.read();
+        let Some(process) = processes.iter().find(|process| process.read().id == pid) else {
+            return Some(0);
+        };
+
+        let mut process = process.write();
+        if options & WUNTRACED != 0
+            && !process.stop_reported
+            && let Some(signal) = process.stopped
         {
-            break;
+            process.stop_reported = true;
+            return Some(signal << 8 | 0x7f);
         }
+        None
+    });
+
+    if result > isize::MAX as usize {
+        return result as isize;
+    }
+    if result != 0 && status != 0 {
+        unsafe { *(status as *mut i32) = result as i32 };
+    }
+    pid.0 as isize
+}
+
+/// Looks up the process `pid`, where 0 is the caller.
+fn find_process(pid: usize) -> Option<SharedProcess> {
+    if pid == 0 {
+        return Some(get_current_process());
+    }
+    PROCESSES
+        .read()
+        .iter()
+        .find(|process| process.read().id == ProcessId::from(pid as u64))
+        .cloned()
+}
+
+/// Starts a new session led by the caller, without a controlling terminal.
+/// A process group leader can't, as its group would straddle two sessions.
+pub fn sys_setsid() -> isize {
+    let process = get_current_process();
+    let mut process = process.write();
+    let id = process.id;
+    if process.pgid == id {
+        return errno(EPERM);
+    }
+
+    process.pgid = id;
+    process.sid = id;
+    process.tty = None;
+    id.0 as isize
+}
+
+/// Moves the caller or one of its children into the group `pgid`, which must
+/// be in the same session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let current = get_current_process();
+    let (current_id, session) = {
+        let current = current.read();
+        (current.id, current.sid)
+    };
+    let Some(target) = find_process(pid) else {
+        return errno(ESRCH);
+    };
+    let (target_id, target_parent, target_session) = {
+        let target = target.read();
+        (target.id, target.parent, target.sid)
+    };
+    if target_id != current_id && target_parent != Some(current_id) {
+        return errno(ESRCH);
+    }
+    if target_session != session || target_session == target_id {
+        return errno(EPERM);
+    }
+
+    let pgid = match pgid {
+        0 => target_id,
+        pgid => ProcessId::from(pgid as u64),
+    };
+    let group_exists = PROCESSES.read().iter().any(|process| {
+        let process = process.read();
+        process.pgid == pgid && process.sid == session
+    });
+    if pgid != target_id && !group_exists {
+        return errno(EPERM);
+    }
+
+    target.write().pgid = pgid;
+    0
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    match find_process(pid) {
+        Some(process) => process.read().pgid.0 as isize,
+        None => errno(ESRCH),
+    }
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    match find_process(pid) {
+        Some(process) => process.read().sid.0 as isize,
+        None => errno(ESRCH),
+    }
+}
+
+/// An ID argument, where `-1` leaves the ID as it is.
+fn id_arg(id: usize) -> Option<u32> {
+    match id as u32 {
+        u32::MAX => None,
+        id => Some(id),
+    }
+}
+
+fn get_credentials<T>(f: impl FnOnce(&Credentials) -> T) -> T {
+    f(&get_current_process().read().credentials)
+}
+
+/// Runs `f` on the caller's user or group IDs, as `select` picks, telling
+/// it whether the caller is privileged.
+fn set_ids(
+    select: fn(&mut Credentials) -> &mut Ids,
+    f: impl FnOnce(&mut Ids, bool) -> Result<(), usize>,
+) -> isize {
+    let process = get_current_process();
+    let mut process = process.write();
+    let privileged = process.credentials.is_root();
+    match f(select(&mut process.credentials), privileged) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+fn user_ids(credentials: &mut Credentials) -> &mut Ids {
+    &mut credentials.user
+}
+
+fn group_ids(credentials: &mut Credentials) -> &mut Ids {
+    &mut credentials.group
+}
+
+pub fn sys_getuid() -> isize {
+    get_credentials(|credentials| credentials.user.real) as isize
+}
+
+pub fn sys_geteuid() -> isize {
+    get_credentials(|credentials| credentials.user.effective) as isize
+}
+
+pub fn sys_getgid() -> isize {
+    get_credentials(|credentials| credentials.group.real) as isize
+}
+
+pub fn sys_getegid() -> isize {
+    get_credentials(|credentials| credentials.group.effective) as isize
+}
 
-        sys_yield();
+pub fn sys_setuid(uid: usize) -> isize {
+    let Some(uid) = id_arg(uid) else {
+        return errno(EINVAL);
+    };
+    set_ids(user_ids, |ids, privileged| ids.set(uid, privileged))
+}
+
+pub fn sys_setgid(gid: usize) -> isize {
+    let Some(gid) = id_arg(gid) else {
+        return errno(EINVAL);
+    };
+    set_ids(group_ids, |ids, privileged| ids.set(gid, privileged))
+}
+
+pub fn sys_setreuid(ruid: usize, euid: usize) -> isize {
+    set_ids(user_ids, |ids, privileged| {
+        ids.set_real_effective(id_arg(ruid), id_arg(euid), privileged)
+    })
+}
+
+pub fn sys_setregid(rgid: usize, egid: usize) -> isize {
+    set_ids(group_ids, |ids, privileged| {
+        ids.set_real_effective(id_arg(rgid), id_arg(egid), privileged)
+    })
+}
+
+pub fn sys_setresuid(ruid: usize, euid: usize, suid: usize) -> isize {
+    set_ids(user_ids, |ids, privileged| {
+        ids.set_all(id_arg(ruid), id_arg(euid), id_arg(suid), privileged)
+    })
+}
+
+pub fn sys_setresgid(rgid: usize, egid: usize, sgid: usize) -> isize {
+    set_ids(group_ids, |ids, privileged| {
+        ids.set_all(id_arg(rgid), id_arg(egid), id_arg(sgid), privileged)
+    })
+}
+
+/// Stores the real, effective and saved IDs at the three addresses.
+fn write_ids(ids: Ids, addrs: [usize; 3]) -> isize {
+    if addrs.contains(&0) {
+        return errno(EFAULT);
+    }
+    for (addr, id) in addrs.into_iter().zip([ids.real, ids.effective, ids.saved]) {
+        unsafe { *(addr as *mut u32) = id };
+    }
+    0
+}
+
+pub fn sys_getresuid(ruid: usize, euid: usize, suid: usize) -> isize {
+    write_ids(
+        get_credentials(|credentials| credentials.user),
+        [ruid, euid, suid],
+    )
+}
+
+pub fn sys_getresgid(rgid: usize, egid: usize, sgid: usize) -> isize {
+    write_ids(
+        get_credentials(|credentials| credentials.group),
+        [rgid, egid, sgid],
+    )
+}
+
+/// Stores the supplementary groups in `list`, or just counts them if `size`
+/// is zero.
+pub fn sys_getgroups(size: usize, list: usize) -> isize {
+    let groups = get_credentials(|credentials| credentials.groups.clone());
+    if size == 0 {
+        return groups.len() as isize;
+    }
+    if size < groups.len() {
+        return errno(EINVAL);
     }
+    if list == 0 {
+        return errno(EFAULT);
+    }
+    unsafe { core::slice::from_raw_parts_mut(list as *mut u32, groups.len()) }
+        .copy_from_slice(&groups);
+    groups.len() as isize
+}
 
-    pid as isize
+pub fn sys_setgroups(size: usize, list: usize) -> isize {
+    if size > NGROUPS_MAX {
+        return errno(EINVAL);
+    }
+    if list == 0 && size != 0 {
+        return errno(EFAULT);
+    }
+    let groups = match size {
+        0 => &[][..],
+        size => unsafe { core::slice::from_raw_parts(list as *const u32, size) },
+    };
+    match get_current_process().write().credentials.set_groups(groups) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
 }
 
+/// Sets the permission bits new files are created without, returning the
+/// previous ones.
+pub fn sys_umask(mask: usize) -> isize {
+    let process = get_current_process();
+    let mut process = process.write();
+    let old = process.umask;
+    process.umask = mask as u16 & 0o777;
+    old as isize
+}
+
+fn heap_layout(len: usize, align: usize) -> Result<Layout, usize> {
+    require(Privileges::KERNEL_HEAP)?;
+    match Layout::from_size_align(len, align) {
+        Ok(layout) if layout.size() != 0 => Ok(layout),
+        _ => Err(EINVAL),
+    }
+}
+
+/// Allocates from the kernel heap, which user space can reach.
 pub fn sys_malloc(len: usize, align: usize) -> isize {
-    unsafe {
-        alloc::alloc::alloc(core::alloc::Layout::from_size_align_unchecked(len, align)) as isize
+    match heap_layout(len, align) {
+        Ok(layout) => unsafe { alloc::alloc::alloc(layout) as isize },
+        Err(err) => errno(err),
     }
 }
 
 pub fn sys_free(addr: usize, len: usize, align: usize) -> isize {
-    unsafe {
-        alloc::alloc::dealloc(
-            addr as *mut u8,
-            core::alloc::Layout::from_size_align_unchecked(len, align),
-        );
+    match heap_layout(len, align) {
+        Ok(layout) => {
+            unsafe { alloc::alloc::dealloc(addr as *mut u8, layout) };
+            0
+        }
+        Err(err) => errno(err),
     }
+}
 
-    0
+/// Fails with `EPERM` unless the calling process holds `privileges`.
+fn require(privileges: Privileges) -> Result<(), usize> {
+    get_current_process().read().capabilities.check(privileges)
 }
 
+/// Maps physical memory the process holds a capability for. The frames are
+/// not the process's own, so its exit leaves them alone.
 pub fn sys_physmap(vaddr: usize, paddr: usize, size: usize) -> isize {
+    let Some(end) = (paddr as u64).checked_add(size as u64) else {
+        return errno(EINVAL);
+    };
+    if !get_current_process()
+        .read()
+        .capabilities
+        .allows_memory(paddr as u64..end)
+    {
+        return errno(EPERM);
+    }
+
     if MemoryManager::map_range_to(
         VirtAddr::new(vaddr as u64),
         PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(paddr as u64)),
         size as u64,
-        MappingType::UserData.flags(),
+        MappingType::UserData.flags() | BORROWED,
         &mut ref_current_page_table(),
     )
     .is_ok()
@@ -107,66 +428,914 @@ pub fn sys_physmap(vaddr: usize, paddr: usize, size: usize) -> isize {
     -1
 }
 
+/// Allocates DMA memory, which the process may then map with
+/// `SYS_PHYSMAP`.
 pub fn sys_alloc_dma(size: usize) -> isize {
-    let (phys, virt) = crate::memory::DmaManager::allocate(size);
+    if let Err(err) = require(Privileges::DMA) {
+        return errno(err);
+    }
+    let (phys, _) = crate::memory::DmaManager::allocate(size);
+    let end = phys.as_u64() + size.next_multiple_of(DmaManager::UNIT_SIZE) as u64;
+    get_current_process()
+        .write()
+        .capabilities
+        .add_memory(phys.as_u64()..end);
     phys.as_u64() as isize
 }
 
 pub fn sys_dealloc_dma(addr: usize) -> isize {
-    crate::memory::DmaManager::deallocate(VirtAddr::new(addr as u64));
+    if let Err(err) = require(Privileges::DMA) {
+        return errno(err);
+    }
+    let Some(phys) = (addr as u64).checked_sub(*PHYSICAL_MEMORY_OFFSET) else {
+        return errno(EINVAL);
+    };
+    let frame = phys..phys + DmaManager::UNIT_SIZE as u64;
+    let process = get_current_process();
+    let mut process = process.write();
+    if !process.capabilities.allows_memory(frame.clone()) {
+        return errno(EPERM);
+    }
+    process.capabilities.remove_memory(frame);
+    drop(process);
+
+    DmaManager::deallocate(VirtAddr::new(addr as u64));
     0
 }
 
-pub fn sys_registfs(fs_name_ptr: usize, fs_name_len: usize, fs_addr: usize) -> isize {
-    let path = str::from_utf8(unsafe {
+/// Reads the `CapabilityGrant` at `grant`; a null one grants nothing.
+fn read_grant(grant: usize) -> Result<Capabilities, usize> {
+    match grant {
+        0 => Ok(Capabilities::none()),
+        grant => Capabilities::from_grant(unsafe { &*(grant as *const CapabilityGrant) }),
+    }
+}
+
+/// Grants or revokes the calling thread's access to the `num` I/O ports
+/// from `from`, as Linux's `ioperm`. Granting needs a capability covering
+/// the ports. Like on Linux, a forked child starts without any.
+pub fn sys_ioperm(from: usize, num: usize, turn_on: usize) -> isize {
+    let Some(end) = from.checked_add(num).filter(|&end| end <= IO_PORTS) else {
+        return errno(EINVAL);
+    };
+    let turn_on = turn_on != 0;
+    if turn_on
+        && !get_current_process()
+            .read()
+            .capabilities
+            .allows_ports(from as u64..end as u64)
+    {
+        return errno(EPERM);
+    }
+
+    let thread = get_current_thread();
+    let mut thread = thread.write();
+    if !turn_on && thread.io_bitmap.is_none() {
+        return 0;
+    }
+    let bitmap = thread.io_bitmap.get_or_insert_with(IoBitmap::new);
+    bitmap.set(from..end, turn_on);
+    if bitmap.is_empty() {
+        thread.io_bitmap = None;
+    }
+
+    load_io_bitmap(&thread);
+    0
+}
+
+/// The scheduler loads the I/O bitmap on switches; syscalls changing the
+/// current thread's return straight to it, so load it now.
+fn load_io_bitmap(thread: &Thread) {
+    let lapic_id = unsafe { LAPIC.lock().id() };
+    CPUS.write()
+        .get_mut(lapic_id)
+        .set_io_bitmap(thread.io_bitmap.as_deref());
+}
+
+/// Narrows the calling process's capabilities to those in the
+/// `CapabilityGrant` at `grant`, before it forks children that should not
+/// have all of them.
+pub fn sys_restrict_capabilities(grant: usize) -> isize {
+    let capabilities = read_grant(grant);
+    let process = get_current_process();
+    let (capabilities, threads) = {
+        let mut process = process.write();
+        match capabilities.and_then(|capabilities| process.capabilities.delegate(capabilities)) {
+            Ok(capabilities) => {
+                process.capabilities = capabilities.clone();
+                (capabilities, process.threads.clone())
+            }
+            Err(err) => return errno(err),
+        }
+    };
+
+    // Ports granted through ioperm go with the capabilities covering them.
+    for thread in threads {
+        let mut thread = thread.write();
+        if let Some(bitmap) = thread.io_bitmap.as_mut() {
+            bitmap.retain(|port| capabilities.allows_ports(port as u64..port as u64 + 1));
+            if bitmap.is_empty() {
+                thread.io_bitmap = None;
+            }
+        }
+    }
+    load_io_bitmap(&get_current_thread().read());
+    0
+}
+
+/// Registers the caller as the filesystem server `name`, which `mount` with
+/// the `userfs` type takes as its source. A name that is an absolute path
+/// is mounted there at once. The kernel maps the request ring at
+/// `ring_addr`, which must be page aligned with `RING_SIZE` bytes free
+/// behind it.
+pub fn sys_registfs(fs_name_ptr: usize, fs_name_len: usize, ring_addr: usize) -> isize {
+    let Ok(path) = str::from_utf8(unsafe {
         core::slice::from_raw_parts(fs_name_ptr as *const u8, fs_name_len)
-    })
-    .ok();
+    }) else {
+        return errno(EFAULT);
+    };
 
-    if let Some(path) = path {
-        let pid = get_current_process_id();
-        USER_FS_MANAGER.lock().insert(pid, fs_addr);
-        PATH_TO_PID.lock().insert(path.to_string(), pid);
+    let registered = require(Privileges::REGISTER_FS)
+        .and_then(|_| user::register_server(ring_addr))
+        .and_then(|_| user::register_name(path));
+    match registered {
+        Ok(()) => 0,
+        Err(err) => errno(err),
     }
+}
 
+/// Hands the server's responses to their clients and, if asked to, waits
+/// for new requests.
+pub fn sys_userfs_wait(flags: usize) -> isize {
+    match user::server_wait(flags) {
+        Ok(queued) => queued as isize,
+        Err(err) => errno(err),
+    }
+}
+
+/// Publishes the calling filesystem server as the character device
+/// `/dev/<name>`. Reads, writes and ioctls on the node reach the server as
+/// commands on the file `name`.
+pub fn sys_register_device(name_ptr: usize, name_len: usize, major: usize, minor: usize) -> isize {
+    let Ok(name) =
+        str::from_utf8(unsafe { core::slice::from_raw_parts(name_ptr as *const u8, name_len) })
+    else {
+        return errno(EFAULT);
+    };
+    if name.is_empty()
+        || name.contains('/')
+        || major > u32::MAX as usize
+        || minor > u32::MAX as usize
+    {
+        return errno(EINVAL);
+    }
+
+    if let Err(err) = require(Privileges::REGISTER_FS) {
+        return errno(err);
+    }
+    let pid = get_current_process_id();
+    if !USER_FS_MANAGER.lock().contains_key(&pid) {
+        return errno(EINVAL);
+    }
+
+    let device = UserFS::new(pid, name, InodeTy::CharDevice);
+    device.write().when_mounted(name.to_string(), None);
+    match devfs::register_device(name, major as u32, minor as u32, device) {
+        Ok(_) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+/// Creates a channel and stores the fds of its two ends at `fds`.
+pub fn sys_channel_create(fds: usize, flags: usize) -> isize {
+    if fds == 0 {
+        return errno(EFAULT);
+    }
+    let fds = unsafe { core::slice::from_raw_parts_mut(fds as *mut usize, 2) };
+    let flags = OpenFlags::from_bits_truncate(flags);
+    for (slot, end) in fds.iter_mut().zip(Channel::pair()) {
+        match crate::fs::operation::install(end, OpenMode::ReadWrite, flags) {
+            Some(fd) => *slot = fd,
+            None => return errno(EBADF),
+        }
+    }
     0
 }
 
-pub fn sys_load_driver(driver_name_ptr: usize, driver_name_len: usize) -> isize {
-    let path = str::from_utf8(unsafe {
-        core::slice::from_raw_parts(driver_name_ptr as *const u8, driver_name_len)
+/// Runs `f` on the channel end open as `fd`, telling it whether the call
+/// must not wait.
+fn with_channel(fd: usize, flags: usize, f: impl FnOnce(&ChannelFS, bool) -> isize) -> isize {
+    let Some(inode) = crate::fs::operation::get_inode_by_fd(fd) else {
+        return errno(EBADF);
+    };
+    let nonblock = flags & CHANNEL_NONBLOCK != 0
+        || crate::fs::operation::get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+    let inode = inode.read();
+    match (&*inode as &dyn Any).downcast_ref::<ChannelFS>() {
+        Some(channel) => f(channel, nonblock),
+        None => errno(EINVAL),
+    }
+}
+
+/// Borrows the [`ChannelMessage`] at `addr` in user memory.
+fn channel_message(addr: usize) -> Option<&'static mut ChannelMessage> {
+    match addr {
+        0 => None,
+        addr => Some(unsafe { &mut *(addr as *mut ChannelMessage) }),
+    }
+}
+
+pub fn sys_channel_send(fd: usize, message: usize, flags: usize) -> isize {
+    let Some(message) = channel_message(message) else {
+        return errno(EFAULT);
+    };
+    with_channel(fd, flags, |channel, nonblock| {
+        match channel.send(message, nonblock) {
+            Ok(()) => 0,
+            Err(err) => errno(err),
+        }
+    })
+}
+
+/// Receives the next message into `message`, returning its data length.
+pub fn sys_channel_recv(fd: usize, message: usize, flags: usize) -> isize {
+    let Some(message) = channel_message(message) else {
+        return errno(EFAULT);
+    };
+    with_channel(fd, flags, |channel, nonblock| {
+        match channel.receive(message, nonblock) {
+            Ok(len) => len as isize,
+            Err(err) => errno(err),
+        }
     })
-    .ok();
+}
+
+/// Sends `request` and waits for the reply, returning its data length.
+pub fn sys_channel_call(fd: usize, request: usize, reply: usize) -> isize {
+    let (Some(request), Some(reply)) = (channel_message(request), channel_message(reply)) else {
+        return errno(EFAULT);
+    };
+    with_channel(fd, 0, |channel, _| match channel.call(request, reply) {
+        Ok(len) => len as isize,
+        Err(err) => errno(err),
+    })
+}
 
-    if let Some(path) = path {
-        crate::module::load_named_module(path);
+pub fn sys_channel_reply(fd: usize, message: usize) -> isize {
+    let Some(message) = channel_message(message) else {
+        return errno(EFAULT);
+    };
+    with_channel(fd, 0, |channel, _| match channel.reply(message) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    })
+}
+
+/// Publishes `name` in the name service, with the file open as `fd` as its
+/// endpoint unless `fd` is -1.
+pub fn sys_service_register(name: usize, fd: usize) -> isize {
+    let Some(name) = c_str(name) else {
+        return errno(EFAULT);
+    };
+    let endpoint = match fd {
+        usize::MAX => None,
+        fd => match crate::fs::operation::get_file_by_fd(fd) {
+            Some(file) => Some(file),
+            None => return errno(EBADF),
+        },
+    };
+    match service::register(name, endpoint) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
     }
+}
 
-    0
+pub fn sys_service_unregister(name: usize) -> isize {
+    let Some(name) = c_str(name) else {
+        return errno(EFAULT);
+    };
+    match service::unregister(name) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+/// Returns the pid providing `name`, waiting for it with `SERVICE_WAIT`.
+/// If `fd` is not null, a new fd for the endpoint is stored there, or -1
+/// if the service has none.
+pub fn sys_service_lookup(name: usize, fd: usize, flags: usize) -> isize {
+    let Some(name) = c_str(name) else {
+        return errno(EFAULT);
+    };
+    let (pid, endpoint) = match service::lookup(name, flags & SERVICE_WAIT != 0) {
+        Ok(found) => found,
+        Err(err) => return errno(err),
+    };
+    if fd != 0 {
+        let installed = match endpoint {
+            Some((inode, mode, flags)) => match crate::fs::operation::install(inode, mode, flags) {
+                Some(installed) => installed,
+                None => return errno(EBADF),
+            },
+            None => usize::MAX,
+        };
+        unsafe { *(fd as *mut usize) = installed };
+    }
+    pid.0 as isize
+}
+
+/// Opens an fd that turns readable once the provider of `name` is gone.
+pub fn sys_service_watch(name: usize, flags: usize) -> isize {
+    let Some(name) = c_str(name) else {
+        return errno(EFAULT);
+    };
+    let watch = match service::watch(name, flags & SERVICE_WAIT != 0) {
+        Ok(watch) => watch,
+        Err(err) => return errno(err),
+    };
+    match crate::fs::operation::install(watch, OpenMode::Read, OpenFlags::empty()) {
+        Some(fd) => fd as isize,
+        None => errno(EBADF),
+    }
+}
+
+/// Starts the driver at `path`, handing it the capabilities in the
+/// `CapabilityGrant` at `grant`, or none if it is null. They must all be
+/// the caller's own.
+pub fn sys_load_driver(driver_name_ptr: usize, driver_name_len: usize, grant: usize) -> isize {
+    if let Err(err) = require(Privileges::LOAD_DRIVER) {
+        return errno(err);
+    }
+    let capabilities = read_grant(grant).and_then(|capabilities| {
+        get_current_process()
+            .read()
+            .capabilities
+            .delegate(capabilities)
+    });
+    let capabilities = match capabilities {
+        Ok(capabilities) => capabilities,
+        Err(err) => return errno(err),
+    };
+
+    let Ok(path) = str::from_utf8(unsafe {
+        core::slice::from_raw_parts(driver_name_ptr as *const u8, driver_name_len)
+    }) else {
+        return errno(EFAULT);
+    };
+
+    let credentials = get_current_process().read().credentials.clone();
+    match crate::module::load_named_module(path, capabilities, &credentials) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
 }
 
 pub fn sys_pipe(fd: usize) -> isize {
-    let fd = unsafe { core::slice::from_raw_parts_mut(fd as *mut usize, 2) };
+    sys_pipe2(fd, 0)
+}
 
-    if let Some(ret) = crate::fs::operation::pipe(fd) {
-        return ret as isize;
+/// Close-on-exec is accepted but has no effect, as exec keeps every
+/// descriptor.
+const O_CLOEXEC: usize = 0o2000000;
+
+/// Stores the two ends in the `int[2]` at `fds`.
+pub fn sys_pipe2(fds: usize, flags: usize) -> isize {
+    if fds == 0 || fds as u64 + 2 * size_of::<i32>() as u64 > crate::memory::USER_END {
+        return errno(EFAULT);
+    }
+    if flags & !(OpenFlags::NONBLOCK.bits() | O_CLOEXEC) != 0 {
+        return errno(EINVAL);
     }
 
-    -1
+    let mut ends = [0; 2];
+    if crate::fs::operation::pipe(&mut ends, OpenFlags::from_bits_truncate(flags)).is_none() {
+        return errno(EMFILE);
+    }
+    unsafe { core::ptr::write_unaligned(fds as *mut [i32; 2], ends.map(|fd| fd as i32)) };
+    0
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    const F_GETFD: usize = 1;
+    const F_SETFD: usize = 2;
+    const F_GETFL: usize = 3;
+    const F_SETFL: usize = 4;
+    const F_ADD_SEALS: usize = 1033;
+    const F_GET_SEALS: usize = 1034;
+
+    if crate::fs::operation::get_inode_by_fd(fd).is_none() {
+        return errno(EBADF);
+    }
+
+    match cmd {
+        F_GETFD | F_SETFD => 0,
+        F_GETFL => crate::fs::operation::get_flags_by_fd(fd).bits() as isize,
+        F_SETFL => {
+            crate::fs::operation::set_flags_by_fd(fd, OpenFlags::from_bits_truncate(arg));
+            0
+        }
+        F_ADD_SEALS => with_memfd(fd, |memfd| {
+            let Some(seals) = Seals::from_bits(arg as u32) else {
+                return errno(EINVAL);
+            };
+            match memfd.add_seals(seals) {
+                Ok(()) => 0,
+                Err(err) => errno(err),
+            }
+        }),
+        F_GET_SEALS => with_memfd(fd, |memfd| memfd.seals().bits() as isize),
+        _ => errno(EINVAL),
+    }
 }
 
 pub fn sys_open(path: usize, mode: usize, len: usize) -> isize {
-    let openmode = OpenMode::from(mode);
+    let Ok(open_mode) = OpenMode::try_from(mode & 0o3) else {
+        return errno(EINVAL);
+    };
 
     let path = str::from_utf8(unsafe { core::slice::from_raw_parts(path as *const u8, len) }).ok();
 
-    if let Some(path) = path {
-        if let Some(ret) = crate::fs::operation::open(path.to_string(), OpenMode::from(mode)) {
-            return ret as isize;
+    let Some(path) = path else {
+        return errno(EINVAL);
+    };
+
+    match crate::fs::operation::open(
+        path.to_string(),
+        open_mode,
+        OpenFlags::from_bits_truncate(mode),
+    ) {
+        Ok(fd) => fd as isize,
+        Err(e) => errno(e),
+    }
+}
+
+/// Only root may change the mount table, which decides what every path
+/// means.
+pub fn sys_mount(source: usize, target: usize, fstype: usize, flags: usize) -> isize {
+    if !get_credentials(Credentials::is_root) {
+        return errno(EPERM);
+    }
+    let (Some(source), Some(target)) = (c_str(source), c_str(target)) else {
+        return errno(EFAULT);
+    };
+    let flags = MountFlags::from_bits_truncate(flags);
+    let fstype = match c_str(fstype) {
+        Some(fstype) => fstype,
+        None if flags.contains(MountFlags::REMOUNT) => "",
+        None => return errno(EFAULT),
+    };
+
+    match crate::fs::mount::mount(source, target, fstype, flags) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_umount2(target: usize, flags: usize) -> isize {
+    if !get_credentials(Credentials::is_root) {
+        return errno(EPERM);
+    }
+    let Some(target) = c_str(target) else {
+        return errno(EFAULT);
+    };
+
+    match crate::fs::mount::umount(target, flags) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+fn timeout_from_ms(timeout: usize) -> Option<Duration> {
+    let timeout = timeout as i32;
+    (timeout >= 0).then(|| Duration::from_millis(timeout as u64))
+}
+
+/// Reads a `struct timespec` or `struct timeval`, whose second field is in
+/// units of `unit`. A null pointer means no timeout.
+fn timeout_from_user(addr: usize, unit: Duration) -> Result<Option<Duration>, usize> {
+    if addr == 0 {
+        return Ok(None);
+    }
+    let [seconds, fraction] = unsafe { *(addr as *const [i64; 2]) };
+    if seconds < 0 || fraction < 0 || fraction as u128 * unit.as_nanos() >= 1_000_000_000 {
+        return Err(EINVAL);
+    }
+    Ok(Some(
+        Duration::from_secs(seconds as u64) + unit * fraction as u32,
+    ))
+}
+
+fn poll_result(result: Result<usize, usize>) -> isize {
+    match result {
+        Ok(ready) => ready as isize,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_poll(fds: usize, nfds: usize, timeout: usize) -> isize {
+    if fds == 0 && nfds != 0 {
+        return errno(EFAULT);
+    }
+    let fds = unsafe { core::slice::from_raw_parts_mut(fds as *mut PollFd, nfds) };
+    poll_result(crate::fs::poll::poll(fds, timeout_from_ms(timeout)))
+}
+
+/// Signal masks are not supported yet, so `sigmask` is ignored.
+pub fn sys_ppoll(fds: usize, nfds: usize, timeout: usize, _sigmask: usize) -> isize {
+    if fds == 0 && nfds != 0 {
+        return errno(EFAULT);
+    }
+    let timeout = match timeout_from_user(timeout, Duration::from_nanos(1)) {
+        Ok(timeout) => timeout,
+        Err(err) => return errno(err),
+    };
+    let fds = unsafe { core::slice::from_raw_parts_mut(fds as *mut PollFd, nfds) };
+    poll_result(crate::fs::poll::poll(fds, timeout))
+}
+
+pub fn sys_select(
+    nfds: usize,
+    readfds: usize,
+    writefds: usize,
+    exceptfds: usize,
+    timeout: usize,
+) -> isize {
+    let nfds = nfds as i32;
+    if nfds < 0 {
+        return errno(EINVAL);
+    }
+    let timeout = match timeout_from_user(timeout, Duration::from_micros(1)) {
+        Ok(timeout) => timeout,
+        Err(err) => return errno(err),
+    };
+
+    let words = (nfds as usize).div_ceil(64);
+    let mut sets = [readfds, writefds, exceptfds].map(|addr| {
+        (addr != 0).then(|| unsafe { core::slice::from_raw_parts_mut(addr as *mut u64, words) })
+    });
+    poll_result(crate::fs::poll::select(nfds as usize, &mut sets, timeout))
+}
+
+const EPOLL_CLOEXEC: usize = 0o2000000;
+
+pub fn sys_epoll_create1(flags: usize) -> isize {
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return errno(EINVAL);
+    }
+    match crate::fs::operation::install(EpollFS::new(), OpenMode::Read, OpenFlags::empty()) {
+        Some(fd) => fd as isize,
+        None => errno(EBADF),
+    }
+}
+
+/// Runs `f` on the epoll instance open as `epfd`.
+fn with_epoll(epfd: usize, f: impl FnOnce(&EpollFS) -> isize) -> isize {
+    let Some(inode) = crate::fs::operation::get_inode_by_fd(epfd) else {
+        return errno(EBADF);
+    };
+    let inode = inode.read();
+    match (&*inode as &dyn Any).downcast_ref::<EpollFS>() {
+        Some(epoll) => f(epoll),
+        None => errno(EINVAL),
+    }
+}
+
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: usize) -> isize {
+    if epfd == fd {
+        return errno(EINVAL);
+    }
+    let Some(inode) = crate::fs::operation::get_inode_by_fd(fd) else {
+        return errno(EBADF);
+    };
+    let event = match (op, event) {
+        (EPOLL_CTL_DEL, _) => EpollEvent::default(),
+        (_, 0) => return errno(EFAULT),
+        (_, event) => unsafe { *(event as *const EpollEvent) },
+    };
+
+    with_epoll(epfd, |epoll| match epoll.control(op, fd, &inode, event) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    })
+}
+
+pub fn sys_epoll_wait(epfd: usize, events: usize, maxevents: usize, timeout: usize) -> isize {
+    let maxevents = maxevents as i32;
+    if maxevents <= 0 {
+        return errno(EINVAL);
+    }
+    if events == 0 {
+        return errno(EFAULT);
+    }
+
+    let events =
+        unsafe { core::slice::from_raw_parts_mut(events as *mut EpollEvent, maxevents as usize) };
+    with_epoll(epfd, |epoll| {
+        poll_result(epoll.wait(events, timeout_from_ms(timeout)))
+    })
+}
+
+pub fn sys_mknod(path: usize, mode: usize, dev: usize) -> isize {
+    sys_mknodat(AT_FDCWD, path, mode, dev)
+}
+
+pub fn sys_mknodat(dirfd: usize, path: usize, mode: usize, _dev: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+
+    let ty = match mode as u32 & S_IFMT {
+        0 | S_IFREG => InodeTy::File,
+        S_IFIFO => InodeTy::Fifo,
+        _ => return errno(EPERM),
+    };
+
+    match crate::fs::operation::mknod(&path, ty, mode as u16) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_mkdir(path: usize, mode: usize) -> isize {
+    sys_mkdirat(AT_FDCWD, path, mode)
+}
+
+pub fn sys_mkdirat(dirfd: usize, path: usize, mode: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+
+    match crate::fs::operation::mknod(&path, InodeTy::Dir, mode as u16) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+const AT_REMOVEDIR: usize = 0x200;
+
+pub fn sys_unlink(path: usize) -> isize {
+    sys_unlinkat(AT_FDCWD, path, 0)
+}
+
+pub fn sys_rmdir(path: usize) -> isize {
+    sys_unlinkat(AT_FDCWD, path, AT_REMOVEDIR)
+}
+
+pub fn sys_unlinkat(dirfd: usize, path: usize, flags: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+
+    match crate::fs::operation::unlink(&path, flags & AT_REMOVEDIR != 0) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_rename(from: usize, to: usize) -> isize {
+    sys_renameat(AT_FDCWD, from, AT_FDCWD, to)
+}
+
+pub fn sys_renameat(from_dirfd: usize, from: usize, to_dirfd: usize, to: usize) -> isize {
+    let (Some(from), Some(to)) = (c_str(from), c_str(to)) else {
+        return errno(EFAULT);
+    };
+    let (Some(from), Some(to)) = (
+        crate::fs::operation::absolute_path(from_dirfd, from),
+        crate::fs::operation::absolute_path(to_dirfd, to),
+    ) else {
+        return errno(EBADF);
+    };
+
+    match crate::fs::operation::rename(&from, &to) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_truncate(path: usize, len: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    let Some(path) = crate::fs::operation::absolute_path(AT_FDCWD, path) else {
+        return errno(EBADF);
+    };
+
+    match crate::fs::operation::truncate(&path, len) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    match crate::fs::operation::ftruncate(fd, len) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_chmod(path: usize, mode: usize) -> isize {
+    sys_fchmodat(AT_FDCWD, path, mode)
+}
+
+pub fn sys_fchmodat(dirfd: usize, path: usize, mode: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+
+    match crate::fs::operation::chmod(&path, mode as u32) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_fchmod(fd: usize, mode: usize) -> isize {
+    match crate::fs::operation::fchmod(fd, mode as u32) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_chown(path: usize, uid: usize, gid: usize) -> isize {
+    sys_fchownat(AT_FDCWD, path, uid, gid, 0)
+}
+
+pub fn sys_lchown(path: usize, uid: usize, gid: usize) -> isize {
+    sys_fchownat(AT_FDCWD, path, uid, gid, AT_SYMLINK_NOFOLLOW)
+}
+
+pub fn sys_fchownat(dirfd: usize, path: usize, uid: usize, gid: usize, flags: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    if path.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return errno(ENOENT);
         }
+        return sys_fchown(dirfd, uid, gid);
+    }
+
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+    let follow = flags & AT_SYMLINK_NOFOLLOW == 0;
+    match crate::fs::operation::chown(&path, follow, id_arg(uid), id_arg(gid)) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_fchown(fd: usize, uid: usize, gid: usize) -> isize {
+    match crate::fs::operation::fchown(fd, id_arg(uid), id_arg(gid)) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_memfd_create(name: usize, flags: usize) -> isize {
+    let Some(name) = c_str(name) else {
+        return errno(EFAULT);
+    };
+    if flags & !(MFD_CLOEXEC | MFD_ALLOW_SEALING) != 0 {
+        return errno(EINVAL);
     }
+    let memfd = MemFS::new(name, flags & MFD_ALLOW_SEALING != 0);
+    match crate::fs::operation::install(memfd, OpenMode::ReadWrite, OpenFlags::empty()) {
+        Some(fd) => fd as isize,
+        None => errno(EBADF),
+    }
+}
 
-    usize::MAX as isize
+/// Runs `f` on the memfd open as `fd`.
+fn with_memfd(fd: usize, f: impl FnOnce(&MemFS) -> isize) -> isize {
+    let Some(inode) = crate::fs::operation::get_inode_by_fd(fd) else {
+        return errno(EBADF);
+    };
+    let inode = inode.read();
+    match (&*inode as &dyn Any).downcast_ref::<MemFS>() {
+        Some(memfd) => f(memfd),
+        None => errno(EINVAL),
+    }
+}
+
+/// Maps anonymous memory, or a memfd. Shared mappings of a memfd see the
+/// same frames in every process; private ones get a copy.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    const PROT_WRITE: usize = 2;
+    const PROT_EXEC: usize = 4;
+    const MAP_SHARED: usize = 1;
+    const MAP_PRIVATE: usize = 2;
+    const MAP_FIXED: usize = 0x10;
+    const MAP_ANONYMOUS: usize = 0x20;
+
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return errno(EINVAL),
+    };
+    if len == 0 || !offset.is_multiple_of(4096) {
+        return errno(EINVAL);
+    }
+    let len = len.next_multiple_of(4096);
+    let writable = prot & PROT_WRITE != 0;
+
+    let mut page_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if writable {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+
+    // Settle where the mapping goes before taking any frames, which would
+    // otherwise have to be given back on failure.
+    let fixed = match flags & MAP_FIXED {
+        0 => None,
+        _ => match crate::memory::unmappable(addr, len) {
+            Ok(pages) => Some(pages),
+            Err(err) => return errno(err),
+        },
+    };
+    let addr = match fixed {
+        Some(_) => addr,
+        None => match crate::memory::find_free(len) {
+            Ok(addr) => addr,
+            Err(err) => return errno(err),
+        },
+    };
+
+    let frames = if flags & MAP_ANONYMOUS != 0 {
+        crate::memory::allocate_zeroed(len / 4096)
+    } else {
+        let Some((inode, mode, _)) = crate::fs::operation::get_file_by_fd(fd) else {
+            return errno(EBADF);
+        };
+        if shared && writable && mode == OpenMode::Read {
+            return errno(EACCES);
+        }
+        let inode = inode.read();
+        let Some(memfd) = (&*inode as &dyn Any).downcast_ref::<MemFS>() else {
+            return errno(ENODEV);
+        };
+        if shared {
+            page_flags |= SHARED;
+            memfd.share_frames(offset, len, writable)
+        } else {
+            memfd.copy_frames(offset, len)
+        }
+    };
+    let frames = match frames {
+        Ok(frames) => frames,
+        Err(err) => return errno(err),
+    };
+
+    if let Some(pages) = fixed {
+        crate::memory::unmap_range(pages);
+    }
+    match crate::memory::map(addr, frames, page_flags) {
+        Ok(()) => addr as isize,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    match crate::memory::unmap(addr, len) {
+        Ok(()) => 0,
+        Err(err) => errno(err),
+    }
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    if crate::fs::operation::fsync(fd).is_none() {
+        return -1;
+    }
+    0
+}
+
+pub fn sys_sync() -> isize {
+    if let Err(err) = crate::block::BUFFER_CACHE.sync_all() {
+        log::warn!("Failed to sync block devices: {:?}", err);
+    }
+    0
 }
 
 pub fn sys_close(fd: usize) -> isize {
@@ -201,12 +1370,76 @@ pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
 }
 
 pub fn sys_fstat(fd: usize, buf: usize) -> isize {
+    if buf == 0 {
+        return errno(EFAULT);
+    }
     if crate::fs::operation::fstat(fd, buf).is_none() {
-        return -1;
+        return errno(EBADF);
     }
     0
 }
 
+pub fn sys_statfs(path: usize, buf: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    if buf == 0 {
+        return errno(EFAULT);
+    }
+    let Some(path) = crate::fs::operation::absolute_path(AT_FDCWD, path) else {
+        return errno(ENOENT);
+    };
+    match crate::fs::operation::statfs(&path, buf) {
+        Some(_) => 0,
+        None => errno(ENOENT),
+    }
+}
+
+pub fn sys_fstatfs(fd: usize, buf: usize) -> isize {
+    if buf == 0 {
+        return errno(EFAULT);
+    }
+    match crate::fs::operation::fstatfs(fd, buf) {
+        Some(_) => 0,
+        None => errno(EBADF),
+    }
+}
+
+pub fn sys_stat(path: usize, buf: usize) -> isize {
+    sys_newfstatat(AT_FDCWD, path, buf, 0)
+}
+
+pub fn sys_lstat(path: usize, buf: usize) -> isize {
+    sys_newfstatat(AT_FDCWD, path, buf, AT_SYMLINK_NOFOLLOW)
+}
+
+const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+const AT_EMPTY_PATH: usize = 0x1000;
+
+pub fn sys_newfstatat(dirfd: usize, path: usize, buf: usize, flags: usize) -> isize {
+    let Some(path) = c_str(path) else {
+        return errno(EFAULT);
+    };
+    if buf == 0 {
+        return errno(EFAULT);
+    }
+    if path.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return errno(ENOENT);
+        }
+        return sys_fstat(dirfd, buf);
+    }
+
+    let Some(path) = crate::fs::operation::absolute_path(dirfd, path) else {
+        return errno(EBADF);
+    };
+    let follow = flags & AT_SYMLINK_NOFOLLOW == 0;
+    match crate::fs::operation::stat(&path, follow, buf) {
+        Ok(()) => 0,
+        Err(e) => errno(e),
+    }
+}
+
 pub fn sys_listdir(fd: usize, buf_addr: usize) -> isize {
     let vec = crate::fs::operation::list_dir(fd);
 
@@ -215,6 +1448,17 @@ pub fn sys_listdir(fd: usize, buf_addr: usize) -> isize {
     0
 }
 
+pub fn sys_getdents64(fd: usize, dirp: usize, count: usize) -> isize {
+    if dirp == 0 {
+        return errno(EFAULT);
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(dirp as *mut u8, count) };
+    match crate::fs::operation::getdents64(fd, buf) {
+        Ok(written) => written as isize,
+        Err(err) => errno(err),
+    }
+}
+
 pub fn sys_dir_itemnum(fd: usize) -> isize {
     crate::fs::operation::list_dir(fd).len() as isize
 }
@@ -0,0 +1,176 @@
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::ops::Range;
+
+use crate::syscall::errno::{EFAULT, EINVAL, EPERM};
+
+/// The most ranges of one kind a grant may list.
+const MAX_GRANT_RANGES: usize = 64;
+
+bitflags! {
+    /// Privileged syscalls a process may make.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Privileges: u64 {
+        /// `SYS_DMA_ALLOCATE` and `SYS_DMA_DEALLOCATE`.
+        const DMA = 1 << 0;
+        /// `SYS_LOAD_DRIVER`.
+        const LOAD_DRIVER = 1 << 1;
+        /// `SYS_REGISTEFS` and `SYS_REGISTER_DEVICE`.
+        const REGISTER_FS = 1 << 2;
+        /// `SYS_MALLOC` and `SYS_FREE`, which hand out and take back
+        /// kernel heap memory.
+        const KERNEL_HEAP = 1 << 3;
+    }
+}
+
+/// A range in a [`CapabilityGrant`], covering `len` units from `start`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrantRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+/// How user space describes capabilities to hand to a driver it loads, or
+/// to keep for itself. Each pair is an array of [`GrantRange`]s and its
+/// length.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapabilityGrant {
+    pub privileges: u64,
+    /// Physical addresses `SYS_PHYSMAP` may map.
+    pub memory: u64,
+    pub memory_count: u64,
+    /// I/O ports.
+    pub ports: u64,
+    pub port_count: u64,
+    /// IRQ lines.
+    pub irqs: u64,
+    pub irq_count: u64,
+}
+
+/// What a process may do beyond what every process can. Processes the
+/// kernel starts get everything; others get what their parent passes on,
+/// never more than the parent has.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub privileges: Privileges,
+    memory: Vec<Range<u64>>,
+    ports: Vec<Range<u64>>,
+    irqs: Vec<Range<u64>>,
+}
+
+fn covers(ranges: &[Range<u64>], range: &Range<u64>) -> bool {
+    range.is_empty()
+        || ranges
+            .iter()
+            .any(|owned| owned.start <= range.start && range.end <= owned.end)
+}
+
+impl Capabilities {
+    pub const fn none() -> Self {
+        Self {
+            privileges: Privileges::empty(),
+            memory: Vec::new(),
+            ports: Vec::new(),
+            irqs: Vec::new(),
+        }
+    }
+
+    pub fn all() -> Self {
+        Self {
+            privileges: Privileges::all(),
+            memory: alloc::vec![0..u64::MAX],
+            ports: alloc::vec![0..0x1_0000],
+            irqs: alloc::vec![0..256],
+        }
+    }
+
+    /// Reads a [`CapabilityGrant`] from user memory.
+    pub fn from_grant(grant: &CapabilityGrant) -> Result<Self, usize> {
+        fn ranges(addr: u64, count: u64) -> Result<Vec<Range<u64>>, usize> {
+            let count = count as usize;
+            if count > MAX_GRANT_RANGES {
+                return Err(EINVAL);
+            }
+            if count == 0 {
+                return Ok(Vec::new());
+            }
+            if addr == 0 {
+                return Err(EFAULT);
+            }
+            let ranges = unsafe { core::slice::from_raw_parts(addr as *const GrantRange, count) };
+            ranges
+                .iter()
+                .map(|range| {
+                    let end = range.start.checked_add(range.len).ok_or(EINVAL)?;
+                    Ok(range.start..end)
+                })
+                .collect()
+        }
+
+        Ok(Self {
+            privileges: Privileges::from_bits(grant.privileges).ok_or(EINVAL)?,
+            memory: ranges(grant.memory, grant.memory_count)?,
+            ports: ranges(grant.ports, grant.port_count)?,
+            irqs: ranges(grant.irqs, grant.irq_count)?,
+        })
+    }
+
+    /// Whether everything in `other` is also in `self`, so `self` may pass
+    /// it on.
+    pub fn contains(&self, other: &Capabilities) -> bool {
+        self.privileges.contains(other.privileges)
+            && other.memory.iter().all(|range| covers(&self.memory, range))
+            && other.ports.iter().all(|range| covers(&self.ports, range))
+            && other.irqs.iter().all(|range| covers(&self.irqs, range))
+    }
+
+    /// `other`, if `self` may pass it on.
+    pub fn delegate(&self, other: Capabilities) -> Result<Capabilities, usize> {
+        match self.contains(&other) {
+            true => Ok(other),
+            false => Err(EPERM),
+        }
+    }
+
+    pub fn check(&self, privileges: Privileges) -> Result<(), usize> {
+        match self.privileges.contains(privileges) {
+            true => Ok(()),
+            false => Err(EPERM),
+        }
+    }
+
+    pub fn allows_memory(&self, range: Range<u64>) -> bool {
+        covers(&self.memory, &range)
+    }
+
+    pub fn allows_ports(&self, range: Range<u64>) -> bool {
+        covers(&self.ports, &range)
+    }
+
+    pub fn allows_irq(&self, irq: u64) -> bool {
+        covers(&self.irqs, &(irq..irq + 1))
+    }
+
+    /// Gives access to physical memory the process was handed, such as a
+    /// DMA buffer.
+    pub fn add_memory(&mut self, range: Range<u64>) {
+        self.memory.push(range);
+    }
+
+    /// Takes `range` out of the physical memory the process may map.
+    pub fn remove_memory(&mut self, range: Range<u64>) {
+        self.memory = self
+            .memory
+            .iter()
+            .flat_map(|owned| {
+                [
+                    owned.start..owned.end.min(range.start),
+                    owned.start.max(range.end)..owned.end,
+                ]
+            })
+            .filter(|piece| !piece.is_empty())
+            .collect();
+    }
+}
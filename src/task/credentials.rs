@@ -0,0 +1,201 @@
+use alloc::vec::Vec;
+use bitflags::bitflags;
+
+use crate::{
+    fs::vfs::{
+        inode::Metadata,
+        stat_struct::{S_IFDIR, S_IFMT},
+    },
+    syscall::errno::{EINVAL, EPERM},
+};
+
+/// The user and group with every permission.
+pub const ROOT: u32 = 0;
+/// The most supplementary groups a process can be in.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// Set-user-ID and set-group-ID on execution.
+pub const S_ISUID: u32 = 0o4000;
+pub const S_ISGID: u32 = 0o2000;
+/// On a directory, only owners may remove or rename entries.
+pub const S_ISVTX: u32 = 0o1000;
+
+bitflags! {
+    /// Kinds of access to a file, as the `rwx` bits order them.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Access: u32 {
+        const READ = 4;
+        const WRITE = 2;
+        const EXECUTE = 1;
+    }
+}
+
+/// The real, effective and saved set IDs of one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ids {
+    pub real: u32,
+    pub effective: u32,
+    pub saved: u32,
+}
+
+impl Ids {
+    const fn new(id: u32) -> Self {
+        Self {
+            real: id,
+            effective: id,
+            saved: id,
+        }
+    }
+
+    fn holds(&self, id: u32) -> bool {
+        id == self.real || id == self.effective || id == self.saved
+    }
+
+    /// `setuid`: a privileged caller sets all three, others only switch the
+    /// effective ID to the real or saved one.
+    pub fn set(&mut self, id: u32, privileged: bool) -> Result<(), usize> {
+        if privileged {
+            *self = Self::new(id);
+        } else if id == self.real || id == self.saved {
+            self.effective = id;
+        } else {
+            return Err(EPERM);
+        }
+        Ok(())
+    }
+
+    /// `setreuid`. The saved ID follows the effective one whenever the real
+    /// ID is set, or the effective one is set to something else than it.
+    pub fn set_real_effective(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        privileged: bool,
+    ) -> Result<(), usize> {
+        if !privileged
+            && (real.is_some_and(|id| id != self.real && id != self.effective)
+                || effective.is_some_and(|id| !self.holds(id)))
+        {
+            return Err(EPERM);
+        }
+
+        let old_real = self.real;
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if real.is_some() || effective.is_some_and(|id| id != old_real) {
+            self.saved = self.effective;
+        }
+        Ok(())
+    }
+
+    /// `setresuid`. Without privilege, each new ID must be one of the
+    /// current three.
+    pub fn set_all(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        saved: Option<u32>,
+        privileged: bool,
+    ) -> Result<(), usize> {
+        if !privileged
+            && [real, effective, saved]
+                .iter()
+                .flatten()
+                .any(|&id| !self.holds(id))
+        {
+            return Err(EPERM);
+        }
+
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if let Some(id) = saved {
+            self.saved = id;
+        }
+        Ok(())
+    }
+}
+
+/// Who a process acts as.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub user: Ids,
+    pub group: Ids,
+    /// Supplementary groups.
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub const fn root() -> Self {
+        Self {
+            user: Ids::new(ROOT),
+            group: Ids::new(ROOT),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.user.effective == ROOT
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.group.effective == gid || self.groups.contains(&gid)
+    }
+
+    /// Whether the file described by `metadata` allows `access`. Root may
+    /// do anything, except execute files nobody may execute.
+    pub fn may(&self, metadata: &Metadata, access: Access) -> bool {
+        if self.is_root() {
+            return !access.contains(Access::EXECUTE)
+                || metadata.mode & S_IFMT == S_IFDIR
+                || metadata.mode & 0o111 != 0;
+        }
+
+        let bits = if metadata.uid == self.user.effective {
+            metadata.mode >> 6
+        } else if self.in_group(metadata.gid) {
+            metadata.mode >> 3
+        } else {
+            metadata.mode
+        };
+        Access::from_bits_truncate(bits & 0o7).contains(access)
+    }
+
+    /// Whether the process may change the mode or times of the file.
+    pub fn owns(&self, metadata: &Metadata) -> bool {
+        self.is_root() || metadata.uid == self.user.effective
+    }
+
+    pub fn set_groups(&mut self, groups: &[u32]) -> Result<(), usize> {
+        if !self.is_root() {
+            return Err(EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(EINVAL);
+        }
+        self.groups = groups.to_vec();
+        Ok(())
+    }
+
+    /// What the credentials become when executing the file described by
+    /// `metadata`, honouring its set-ID bits.
+    pub fn for_exec(&self, metadata: &Metadata) -> Self {
+        let mut credentials = self.clone();
+        if metadata.mode & S_ISUID != 0 {
+            credentials.user.effective = metadata.uid;
+        }
+        if metadata.mode & S_ISGID != 0 {
+            credentials.group.effective = metadata.gid;
+        }
+        credentials.user.saved = credentials.user.effective;
+        credentials.group.saved = credentials.group.effective;
+        credentials
+    }
+}
@@ -1,9 +1,13 @@
+pub mod capability;
 pub mod context;
+pub mod credentials;
 pub mod process;
 pub mod scheduler;
+pub mod signal;
 pub mod stack;
 pub mod thread;
 pub mod timer;
+pub mod wait_queue;
 
 use process::{ProcessId, SharedProcess};
 use scheduler::SCHEDULER;
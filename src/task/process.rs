@@ -7,13 +7,21 @@ use object::{File, Object, ObjectSegment};
 use spin::{Lazy, RwLock};
 use x86_64::VirtAddr;
 use x86_64::structures::paging::OffsetPageTable;
+use x86_64::structures::paging::mapper::MapToError;
 
+use super::capability::Capabilities;
+use super::credentials::{Access, Credentials};
 use super::thread::{SharedThread, Thread};
+use super::wait_queue::WaitQueue;
+use crate::fs::mount::{MountFlags, mount_flags};
+use crate::fs::vfs::inode::InodeTy;
 use crate::memory::{ExtendedPageTable, ref_current_page_table};
 use crate::memory::{FRAME_ALLOCATOR, KERNEL_PAGE_TABLE};
-use crate::memory::{MappingType, MemoryManager};
+use crate::memory::{MappingType, MemoryManager, USER_END};
+use crate::syscall::errno::{EACCES, EIO, ENOEXEC, ENOMEM};
+use crate::tty::Tty;
 
-pub(super) type SharedProcess = Arc<RwLock<Process>>;
+pub type SharedProcess = Arc<RwLock<Process>>;
 pub(super) type WeakSharedProcess = Weak<RwLock<Process>>;
 
 pub static KERNEL_PROCESS: Lazy<SharedProcess> = Lazy::new(|| {
@@ -25,6 +33,9 @@ pub static KERNEL_PROCESS: Lazy<SharedProcess> = Lazy::new(|| {
 
 pub static PROCESSES: RwLock<Vec<SharedProcess>> = RwLock::new(Vec::new());
 
+/// Woken whenever a process exits, for `wait4`.
+pub static EXITED: WaitQueue = WaitQueue::new();
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ProcessId(pub u64);
 
@@ -45,17 +56,47 @@ impl From<u64> for ProcessId {
 pub struct Process {
     pub id: ProcessId,
     pub name: String,
+    /// The process this one was forked from.
+    pub parent: Option<ProcessId>,
     pub page_table: OffsetPageTable<'static>,
     pub threads: Vec<SharedThread>,
+    pub pending_signals: u64,
+    /// The process group, which job control signals are sent to.
+    pub pgid: ProcessId,
+    /// The session, named after its leader.
+    pub sid: ProcessId,
+    /// The controlling terminal of the session.
+    pub tty: Option<Arc<Tty>>,
+    /// The signal the process was stopped by, until it gets `SIGCONT`.
+    pub stopped: Option<usize>,
+    /// Whether `wait4` has told the parent about the current stop.
+    pub stop_reported: bool,
+    /// What the process may do beyond what every process can.
+    pub capabilities: Capabilities,
+    /// Who the process acts as when permissions are checked.
+    pub credentials: Credentials,
+    /// Permission bits cleared from the mode of files the process creates.
+    pub umask: u16,
 }
 
 impl Process {
     pub fn new(name: &str, page_table: OffsetPageTable<'static>) -> Self {
+        let id = ProcessId::new();
         Self {
-            id: ProcessId::new(),
+            id,
             name: String::from(name),
+            parent: None,
             page_table,
             threads: Vec::new(),
+            pending_signals: 0,
+            pgid: id,
+            sid: id,
+            tty: None,
+            stopped: None,
+            stop_reported: false,
+            capabilities: Capabilities::none(),
+            credentials: Credentials::root(),
+            umask: 0o022,
         }
     }
 
@@ -67,29 +108,97 @@ impl Process {
         {
             processes.remove(index);
         }
-    }
+        drop(processes);
 
-    pub fn create(name: &str, elf_data: &'static [u8]) {
-        let binary = ProcessBinary::parse(elf_data);
-        let mut page_table = unsafe { KERNEL_PAGE_TABLE.lock().deep_copy() };
-        ProcessBinary::map_segments(&binary, &mut page_table);
+        let names = crate::service::provider_exited(self.id);
+        crate::fs::user::server_exited(self.id, &names);
+        if self.sid == self.id
+            && let Some(tty) = &self.tty
+        {
+            tty.hang_up(self.sid);
+        }
+        EXITED.wake_all();
+    }
 
-        let process = Arc::new(RwLock::new(Self::new(name, page_table)));
+    /// Loads the ELF image `elf_data` into a new process and starts it.
+    /// Fails with `ENOEXEC` if the image is not a valid user program, or
+    /// `ENOMEM` if its segments don't fit in memory.
+    pub fn create(
+        name: &str,
+        elf_data: &[u8],
+        capabilities: Capabilities,
+        credentials: Credentials,
+    ) -> Result<ProcessId, usize> {
+        let binary = ProcessBinary::parse(elf_data)?;
+        let page_table = unsafe { KERNEL_PAGE_TABLE.lock().deep_copy() };
+
+        // Dropping the process on failure frees what was mapped so far.
+        let mut process = Self::new(name, page_table);
+        ProcessBinary::map_segments(&binary, &mut process.page_table)?;
+        process.capabilities = capabilities;
+        process.credentials = credentials;
+        let process = Arc::new(RwLock::new(process));
         Thread::new_user_thread(Arc::downgrade(&process), binary.entry() as usize);
         crate::fs::operation::init_file_descriptor_manager(process.read().id);
         PROCESSES.write().push(process.clone());
+
+        Ok(process.read().id)
     }
 }
 
+/// Starts the program at `path` as `credentials`, which must allow
+/// reaching and executing it. Set-ID bits on the file take effect unless its filesystem
+/// is mounted `nosuid`.
+pub fn exec(
+    path: &str,
+    capabilities: Capabilities,
+    credentials: &Credentials,
+) -> Result<ProcessId, usize> {
+    let inode = crate::fs::operation::open_as(path, credentials)?;
+    let flags = mount_flags(&inode.read().get_path());
+    let metadata = inode.read().metadata(0);
+    if inode.read().inode_type() != InodeTy::File
+        || flags.contains(MountFlags::NOEXEC)
+        || !credentials.may(&metadata, Access::EXECUTE)
+    {
+        return Err(EACCES);
+    }
+    let credentials = match flags.contains(MountFlags::NOSUID) {
+        true => credentials.clone(),
+        false => credentials.for_exec(&metadata),
+    };
+
+    let size = inode.read().size(0);
+    let mut elf_data = alloc::vec![0u8; size];
+    if inode.read().read_at(0, 0, &mut elf_data) != size {
+        return Err(EIO);
+    }
+
+    Process::create(path, &elf_data, capabilities, credentials)
+}
+
 struct ProcessBinary;
 
 impl ProcessBinary {
-    fn parse(bin: &'static [u8]) -> File<'static> {
-        File::parse(bin).expect("Failed to parse ELF binary!")
+    fn parse(bin: &[u8]) -> Result<File<'_>, usize> {
+        File::parse(bin).map_err(|_| ENOEXEC)
     }
 
-    fn map_segments(elf_file: &File, page_table: &mut OffsetPageTable<'static>) {
+    fn map_segments(
+        elf_file: &File,
+        page_table: &mut OffsetPageTable<'static>,
+    ) -> Result<(), usize> {
         for segment in elf_file.segments() {
+            if segment.size() == 0 {
+                continue;
+            }
+            let end = segment
+                .address()
+                .checked_add(segment.size())
+                .ok_or(ENOEXEC)?;
+            if end > USER_END {
+                return Err(ENOEXEC);
+            }
             let address = VirtAddr::new(segment.address());
 
             MemoryManager::alloc_range(
@@ -98,12 +207,19 @@ impl ProcessBinary {
                 MappingType::UserCode.flags(),
                 page_table,
             )
-            .expect("Failed to allocate memory for ELF segment");
+            .map_err(|err| match err {
+                MapToError::FrameAllocationFailed => ENOMEM,
+                _ => ENOEXEC,
+            })?;
 
             if let Ok(data) = segment.data() {
+                if data.len() as u64 > segment.size() {
+                    return Err(ENOEXEC);
+                }
                 page_table.write_to_mapped_address(data, address);
             }
         }
+        Ok(())
     }
 }
 
@@ -6,7 +6,7 @@ use x86_64::VirtAddr;
 
 use super::context::Context;
 use super::process::{PROCESSES, ProcessId, WeakSharedProcess};
-use super::thread::{Thread, WeakSharedThread};
+use super::thread::{Thread, ThreadState, WeakSharedThread};
 use crate::acpi::apic::LAPIC;
 use crate::smp::CPUS;
 
@@ -58,6 +58,24 @@ impl Scheduler {
             .retain(|other| !Weak::ptr_eq(other, &thread));
     }
 
+    /// Makes a blocked thread runnable again. A thread that is only about to
+    /// block keeps running instead.
+    pub fn wake(&mut self, thread: WeakSharedThread) {
+        let Some(shared) = thread.upgrade() else {
+            return;
+        };
+
+        let mut shared = shared.write();
+        match shared.state {
+            ThreadState::Blocked => {
+                shared.state = ThreadState::Running;
+                self.ready_threads.push_back(thread);
+            }
+            ThreadState::Blocking => shared.state = ThreadState::Running,
+            ThreadState::Running => {}
+        }
+    }
+
     #[inline]
     pub fn current(&self) -> WeakSharedThread {
         let lapic_id = unsafe { LAPIC.lock().id() };
@@ -74,22 +92,29 @@ impl Scheduler {
                 let mut thread = thread.write();
                 thread.context = Context::from_address(context);
 
-                if !thread.sleeping {
-                    self.ready_threads.push_back(weak.clone());
+                match thread.state {
+                    ThreadState::Running => self.ready_threads.push_back(weak.clone()),
+                    ThreadState::Blocking => thread.state = ThreadState::Blocked,
+                    ThreadState::Blocked => {}
                 }
-                thread.sleeping = false;
             }
         }
 
         if let Some(next_thread) = self.ready_threads.pop_front() {
             self.current_threads.insert(lapic_id, next_thread);
+        } else if let Some(thread) = self.current_threads[&lapic_id].upgrade() {
+            // Nothing else to run: let the blocked thread recheck its condition.
+            thread.write().state = ThreadState::Running;
         }
 
         let next_thread = self.current_threads[&lapic_id].upgrade().unwrap();
         let next_thread = next_thread.read();
 
         let kernel_address = next_thread.kernel_stack.end_address();
-        CPUS.write().get_mut(lapic_id).set_ring0_rsp(kernel_address);
+        let mut cpus = CPUS.write();
+        let cpu = cpus.get_mut(lapic_id);
+        cpu.set_ring0_rsp(kernel_address);
+        cpu.set_io_bitmap(next_thread.io_bitmap.as_deref());
 
         next_thread.context.address()
     }
@@ -0,0 +1,146 @@
+use super::get_current_process;
+use super::process::EXITED;
+use super::process::{PROCESSES, ProcessId};
+use super::scheduler::SCHEDULER;
+use super::wait_queue::WaitQueue;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGKILL: usize = 9;
+pub const SIGPIPE: usize = 13;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+const SIGNAL_COUNT: usize = 64;
+
+/// Signals whose default action is to do nothing. `SIGCONT` resumes a
+/// stopped process when it is sent, not when it is handled.
+const IGNORED_SIGNALS: [usize; 4] = [SIGCHLD, SIGCONT, SIGURG, SIGWINCH];
+
+/// Signals whose default action is to stop the process.
+const STOP_SIGNALS: [usize; 4] = [SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU];
+
+/// Stopped processes sleep here until they are continued or killed.
+static CONTINUED: WaitQueue = WaitQueue::new();
+
+fn signal_bit(signal: usize) -> u64 {
+    1 << (signal - 1)
+}
+
+fn mask(signals: &[usize]) -> u64 {
+    signals
+        .iter()
+        .fold(0, |mask, &signal| mask | signal_bit(signal))
+}
+
+/// The pending signals that do something when handled.
+fn actionable(pending: u64) -> u64 {
+    pending & !mask(&IGNORED_SIGNALS)
+}
+
+fn terminating(pending: u64) -> u64 {
+    actionable(pending) & !mask(&STOP_SIGNALS)
+}
+
+/// Marks `signal` pending for the process `pid` and wakes its threads so
+/// interruptible waits can notice it.
+pub fn send_signal(pid: ProcessId, signal: usize) -> Option<()> {
+    if signal == 0 || signal > SIGNAL_COUNT {
+        return None;
+    }
+
+    let processes = PROCESSES.read();
+    let process = processes.iter().find(|process| process.read().id == pid)?;
+    let mut process = process.write();
+    process.pending_signals |= signal_bit(signal);
+
+    if signal == SIGCONT {
+        process.pending_signals &= !mask(&STOP_SIGNALS);
+        process.stopped = None;
+        CONTINUED.wake_all();
+    } else if STOP_SIGNALS.contains(&signal) {
+        process.pending_signals &= !signal_bit(SIGCONT);
+    }
+
+    if actionable(signal_bit(signal)) != 0 {
+        let mut scheduler = SCHEDULER.lock();
+        for thread in process.threads.iter() {
+            scheduler.wake(alloc::sync::Arc::downgrade(thread));
+        }
+    }
+
+    Some(())
+}
+
+/// Sends `signal` to every process in the process group `pgid`.
+pub fn send_signal_to_group(pgid: ProcessId, signal: usize) {
+    let members: alloc::vec::Vec<ProcessId> = PROCESSES
+        .read()
+        .iter()
+        .map(|process| process.read())
+        .filter(|process| process.pgid == pgid)
+        .map(|process| process.id)
+        .collect();
+
+    for pid in members {
+        send_signal(pid, signal);
+    }
+}
+
+pub fn send_signal_to_current(signal: usize) {
+    let pid = get_current_process().read().id;
+    send_signal(pid, signal);
+}
+
+/// Whether the current process has a pending signal that will terminate or
+/// stop it.
+pub fn has_pending_signal() -> bool {
+    actionable(get_current_process().read().pending_signals) != 0
+}
+
+/// Carries out the default action of every pending signal of the current
+/// process. Called on the way back from each syscall.
+pub fn handle_pending_signals() {
+    let process = get_current_process();
+    let pending = core::mem::take(&mut process.write().pending_signals);
+
+    let terminate = terminating(pending);
+    if terminate != 0 {
+        drop(process);
+        let signal = terminate.trailing_zeros() as usize + 1;
+        crate::syscall::op::sys_exit(128 + signal);
+        return;
+    }
+
+    let stop = actionable(pending);
+    if stop == 0 {
+        return;
+    }
+
+    {
+        let mut process = process.write();
+        // Continued before it even got to stop.
+        if process.pending_signals & signal_bit(SIGCONT) != 0 {
+            return;
+        }
+        process.stopped = Some(stop.trailing_zeros() as usize + 1);
+        process.stop_reported = false;
+    }
+    EXITED.wake_all();
+
+    CONTINUED.wait_until(|| {
+        let process = process.read();
+        (process.stopped.is_none() || terminating(process.pending_signals) != 0).then_some(0)
+    });
+    drop(process);
+
+    handle_pending_signals();
+}
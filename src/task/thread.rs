@@ -1,3 +1,4 @@
+use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
 use core::fmt::Debug;
 use core::sync::atomic::{AtomicU64, Ordering};
@@ -7,7 +8,7 @@ use super::context::Context;
 use super::process::{KERNEL_PROCESS, PROCESSES, WeakSharedProcess};
 use super::scheduler::SCHEDULER;
 use super::stack::{KernelStack, UserStack};
-use crate::gdt::Selectors;
+use crate::gdt::{IoBitmap, Selectors};
 use crate::memory::{ExtendedPageTable, KERNEL_PAGE_TABLE, ref_current_page_table};
 
 pub(super) type SharedThread = Arc<RwLock<Thread>>;
@@ -23,12 +24,23 @@ impl ThreadId {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    /// About to block; a wakeup arriving before the next reschedule cancels it.
+    Blocking,
+    Blocked,
+}
+
 pub struct Thread {
     pub id: ThreadId,
     pub kernel_stack: KernelStack,
     pub context: Context,
     pub process: WeakSharedProcess,
-    pub sleeping: bool,
+    pub state: ThreadState,
+    /// The I/O ports the thread was granted with `ioperm`, loaded into the
+    /// TSS whenever it is scheduled.
+    pub io_bitmap: Option<Box<IoBitmap>>,
 }
 
 impl Thread {
@@ -38,7 +50,8 @@ impl Thread {
             context: Context::default(),
             kernel_stack: KernelStack::default(),
             process,
-            sleeping: false,
+            state: ThreadState::Running,
+            io_bitmap: None,
         }
     }
 
@@ -85,10 +98,18 @@ impl Thread {
     }
 
     pub fn fork_thread(&self, regs: &mut Context) -> isize {
-        let current_process = Arc::new(RwLock::new(super::process::Process::new(
-            &self.process.upgrade().unwrap().read().name,
-            ref_current_page_table(),
-        )));
+        let parent = self.process.upgrade().unwrap();
+        let parent = parent.read();
+        let mut forked = super::process::Process::new(&parent.name, ref_current_page_table());
+        forked.parent = Some(parent.id);
+        forked.pgid = parent.pgid;
+        forked.sid = parent.sid;
+        forked.tty = parent.tty.clone();
+        forked.capabilities = parent.capabilities.clone();
+        forked.credentials = parent.credentials.clone();
+        forked.umask = parent.umask;
+        drop(parent);
+        let current_process = Arc::new(RwLock::new(forked));
 
         crate::fs::operation::init_file_descriptor_manager_for_fork(current_process.read().id);
 
@@ -32,7 +32,11 @@ impl Timer {
 
 impl Timer {
     pub fn add(&mut self, duration: Duration) {
-        let target_tick = HPET.estimate(duration);
+        self.add_deadline(HPET.estimate(duration));
+    }
+
+    /// Wakes the current thread once the HPET counter reaches `target_tick`.
+    pub fn add_deadline(&mut self, target_tick: u64) {
         let current_thread = SCHEDULER.lock().current();
         self.0.push(TimerInfo(Reverse(target_tick), current_thread));
         self.update_timer();
@@ -40,10 +44,21 @@ impl Timer {
 
     pub fn wakeup(&mut self) {
         if let Some(TimerInfo(_, thread)) = self.0.pop() {
-            if thread.upgrade().is_some() {
-                SCHEDULER.lock().add(thread);
-                self.update_timer();
+            let mut scheduler = SCHEDULER.lock();
+            scheduler.wake(thread);
+
+            // The comparator only fires once, so also take every other
+            // timer that expired in the meantime.
+            let now = HPET.ticks();
+            while let Some(TimerInfo(Reverse(target_tick), _)) = self.0.peek()
+                && *target_tick <= now
+            {
+                let TimerInfo(_, thread) = self.0.pop().unwrap();
+                scheduler.wake(thread);
             }
+
+            drop(scheduler);
+            self.update_timer();
         }
     }
 }
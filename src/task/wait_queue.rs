@@ -0,0 +1,134 @@
+use alloc::{collections::VecDeque, sync::Weak};
+use core::time::Duration;
+use spin::Mutex;
+
+use super::scheduler::SCHEDULER;
+use super::signal::has_pending_signal;
+use super::thread::{ThreadState, WeakSharedThread};
+use super::timer::TIMER;
+use crate::acpi::hpet::HPET;
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+
+/// Threads waiting for some condition, woken by whoever changes it.
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<WeakSharedThread>>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Marks the current thread as about to block, or as running again.
+pub fn set_current_blocking(blocking: bool) {
+    let thread = SCHEDULER.lock().current();
+    if let Some(shared) = thread.upgrade() {
+        shared.write().state = match blocking {
+            true => ThreadState::Blocking,
+            false => ThreadState::Running,
+        };
+    }
+}
+
+/// Whether the HPET counter has passed `deadline`.
+pub fn deadline_passed(deadline: Option<u64>) -> bool {
+    deadline.is_some_and(|deadline| HPET.ticks() >= deadline)
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn remove(&self, thread: &WeakSharedThread) {
+        self.waiters
+            .lock()
+            .retain(|other| !Weak::ptr_eq(other, thread));
+    }
+
+    /// Adds the current thread to the queue without blocking, for waits
+    /// spanning several queues. Pair with [`WaitQueue::unregister`].
+    pub fn register(&self) {
+        let thread = SCHEDULER.lock().current();
+        self.waiters.lock().push_back(thread);
+    }
+
+    pub fn unregister(&self) {
+        let thread = SCHEDULER.lock().current();
+        self.remove(&thread);
+    }
+
+    fn wait(
+        &self,
+        interruptible: bool,
+        deadline: Option<u64>,
+        mut condition: impl FnMut() -> Option<usize>,
+    ) -> usize {
+        if let Some(deadline) = deadline {
+            TIMER.lock().add_deadline(deadline);
+        }
+
+        loop {
+            // Register before checking, so a wakeup racing with the check
+            // turns the upcoming block into a no-op instead of being lost.
+            self.register();
+            set_current_blocking(true);
+
+            let result = match condition() {
+                Some(value) => Some(value),
+                None if interruptible && has_pending_signal() => Some(EINTR.wrapping_neg()),
+                None if deadline_passed(deadline) => Some(ETIMEDOUT.wrapping_neg()),
+                None => None,
+            };
+
+            if let Some(value) = result {
+                self.unregister();
+                set_current_blocking(false);
+                return value;
+            }
+
+            crate::syscall::op::sys_yield();
+            self.unregister();
+        }
+    }
+
+    /// Blocks the current thread until `condition` returns a value. The
+    /// condition is re-evaluated after every wakeup.
+    pub fn wait_until(&self, condition: impl FnMut() -> Option<usize>) -> usize {
+        self.wait(false, None, condition)
+    }
+
+    /// Like [`WaitQueue::wait_until`], but gives up with `-EINTR` once a
+    /// signal is pending for the current process.
+    pub fn wait_interruptible(&self, condition: impl FnMut() -> Option<usize>) -> usize {
+        self.wait(true, None, condition)
+    }
+
+    /// Like [`WaitQueue::wait_until`], but gives up with `-ETIMEDOUT` after
+    /// `timeout`.
+    pub fn wait_timeout(
+        &self,
+        timeout: Duration,
+        condition: impl FnMut() -> Option<usize>,
+    ) -> usize {
+        self.wait(false, Some(HPET.estimate(timeout)), condition)
+    }
+
+    pub fn wake_one(&self) {
+        let thread = self.waiters.lock().pop_front();
+        if let Some(thread) = thread {
+            SCHEDULER.lock().wake(thread);
+        }
+    }
+
+    pub fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        let mut scheduler = SCHEDULER.lock();
+        for thread in waiters {
+            scheduler.wake(thread);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use core::time::Duration;
+use spin::Lazy;
+use x86_64::instructions::port::Port;
+
+use crate::acpi::hpet::HPET;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0a;
+const RTC_STATUS_B: u8 = 0x0b;
+
+/// Status A: the clock is mid-update and its registers are inconsistent.
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+/// Status B: values are binary rather than BCD.
+const BINARY_MODE: u8 = 0x04;
+/// Status B: hours run 0-23 rather than 1-12 with a PM bit.
+const HOUR_24: u8 = 0x02;
+const HOUR_PM: u8 = 0x80;
+
+/// Seconds since the Unix epoch at the moment [`HPET`] started counting.
+static BOOT_TIME: Lazy<Duration> = Lazy::new(|| read_rtc().saturating_sub(HPET.elapsed()));
+
+/// The wall-clock time, as a duration since the Unix epoch.
+pub fn now() -> Duration {
+    *BOOT_TIME + HPET.elapsed()
+}
+
+fn read_cmos(register: u8) -> u8 {
+    unsafe {
+        Port::new(CMOS_ADDRESS).write(register);
+        Port::new(CMOS_DATA).read()
+    }
+}
+
+fn read_registers() -> [u8; 6] {
+    while read_cmos(RTC_STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    [
+        RTC_SECONDS,
+        RTC_MINUTES,
+        RTC_HOURS,
+        RTC_DAY,
+        RTC_MONTH,
+        RTC_YEAR,
+    ]
+    .map(read_cmos)
+}
+
+/// Reads the CMOS real-time clock, which keeps UTC. Two equal reads in a
+/// row guard against an update landing in between.
+fn read_rtc() -> Duration {
+    let mut registers = read_registers();
+    loop {
+        let again = read_registers();
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+    let [second, minute, hour, day, month, year] = registers;
+
+    let status = read_cmos(RTC_STATUS_B);
+    let decode = |value: u8| {
+        if status & BINARY_MODE != 0 {
+            value as u64
+        } else {
+            ((value >> 4) * 10 + (value & 0x0f)) as u64
+        }
+    };
+
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = decode(hour & !HOUR_PM);
+    if status & HOUR_24 == 0 {
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    // The century register isn't standard; assume this one.
+    let year = 2000 + decode(year);
+
+    let days = days_from_civil(year, decode(month), decode(day));
+    Duration::from_secs(((days * 24 + hour) * 60 + decode(minute)) * 60 + decode(second))
+}
+
+/// Days from 1970-01-01 to the given date, for years from 1970 on.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
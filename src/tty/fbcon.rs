@@ -0,0 +1,312 @@
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use spin::{Mutex, Once};
+
+use super::{Tty, TtyDriver, termios::WinSize};
+use crate::fs::vfs::fb::{FramebufferInfo, framebuffer_info};
+
+const FONT_MODULE: &str = "/font.psf";
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+
+const FOREGROUND: u32 = 0x00aa_aaaa;
+const BACKGROUND: u32 = 0x0000_0000;
+
+/// `tty1`, drawn on the framebuffer and fed by the PS/2 keyboard.
+static FB_CONSOLE: Once<Arc<Tty>> = Once::new();
+
+/// A PC Screen Font, version 1 or 2.
+struct Font {
+    glyphs: &'static [u8],
+    count: usize,
+    width: usize,
+    height: usize,
+    bytes_per_glyph: usize,
+}
+
+impl Font {
+    fn parse(data: &'static [u8]) -> Option<Self> {
+        if data.starts_with(&PSF1_MAGIC) {
+            let mode = *data.get(2)?;
+            let height = *data.get(3)? as usize;
+            let count = if mode & 1 != 0 { 512 } else { 256 };
+            return Some(Self {
+                glyphs: data.get(4..4 + count * height)?,
+                count,
+                width: 8,
+                height,
+                bytes_per_glyph: height,
+            });
+        }
+
+        if data.starts_with(&PSF2_MAGIC) {
+            let field = |index: usize| -> Option<usize> {
+                let bytes = data.get(index * 4..index * 4 + 4)?;
+                Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+            };
+            let header_size = field(2)?;
+            let count = field(4)?;
+            let bytes_per_glyph = field(5)?;
+            let (height, width) = (field(6)?, field(7)?);
+            if width == 0 || height == 0 || bytes_per_glyph < height * width.div_ceil(8) {
+                return None;
+            }
+            return Some(Self {
+                glyphs: data.get(header_size..header_size + count * bytes_per_glyph)?,
+                count,
+                width,
+                height,
+                bytes_per_glyph,
+            });
+        }
+
+        None
+    }
+
+    fn glyph(&self, byte: u8) -> &[u8] {
+        let index = match (byte as usize) < self.count {
+            true => byte as usize,
+            false => b'?' as usize,
+        };
+        &self.glyphs[index * self.bytes_per_glyph..][..self.bytes_per_glyph]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// After `ESC`.
+    Start,
+    /// Inside `ESC [`, collecting parameters.
+    Csi,
+}
+
+/// The text on screen. Cells are kept so the character under the cursor can
+/// be redrawn when the cursor moves away.
+struct Screen {
+    framebuffer: &'static mut [u8],
+    pitch: usize,
+    font: Font,
+    cols: usize,
+    rows: usize,
+    cells: Vec<u8>,
+    x: usize,
+    y: usize,
+    escape: Escape,
+    params: [usize; 4],
+    param_count: usize,
+}
+
+impl Screen {
+    fn new(info: FramebufferInfo, font: Font) -> Self {
+        let cols = info.width / font.width;
+        let rows = info.height / font.height;
+        let framebuffer = unsafe {
+            core::slice::from_raw_parts_mut(info.addr as *mut u8, info.pitch * info.height)
+        };
+
+        let mut screen = Self {
+            framebuffer,
+            pitch: info.pitch,
+            font,
+            cols,
+            rows,
+            cells: vec![b' '; cols * rows],
+            x: 0,
+            y: 0,
+            escape: Escape::None,
+            params: [0; 4],
+            param_count: 0,
+        };
+        screen.clear(0, cols * rows);
+        screen.draw_cursor(true);
+        screen
+    }
+
+    fn draw_cell(&mut self, col: usize, row: usize, inverted: bool) {
+        let (foreground, background) = match inverted {
+            true => (BACKGROUND, FOREGROUND),
+            false => (FOREGROUND, BACKGROUND),
+        };
+        let byte = self.cells[row * self.cols + col];
+        let stride = self.font.width.div_ceil(8);
+
+        for line in 0..self.font.height {
+            let bits = &self.font.glyph(byte)[line * stride..][..stride];
+            let offset = (row * self.font.height + line) * self.pitch + col * self.font.width * 4;
+            for column in 0..self.font.width {
+                let set = bits[column / 8] & (0x80 >> (column % 8)) != 0;
+                let color = if set { foreground } else { background };
+                let pixel = offset + column * 4;
+                self.framebuffer[pixel..pixel + 4].copy_from_slice(&color.to_ne_bytes());
+            }
+        }
+    }
+
+    fn draw_cursor(&mut self, visible: bool) {
+        self.draw_cell(self.x.min(self.cols - 1), self.y, visible);
+    }
+
+    /// Blanks the cells from `start` up to `end`, counted row by row.
+    fn clear(&mut self, start: usize, end: usize) {
+        for cell in start..end.min(self.cells.len()) {
+            self.cells[cell] = b' ';
+            self.draw_cell(cell % self.cols, cell / self.cols, false);
+        }
+    }
+
+    fn scroll(&mut self) {
+        let row_bytes = self.font.height * self.pitch;
+        self.framebuffer
+            .copy_within(row_bytes..row_bytes * self.rows, 0);
+        self.cells.copy_within(self.cols.., 0);
+        let last = (self.rows - 1) * self.cols;
+        self.clear(last, last + self.cols);
+    }
+
+    fn newline(&mut self) {
+        if self.y + 1 == self.rows {
+            self.scroll();
+        } else {
+            self.y += 1;
+        }
+    }
+
+    fn put(&mut self, byte: u8) {
+        if self.x == self.cols {
+            self.x = 0;
+            self.newline();
+        }
+        self.cells[self.y * self.cols + self.x] = byte;
+        self.draw_cell(self.x, self.y, false);
+        self.x += 1;
+    }
+
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params[index] {
+            0 => default,
+            value => value,
+        }
+    }
+
+    /// Handles the final byte of a CSI sequence. Colors (`m`) and anything
+    /// else not listed are ignored.
+    fn csi(&mut self, byte: u8) {
+        match byte {
+            b'A' => self.y = self.y.saturating_sub(self.param(0, 1)),
+            b'B' => self.y = (self.y + self.param(0, 1)).min(self.rows - 1),
+            b'C' => self.x = (self.x + self.param(0, 1)).min(self.cols - 1),
+            b'D' => self.x = self.x.saturating_sub(self.param(0, 1)),
+            b'H' | b'f' => {
+                self.y = (self.param(0, 1) - 1).min(self.rows - 1);
+                self.x = (self.param(1, 1) - 1).min(self.cols - 1);
+            }
+            b'J' => {
+                let cursor = self.y * self.cols + self.x;
+                match self.params[0] {
+                    0 => self.clear(cursor, self.cells.len()),
+                    1 => self.clear(0, cursor + 1),
+                    _ => self.clear(0, self.cells.len()),
+                }
+            }
+            b'K' => {
+                let line = self.y * self.cols;
+                let cursor = line + self.x;
+                match self.params[0] {
+                    0 => self.clear(cursor, line + self.cols),
+                    1 => self.clear(line, cursor + 1),
+                    _ => self.clear(line, line + self.cols),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.escape {
+            Escape::Start => {
+                self.escape = match byte {
+                    b'[' => {
+                        self.params = [0; 4];
+                        self.param_count = 0;
+                        Escape::Csi
+                    }
+                    _ => Escape::None,
+                };
+            }
+            Escape::Csi => match byte {
+                b'0'..=b'9' => {
+                    if let Some(param) = self.params.get_mut(self.param_count) {
+                        *param = *param * 10 + (byte - b'0') as usize;
+                    }
+                }
+                b';' => self.param_count += 1,
+                b'?' => {}
+                _ => {
+                    self.escape = Escape::None;
+                    self.csi(byte);
+                }
+            },
+            Escape::None => match byte {
+                0x1b => self.escape = Escape::Start,
+                b'\n' => self.newline(),
+                b'\r' => self.x = 0,
+                0x08 => self.x = self.x.saturating_sub(1),
+                b'\t' => {
+                    for _ in 0..8 - self.x % 8 {
+                        self.put(b' ');
+                    }
+                }
+                0x07 => {}
+                _ => self.put(byte),
+            },
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        self.draw_cursor(false);
+        for &byte in buf {
+            self.write_byte(byte);
+        }
+        self.draw_cursor(true);
+    }
+}
+
+struct FbConsoleDriver {
+    screen: Mutex<Screen>,
+}
+
+impl TtyDriver for FbConsoleDriver {
+    fn write(&self, buf: &[u8]) {
+        self.screen.lock().write(buf);
+    }
+}
+
+pub fn console() -> Option<&'static Arc<Tty>> {
+    FB_CONSOLE.get()
+}
+
+/// Sets up `tty1` if there is a 32-bit framebuffer and the font module was
+/// loaded.
+pub(super) fn init() -> Option<Arc<Tty>> {
+    let font = Font::parse(crate::module::get_module(FONT_MODULE)?)?;
+    let info = framebuffer_info()
+        .filter(|info| info.bpp == 32 && info.width >= font.width && info.height >= font.height)?;
+
+    let screen = Screen::new(info, font);
+    let winsize = WinSize {
+        ws_row: screen.rows as u16,
+        ws_col: screen.cols as u16,
+        ws_xpixel: (screen.cols * screen.font.width) as u16,
+        ws_ypixel: (screen.rows * screen.font.height) as u16,
+    };
+    let driver = FbConsoleDriver {
+        screen: Mutex::new(screen),
+    };
+
+    Some(
+        FB_CONSOLE
+            .call_once(|| Tty::new("tty1", Box::new(driver), winsize))
+            .clone(),
+    )
+}
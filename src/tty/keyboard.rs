@@ -0,0 +1,109 @@
+use spin::Mutex;
+
+/// Scancode set 1 on a US layout, from 0x00 up to the space bar. Zero marks
+/// modifiers and keys that produce nothing.
+const NORMAL: &[u8; 0x3a] =
+    b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const SHIFTED: &[u8; 0x3a] =
+    b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+const EXTENDED_PREFIX: u8 = 0xe0;
+const RELEASED: u8 = 0x80;
+
+const LEFT_SHIFT: u8 = 0x2a;
+const RIGHT_SHIFT: u8 = 0x36;
+const CTRL: u8 = 0x1d;
+const CAPS_LOCK: u8 = 0x3a;
+
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard {
+    shift: false,
+    ctrl: false,
+    caps_lock: false,
+    extended: false,
+});
+
+struct Keyboard {
+    shift: bool,
+    ctrl: bool,
+    caps_lock: bool,
+    /// The previous byte was the 0xe0 prefix.
+    extended: bool,
+}
+
+impl Keyboard {
+    /// Turns a scancode into the bytes a terminal would send for it.
+    fn translate(&mut self, scancode: u8) -> &'static [u8] {
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return b"";
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let pressed = scancode & RELEASED == 0;
+        let key = scancode & !RELEASED;
+
+        match key {
+            LEFT_SHIFT | RIGHT_SHIFT if !extended => self.shift = pressed,
+            CTRL => self.ctrl = pressed,
+            CAPS_LOCK if pressed => self.caps_lock = !self.caps_lock,
+            _ if !pressed => {}
+            _ if extended => {
+                return match key {
+                    0x1c => b"\r",
+                    0x47 => b"\x1b[H",
+                    0x48 => b"\x1b[A",
+                    0x4b => b"\x1b[D",
+                    0x4d => b"\x1b[C",
+                    0x4f => b"\x1b[F",
+                    0x50 => b"\x1b[B",
+                    0x53 => b"\x1b[3~",
+                    _ => b"",
+                };
+            }
+            _ if (key as usize) < NORMAL.len() => return self.character(key as usize),
+            _ => {}
+        }
+        b""
+    }
+
+    fn character(&self, key: usize) -> &'static [u8] {
+        let normal = NORMAL[key];
+        let mut shift = self.shift;
+        if normal.is_ascii_lowercase() && self.caps_lock {
+            shift = !shift;
+        }
+        let table = match shift {
+            true => SHIFTED,
+            false => NORMAL,
+        };
+
+        if self.ctrl && normal.is_ascii_lowercase() {
+            return &CONTROL[(normal - b'a') as usize..][..1];
+        }
+        match table[key] {
+            0 => b"",
+            _ => &table[key..][..1],
+        }
+    }
+}
+
+/// `^A` to `^Z`.
+const CONTROL: &[u8; 26] = &{
+    let mut control = [0u8; 26];
+    let mut index = 0;
+    while index < control.len() {
+        control[index] = index as u8 + 1;
+        index += 1;
+    }
+    control
+};
+
+/// Feeds a scancode from the PS/2 keyboard to the framebuffer console.
+pub fn handle_scancode(scancode: u8) {
+    let bytes = KEYBOARD.lock().translate(scancode);
+    if !bytes.is_empty()
+        && let Some(console) = super::fbcon::console()
+    {
+        console.receive(bytes);
+    }
+}
@@ -0,0 +1,737 @@
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::time::Duration;
+use spin::{Mutex, Once, RwLock};
+
+use crate::{
+    fs::{
+        operation::{OpenFlags, OpenMode, get_flags_by_fd},
+        poll::{PollEvents, PollTable},
+        vfs::{
+            devfs::register_device,
+            inode::{Inode, InodeRef, InodeTy},
+        },
+    },
+    syscall::errno::{EAGAIN, EINTR, EIO, ENOTTY, ENXIO, EPERM, ETIMEDOUT},
+    task::{
+        get_current_process,
+        process::{PROCESSES, ProcessId},
+        signal::{
+            SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU, SIGWINCH, send_signal_to_group,
+        },
+        wait_queue::WaitQueue,
+    },
+};
+
+use termios::*;
+
+pub mod fbcon;
+pub mod keyboard;
+pub mod pty;
+pub mod serial;
+pub mod termios;
+
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TCSETSW: usize = 0x5403;
+const TCSETSF: usize = 0x5404;
+const TCSBRK: usize = 0x5409;
+const TCXONC: usize = 0x540a;
+const TCFLSH: usize = 0x540b;
+const TIOCSCTTY: usize = 0x540e;
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+const TIOCOUTQ: usize = 0x5411;
+const TIOCGWINSZ: usize = 0x5413;
+const TIOCSWINSZ: usize = 0x5414;
+const FIONREAD: usize = 0x541b;
+const TIOCNOTTY: usize = 0x5422;
+const TIOCGSID: usize = 0x5429;
+
+const TCIFLUSH: usize = 0;
+const TCIOFLUSH: usize = 2;
+
+/// The terminal `/dev/console` and `SYS_PUT_STRING` write to.
+static CONSOLE: Once<Arc<Tty>> = Once::new();
+
+/// The hardware side of a terminal.
+pub trait TtyDriver: Send + Sync {
+    /// Puts bytes on the wire or the screen. Output processing has already
+    /// been applied.
+    fn write(&self, buf: &[u8]);
+}
+
+pub struct Tty {
+    name: String,
+    driver: Box<dyn TtyDriver>,
+    state: Mutex<TtyState>,
+    input_ready: Arc<WaitQueue>,
+}
+
+struct TtyState {
+    termios: Termios,
+    winsize: WinSize,
+    /// Finished lines in canonical mode. A line without a trailing newline
+    /// was ended by `VEOF`; an empty one reads as end of file.
+    lines: VecDeque<Vec<u8>>,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// Input in non-canonical mode.
+    raw: VecDeque<u8>,
+    /// The next byte is taken literally (`VLNEXT`).
+    literal_next: bool,
+    session: Option<ProcessId>,
+    foreground: Option<ProcessId>,
+    /// The other end went away, as when a pty master is closed. Reads drain
+    /// what is left and then see end of file; writes fail.
+    hung_up: bool,
+}
+
+impl TtyState {
+    fn canonical(&self) -> bool {
+        self.termios.lflag(ICANON)
+    }
+
+    fn available(&self) -> usize {
+        match self.canonical() {
+            true => self.lines.iter().map(Vec::len).sum(),
+            false => self.raw.len(),
+        }
+    }
+
+    fn flush_input(&mut self) {
+        self.lines.clear();
+        self.line.clear();
+        self.raw.clear();
+    }
+
+    /// Moves pending input over when `ICANON` is switched.
+    fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.canonical();
+        self.termios = termios;
+        match (was_canonical, self.canonical()) {
+            (true, false) => {
+                for line in self.lines.drain(..) {
+                    self.raw.extend(line);
+                }
+                self.raw.extend(self.line.drain(..));
+            }
+            (false, true) => self.line.extend(self.raw.drain(..)),
+            _ => {}
+        }
+    }
+
+    /// Copies out what a read may return right now.
+    fn take_input(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.canonical() {
+            let line = self.lines.front_mut()?;
+            let len = buf.len().min(line.len());
+            buf[..len].copy_from_slice(&line[..len]);
+            if len == line.len() {
+                self.lines.pop_front();
+            } else {
+                line.drain(..len);
+            }
+            return Some(len);
+        }
+
+        let wanted = (self.termios.c_cc[VMIN] as usize).min(buf.len());
+        if self.raw.len() < wanted.max(1) {
+            return (wanted == 0).then_some(0);
+        }
+        let len = buf.len().min(self.raw.len());
+        for (slot, byte) in buf.iter_mut().zip(self.raw.drain(..len)) {
+            *slot = byte;
+        }
+        Some(len)
+    }
+}
+
+/// How a character is echoed: control characters as `^X` under `ECHOCTL`.
+fn echo_width(termios: &Termios, byte: u8) -> usize {
+    match byte {
+        b'\t' | b'\n' => 1,
+        0..0x20 | 0x7f if termios.lflag(ECHOCTL) => 2,
+        _ => 1,
+    }
+}
+
+impl Tty {
+    pub fn new(name: &str, driver: Box<dyn TtyDriver>, winsize: WinSize) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_string(),
+            driver,
+            state: Mutex::new(TtyState {
+                termios: Termios::default(),
+                winsize,
+                lines: VecDeque::new(),
+                line: Vec::new(),
+                raw: VecDeque::new(),
+                literal_next: false,
+                session: None,
+                foreground: None,
+                hung_up: false,
+            }),
+            input_ready: Arc::new(WaitQueue::new()),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Writes `buf` to the driver, applying `OPOST` processing.
+    fn emit(&self, termios: &Termios, buf: &[u8]) {
+        if !termios.oflag(OPOST) {
+            self.driver.write(buf);
+            return;
+        }
+
+        let mut start = 0;
+        for (index, &byte) in buf.iter().enumerate() {
+            let replacement: &[u8] = match byte {
+                b'\n' if termios.oflag(ONLCR) => b"\r\n",
+                b'\r' if termios.oflag(OCRNL) => b"\n",
+                _ => continue,
+            };
+            self.driver.write(&buf[start..index]);
+            self.driver.write(replacement);
+            start = index + 1;
+        }
+        self.driver.write(&buf[start..]);
+    }
+
+    fn echo(&self, termios: &Termios, byte: u8) {
+        if echo_width(termios, byte) == 2 {
+            self.emit(termios, &[b'^', byte ^ 0x40]);
+        } else {
+            self.emit(termios, &[byte]);
+        }
+    }
+
+    fn rub_out(&self, termios: &Termios, byte: u8) {
+        if termios.lflag(ECHO) && termios.lflag(ECHOE) {
+            for _ in 0..echo_width(termios, byte) {
+                self.emit(termios, b"\x08 \x08");
+            }
+        }
+    }
+
+    /// Feeds bytes typed on the terminal through the line discipline. Called
+    /// by drivers, usually from their interrupt handler.
+    pub fn receive(&self, bytes: &[u8]) {
+        let mut signals = Vec::new();
+        let mut state = self.state.lock();
+        for &byte in bytes {
+            if let Some(signal) = self.receive_byte(&mut state, byte) {
+                signals.push(signal);
+            }
+        }
+        let foreground = state.foreground;
+        drop(state);
+
+        if let Some(pgid) = foreground {
+            for signal in signals {
+                send_signal_to_group(pgid, signal);
+            }
+        }
+        self.input_ready.wake_all();
+    }
+
+    /// Returns the signal the byte raises, if any.
+    fn receive_byte(&self, state: &mut TtyState, mut byte: u8) -> Option<usize> {
+        let termios = state.termios;
+        let echo = termios.lflag(ECHO);
+
+        if core::mem::take(&mut state.literal_next) {
+            match state.canonical() {
+                true => state.line.push(byte),
+                false => state.raw.push_back(byte),
+            }
+            if echo {
+                self.echo(&termios, byte);
+            }
+            return None;
+        }
+
+        match byte {
+            b'\r' if termios.iflag(IGNCR) => return None,
+            b'\r' if termios.iflag(ICRNL) => byte = b'\n',
+            b'\n' if termios.iflag(INLCR) => byte = b'\r',
+            _ => {}
+        }
+
+        if termios.lflag(ISIG) {
+            let signal = if termios.is_cc(VINTR, byte) {
+                Some(SIGINT)
+            } else if termios.is_cc(VQUIT, byte) {
+                Some(SIGQUIT)
+            } else if termios.is_cc(VSUSP, byte) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+
+            if signal.is_some() {
+                if !termios.lflag(NOFLSH) {
+                    state.flush_input();
+                }
+                if echo {
+                    self.echo(&termios, byte);
+                }
+                return signal;
+            }
+        }
+
+        if termios.lflag(IEXTEN) && termios.is_cc(VLNEXT, byte) {
+            state.literal_next = true;
+            return None;
+        }
+
+        if !state.canonical() {
+            state.raw.push_back(byte);
+            if echo {
+                self.echo(&termios, byte);
+            }
+            return None;
+        }
+
+        if termios.is_cc(VERASE, byte) {
+            if let Some(erased) = state.line.pop() {
+                self.rub_out(&termios, erased);
+            }
+        } else if termios.lflag(IEXTEN) && termios.is_cc(VWERASE, byte) {
+            while state.line.last().is_some_and(u8::is_ascii_whitespace) {
+                let erased = state.line.pop().unwrap();
+                self.rub_out(&termios, erased);
+            }
+            while state
+                .line
+                .last()
+                .is_some_and(|byte| !byte.is_ascii_whitespace())
+            {
+                let erased = state.line.pop().unwrap();
+                self.rub_out(&termios, erased);
+            }
+        } else if termios.is_cc(VKILL, byte) {
+            if echo && termios.lflag(ECHOKE) {
+                for erased in core::mem::take(&mut state.line).into_iter().rev() {
+                    self.rub_out(&termios, erased);
+                }
+            } else {
+                state.line.clear();
+                if echo {
+                    self.echo(&termios, byte);
+                    if termios.lflag(ECHOK) {
+                        self.emit(&termios, b"\n");
+                    }
+                }
+            }
+        } else if termios.lflag(IEXTEN) && termios.is_cc(VREPRINT, byte) {
+            if echo {
+                self.echo(&termios, byte);
+                self.emit(&termios, b"\n");
+                self.emit(&termios, &state.line);
+            }
+        } else if termios.is_cc(VEOF, byte) {
+            let line = core::mem::take(&mut state.line);
+            state.lines.push_back(line);
+        } else if byte == b'\n' || termios.is_cc(VEOL, byte) || termios.is_cc(VEOL2, byte) {
+            if echo || (byte == b'\n' && termios.lflag(ECHONL)) {
+                self.echo(&termios, byte);
+            }
+            state.line.push(byte);
+            let line = core::mem::take(&mut state.line);
+            state.lines.push_back(line);
+        } else {
+            state.line.push(byte);
+            if echo {
+                self.echo(&termios, byte);
+            }
+        }
+
+        None
+    }
+
+    /// Whether `self` is the controlling terminal of the current process.
+    fn is_controlling(&self) -> bool {
+        let process = get_current_process();
+        let process = process.read();
+        process
+            .tty
+            .as_ref()
+            .is_some_and(|tty| core::ptr::eq(Arc::as_ptr(tty), self))
+    }
+
+    /// Stops a background process group touching its controlling terminal,
+    /// returning the error the syscall fails with.
+    fn check_foreground(&self, signal: usize) -> Option<usize> {
+        if !self.is_controlling() {
+            return None;
+        }
+
+        let pgid = get_current_process().read().pgid;
+        let foreground = self.state.lock().foreground;
+        if foreground.is_none_or(|foreground| foreground == pgid) {
+            return None;
+        }
+
+        send_signal_to_group(pgid, signal);
+        Some(EINTR.wrapping_neg())
+    }
+
+    pub fn read(&self, buf: &mut [u8], nonblock: bool) -> usize {
+        if let Some(err) = self.check_foreground(SIGTTIN) {
+            return err;
+        }
+
+        let mut take = || {
+            let mut state = self.state.lock();
+            let hung_up = state.hung_up;
+            state.take_input(buf).or(hung_up.then_some(0))
+        };
+        if nonblock {
+            return take().unwrap_or(EAGAIN.wrapping_neg());
+        }
+
+        let termios = self.state.lock().termios;
+        let vtime = termios.c_cc[VTIME];
+        if !termios.lflag(ICANON) && termios.c_cc[VMIN] == 0 && vtime != 0 {
+            let timeout = Duration::from_millis(vtime as u64 * 100);
+            return match self.input_ready.wait_timeout(timeout, take) {
+                err if err == ETIMEDOUT.wrapping_neg() => 0,
+                len => len,
+            };
+        }
+
+        self.input_ready.wait_interruptible(take)
+    }
+
+    pub fn write(&self, buf: &[u8]) -> usize {
+        let (termios, hung_up) = {
+            let state = self.state.lock();
+            (state.termios, state.hung_up)
+        };
+        if hung_up {
+            return EIO.wrapping_neg();
+        }
+        if termios.lflag(TOSTOP)
+            && let Some(err) = self.check_foreground(SIGTTOU)
+        {
+            return err;
+        }
+
+        self.emit(&termios, buf);
+        buf.len()
+    }
+
+    pub fn poll(&self, table: &mut PollTable) -> PollEvents {
+        table.register(&self.input_ready);
+
+        let mut events = PollEvents::OUT | PollEvents::WRNORM;
+        let state = self.state.lock();
+        if state.available() > 0 || !state.lines.is_empty() {
+            events |= PollEvents::IN | PollEvents::RDNORM;
+        }
+        if state.hung_up {
+            events |= PollEvents::HUP;
+        }
+        events
+    }
+
+    pub fn winsize(&self) -> WinSize {
+        self.state.lock().winsize
+    }
+
+    /// Changes the window size, telling the foreground group with `SIGWINCH`.
+    pub fn set_winsize(&self, winsize: WinSize) {
+        let mut state = self.state.lock();
+        if state.winsize == winsize {
+            return;
+        }
+        state.winsize = winsize;
+        let foreground = state.foreground;
+        drop(state);
+
+        if let Some(pgid) = foreground {
+            send_signal_to_group(pgid, SIGWINCH);
+        }
+    }
+
+    /// Makes `self` the controlling terminal of the current process's
+    /// session, which the process must lead.
+    fn set_controlling(self: &Arc<Self>) -> Result<(), usize> {
+        let process = get_current_process();
+        let mut process = process.write();
+        if process.sid != process.id || process.tty.is_some() {
+            return Err(EPERM);
+        }
+
+        let mut state = self.state.lock();
+        if state.session.is_some_and(|session| session != process.sid) {
+            return Err(EPERM);
+        }
+        state.session = Some(process.sid);
+        state.foreground = Some(process.pgid);
+        process.tty = Some(self.clone());
+        Ok(())
+    }
+
+    /// Detaches the terminal from its session when the session leader exits
+    /// or gives it up, hanging up the foreground group.
+    pub fn hang_up(&self, session: ProcessId) {
+        let mut state = self.state.lock();
+        if state.session != Some(session) {
+            return;
+        }
+        state.session = None;
+        let foreground = state.foreground.take();
+        drop(state);
+
+        for process in PROCESSES.read().iter() {
+            if process.read().sid == session {
+                process.write().tty = None;
+            }
+        }
+        if let Some(pgid) = foreground {
+            send_signal_to_group(pgid, SIGHUP);
+        }
+        self.input_ready.wake_all();
+    }
+
+    /// Hangs up for good: the session loses the terminal and later reads
+    /// and writes fail.
+    pub fn disconnect(&self) {
+        let session = {
+            let mut state = self.state.lock();
+            state.hung_up = true;
+            state.session
+        };
+        match session {
+            Some(session) => self.hang_up(session),
+            None => self.input_ready.wake_all(),
+        }
+    }
+
+    fn set_foreground(&self, pgid: ProcessId) -> Result<(), usize> {
+        if !self.is_controlling() {
+            return Err(ENOTTY);
+        }
+
+        let session = get_current_process().read().sid;
+        let valid = PROCESSES.read().iter().any(|process| {
+            let process = process.read();
+            process.pgid == pgid && process.sid == session
+        });
+        if !valid {
+            return Err(EPERM);
+        }
+
+        self.state.lock().foreground = Some(pgid);
+        Ok(())
+    }
+
+    pub fn ioctl(self: &Arc<Self>, cmd: usize, arg: usize) -> usize {
+        let result = match cmd {
+            TCGETS => {
+                unsafe { *(arg as *mut Termios) = self.state.lock().termios };
+                Ok(())
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = unsafe { *(arg as *const Termios) };
+                let mut state = self.state.lock();
+                if cmd == TCSETSF {
+                    state.flush_input();
+                }
+                state.set_termios(termios);
+                Ok(())
+            }
+            TCSBRK | TCXONC => Ok(()),
+            TCFLSH => {
+                if arg == TCIFLUSH || arg == TCIOFLUSH {
+                    self.state.lock().flush_input();
+                }
+                Ok(())
+            }
+            TIOCGWINSZ => {
+                unsafe { *(arg as *mut WinSize) = self.winsize() };
+                Ok(())
+            }
+            TIOCSWINSZ => {
+                self.set_winsize(unsafe { *(arg as *const WinSize) });
+                Ok(())
+            }
+            TIOCSCTTY => self.set_controlling(),
+            TIOCNOTTY => {
+                if !self.is_controlling() {
+                    Err(ENOTTY)
+                } else {
+                    let process = get_current_process();
+                    let (id, sid) = {
+                        let process = process.read();
+                        (process.id, process.sid)
+                    };
+                    if id == sid {
+                        self.hang_up(sid);
+                    } else {
+                        process.write().tty = None;
+                    }
+                    Ok(())
+                }
+            }
+            TIOCGPGRP | TIOCGSID => {
+                let state = self.state.lock();
+                let id = match cmd {
+                    TIOCGPGRP => state.foreground,
+                    _ => state.session,
+                };
+                match id {
+                    Some(id) => {
+                        unsafe { *(arg as *mut i32) = id.0 as i32 };
+                        Ok(())
+                    }
+                    None => Err(ENOTTY),
+                }
+            }
+            TIOCSPGRP => {
+                let pgid = unsafe { *(arg as *const i32) };
+                self.set_foreground(ProcessId(pgid as u64))
+            }
+            FIONREAD => {
+                unsafe { *(arg as *mut i32) = self.state.lock().available() as i32 };
+                Ok(())
+            }
+            TIOCOUTQ => {
+                unsafe { *(arg as *mut i32) = 0 };
+                Ok(())
+            }
+            _ => Err(ENOTTY),
+        };
+
+        match result {
+            Ok(()) => 0,
+            Err(err) => err.wrapping_neg(),
+        }
+    }
+}
+
+/// A terminal as it appears in `/dev`.
+pub struct TtyInode {
+    path: String,
+    tty: Arc<Tty>,
+}
+
+impl TtyInode {
+    pub fn new(tty: Arc<Tty>) -> InodeRef {
+        Arc::new(RwLock::new(Self {
+            path: String::new(),
+            tty,
+        }))
+    }
+}
+
+impl Inode for TtyInode {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// A session leader without a controlling terminal acquires the first
+    /// one it opens, unless it passes `O_NOCTTY`.
+    fn on_open(&self, _mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        if !flags.contains(OpenFlags::NOCTTY) {
+            let _ = self.tty.set_controlling();
+        }
+        Ok(None)
+    }
+
+    fn read_at(&self, fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+        self.tty.read(buf, nonblock)
+    }
+
+    fn write_at(&self, _fd: usize, _offset: usize, buf: &[u8]) -> usize {
+        self.tty.write(buf)
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        self.tty.poll(table)
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        self.tty.ioctl(cmd, arg)
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o620
+    }
+}
+
+/// `/dev/tty`, which opens the controlling terminal of whoever opens it.
+struct ControllingTty;
+
+impl Inode for ControllingTty {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        String::new()
+    }
+
+    fn on_open(&self, _mode: OpenMode, _flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        let tty = get_current_process().read().tty.clone().ok_or(ENXIO)?;
+        Ok(Some(TtyInode::new(tty)))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o666
+    }
+}
+
+/// Writes to the console terminal, or straight to the serial port before
+/// there is one.
+pub fn console_write(buf: &[u8]) {
+    match CONSOLE.get() {
+        Some(console) => {
+            console.write(buf);
+        }
+        None => serial::write_raw(buf),
+    }
+}
+
+/// Sets up the serial terminal, which is also the console, the framebuffer
+/// console when a font was loaded, and ptys. Needs devfs and the IOAPIC.
+pub fn init() {
+    let serial = serial::init();
+    CONSOLE.call_once(|| serial.clone());
+
+    let _ = register_device("tty", 5, 0, Arc::new(RwLock::new(ControllingTty)));
+    let _ = register_device("console", 5, 1, TtyInode::new(serial.clone()));
+    let _ = register_device("ttyS0", 4, 64, TtyInode::new(serial));
+
+    if let Some(console) = fbcon::init() {
+        let _ = register_device("tty1", 4, 1, TtyInode::new(console));
+    }
+
+    pty::init();
+}
@@ -0,0 +1,403 @@
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::{Lazy, Mutex, RwLock};
+
+use super::{
+    FIONREAD, TCFLSH, TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGWINSZ, TIOCSWINSZ, Tty, TtyDriver,
+    TtyInode, termios::WinSize,
+};
+use crate::{
+    fs::{
+        mount,
+        operation::{OpenFlags, OpenMode, get_flags_by_fd},
+        poll::{PollEvents, PollTable},
+        vfs::{
+            devfs::{DEV_FS, makedev, register_device},
+            inode::{FileInfo, Inode, InodeRef, InodeTy, Metadata, mount_to},
+        },
+    },
+    syscall::errno::{EAGAIN, EIO, ENOSPC, ENOTTY},
+    task::wait_queue::WaitQueue,
+};
+
+const TIOCGPTN: usize = 0x8004_5430;
+const TIOCSPTLCK: usize = 0x4004_5431;
+
+/// The major number Linux gives `/dev/pts/N`.
+const PTS_MAJOR: u32 = 136;
+const PTY_MAX: u32 = 256;
+
+/// Slave output the master hasn't read yet is capped here; past it, output
+/// is dropped the way an overrun UART drops input.
+const OUTPUT_CAPACITY: usize = 64 * 1024;
+
+/// `/dev/pts`, holding a slave node for every open master.
+pub static DEV_PTS: Lazy<Arc<RwLock<DevPts>>> = Lazy::new(|| {
+    Arc::new(RwLock::new(DevPts {
+        ptys: BTreeMap::new(),
+        path: String::new(),
+    }))
+});
+
+/// What the slave side wrote, waiting for the master to read it.
+struct PtyOutput {
+    buffer: Mutex<VecDeque<u8>>,
+    ready: Arc<WaitQueue>,
+}
+
+struct PtyDriver {
+    output: Arc<PtyOutput>,
+}
+
+impl TtyDriver for PtyDriver {
+    fn write(&self, buf: &[u8]) {
+        let mut buffer = self.output.buffer.lock();
+        let len = buf.len().min(OUTPUT_CAPACITY - buffer.len());
+        buffer.extend(&buf[..len]);
+        drop(buffer);
+        self.output.ready.wake_all();
+    }
+}
+
+/// A pseudo-terminal pair. The slave is an ordinary terminal whose driver
+/// hands output to the master; what the master writes is typed on the slave.
+pub struct Pty {
+    index: u32,
+    tty: Arc<Tty>,
+    output: Arc<PtyOutput>,
+    /// Set when `/dev/ptmx` is opened and cleared by `unlockpt`; the slave
+    /// can't be opened in between.
+    locked: AtomicBool,
+    slaves: AtomicUsize,
+    slave_opened: AtomicBool,
+}
+
+impl Pty {
+    /// Whether every slave that was ever opened has been closed again.
+    fn slave_gone(&self) -> bool {
+        self.slave_opened.load(Ordering::SeqCst) && self.slaves.load(Ordering::SeqCst) == 0
+    }
+
+    fn read(&self, buf: &mut [u8], nonblock: bool) -> usize {
+        let mut take = || {
+            let mut buffer = self.output.buffer.lock();
+            if buffer.is_empty() {
+                return self.slave_gone().then_some(EIO.wrapping_neg());
+            }
+            let len = buf.len().min(buffer.len());
+            for (slot, byte) in buf.iter_mut().zip(buffer.drain(..len)) {
+                *slot = byte;
+            }
+            Some(len)
+        };
+
+        match nonblock {
+            true => take().unwrap_or(EAGAIN.wrapping_neg()),
+            false => self.output.ready.wait_interruptible(take),
+        }
+    }
+}
+
+/// Allocates a pair with the lowest free number.
+fn allocate() -> Result<Arc<Pty>, usize> {
+    let mut dev_pts = DEV_PTS.write();
+    let index = (0..PTY_MAX)
+        .find(|index| !dev_pts.ptys.contains_key(index))
+        .ok_or(ENOSPC)?;
+
+    let output = Arc::new(PtyOutput {
+        buffer: Mutex::new(VecDeque::new()),
+        ready: Arc::new(WaitQueue::new()),
+    });
+    let winsize = WinSize {
+        ws_row: 24,
+        ws_col: 80,
+        ..WinSize::default()
+    };
+    let driver = PtyDriver {
+        output: output.clone(),
+    };
+    let pty = Arc::new(Pty {
+        index,
+        tty: Tty::new(&format!("pts/{}", index), Box::new(driver), winsize),
+        output,
+        locked: AtomicBool::new(true),
+        slaves: AtomicUsize::new(0),
+        slave_opened: AtomicBool::new(false),
+    });
+
+    dev_pts.ptys.insert(index, pty.clone());
+    Ok(pty)
+}
+
+/// `/dev/ptmx`. Every open creates a new pair and yields its master.
+struct PtyMultiplexer;
+
+impl Inode for PtyMultiplexer {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        String::new()
+    }
+
+    fn on_open(&self, _mode: OpenMode, _flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        let pty = allocate()?;
+        Ok(Some(Arc::new(RwLock::new(PtyMaster {
+            path: String::from("/dev/ptmx"),
+            pty,
+        }))))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o666
+    }
+}
+
+/// An open master. Closing it hangs up the slave and frees the number.
+struct PtyMaster {
+    path: String,
+    pty: Arc<Pty>,
+}
+
+impl Inode for PtyMaster {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn read_at(&self, fd: usize, _offset: usize, buf: &mut [u8]) -> usize {
+        let nonblock = get_flags_by_fd(fd).contains(OpenFlags::NONBLOCK);
+        self.pty.read(buf, nonblock)
+    }
+
+    fn write_at(&self, _fd: usize, _offset: usize, buf: &[u8]) -> usize {
+        self.pty.tty.receive(buf);
+        buf.len()
+    }
+
+    fn poll(&self, _fd: usize, table: &mut PollTable) -> PollEvents {
+        table.register(&self.pty.output.ready);
+
+        let mut events = PollEvents::OUT | PollEvents::WRNORM;
+        if !self.pty.output.buffer.lock().is_empty() {
+            events |= PollEvents::IN | PollEvents::RDNORM;
+        }
+        if self.pty.slave_gone() {
+            events |= PollEvents::HUP;
+        }
+        events
+    }
+
+    /// Besides the pty ioctls, the master can get and set the slave's
+    /// termios and window size. Setting the size sends `SIGWINCH` to the
+    /// slave's foreground group.
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        match cmd {
+            TIOCGPTN => {
+                unsafe { *(arg as *mut u32) = self.pty.index };
+                0
+            }
+            TIOCSPTLCK => {
+                let lock = unsafe { *(arg as *const i32) };
+                self.pty.locked.store(lock != 0, Ordering::SeqCst);
+                0
+            }
+            FIONREAD => {
+                let len = self.pty.output.buffer.lock().len();
+                unsafe { *(arg as *mut i32) = len as i32 };
+                0
+            }
+            TCGETS | TCSETS | TCSETSW | TCSETSF | TCFLSH | TIOCGWINSZ | TIOCSWINSZ => {
+                self.pty.tty.ioctl(cmd, arg)
+            }
+            _ => ENOTTY.wrapping_neg(),
+        }
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        0o666
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        DEV_PTS.write().ptys.remove(&self.pty.index);
+        self.pty.tty.disconnect();
+    }
+}
+
+/// `/dev/pts`.
+pub struct DevPts {
+    ptys: BTreeMap<u32, Arc<Pty>>,
+    path: String,
+}
+
+impl Inode for DevPts {
+    fn when_mounted(&mut self, path: String, _father: Option<InodeRef>) {
+        self.path.clear();
+        self.path.push_str(path.as_str());
+    }
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn open(&self, name: String) -> Option<InodeRef> {
+        let index = name.parse().ok()?;
+        let pty = self.ptys.get(&index)?.clone();
+        Some(Arc::new(RwLock::new(PtsNode {
+            path: format!("{}{}", self.path, index),
+            pty,
+        })))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::Dir
+    }
+
+    fn list(&self, _fd: usize) -> Vec<FileInfo> {
+        self.ptys
+            .keys()
+            .map(|index| FileInfo::new(index.to_string(), InodeTy::CharDevice))
+            .collect()
+    }
+}
+
+/// `/dev/pts/N` as found in the directory. Opening it yields a
+/// [`PtySlave`].
+struct PtsNode {
+    path: String,
+    pty: Arc<Pty>,
+}
+
+impl Inode for PtsNode {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn on_open(&self, mode: OpenMode, flags: OpenFlags) -> Result<Option<InodeRef>, usize> {
+        if self.pty.locked.load(Ordering::SeqCst) {
+            return Err(EIO);
+        }
+
+        let slave = PtySlave::new(self.path.clone(), self.pty.clone());
+        slave.inner.on_open(mode, flags)?;
+        Ok(Some(Arc::new(RwLock::new(slave))))
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn metadata(&self, _fd: usize) -> Metadata {
+        Metadata {
+            ino: self.pty.index as u64 + 3,
+            mode: self.inode_type().mode_bits() | self.mode() as u32,
+            nlink: 1,
+            rdev: makedev(PTS_MAJOR, self.pty.index),
+            blksize: 1024,
+            ..Default::default()
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        0o620
+    }
+}
+
+/// An open slave. The master reads `EIO` once the last one is closed.
+struct PtySlave {
+    inner: TtyInode,
+    pty: Arc<Pty>,
+}
+
+impl PtySlave {
+    fn new(path: String, pty: Arc<Pty>) -> Self {
+        pty.slaves.fetch_add(1, Ordering::SeqCst);
+        pty.slave_opened.store(true, Ordering::SeqCst);
+        Self {
+            inner: TtyInode {
+                path,
+                tty: pty.tty.clone(),
+            },
+            pty,
+        }
+    }
+}
+
+impl Inode for PtySlave {
+    fn when_mounted(&mut self, _path: String, _father: Option<InodeRef>) {}
+
+    fn when_umounted(&mut self) {}
+
+    fn get_path(&self) -> String {
+        self.inner.get_path()
+    }
+
+    fn read_at(&self, fd: usize, offset: usize, buf: &mut [u8]) -> usize {
+        self.inner.read_at(fd, offset, buf)
+    }
+
+    fn write_at(&self, fd: usize, offset: usize, buf: &[u8]) -> usize {
+        self.inner.write_at(fd, offset, buf)
+    }
+
+    fn poll(&self, fd: usize, table: &mut PollTable) -> PollEvents {
+        self.inner.poll(fd, table)
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> usize {
+        self.inner.ioctl(cmd, arg)
+    }
+
+    fn inode_type(&self) -> InodeTy {
+        InodeTy::CharDevice
+    }
+
+    fn mode(&self) -> u16 {
+        self.inner.mode()
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        if self.pty.slaves.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.pty.output.ready.wake_all();
+        }
+    }
+}
+
+/// Registers `/dev/ptmx` and mounts devpts on `/dev/pts`.
+pub(super) fn init() {
+    let _ = register_device("ptmx", 5, 2, Arc::new(RwLock::new(PtyMultiplexer)));
+
+    let dev_pts: InodeRef = DEV_PTS.clone();
+    mount_to(dev_pts.clone(), DEV_FS.clone(), "pts".to_string());
+    mount::record("devpts", "/dev/pts", "devpts", dev_pts);
+}
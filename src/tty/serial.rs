@@ -0,0 +1,63 @@
+use alloc::{boxed::Box, sync::Arc};
+use spin::Once;
+use x86_64::instructions::interrupts;
+
+use super::{Tty, TtyDriver, termios::WinSize};
+use crate::serial::SERIAL;
+
+/// `ttyS0`, fed by the receive interrupt of the first serial port.
+static SERIAL_TTY: Once<Arc<Tty>> = Once::new();
+
+struct SerialDriver;
+
+impl TtyDriver for SerialDriver {
+    fn write(&self, buf: &[u8]) {
+        write_raw(buf);
+    }
+}
+
+/// Sends bytes as they are, bypassing the line discipline.
+pub fn write_raw(buf: &[u8]) {
+    interrupts::without_interrupts(|| {
+        let mut serial = SERIAL.lock();
+        for &byte in buf {
+            serial.send_raw(byte);
+        }
+    });
+}
+
+pub(super) fn init() -> Arc<Tty> {
+    SERIAL_TTY
+        .call_once(|| {
+            let winsize = WinSize {
+                ws_row: 24,
+                ws_col: 80,
+                ..WinSize::default()
+            };
+            Tty::new("ttyS0", Box::new(SerialDriver), winsize)
+        })
+        .clone()
+}
+
+/// Drains the receive buffer of the UART into the terminal.
+pub fn handle_interrupt() {
+    let mut buf = [0u8; 16];
+    loop {
+        let mut len = 0;
+        {
+            let mut serial = SERIAL.lock();
+            while len < buf.len()
+                && let Ok(byte) = serial.try_receive()
+            {
+                buf[len] = byte;
+                len += 1;
+            }
+        }
+        if len == 0 {
+            return;
+        }
+        if let Some(tty) = SERIAL_TTY.get() {
+            tty.receive(&buf[..len]);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// The kernel's `struct termios` and `struct winsize`, as `TCGETS` and
+// `TIOCGWINSZ` hand them to Linux programs.
+
+pub const NCCS: usize = 19;
+
+// c_cc indices.
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VDISCARD: usize = 13;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+// c_iflag bits.
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+pub const IXON: u32 = 0o2000;
+
+// c_oflag bits.
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+pub const OCRNL: u32 = 0o10;
+
+// c_cflag bits.
+pub const B38400: u32 = 0o17;
+pub const CS8: u32 = 0o60;
+pub const CREAD: u32 = 0o200;
+pub const HUPCL: u32 = 0o2000;
+
+// c_lflag bits.
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+pub const ECHONL: u32 = 0o100;
+pub const NOFLSH: u32 = 0o200;
+pub const TOSTOP: u32 = 0o400;
+pub const ECHOCTL: u32 = 0o1000;
+pub const ECHOKE: u32 = 0o4000;
+pub const IEXTEN: u32 = 0o100000;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+const _: () = assert!(size_of::<Termios>() == 36);
+
+impl Default for Termios {
+    /// What Linux sets up for a freshly opened terminal: cooked mode with
+    /// echo and the usual control characters.
+    fn default() -> Self {
+        let mut c_cc = [0; NCCS];
+        c_cc[VINTR] = 0x03;
+        c_cc[VQUIT] = 0x1c;
+        c_cc[VERASE] = 0x7f;
+        c_cc[VKILL] = 0x15;
+        c_cc[VEOF] = 0x04;
+        c_cc[VTIME] = 0;
+        c_cc[VMIN] = 1;
+        c_cc[VSTART] = 0x11;
+        c_cc[VSTOP] = 0x13;
+        c_cc[VSUSP] = 0x1a;
+        c_cc[VREPRINT] = 0x12;
+        c_cc[VDISCARD] = 0x0f;
+        c_cc[VWERASE] = 0x17;
+        c_cc[VLNEXT] = 0x16;
+
+        Self {
+            c_iflag: ICRNL | IXON,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: B38400 | CS8 | CREAD | HUPCL,
+            c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            c_line: 0,
+            c_cc,
+        }
+    }
+}
+
+impl Termios {
+    pub fn iflag(&self, flag: u32) -> bool {
+        self.c_iflag & flag != 0
+    }
+
+    pub fn oflag(&self, flag: u32) -> bool {
+        self.c_oflag & flag != 0
+    }
+
+    pub fn lflag(&self, flag: u32) -> bool {
+        self.c_lflag & flag != 0
+    }
+
+    /// Whether `byte` is the control character at `index`. A zero entry
+    /// disables the character.
+    pub fn is_cc(&self, index: usize, byte: u8) -> bool {
+        self.c_cc[index] != 0 && self.c_cc[index] == byte
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}